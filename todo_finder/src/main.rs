@@ -1,23 +1,250 @@
 use clap::{App, Arg};
-use std::{fs::File, io::prelude::*, path::Path};
-use todo_finder_lib::{github, parser::IssueMap};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::File,
+    io::{prelude::*, IsTerminal},
+    path::Path,
+    str::FromStr,
+    time::Duration,
+};
+use todo_finder_lib::{
+    actions,
+    baseline::Baseline,
+    config::{Config, CONFIG_FILE_NAME},
+    finder::{FileSearcher, SearchBackend, TEST_PATTERNS},
+    github,
+    message::Message,
+    parser::{
+        langs,
+        source::{TitleMode, TodoTags},
+        FileLimit, FileTodoLocation, GroupBy, IssueMap, LinkStyle, MarkdownOptions, ParsingSource,
+        ScanOptions, SortKey, SortOrder, TodoKey,
+    },
+    schema,
+    stats::{AssigneeStats, DirectoryStats, DuplicateTodos},
+    utils,
+};
+
+/// The line ending used in generated files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    fn from_arg(arg: &str) -> Result<Self, String> {
+        match arg {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            "native" if cfg!(windows) => Ok(LineEnding::Crlf),
+            "native" => Ok(LineEnding::Lf),
+            _ => Err(format!("invalid line ending '{}'", arg)),
+        }
+    }
+
+    /// Rewrite all line endings in the given text.
+    #[cfg(test)]
+    fn apply(&self, text: &str) -> String {
+        let mut writer = self.writer(vec![]);
+        writer.write_all(text.as_bytes()).unwrap();
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    }
+
+    /// Wrap a writer to rewrite all line endings written to it.
+    fn writer<W: Write>(self, inner: W) -> LineEndingWriter<W> {
+        LineEndingWriter {
+            inner,
+            ending: self,
+            pending_cr: false,
+        }
+    }
+}
+
+/// Rewrites line endings on their way to the inner writer, see
+/// `LineEnding::writer`. Call `finish` when done, as a `\r` at the very end
+/// is held back in case a `\n` follows it.
+struct LineEndingWriter<W> {
+    inner: W,
+    ending: LineEnding,
+    pending_cr: bool,
+}
+
+impl<W: Write> LineEndingWriter<W> {
+    fn finish(mut self) -> std::io::Result<W> {
+        if self.pending_cr {
+            self.inner.write_all(b"\r")?;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for LineEndingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let newline: &[u8] = match self.ending {
+            LineEnding::Lf => b"\n",
+            LineEnding::Crlf => b"\r\n",
+        };
+        let mut out = Vec::with_capacity(buf.len() + buf.len() / 8);
+        for byte in buf.iter() {
+            match (self.pending_cr, byte) {
+                (true, b'\n') => {}
+                (true, _) => out.push(b'\r'),
+                _ => {}
+            }
+            self.pending_cr = false;
+            match byte {
+                b'\r' => self.pending_cr = true,
+                b'\n' => out.extend_from_slice(newline),
+                byte => out.push(*byte),
+            }
+        }
+        self.inner.write_all(&out)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Fail, listing them, if any TODOs have no assignee.
+fn require_assignees(issues: &IssueMap<(), FileTodoLocation>, cwd: &str) -> Result<(), String> {
+    let unassigned = issues.unassigned_locations(cwd);
+    if unassigned.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} TODOs have no assignee:\n{}",
+            unassigned.len(),
+            unassigned.join("\n")
+        ))
+    }
+}
+
+/// Parse an `ASSIGNEE=LOGIN` pair of `--assignee-map`.
+fn assignee_pair(pair: &str) -> Result<(String, String), String> {
+    match pair.split_once('=') {
+        Some((assignee, login)) if !assignee.is_empty() && !login.is_empty() => {
+            Ok((assignee.to_string(), login.to_string()))
+        }
+        _ => Err(format!("'{}' is not of the form ASSIGNEE=LOGIN", pair)),
+    }
+}
+
+/// Parse an `EMOJI=LABEL` pair of `--emoji-label`.
+fn emoji_label_pair(pair: &str) -> Result<(String, String), String> {
+    match pair.split_once('=') {
+        Some((emoji, label)) if !emoji.is_empty() && !label.is_empty() => {
+            Ok((emoji.to_string(), label.to_string()))
+        }
+        _ => Err(format!("'{}' is not of the form EMOJI=LABEL", pair)),
+    }
+}
+
+/// Split the command of `--exec` into its words, the way a shell would.
+fn exec_argv(exec: &str) -> Result<Vec<String>, String> {
+    match shell_words::split(exec) {
+        Ok(argv) if !argv.is_empty() => Ok(argv),
+        Ok(_) => Err("it has no command".to_string()),
+        Err(e) => Err(format!("'{}' could not be split: {}", exec, e)),
+    }
+}
+
+/// Parse the `FILE:LINE` of `--lookup-issue`.
+fn file_and_line(arg: &str) -> Result<(String, usize), String> {
+    match arg.rsplit_once(':') {
+        Some((file, line)) if !file.is_empty() => match line.parse() {
+            Ok(line) if line > 0 => Ok((file.to_string(), line)),
+            _ => Err(format!("'{}' is not a line number", line)),
+        },
+        _ => Err(format!("'{}' is not of the form FILE:LINE", arg)),
+    }
+}
+
+/// Parse a number, eg. of `--max-operations`.
+fn number<T: FromStr>(arg: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    arg.parse()
+        .map_err(|e| format!("'{}' is not a number: {}", arg, e))
+}
+
+/// Parse a title similarity of `--title-similarity`, from 0 to 1.
+fn similarity(arg: &str) -> Result<f64, String> {
+    match number::<f64>(arg)? {
+        similarity if (0.0..=1.0).contains(&similarity) => Ok(similarity),
+        _ => Err(format!("'{}' is not from 0 to 1", arg)),
+    }
+}
+
+/// Parse a count that must be at least one, eg. of `--jobs`.
+fn positive_count(arg: &str) -> Result<usize, String> {
+    match arg.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("'{}' is not a positive integer", arg)),
+    }
+}
+
+/// Fail if more TODOs were found than the maximum, counting each location.
+fn check_max_todos(total: usize, distinct: usize, max: usize) -> Result<(), String> {
+    if total > max {
+        Err(format!(
+            "found {} TODOs ({} distinct), more than the maximum of {}",
+            total, distinct, max
+        ))
+    } else {
+        Ok(())
+    }
+}
 
 #[tokio::main]
 async fn main() {
     let cwd = std::env::current_dir().expect("could not get current dir");
     let cwd_str = cwd.to_str().expect("could not convert cwd path");
+    let in_github_actions = std::env::var("GITHUB_ACTIONS").is_ok_and(|v| v == "true");
+
+    let mut output_arg = Arg::with_name("output")
+        .short("o")
+        .long("issue_provider")
+        .value_name("PROVIDER")
+        .help(
+            "One of 'markdown', 'json', 'github', 'actions' or 'json-schema'. Defaults to 'actions' when run in GitHub Actions.",
+        )
+        .takes_value(true);
+    // The output may come from a config file in the working directory, which
+    // is checked once it has been read
+    if !in_github_actions && !cwd.join(CONFIG_FILE_NAME).is_file() {
+        output_arg =
+            output_arg.required_unless_one(&["exec", "format", "config", "stdin", "lookup_issue"]);
+    }
 
     let app = App::new("todo_finder")
         .version("0.1.0")
         .author("Schell Carl Scivally")
         .about("Finds TODOs in source code")
+        .arg(output_arg)
         .arg(
-            Arg::with_name("output")
-                .short("o")
-                .long("issue_provider")
-                .value_name("PROVIDER")
-                .help("One of 'markdown' or 'github'")
-                .required(true)
+            Arg::with_name("exec")
+                .long("exec")
+                .value_name("COMMAND")
+                .help(
+                    "Run this command with all found TODOs written to its stdin as JSON, and \
+                     stream its output. The command is split into arguments like a shell \
+                     would, eg. \"jq '.todos | length'\"",
+                )
+                .validator(|exec| exec_argv(&exec).map(|_| ()))
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Also print a 'mermaid' pie chart of the TODOs in each top-level directory")
+                .possible_values(&["mermaid"])
                 .takes_value(true),
         )
         .arg(
@@ -37,6 +264,289 @@ async fn main() {
                 .default_value("todo")
                 .takes_value(true),
         )
+        .arg(Arg::with_name("label_missing").long("label-missing").help(
+            "With the github provider, adopt open issues without the label whose title \
+                     matches a TODO, adding the label to them",
+        ))
+        .arg(Arg::with_name("self_assign").long("self-assign").help(
+            "With the github provider, assign created issues without an assignee to \
+                     the owner of the auth token",
+        ))
+        .arg(
+            Arg::with_name("assignee_map")
+                .long("assignee-map")
+                .value_name("ASSIGNEE=LOGIN")
+                .validator(|pair| assignee_pair(&pair).map(|_| ()))
+                .help(
+                    "With the github provider, assign the issues of TODOs assigned to \
+                     ASSIGNEE to the GitHub user LOGIN instead, eg. \
+                     'alice@example.com=alice'. Email addresses that aren't mapped are \
+                     mentioned instead. With --blame-assign, maps commit emails to logins \
+                     too. May be supplied multiple times. Replaces the assignee_map of the \
+                     config file",
+                )
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max_operations")
+                .long("max-operations")
+                .value_name("N")
+                .help(
+                    "With the github provider, refuse to sync if it would create, edit and close \
+                     more than N issues in total",
+                )
+                .validator(|n| number::<usize>(&n).map(|_| ()))
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Sync even if it takes more than --max-operations"),
+        )
+        .arg(
+            Arg::with_name("project")
+                .long("project")
+                .value_name("NUMBER")
+                .help(
+                    "With the github provider, add created issues to the GitHub Project (v2) \
+                     with this number, owned by the repo's owner",
+                )
+                .validator(|n| number::<u64>(&n).map(|_| ()))
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("github_url")
+                .long("github-url")
+                .value_name("URL")
+                .help(
+                    "With the github provider, the web url of a GitHub Enterprise Server to \
+                     sync with instead of https://github.com, eg. https://github.example.com",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("lookup_issue")
+                .long("lookup-issue")
+                .value_name("FILE:LINE")
+                .validator(|arg| file_and_line(&arg).map(|_| ()))
+                .help(
+                    "Print the number and url of the open GitHub issue of the TODO at this \
+                     line, eg. for an editor to jump to it, without scanning or syncing. \
+                     Needs an --auth",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("milestone")
+                .long("milestone")
+                .value_name("NUMBER|TITLE")
+                .help(
+                    "With the github provider, create issues in the milestone with this \
+                     number or title",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rename_detection")
+                .long("rename-detection")
+                .help(
+                    "With the github provider, follow TODOs into files git detects as renamed, \
+                     so a TODO that was moved and reworded keeps its issue",
+                ),
+        )
+        .arg(
+            Arg::with_name("title_similarity")
+                .long("title-similarity")
+                .value_name("SIMILARITY")
+                .help(
+                    "With the github provider, let a TODO whose title was edited keep the \
+                     issue whose title is at least this similar, from 0 to 1, eg. 0.8, \
+                     instead of closing it and opening another",
+                )
+                .validator(|n| similarity(&n).map(|_| ()))
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("dry_run").long("dry-run").help(
+            "With the markdown provider, print which TODOs would be created, updated or \
+             deleted in todos.md instead of writing it",
+        ))
+        .arg(Arg::with_name("update").long("update").help(
+            "Only replace the TODOs between the todo_finder markers of todos.md, keeping any \
+             notes written around them. Requires the markdown output",
+        ))
+        .arg(Arg::with_name("dry_run_diff").long("dry-run-diff").help(
+            "With the github provider, print which issues would be created, edited or \
+                     closed, and what each edit changes, without changing anything",
+        ))
+        .arg(
+            Arg::with_name("dedupe_by")
+                .long("dedupe-by")
+                .value_name("KEY")
+                .possible_values(&["title", "first-line", "description"])
+                .help(
+                    "What tells TODOs apart, so TODOs with the same title but different \
+                     descriptions become separate issues: 'title' (the default), the title \
+                     and 'first-line' of the description, or the whole 'description'",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("close_reason")
+                .long("close-reason")
+                .value_name("REASON")
+                .possible_values(&["completed", "not-planned"])
+                .help(
+                    "With the github provider, why the issues of TODOs that are gone are \
+                     closed: 'completed' (the default) or 'not-planned'",
+                )
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("require_clean").long("require-clean").help(
+            "Refuse to generate GitHub links to TODOs from a working tree with uncommitted \
+             changes, instead of warning that the links may not match",
+        ))
+        .arg(
+            Arg::with_name("context_lines")
+                .long("context-lines")
+                .value_name("N")
+                .help(
+                    "With the github provider, widen the lines linked to in issues by N lines \
+                     of code on each side",
+                )
+                .validator(|n| number::<usize>(&n).map(|_| ()))
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("relative_links")
+                .long("relative-links")
+                .help(
+                    "With the github provider, link to TODOs in issues relative to the repo, like \
+             `../blob/<sha>/src/lib.rs#L3`, instead of with full permalinks",
+                ),
+        )
+        .arg(
+            Arg::with_name("sync_log")
+                .long("sync-log")
+                .value_name("PATH")
+                .takes_value(true)
+                .help(
+                    "With the github provider, log each issue created, edited or closed to \
+                     this file, so a sync that fails midway skips them when run again",
+                ),
+        )
+        .arg(Arg::with_name("collapsible").long("collapsible").help(
+            "With the github provider, wrap each TODO location in an issue body in a \
+             collapsible section",
+        ))
+        .arg(
+            Arg::with_name("check_stale_refs")
+                .long("check-stale-refs")
+                .help(
+                    "Look up the GitHub issues referenced by TODOs, like TODO(#123), and warn \
+                     about TODOs that reference closed issues. Requires --auth",
+                ),
+        )
+        .arg(
+            Arg::with_name("fail_on_stale_ref")
+                .long("fail-on-stale-ref")
+                .help("Like --check-stale-refs, but exit with an error if any are found"),
+        )
+        .arg(Arg::with_name("link_check").long("link-check").help(
+            "Check that the GitHub permalinks to TODOs resolve, eg. that their commit was \
+             pushed, and exit with an error listing any that don't",
+        ))
+        .arg(
+            Arg::with_name("link_sample")
+                .long("link-sample")
+                .value_name("N")
+                .requires("link_check")
+                .help("Only check N permalinks, spread evenly over the TODOs")
+                .validator(|n| number::<usize>(&n).map(|_| ()))
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("fail_on_found")
+                .long("fail-on-found")
+                .conflicts_with("max_todos")
+                .help("Exit with an error if any TODOs are found, after the output is written"),
+        )
+        .arg(
+            Arg::with_name("max_todos")
+                .long("max-todos")
+                .value_name("N")
+                .help(
+                    "Exit with an error if more than N TODOs are found, counting each place a \
+                     TODO is found, after the output is written",
+                )
+                .validator(|n| number::<usize>(&n).map(|_| ()))
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tracker_issue")
+                .long("tracker-issue")
+                .value_name("TITLE")
+                .help(
+                    "With the github provider, keep one issue with this title whose body is a \
+                     task list of all TODOs, instead of one issue per TODO",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("diff_file")
+                .long("diff-file")
+                .value_name("FILE")
+                .help(
+                    "Instead of scanning, find the TODOs added in this unified diff, or in a \
+                     diff read from stdin if '-'",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rg_json")
+                .long("rg-json")
+                .value_name("FILE")
+                .conflicts_with("diff_file")
+                .help(
+                    "Instead of searching with rg, parse the possible TODOs that an earlier \
+                     `rg --json` found, from this file or from stdin if '-'",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("since")
+                .long("since")
+                .value_name("REV")
+                .conflicts_with_all(&["diff_file", "stdin"])
+                .help(
+                    "Only find the TODOs on lines added or changed since this git revision, \
+                     eg. the base branch of a PR, including uncommitted changes",
+                )
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("stdin").long("stdin").requires("ext").help(
+            "Instead of scanning, print the TODOs in a single file read from stdin as \
+                     JSON, without running rg or git",
+        ))
+        .arg(
+            Arg::with_name("ext")
+                .long("ext")
+                .value_name("EXT")
+                .help("The extension of the file read with --stdin, eg. 'rs'")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("PATH")
+                .help(
+                    "Read settings from this TOML file: exclude, label, tags and output. \
+                     Flags override its values. Defaults to .todo_finder.toml in the \
+                     working directory, if there is one",
+                )
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("exclude")
                 .short("e")
@@ -45,43 +555,942 @@ async fn main() {
                 .help("Regex of files or directories to ignore, may be supplied multiple times")
                 .multiple(true)
                 .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("emoji_label")
+                .long("emoji-label")
+                .value_name("EMOJI=LABEL")
+                .validator(|pair| emoji_label_pair(&pair).map(|_| ()))
+                .help(
+                    "Strip this emoji from the start of TODO titles, like `TODO: 🔴 Fix it.`, \
+                     and label the TODO with LABEL, eg. '🔴=critical'. May be supplied \
+                     multiple times. Replaces the emoji_labels of the config file",
+                )
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ignore_title")
+                .long("ignore-title")
+                .value_name("TITLE")
+                .help(
+                    "Ignore TODOs with this title, or titles matching it as a glob with * and \
+                     ?, eg. 'Example: *'. Case, spacing and a trailing period don't matter. \
+                     May be supplied multiple times. Replaces the ignore_titles of the config \
+                     file",
+                )
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("exclude_tests").long("exclude-tests").help(
+            "Ignore test files and directories by convention: **/tests/**, *_test.*, \
+             test_*.*, **/__tests__/** and *.spec.*",
+        ))
+        .arg(
+            Arg::with_name("test_pattern")
+                .long("test-pattern")
+                .value_name("GLOB")
+                .requires("exclude_tests")
+                .help(
+                    "A glob of test files to ignore with --exclude-tests, may be supplied \
+                     multiple times. Replaces the conventional ones",
+                )
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tag")
+                .long("tag")
+                .value_name("KEYWORD")
+                .help(
+                    "A keyword that starts a TODO, like REVIEW, may be supplied multiple times. \
+                     Replaces the default TODO, FIXME, HACK, XXX, NOTE, BUG, OPTIMIZE and @todo",
+                )
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("no_border")
+                .long("no-border")
+                .value_name("LANGUAGE=BORDER")
+                .validator(|arg| langs::parse_disabled_border(&arg).map(|_| ()))
+                .help(
+                    "Don't strip a comment border from TODOs in a language, eg. swift=: keeps \
+                     titles starting with ':', may be supplied multiple times",
+                )
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("timeout_per_file")
+                .long("timeout-per-file")
+                .value_name("MILLISECONDS")
+                .help("Skip any file that takes longer than this to read and parse")
+                .validator(|ms| positive_count(&ms).map(|_| ()))
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("file_concurrency")
+                .long("file-concurrency")
+                .value_name("N")
+                .help("The most files to have open at once while reading them [default: 64]")
+                .validator(|n| positive_count(&n).map(|_| ()))
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cache")
+                .long("cache")
+                .value_name("PATH")
+                .help(
+                    "Cache the TODOs parsed from each file in this file, and reuse them for \
+                     files that haven't changed since, until the next commit",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("jobs")
+                .long("jobs")
+                .short("j")
+                .value_name("N")
+                .help("How many files to read and parse at once [default: one per core]")
+                .validator(|n| positive_count(&n).map(|_| ()))
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("searcher")
+                .long("searcher")
+                .value_name("BACKEND")
+                .help(
+                    "How to search for TODOs, either 'rg', 'builtin' (a file walker that \
+                     respects .gitignore, for when rg isn't installed) or 'auto' (rg if it's \
+                     installed, otherwise builtin)",
+                )
+                .possible_values(&["auto", "rg", "builtin"])
+                .default_value("auto")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rg_args")
+                .long("rg-args")
+                .value_name("ARGS")
+                .help(
+                    "Extra arguments passed along to rg, eg. \"--hidden --follow\", split \
+                     like a shell would. `--heading --line-number` are always passed.",
+                )
+                .allow_hyphen_values(true)
+                .validator(|args| {
+                    shell_words::split(&args)
+                        .map(|_| ())
+                        .map_err(|e| format!("invalid --rg-args '{}': {}", args, e))
+                })
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("git_tracked_only")
+                .long("git-tracked-only")
+                .help("Only scan files tracked by git, skipping untracked ones like build output"),
+        )
+        .arg(
+            Arg::with_name("assignee_required")
+                .long("assignee-required")
+                .help("Exit with an error, listing them, if any TODOs have no assignee"),
+        )
+        .arg(
+            Arg::with_name("report_malformed")
+                .long("report-malformed")
+                .help("List the TODOs that look malformed, eg. with no title, and why"),
+        )
+        .arg(
+            Arg::with_name("warn_conflicting_assignees")
+                .long("warn-conflicting-assignees")
+                .help(
+                    "Warn when TODOs with the same title have different assignees in different \
+                     places, as they may be different tasks",
+                ),
+        )
+        .arg(
+            Arg::with_name("merge_adjacent")
+                .long("merge-adjacent")
+                .help(
+                    "Treat a comment block separated from a TODO only by blank lines as more of \
+                     its description",
+                ),
+        )
+        .arg(Arg::with_name("verbose").short("v").long("verbose").help(
+            "List each file with possible TODOs in an unsupported language, not only their \
+             count by extension at the end of a scan",
+        ))
+        .arg(Arg::with_name("coverage").long("coverage").help(
+            "Print the percentage of source files in a supported language that hold at least \
+             one TODO, counting the files without possible TODOs too",
+        ))
+        .arg(Arg::with_name("blame_assign").long("blame-assign").help(
+            "Assign TODOs without an assignee to the author of their line by git blame, if \
+             their email is a GitHub noreply address or is in the --assignee-map",
+        ))
+        .arg(
+            Arg::with_name("skip_doc_examples")
+                .long("skip-doc-examples")
+                .help(
+                    "Skip TODOs in the fenced code examples of '///' and '//!' doc comments, \
+                     as they're illustrative",
+                ),
+        )
+        .arg(
+            Arg::with_name("strip_tag_from_title")
+                .long("strip-tag-from-title")
+                .help("Strip redundant tags from the start of titles, as in 'TODO: TODO: Do it.'"),
+        )
+        .arg(
+            Arg::with_name("title_mode")
+                .long("title-mode")
+                .value_name("MODE")
+                .help(
+                    "How TODO titles are found, either 'sentence' (the first sentence) or \
+                     'firstline' (the whole first line)",
+                )
+                .possible_values(&["sentence", "firstline"])
+                .default_value("sentence")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("title_separator")
+                .long("title-separator")
+                .value_name("SEPARATOR")
+                .help(
+                    "Split TODO titles from their descriptions on this, as in 'TODO: Title || \
+                     Description', instead of using --title-mode",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("line_ending")
+                .long("line-ending")
+                .value_name("ENDING")
+                .help("Line endings of generated files, one of 'lf', 'crlf' or 'native'")
+                .possible_values(&["lf", "crlf", "native"])
+                .default_value("lf")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("anchor_format")
+                .long("anchor-format")
+                .value_name("TEMPLATE")
+                .validator(|format| {
+                    if format.contains("{start}") {
+                        Ok(())
+                    } else {
+                        Err(format!("'{}' has no {{start}}", format))
+                    }
+                })
+                .help(
+                    "Anchor the lines of each location in todos.md with this template, where \
+                     {start} and {end} are its first and last lines, eg. '#L{start}-L{end}' \
+                     like GitHub or ':{start}'",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sort")
+                .long("sort")
+                .value_name("KEY")
+                .possible_values(&[
+                    "title",
+                    "file",
+                    "line",
+                    "assignee",
+                    "severity",
+                    "occurrences",
+                ])
+                .help(
+                    "Sort the TODOs of the markdown, JSON and actions outputs by this key. \
+                     Defaults to 'title', or to 'file' for actions",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("reverse")
+                .long("reverse")
+                .requires("sort")
+                .help("Reverse the order given by --sort"),
+        )
+        .arg(
+            Arg::with_name("group_by")
+                .long("group-by")
+                .value_name("GROUPING")
+                .possible_values(&["todo", "file"])
+                .help(
+                    "List each TODO once with all of its locations, or under a heading for \
+                     each file it's in. Defaults to 'todo'. Only for the markdown output",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("baseline")
+                .long("baseline")
+                .value_name("FILE")
+                .help(
+                    "A file of previously recorded TODO counts. Exits with an error if the \
+                     current count is higher.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("write_baseline")
+                .long("write-baseline")
+                .help("Write the current TODO counts to the --baseline file instead of comparing")
+                .requires("baseline"),
         );
 
     let matches = app.get_matches();
-    let exclusions: Vec<String> = matches
+    if matches.value_of("output") == Some("json-schema") {
+        // The schema of the JSON written to --exec commands, no scan needed
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&schema::todos_schema()).unwrap()
+        );
+        return;
+    }
+    let config = matches
+        .value_of("config")
+        .map(|path| Config::read(path).map(Some))
+        .unwrap_or_else(|| Config::discover(&cwd))
+        .unwrap_or_else(|e| panic!("{}", e))
+        .unwrap_or_default();
+    let output = matches
+        .value_of("output")
+        .or(config.output.as_deref())
+        .or(if in_github_actions {
+            Some("actions")
+        } else {
+            None
+        });
+    // A config file only waives -o if it sets an output, see `output_arg`
+    if output.is_none()
+        && !matches.is_present("exec")
+        && !matches.is_present("format")
+        && !matches.is_present("stdin")
+        && !matches.is_present("lookup_issue")
+    {
+        eprintln!(
+            "error: no output, pass --issue_provider <PROVIDER> or set `output` in the config \
+             file"
+        );
+        std::process::exit(1);
+    }
+    if matches.is_present("update") && output != Some("markdown") {
+        eprintln!("error: --update only works with the markdown output");
+        std::process::exit(1);
+    }
+    // Keep the stdout of -o json parseable
+    if output == Some("json") && (matches.is_present("exec") || matches.is_present("format")) {
+        eprintln!("error: --exec and --format print to stdout, so they don't work with -o json");
+        std::process::exit(1);
+    }
+
+    let mut exclusions: Vec<String> = matches
         .value_of("exclude")
         .map(|s| s.split(" ").map(|s| s.to_string()).collect::<Vec<_>>())
-        .unwrap_or(vec![]);
+        .unwrap_or_else(|| config.exclude.clone());
+    if matches.is_present("exclude_tests") {
+        let test_patterns = matches
+            .values_of("test_pattern")
+            .map(|patterns| patterns.map(String::from).collect::<Vec<_>>())
+            .unwrap_or_else(|| config.test_patterns.clone());
+        if test_patterns.is_empty() {
+            exclusions.extend(TEST_PATTERNS.iter().map(|pattern| pattern.to_string()));
+        } else {
+            exclusions.extend(test_patterns);
+        }
+    }
+
+    let line_ending = matches
+        .value_of("line_ending")
+        .map(|arg| LineEnding::from_arg(arg).unwrap())
+        .unwrap_or(LineEnding::Lf);
+    // Checked by its validator
+    let anchor_format = matches.value_of("anchor_format");
+    let sort_order = matches.value_of("sort").map(|arg| SortOrder {
+        key: SortKey::from_arg(arg).unwrap(),
+        reverse: matches.is_present("reverse"),
+    });
 
-    match matches.value_of("output").expect("--output required") {
-        "markdown" => {
+    // Checked by its validator
+    let timeout_per_file = matches
+        .value_of("timeout_per_file")
+        .map(|ms| Duration::from_millis(positive_count(ms).unwrap() as u64));
+    let rg_args = matches
+        .value_of("rg_args")
+        // Checked by its validator
+        .and_then(|args| shell_words::split(args).ok())
+        .unwrap_or_default();
+    let search_backend = matches
+        .value_of("searcher")
+        .map(|arg| SearchBackend::from_arg(arg).unwrap())
+        .unwrap_or_default();
+    let assignee_map: BTreeMap<String, String> = matches
+        .values_of("assignee_map")
+        // Checked by its validator
+        .map(|pairs| pairs.filter_map(|pair| assignee_pair(pair).ok()).collect())
+        .unwrap_or_else(|| config.assignee_map.clone());
+    let scan_options = ScanOptions {
+        excludes: exclusions,
+        timeout_per_file,
+        rg_args,
+        search_backend,
+        disabled_borders: matches
+            .values_of("no_border")
+            // Checked by its validator
+            .map(|args| {
+                args.filter_map(|arg| langs::parse_disabled_border(arg).ok())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        merge_adjacent: matches.is_present("merge_adjacent"),
+        git_tracked_only: matches.is_present("git_tracked_only"),
+        warn_conflicting_assignees: matches.is_present("warn_conflicting_assignees"),
+        strip_tag_from_title: matches.is_present("strip_tag_from_title"),
+        skip_doc_examples: matches.is_present("skip_doc_examples"),
+        since: matches.value_of("since").map(String::from),
+        blame_assign: matches.is_present("blame_assign"),
+        assignee_map: assignee_map.clone(),
+        report_unsupported_files: matches.is_present("verbose"),
+        todo_coverage: matches.is_present("coverage"),
+        cache: matches.value_of("cache").map(String::from),
+        custom_languages: config.supported_languages(),
+        emoji_labels: matches
+            .values_of("emoji_label")
+            // Checked by its validator
+            .map(|pairs| {
+                pairs
+                    .filter_map(|pair| emoji_label_pair(pair).ok())
+                    .collect()
+            })
+            .unwrap_or_else(|| config.emoji_labels.clone().into_iter().collect()),
+        ignore_titles: matches
+            .values_of("ignore_title")
+            .map(|titles| titles.map(String::from).collect())
+            .unwrap_or_else(|| config.ignore_titles.clone()),
+        todo_key: matches
+            .value_of("dedupe_by")
+            .map(|arg| TodoKey::from_arg(arg).unwrap())
+            .unwrap_or_default(),
+        file_limit: matches
+            .value_of("file_concurrency")
+            // Checked by its validator
+            .and_then(|n| positive_count(n).ok())
+            .map(FileLimit::new)
+            .unwrap_or_default(),
+        // Checked by its validator
+        jobs: matches
+            .value_of("jobs")
+            .and_then(|n| positive_count(n).ok()),
+        tags: matches
+            .values_of("tag")
+            .map(TodoTags::new)
+            .or_else(|| {
+                if config.tags.is_empty() {
+                    None
+                } else {
+                    Some(TodoTags::new(config.tags.clone()))
+                }
+            })
+            .unwrap_or_default(),
+        title_mode: match matches.value_of("title_separator") {
+            Some(separator) => TitleMode::Separator(separator.into()),
+            None => matches
+                .value_of("title_mode")
+                .map(|arg| TitleMode::from_arg(arg).unwrap())
+                .unwrap_or_default(),
+        },
+    };
+
+    if let Some((file, line)) = matches
+        .value_of("lookup_issue")
+        // Checked by its validator
+        .and_then(|arg| file_and_line(arg).ok())
+    {
+        let auth_token = matches
+            .value_of("auth")
+            .expect("--lookup-issue requires an auth");
+        let issue_label = match (matches.occurrences_of("label"), config.label.as_deref()) {
+            (0, Some(label)) => label,
+            _ => matches
+                .value_of("label")
+                .expect("--lookup-issue requires an issue label"),
+        };
+        let options = github::GitHubOptions {
+            github_url: matches.value_of("github_url").map(String::from),
+            todo_key: scan_options.todo_key.clone(),
+            ..github::GitHubOptions::default()
+        };
+        let loc = FileTodoLocation {
+            file,
+            src_span: (line, None),
+        };
+        let found = github::run_lookup_issue_github(
+            auth_token.into(),
+            issue_label.into(),
+            cwd_str.into(),
+            options,
+            &loc,
+        )
+        .await;
+        match found {
+            Ok(Some((number, url))) => println!("{} {}", number, url),
+            Err(e) => {
+                eprintln!(
+                    "error: could not look up the issue of the TODO at {}:{}: {}",
+                    loc.file, line, e
+                );
+                std::process::exit(2);
+            }
+            Ok(None) => {
+                eprintln!("no open issue for the TODO at {}:{}", loc.file, line);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if matches.is_present("stdin") {
+        let ext = matches.value_of("ext").expect("--stdin requires an --ext");
+        let mut contents = String::new();
+        std::io::stdin()
+            .read_to_string(&mut contents)
+            .unwrap_or_else(|e| panic!("could not read stdin: {}", e));
+        let issues = IssueMap::from_contents_with_extension("-", &contents, ext, &scan_options)
+            .unwrap_or_else(|e| panic!("{}", e));
+        // Only the JSON, so editors can parse the output
+        println!("{}", issues.as_json_sorted(&sort_order.unwrap_or_default()));
+        return;
+    }
+
+    let issues = if let Some(diff_file) = matches.value_of("diff_file") {
+        let mut diff = String::new();
+        if diff_file == "-" {
+            std::io::stdin()
+                .read_to_string(&mut diff)
+                .unwrap_or_else(|e| panic!("could not read a diff from stdin: {}", e));
+        } else {
+            File::open(diff_file)
+                .and_then(|mut file| file.read_to_string(&mut diff))
+                .unwrap_or_else(|e| panic!("could not read diff file {}: {}", diff_file, e));
+        }
+        // Diff paths are relative to the repo
+        IssueMap::from_diff(cwd_str, &diff, &scan_options).unwrap_or_else(|e| panic!("{}", e))
+    } else if let Some(rg_json_file) = matches.value_of("rg_json") {
+        let mut rg_json = String::new();
+        if rg_json_file == "-" {
+            std::io::stdin()
+                .read_to_string(&mut rg_json)
+                .unwrap_or_else(|e| panic!("could not read rg JSON from stdin: {}", e));
+        } else {
+            File::open(rg_json_file)
+                .and_then(|mut file| file.read_to_string(&mut rg_json))
+                .unwrap_or_else(|e| panic!("could not read rg JSON file {}: {}", rg_json_file, e));
+        }
+        let possible_todos = FileSearcher::parse_rg_json(&rg_json)
+            .unwrap_or_else(|e| panic!("{}", e))
+            .into_iter()
+            .map(|mut possible_todo| {
+                // rg reports paths relative to where it ran
+                possible_todo.file = cwd.join(&possible_todo.file).display().to_string();
+                possible_todo
+            })
+            .collect();
+        IssueMap::from_possible_todos_in_directory(cwd_str, possible_todos, &scan_options).unwrap()
+    } else {
+        match search_backend {
+            SearchBackend::Ripgrep => {
+                FileSearcher::check_rg_version()
+                    .unwrap_or_else(|e| panic!("could not run rg: {}", e));
+            }
+            // Falls back to the built-in walker if rg can't be found
+            SearchBackend::Auto => {
+                let _ = FileSearcher::check_rg_version();
+            }
+            SearchBackend::Builtin => {}
+        }
+        IssueMap::from_files_in_directory_with_options(cwd_str, &scan_options).unwrap()
+    };
+    let baseline = Baseline::from_issue_map(&issues, cwd_str);
+    let max_todos = if matches.is_present("fail_on_found") {
+        Some(0)
+    } else {
+        // Checked by its validator
+        matches.value_of("max_todos").map(|n| number(n).unwrap())
+    };
+    let (num_total, num_distinct) = (issues.total_len(), issues.distinct_len());
+    Message::AssigneeStats(AssigneeStats::from_issue_map(&issues)).send();
+    let duplicates = DuplicateTodos::from_issue_map(&issues);
+    if !duplicates.is_empty() {
+        Message::DuplicateTodos(duplicates).send();
+    }
+    if matches.is_present("report_malformed") {
+        let entries = issues.malformed_todos(cwd_str);
+        if !entries.is_empty() {
+            Message::MalformedTodos { entries }.send();
+        }
+    }
+
+    if matches.is_present("assignee_required") {
+        // Before anything is written or sent to an issue provider
+        if let Err(e) = require_assignees(&issues, cwd_str) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(exec) = matches.value_of("exec") {
+        // Checked by its validator
+        let argv = exec_argv(exec).unwrap_or_default();
+        let argv = argv.iter().map(String::as_str).collect::<Vec<_>>();
+        if let Some((program, args)) = argv.split_first() {
+            // The same JSON as `-o json`, described by `-o json-schema`
+            let json = issues.as_json_sorted(&sort_order.unwrap_or_default());
+            if let Err(e) = utils::stream_command_with_stdin(program, args, cwd_str, &json) {
+                eprintln!("error: the --exec command failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if matches.value_of("format") == Some("mermaid") {
+        println!(
+            "{}",
+            DirectoryStats::from_issue_map(&issues, cwd_str).as_mermaid()
+        );
+    }
+
+    let github_url = || matches.value_of("github_url").map(String::from);
+    let context_lines = matches
+        .value_of("context_lines")
+        // Checked by its validator
+        .map(|n| number(n).unwrap())
+        .unwrap_or(0);
+    let fail_on_stale_ref = matches.is_present("fail_on_stale_ref");
+    if fail_on_stale_ref || matches.is_present("check_stale_refs") {
+        let auth_token = matches
+            .value_of("auth")
+            .expect("checking for stale references requires an auth");
+        let stale =
+            github::run_stale_refs_github(auth_token.into(), cwd_str.into(), &issues, github_url())
+                .await
+                .unwrap();
+        for message in stale.iter() {
+            message.send();
+        }
+        if fail_on_stale_ref && !stale.is_empty() {
+            eprintln!("{} TODOs reference closed issues", stale.len());
+            std::process::exit(1);
+        }
+    }
+
+    if matches.is_present("link_check") {
+        if matches.is_present("require_clean") {
+            github::check_working_tree(cwd_str, true).unwrap();
+        }
+        // Checked by its validator
+        let sample = matches.value_of("link_sample").map(|n| number(n).unwrap());
+        // The links a sync would write
+        let options = github::GitHubOptions {
+            github_url: github_url(),
+            context_lines,
+            ..github::GitHubOptions::default()
+        };
+        let (checked, broken) =
+            github::run_link_check_github(cwd_str.into(), &issues, sample, options)
+                .await
+                .unwrap();
+        for message in broken.iter() {
+            message.send();
+        }
+        if !broken.is_empty() {
+            eprintln!(
+                "{} of {} permalinks are broken, was the commit pushed?",
+                broken.len(),
+                checked
+            );
+            std::process::exit(1);
+        }
+    }
+
+    match output {
+        None => {}
+
+        Some("markdown") => {
             let file_name = "todos.md";
-            let issues = IssueMap::from_files_in_directory(cwd_str, &exclusions).unwrap();
-            let markdown = issues.as_markdown();
+            if matches.is_present("dry_run") {
+                let existing = match std::fs::read_to_string(file_name) {
+                    Ok(markdown) => IssueMap::from_markdown_anchored(&markdown, anchor_format)
+                        .unwrap_or_else(|e| panic!("{}: {}", file_name, e)),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        IssueMap::new(ParsingSource::MarkdownFile)
+                    }
+                    Err(e) => panic!("could not read file {}: {}", file_name, e),
+                };
+                existing.preview_patch(&issues).send();
+                return;
+            }
+            let options = MarkdownOptions {
+                order: sort_order.unwrap_or_default(),
+                group_by: matches
+                    .value_of("group_by")
+                    .map(|arg| GroupBy::from_arg(arg).unwrap())
+                    .unwrap_or_default(),
+                anchor_format: anchor_format.map(String::from),
+            };
+            // Read before the file is truncated by creating it
+            let current =
+                matches
+                    .is_present("update")
+                    .then(|| match std::fs::read_to_string(file_name) {
+                        Ok(markdown) => markdown,
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                        Err(e) => panic!("could not read file {}: {}", file_name, e),
+                    });
             let path = Path::new(file_name);
-            let mut file =
-                File::create(path).expect(&format!("could not create file {}", file_name));
-            let bytes = markdown.as_bytes();
-            file.write_all(bytes)
-                .expect(&format!("could not write to file {}", file_name));
+            let file = File::create(path)
+                .unwrap_or_else(|e| panic!("could not create file {}: {}", file_name, e));
+            let mut writer = line_ending.writer(std::io::BufWriter::new(file));
+            match current {
+                Some(current) => issues.write_updated_markdown(&mut writer, &current, &options),
+                None => issues.write_markdown_with(&mut writer, &options),
+            }
+            .and_then(|_| writer.finish())
+            .unwrap_or_else(|e| panic!("could not write to file {}: {}", file_name, e));
             println!("TODOs written to {:#?}", path);
         }
 
-        "github" => {
+        Some("json") => {
+            println!("{}", issues.as_json_sorted(&sort_order.unwrap_or_default()));
+        }
+
+        Some("actions") => {
+            let order = sort_order.unwrap_or(SortOrder::by(SortKey::File));
+            for annotation in actions::annotations_sorted(&issues, cwd_str, &order) {
+                println!("{}", annotation);
+            }
+        }
+
+        Some("github") => {
             let auth_token = matches.value_of("auth").expect("github requires an auth");
-            let issue_label = matches
-                .value_of("label")
-                .expect("github requires an issue label");
-            github::run_ts_github(
-                auth_token.into(),
-                issue_label.into(),
-                cwd_str.into(),
-                &exclusions,
-            )
-            .await
-            .unwrap();
+            // The flag has a default, so only take it over the config if given
+            let issue_label = match (matches.occurrences_of("label"), config.label.as_deref()) {
+                (0, Some(label)) => label,
+                _ => matches
+                    .value_of("label")
+                    .expect("github requires an issue label"),
+            };
+            if let Some(tracker_title) = matches.value_of("tracker_issue") {
+                if matches.is_present("require_clean") {
+                    github::check_working_tree(cwd_str, true).unwrap();
+                }
+                github::run_tracker_github(
+                    auth_token.into(),
+                    issue_label.into(),
+                    cwd_str.into(),
+                    tracker_title.into(),
+                    issues,
+                    github_url(),
+                )
+                .await
+                .unwrap();
+            } else {
+                let options = github::GitHubOptions {
+                    adopt_unlabeled: matches.is_present("label_missing"),
+                    self_assign: matches.is_present("self_assign"),
+                    collapsible: matches.is_present("collapsible"),
+                    max_operations: if matches.is_present("force") {
+                        None
+                    } else {
+                        // Checked by its validator
+                        matches
+                            .value_of("max_operations")
+                            .map(|n| number(n).unwrap())
+                    },
+                    rename_detection: matches.is_present("rename_detection"),
+                    // Checked by its validator
+                    min_title_similarity: matches
+                        .value_of("title_similarity")
+                        .map(|n| similarity(n).unwrap()),
+                    // Checked by its validator
+                    project: matches.value_of("project").map(|n| number(n).unwrap()),
+                    milestone: matches.value_of("milestone").map(String::from),
+                    github_url: github_url(),
+                    assignee_map: assignee_map.clone(),
+                    context_lines,
+                    link_style: if matches.is_present("relative_links") {
+                        LinkStyle::RepoRelative
+                    } else {
+                        LinkStyle::Absolute
+                    },
+                    sync_log: matches.value_of("sync_log").map(String::from),
+                    todo_key: scan_options.todo_key.clone(),
+                    close_reason: matches
+                        .value_of("close_reason")
+                        .map(|arg| github::CloseReason::from_arg(arg).unwrap())
+                        .unwrap_or_default(),
+                    require_clean: matches.is_present("require_clean"),
+                };
+                if matches.is_present("dry_run_diff") {
+                    github::run_dry_run_diff_github(
+                        auth_token.into(),
+                        issue_label.into(),
+                        cwd_str.into(),
+                        options,
+                        issues,
+                        std::io::stdout().is_terminal(),
+                    )
+                    .await
+                    .unwrap();
+                } else {
+                    github::run_ts_github(
+                        auth_token.into(),
+                        issue_label.into(),
+                        cwd_str.into(),
+                        options,
+                        issues,
+                    )
+                    .await
+                    .unwrap();
+                }
+            }
+        }
+
+        Some(_) => panic!("invalid value for 'output'"),
+    }
+
+    if let Some(baseline_file) = matches.value_of("baseline") {
+        if matches.is_present("write_baseline") {
+            baseline.write(baseline_file).unwrap();
+            eprintln!(
+                "Wrote a baseline of {} TODOs to {}",
+                baseline.total, baseline_file
+            );
+        } else {
+            let previous = Baseline::read(baseline_file).unwrap();
+            let delta = previous.compare(&baseline);
+            eprintln!(
+                "TODOs: {} (baseline {})",
+                delta.current_total, delta.previous_total
+            );
+            for grown in delta.grown_files.iter() {
+                eprintln!("  {}: {} -> {}", grown.file, grown.previous, grown.current);
+            }
+            if delta.increased() {
+                eprintln!("the number of TODOs increased since the baseline");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(max) = max_todos {
+        if let Err(e) = check_max_todos(num_total, num_distinct, max) {
+            eprintln!("{}", e);
+            std::process::exit(1);
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_convert_line_endings() {
+        let text = "# TODOs\n\n1. A todo.\r\n2. Another.\n";
+        assert_eq!(
+            LineEnding::Crlf.apply(text),
+            "# TODOs\r\n\r\n1. A todo.\r\n2. Another.\r\n"
+        );
+        assert_eq!(
+            LineEnding::Lf.apply(text),
+            "# TODOs\n\n1. A todo.\n2. Another.\n"
+        );
+        assert!(LineEnding::from_arg("cr").is_err());
+
+        // A `\r\n` split across writes is still one line ending
+        let mut writer = LineEnding::Crlf.writer(vec![]);
+        for chunk in ["a\r", "\nb\r", "c\n", "d\r"].iter() {
+            writer.write_all(chunk.as_bytes()).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), b"a\r\nb\rc\r\nd\r");
+    }
+
+    #[test]
+    fn todos_without_assignees_are_listed() {
+        let mut issues = IssueMap::new_source_todos();
+        let contents = "// TODO(schell): Do A.\n// TODO: Do B.\n// FIXME: Do C.\n";
+        issues.merge(
+            IssueMap::from_file_contents(
+                "/repo/src/lib.rs",
+                contents,
+                &[1, 2, 3],
+                todo_finder_lib::parser::langs::language_map()["rs"].as_slice(),
+                &ScanOptions::default(),
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            require_assignees(&issues, "/repo"),
+            Err("2 TODOs have no assignee:\nsrc/lib.rs:2: Do B.\nsrc/lib.rs:3: Do C.".into())
+        );
+
+        issues.todos.retain(|title, _| title == "Do A.");
+        assert_eq!(require_assignees(&issues, "/repo"), Ok(()));
+    }
+
+    #[test]
+    fn too_many_todos_fail() {
+        assert_eq!(check_max_todos(0, 0, 0), Ok(()));
+        assert_eq!(check_max_todos(3, 2, 3), Ok(()));
+        assert_eq!(
+            check_max_todos(3, 2, 0),
+            Err("found 3 TODOs (2 distinct), more than the maximum of 0".into())
+        );
+    }
+
+    #[test]
+    fn exec_commands_are_split_like_a_shell() {
+        assert_eq!(
+            exec_argv("jq '.todos | length'"),
+            Ok(vec!["jq".to_string(), ".todos | length".to_string()])
+        );
+        assert!(exec_argv("  ").is_err());
+        assert!(exec_argv("jq '.todos").is_err());
+    }
+
+    #[test]
+    fn lookups_are_of_a_file_and_line() {
+        assert_eq!(
+            file_and_line("src/lib.rs:12"),
+            Ok(("src/lib.rs".into(), 12))
+        );
+        assert!(file_and_line("src/lib.rs").is_err());
+        assert!(file_and_line("src/lib.rs:0").is_err());
+        assert!(file_and_line(":12").is_err());
+    }
+
+    #[test]
+    fn similarities_are_from_0_to_1() {
+        assert_eq!(similarity("0.8"), Ok(0.8));
+        assert_eq!(similarity("1"), Ok(1.0));
+        assert!(similarity("1.5").is_err());
+        assert!(similarity("x").is_err());
+    }
 
-        _ => panic!("invalid value for 'output'"),
+    #[test]
+    fn counts_must_be_positive() {
+        assert_eq!(positive_count("4"), Ok(4));
+        assert!(positive_count("0").is_err());
+        assert!(positive_count("-1").is_err());
+        assert!(positive_count("x").is_err());
     }
 }
@@ -0,0 +1,54 @@
+use std::{fs, process::Command};
+
+/// Run todo_finder with the given args in a scratch project holding a few
+/// TODOs, returning its stdout.
+fn run_in_scratch(args: &[&str]) -> String {
+    let scratch = tempfile::tempdir().unwrap();
+    let dir = scratch.path();
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("src/lib.rs"),
+        "// TODO(alice): Do A.\n// TODO: Do B.\nfn main() {}\n",
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_todo_finder"))
+        .args(args)
+        .current_dir(dir)
+        .env_remove("GITHUB_ACTIONS")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn json_output_is_only_json() {
+    let stdout = run_in_scratch(&["-o", "json", "--searcher", "builtin"]);
+    let json: serde_json::Value = serde_json::from_str(&stdout)
+        .unwrap_or_else(|e| panic!("stdout is not JSON ({}):\n{}", e, stdout));
+    assert!(json["todos"].is_array(), "{}", stdout);
+}
+
+#[test]
+fn json_output_is_only_json_with_the_default_searcher_and_a_baseline() {
+    // rg is used if it's installed, or the built-in walker if not
+    let stdout = run_in_scratch(&[
+        "-o",
+        "json",
+        "--baseline",
+        "baseline.json",
+        "--write-baseline",
+    ]);
+    let json: serde_json::Value = serde_json::from_str(&stdout)
+        .unwrap_or_else(|e| panic!("stdout is not JSON ({}):\n{}", e, stdout));
+    assert_eq!(
+        json["todos"].as_array().map(Vec::len),
+        Some(2),
+        "{}",
+        stdout
+    );
+}
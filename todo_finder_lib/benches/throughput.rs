@@ -0,0 +1,135 @@
+//! Parse throughput, in MB/s and TODOs/s, over a generated corpus.
+//!
+//! Run with `cargo bench -p todo_finder_lib`. This needs neither the network
+//! nor `rg`, as files are found with the built-in walker. Pass a number of
+//! files to change the size of the corpus, eg.
+//! `cargo bench -p todo_finder_lib -- 2000`.
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use todo_finder_lib::{
+    finder::SearchBackend,
+    parser::{langs, source::parse_todos, IssueMap, ScanOptions},
+};
+
+/// How many times each benchmark is run, the fastest run is reported.
+const RUNS: usize = 5;
+
+/// The number of files in the corpus by default.
+const DEFAULT_FILES: usize = 500;
+
+/// A Rust source file of around 8KB with a mix of todos, plain comments and
+/// code. The same `n` always makes the same file.
+fn rust_file(n: usize) -> String {
+    let mut lines = vec![format!("//! Module {}.", n)];
+    for f in 0..40 {
+        match (n + f) % 5 {
+            0 => lines.push(format!("// TODO: Handle the error in f{}.", f)),
+            1 => {
+                lines.push(format!(
+                    "// FIXME(schell): Make f{} faster. It's quadratic.",
+                    f
+                ));
+                lines.push("//   Use a map instead of a vec.".into());
+            }
+            2 => {
+                lines.push("/* TODO: Document this.".into());
+                lines.push(" * It's public.".into());
+                lines.push(" */".into());
+            }
+            _ => lines.push(format!("// f{} adds one to its input.", f)),
+        }
+        lines.push(format!("pub fn f{}(x: usize) -> usize {{", f));
+        lines.push("    let y = x + 1;".into());
+        if (n + f).is_multiple_of(7) {
+            lines.push("    todo!(\"Check for overflow.\");".into());
+        }
+        lines.push("    y".into());
+        lines.push("}".into());
+        lines.push("".into());
+    }
+    lines.join("\n")
+}
+
+/// Write the corpus into a fresh directory, returning its size in bytes.
+fn write_corpus(dir: &Path, files: usize) -> usize {
+    let mut bytes = 0;
+    for n in 0..files {
+        let sub = dir.join(format!("mod{}", n % 10));
+        fs::create_dir_all(&sub).unwrap();
+        let contents = rust_file(n);
+        bytes += contents.len();
+        fs::write(sub.join(format!("file{}.rs", n)), contents).unwrap();
+    }
+    bytes
+}
+
+/// Run `f` `RUNS` times, returning the fastest time and the number of todos
+/// it found.
+fn fastest(mut f: impl FnMut() -> usize) -> (Duration, usize) {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            let todos = f();
+            (start.elapsed(), todos)
+        })
+        .min_by_key(|(elapsed, _)| *elapsed)
+        .unwrap()
+}
+
+fn report(name: &str, bytes: usize, (elapsed, todos): (Duration, usize)) {
+    let secs = elapsed.as_secs_f64();
+    println!(
+        "{:<24} {:>8.2?} {:>10.2} MB/s {:>12.0} TODOs/s ({} TODOs)",
+        name,
+        elapsed,
+        bytes as f64 / 1_000_000.0 / secs,
+        todos as f64 / secs,
+        todos
+    );
+}
+
+fn main() {
+    // `cargo bench` passes `--bench` along, ignore any flags
+    let files = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with('-'))
+        .map(|n| n.parse().expect("the number of files must be a number"))
+        .unwrap_or(DEFAULT_FILES);
+
+    let contents = (0..files).map(rust_file).collect::<Vec<_>>();
+    let bytes = contents.iter().map(String::len).sum();
+    let rust = langs::language_map().get("rs").unwrap()[0].clone();
+    report(
+        "parse_todos",
+        bytes,
+        fastest(|| {
+            let mut parse = parse_todos(rust.as_todo_parser_config());
+            contents.iter().map(|file| parse(file).len()).sum()
+        }),
+    );
+
+    let scratch = tempfile::tempdir().unwrap();
+    let dir = scratch.path();
+    let bytes = write_corpus(dir, files);
+    let dir_str = dir.to_str().unwrap();
+    let options = ScanOptions {
+        search_backend: SearchBackend::Builtin,
+        ..ScanOptions::default()
+    };
+    report(
+        "from_files_in_directory",
+        bytes,
+        fastest(|| {
+            let issues = IssueMap::from_files_in_directory_with_options(dir_str, &options).unwrap();
+            issues
+                .todos
+                .values()
+                .map(|issue| issue.body.descs_and_srcs.len())
+                .sum()
+        }),
+    );
+}
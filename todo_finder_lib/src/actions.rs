@@ -0,0 +1,154 @@
+//! Rendering TODOs as GitHub Actions workflow commands, which show up as
+//! inline annotations on pull requests.
+use std::path::Path;
+
+use super::parser::{FileTodoLocation, IssueMap, SortKey, SortOrder};
+
+/// The level of an annotation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnnotationLevel {
+    Notice,
+    Warning,
+}
+
+impl AnnotationLevel {
+    /// `FIXME`s and `BUG`s are warnings, everything else is a notice.
+    pub fn from_tag(tag: Option<&str>) -> Self {
+        match tag {
+            Some("FIXME") | Some("BUG") => AnnotationLevel::Warning,
+            _ => AnnotationLevel::Notice,
+        }
+    }
+
+    fn as_command(&self) -> &'static str {
+        match self {
+            AnnotationLevel::Notice => "notice",
+            AnnotationLevel::Warning => "warning",
+        }
+    }
+}
+
+/// Escape the message of a workflow command.
+///
+/// ```rust
+/// use todo_finder_lib::actions::escape_data;
+///
+/// assert_eq!(escape_data("100%\nDone"), "100%25%0ADone");
+/// ```
+pub fn escape_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape a property value of a workflow command.
+///
+/// ```rust
+/// use todo_finder_lib::actions::escape_property;
+///
+/// assert_eq!(escape_property("a:b,c"), "a%3Ab%2Cc");
+/// ```
+pub fn escape_property(s: &str) -> String {
+    escape_data(s).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// Render one workflow command for a todo at the given location. The file is
+/// made relative to `cwd`, as annotations expect paths relative to the repo.
+pub fn annotation(
+    level: AnnotationLevel,
+    cwd: &str,
+    loc: &FileTodoLocation,
+    title: &str,
+    desc_lines: &[String],
+) -> String {
+    let path = Path::new(&loc.file);
+    let file = path.strip_prefix(cwd).unwrap_or(path);
+    let mut properties = vec![
+        format!("file={}", escape_property(&file.display().to_string())),
+        format!("line={}", loc.src_span.0),
+    ];
+    if let Some(end) = loc.src_span.1 {
+        properties.push(format!("endLine={}", end));
+    }
+    properties.push(format!("title={}", escape_property(title)));
+    let message = if desc_lines.is_empty() {
+        title.to_string()
+    } else {
+        desc_lines.join("\n")
+    };
+    format!(
+        "::{} {}::{}",
+        level.as_command(),
+        properties.join(","),
+        escape_data(&message)
+    )
+}
+
+/// Render a workflow command for every todo location, sorted by file and line.
+pub fn annotations(issues: &IssueMap<(), FileTodoLocation>, cwd: &str) -> Vec<String> {
+    annotations_sorted(issues, cwd, &SortOrder::by(SortKey::File))
+}
+
+/// Like `annotations`, but with the todo locations in the given order.
+pub fn annotations_sorted(
+    issues: &IssueMap<(), FileTodoLocation>,
+    cwd: &str,
+    order: &SortOrder,
+) -> Vec<String> {
+    let mut locs = issues
+        .todos
+        .values()
+        .flat_map(|issue| {
+            issue
+                .body
+                .descs_and_srcs
+                .iter()
+                .map(move |(descs, loc)| (issue, loc, descs))
+        })
+        .collect::<Vec<_>>();
+    locs.sort_by(|a, b| order.compare((&a.0.head, Some(a.1)), (&b.0.head, Some(b.1))));
+    locs.into_iter()
+        .map(|(issue, loc, descs)| {
+            let level = AnnotationLevel::from_tag(issue.head.tag.as_deref());
+            annotation(level, cwd, loc, &issue.head.title, descs)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::source::ParsedTodo;
+
+    #[test]
+    fn can_render_annotations() {
+        let mut issues = IssueMap::new_source_todos();
+        issues.add_parsed_todo(
+            &ParsedTodo {
+                desc_lines: vec!["Like this:", "a, b"],
+                tag: "FIXME",
+                ..ParsedTodo::new("Handle 100% of cases, not some.")
+            },
+            FileTodoLocation {
+                file: "/repo/src/lib.rs".into(),
+                src_span: (12, Some(14)),
+            },
+        );
+        issues.add_parsed_todo(
+            &ParsedTodo::new("Do it."),
+            FileTodoLocation {
+                file: "/repo/src/a,b.rs".into(),
+                src_span: (3, None),
+            },
+        );
+
+        assert_eq!(
+            annotations(&issues, "/repo"),
+            vec![
+                "::notice file=src/a%2Cb.rs,line=3,title=Do it.::Do it.",
+                "::warning file=src/lib.rs,line=12,endLine=14,title=Handle 100%25 of cases%2C \
+                 not some.::Like this:%0Aa, b",
+            ]
+        );
+    }
+}
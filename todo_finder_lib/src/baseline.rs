@@ -0,0 +1,178 @@
+//! Recording TODO counts so CI can ratchet down tech debt.
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+use super::parser::{FileTodoLocation, IssueMap};
+
+/// A recorded count of TODO locations, in total and per file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub total: usize,
+    #[serde(default)]
+    pub files: BTreeMap<String, usize>,
+}
+
+/// A file whose TODO count grew since the baseline.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrownFile {
+    pub file: String,
+    pub previous: usize,
+    pub current: usize,
+}
+
+/// The difference between a baseline and the current scan.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BaselineDelta {
+    pub previous_total: usize,
+    pub current_total: usize,
+    pub grown_files: Vec<GrownFile>,
+}
+
+impl BaselineDelta {
+    /// Whether the total TODO count went up.
+    pub fn increased(&self) -> bool {
+        self.current_total > self.previous_total
+    }
+}
+
+impl Baseline {
+    /// Count the TODO locations in the map. File paths are recorded relative to
+    /// `cwd` so the baseline can be committed.
+    pub fn from_issue_map(issues: &IssueMap<(), FileTodoLocation>, cwd: &str) -> Self {
+        let mut baseline = Baseline::default();
+        for issue in issues.todos.values() {
+            for (_, loc) in issue.body.descs_and_srcs.iter() {
+                let path = Path::new(&loc.file);
+                let file = path.strip_prefix(cwd).unwrap_or(path);
+                *baseline
+                    .files
+                    .entry(format!("{}", file.display()))
+                    .or_insert(0) += 1;
+                baseline.total += 1;
+            }
+        }
+        baseline
+    }
+
+    pub fn read(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("could not read baseline {}: {}", path, e))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("could not parse baseline {}: {}", path, e))
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("could not serialize baseline: {}", e))?;
+        fs::write(path, contents).map_err(|e| format!("could not write baseline {}: {}", path, e))
+    }
+
+    /// Compare this (previous) baseline with the current one, listing the
+    /// files whose TODO count grew.
+    pub fn compare(&self, current: &Baseline) -> BaselineDelta {
+        let grown_files = current
+            .files
+            .iter()
+            .filter_map(|(file, count)| {
+                let previous = self.files.get(file).copied().unwrap_or(0);
+                if *count > previous {
+                    Some(GrownFile {
+                        file: file.clone(),
+                        previous,
+                        current: *count,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+        BaselineDelta {
+            previous_total: self.total,
+            current_total: current.total,
+            grown_files,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn baseline(files: &[(&str, usize)]) -> Baseline {
+        Baseline {
+            total: files.iter().map(|(_, n)| n).sum(),
+            files: files.iter().map(|(f, n)| (f.to_string(), *n)).collect(),
+        }
+    }
+
+    #[test]
+    fn can_count_from_issue_map() {
+        let mut issues = IssueMap::new_source_todos();
+        for (file, line) in [
+            ("/repo/src/a.rs", 1),
+            ("/repo/src/a.rs", 9),
+            ("/repo/b.rs", 3),
+        ] {
+            let todo = crate::parser::source::ParsedTodo::new(format!("Todo on line {}.", line));
+            issues.add_parsed_todo(
+                &todo,
+                FileTodoLocation {
+                    file: file.into(),
+                    src_span: (line, None),
+                },
+            );
+        }
+        assert_eq!(
+            Baseline::from_issue_map(&issues, "/repo"),
+            baseline(&[("src/a.rs", 2), ("b.rs", 1)])
+        );
+    }
+
+    #[test]
+    fn delta_increased() {
+        let previous = baseline(&[("a.rs", 2), ("b.rs", 1)]);
+        let current = baseline(&[("a.rs", 3), ("b.rs", 1), ("c.rs", 1)]);
+        let delta = previous.compare(&current);
+        assert!(delta.increased());
+        assert_eq!(
+            delta.grown_files,
+            vec![
+                GrownFile {
+                    file: "a.rs".into(),
+                    previous: 2,
+                    current: 3
+                },
+                GrownFile {
+                    file: "c.rs".into(),
+                    previous: 0,
+                    current: 1
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn delta_decreased() {
+        let previous = baseline(&[("a.rs", 2), ("b.rs", 1)]);
+        let current = baseline(&[("a.rs", 1)]);
+        let delta = previous.compare(&current);
+        assert!(!delta.increased());
+        assert!(delta.grown_files.is_empty());
+    }
+
+    #[test]
+    fn delta_equal() {
+        let previous = baseline(&[("a.rs", 2), ("b.rs", 1)]);
+        let current = baseline(&[("a.rs", 1), ("b.rs", 2)]);
+        let delta = previous.compare(&current);
+        assert!(!delta.increased());
+        assert_eq!(
+            delta.grown_files,
+            vec![GrownFile {
+                file: "b.rs".into(),
+                previous: 1,
+                current: 2
+            }]
+        );
+    }
+}
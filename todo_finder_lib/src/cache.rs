@@ -0,0 +1,201 @@
+//! Caching the todos parsed from each file between runs, so files that haven't
+//! changed aren't read and parsed again.
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, time::UNIX_EPOCH};
+
+use super::parser::{FileTodoLocation, IssueMap};
+
+/// The todos parsed from some lines of one file, as of when it was last
+/// modified.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CachedFile {
+    /// The file's modification time, as seconds and nanoseconds since the
+    /// unix epoch.
+    pub modified: (u64, u32),
+    /// The lines searched for todos, as a file may be searched once for each
+    /// tag.
+    pub lines: Vec<usize>,
+    pub todos: IssueMap<(), FileTodoLocation>,
+}
+
+/// The todos parsed from each file of a scan, valid only for the same git
+/// checkout, version of todo_finder and options that affect parsing.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ScanCache {
+    pub version: String,
+    /// The git hash of the checkout the files were parsed at.
+    pub checkout: String,
+    /// The options the files were parsed with, see `ScanOptions::cache_key`.
+    pub options: String,
+    #[serde(default)]
+    pub files: BTreeMap<String, Vec<CachedFile>>,
+}
+
+/// When a file was last modified, if that can be read.
+pub fn modified(file: &str) -> Option<(u64, u32)> {
+    let since_epoch = fs::metadata(file)
+        .ok()?
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?;
+    Some((since_epoch.as_secs(), since_epoch.subsec_nanos()))
+}
+
+impl ScanCache {
+    /// An empty cache for the given checkout and options.
+    pub fn new(checkout: &str, options: &str) -> Self {
+        ScanCache {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            checkout: checkout.to_string(),
+            options: options.to_string(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    pub fn read(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("could not read cache {}: {}", path, e))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("could not parse cache {}: {}", path, e))
+    }
+
+    /// Read the cache at `path` if it was written for the given checkout and
+    /// options, otherwise start an empty one. A missing or unreadable cache is
+    /// only slower, never an error.
+    pub fn read_or_new(path: &str, checkout: &str, options: &str) -> Self {
+        let fresh = ScanCache::new(checkout, options);
+        match ScanCache::read(path) {
+            Ok(cache)
+                if cache.version == fresh.version
+                    && cache.checkout == fresh.checkout
+                    && cache.options == fresh.options =>
+            {
+                cache
+            }
+            _ => fresh,
+        }
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        let contents =
+            serde_json::to_string(self).map_err(|e| format!("could not serialize cache: {}", e))?;
+        fs::write(path, contents).map_err(|e| format!("could not write cache {}: {}", path, e))
+    }
+
+    /// The todos cached for some lines of a file, unless it was modified
+    /// since, as of its `modified` time now.
+    pub fn get(
+        &self,
+        file: &str,
+        modified: (u64, u32),
+        lines: &[usize],
+    ) -> Option<&IssueMap<(), FileTodoLocation>> {
+        self.files
+            .get(file)?
+            .iter()
+            .find(|cached| cached.modified == modified && cached.lines == lines)
+            .map(|cached| &cached.todos)
+    }
+
+    /// Cache the todos parsed from some lines of a file, as of its `modified`
+    /// time before it was read.
+    pub fn insert(
+        &mut self,
+        file: &str,
+        modified: (u64, u32),
+        lines: &[usize],
+        todos: IssueMap<(), FileTodoLocation>,
+    ) {
+        let cached = self.files.entry(file.to_string()).or_default();
+        cached.retain(|cached| cached.lines != lines);
+        cached.push(CachedFile {
+            modified,
+            lines: lines.to_vec(),
+            todos,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        finder::SearchBackend,
+        parser::{IssueMap, ScanOptions},
+    };
+
+    #[test]
+    fn unchanged_files_are_read_from_the_cache() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        let cache_path = dir.join("cache.json");
+        let cache_path = cache_path.to_str().unwrap();
+        let src = dir.join("src");
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::write(src.join("a.rs"), "// TODO: Parsed.\n").unwrap();
+        std::fs::write(src.join("b.rs"), "// TODO: Changed.\n").unwrap();
+        let options = ScanOptions {
+            search_backend: SearchBackend::Builtin,
+            cache: Some(cache_path.to_string()),
+            ..ScanOptions::default()
+        };
+        let src = src.to_str().unwrap();
+        let titles = |todos: &IssueMap<(), FileTodoLocation>| {
+            let mut titles = todos.todos.keys().cloned().collect::<Vec<_>>();
+            titles.sort();
+            titles
+        };
+
+        let todos = IssueMap::from_files_in_directory_with_options(src, &options).unwrap();
+        assert_eq!(titles(&todos), vec!["Changed.", "Parsed."]);
+        let cache = ScanCache::read(cache_path).unwrap();
+        assert_eq!(cache.files.len(), 2);
+        assert_eq!(cache.files.values().map(Vec::len).sum::<usize>(), 2);
+
+        // Tamper with the cached todos of a.rs to tell that they're reused, and
+        // change b.rs so that it's parsed again.
+        let mut cache = cache;
+        let a = cache
+            .files
+            .keys()
+            .find(|file| file.ends_with("a.rs"))
+            .unwrap()
+            .clone();
+        let cached = &mut cache.files.get_mut(&a).unwrap()[0].todos;
+        let mut issue = cached.todos.remove("Parsed.").unwrap();
+        issue.head.title = "From the cache.".to_string();
+        cached.todos.insert(issue.head.title.clone(), issue);
+        cache.write(cache_path).unwrap();
+        let b = std::path::Path::new(src).join("b.rs");
+        std::fs::write(&b, "// TODO: Changed again.\n").unwrap();
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(2);
+        std::fs::File::options()
+            .write(true)
+            .open(&b)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+
+        let todos = IssueMap::from_files_in_directory_with_options(src, &options).unwrap();
+        assert_eq!(titles(&todos), vec!["Changed again.", "From the cache."]);
+
+        // Ignored titles are left out of the cached todos too, which are kept
+        let ignoring = ScanOptions {
+            ignore_titles: vec!["From the cache.".into()],
+            ..options.clone()
+        };
+        let todos = IssueMap::from_files_in_directory_with_options(src, &ignoring).unwrap();
+        assert_eq!(titles(&todos), vec!["Changed again."]);
+        let todos = IssueMap::from_files_in_directory_with_options(src, &options).unwrap();
+        assert_eq!(titles(&todos), vec!["Changed again.", "From the cache."]);
+
+        // Other options invalidate the whole cache
+        let options = ScanOptions {
+            merge_adjacent: true,
+            ..options
+        };
+        let todos = IssueMap::from_files_in_directory_with_options(src, &options).unwrap();
+        assert_eq!(titles(&todos), vec!["Changed again.", "Parsed."]);
+    }
+}
@@ -0,0 +1,223 @@
+//! Settings read from a config file, so they needn't be passed on every run.
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use crate::parser::langs::{CommentStyle, SupportedLanguage};
+
+/// The settings of a TOML config file. Each is overridden by its command line
+/// flag.
+///
+/// ```rust
+/// use todo_finder_lib::config::Config;
+///
+/// let config = Config::parse(
+///     r#"
+/// exclude = ["vendor/**", "*.min.js"]
+/// label = "tech-debt"
+/// "#,
+/// )
+/// .unwrap();
+/// assert_eq!(config.exclude, vec!["vendor/**", "*.min.js"]);
+/// assert_eq!(config.label.as_deref(), Some("tech-debt"));
+/// assert!(config.tags.is_empty());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Globs of files or directories to ignore, like `--exclude`.
+    pub exclude: Vec<String>,
+    /// The label of created issues, like `--label`.
+    pub label: Option<String>,
+    /// The keywords that start a todo, like `--tag`.
+    pub tags: Vec<String>,
+    /// Where todos are written, like `--output`, eg. `github`.
+    pub output: Option<String>,
+    /// Languages to know in addition to the built-in ones.
+    pub languages: Vec<LanguageConfig>,
+    /// Globs of test files excluded by `--exclude-tests` instead of the
+    /// conventional `TEST_PATTERNS`, like `--test-pattern`.
+    pub test_patterns: Vec<String>,
+    /// Emoji that mark a todo's severity at the start of its title, and the
+    /// label each adds, like `--emoji-label`.
+    ///
+    /// ```rust
+    /// use todo_finder_lib::config::Config;
+    ///
+    /// let config = Config::parse(
+    ///     r#"
+    /// [emoji_labels]
+    /// "🔴" = "critical"
+    /// "🟡" = "minor"
+    /// "#,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(config.emoji_labels["🔴"], "critical");
+    /// assert_eq!(config.emoji_labels.len(), 2);
+    /// ```
+    pub emoji_labels: BTreeMap<String, String>,
+    /// Titles of todos to ignore, exactly or as globs, like `--ignore-title`.
+    pub ignore_titles: Vec<String>,
+    /// The GitHub logins to assign instead of the assignees of todos, eg. of
+    /// email addresses, like `--assignee-map`.
+    pub assignee_map: BTreeMap<String, String>,
+}
+
+/// A language that isn't built in, eg. an in-house DSL. Its extensions are
+/// added to those of any built-in language, so both are parsed.
+///
+/// ```rust
+/// use todo_finder_lib::config::Config;
+///
+/// let config = Config::parse(
+///     r#"
+/// [[languages]]
+/// name = "Pipeline"
+/// extensions = ["pipe"]
+/// single = ["%%"]
+/// multi = [["%{", "}%"]]
+/// borders = ["|"]
+/// "#,
+/// )
+/// .unwrap();
+/// let pipeline = config.languages[0].to_supported_language();
+/// assert_eq!(pipeline.name, "Pipeline");
+/// assert_eq!(pipeline.file_extensions, vec!["pipe"]);
+/// assert_eq!(pipeline.comment_styles.len(), 3);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LanguageConfig {
+    pub name: String,
+    /// File extensions without the dot, eg. `pipe`.
+    pub extensions: Vec<String>,
+    /// Whole file names, for files known by name rather than extension.
+    pub file_names: Vec<String>,
+    /// Single line comment prefixes, eg. `%%`.
+    pub single: Vec<String>,
+    /// Multi line comment prefixes and suffixes, eg. `["%{", "}%"]`.
+    pub multi: Vec<(String, String)>,
+    /// Borders stripped from the start and end of comment lines, eg. `*`.
+    pub borders: Vec<String>,
+    /// Macros whose message is a todo, eg. `todo!`, which aren't todos in
+    /// other languages.
+    pub macros: Vec<String>,
+}
+
+impl LanguageConfig {
+    pub fn to_supported_language(&self) -> SupportedLanguage {
+        let singles = self.single.iter().cloned().map(CommentStyle::Single);
+        let multis = self
+            .multi
+            .iter()
+            .cloned()
+            .map(|(prefix, suffix)| CommentStyle::Multi(prefix, suffix));
+        let borders = self.borders.iter().cloned().map(CommentStyle::Border);
+        let macros = self.macros.iter().cloned().map(CommentStyle::Macro);
+        SupportedLanguage {
+            name: self.name.clone(),
+            comment_styles: singles.chain(multis).chain(borders).chain(macros).collect(),
+            file_extensions: self.extensions.clone(),
+            file_names: self.file_names.clone(),
+        }
+    }
+}
+
+/// The name of the config file read from the working directory when no
+/// `--config` is given.
+pub const CONFIG_FILE_NAME: &str = ".todo_finder.toml";
+
+impl Config {
+    /// Parse a config from the contents of a TOML file.
+    pub fn parse(contents: &str) -> Result<Config, String> {
+        toml::from_str(contents).map_err(|e| format!("invalid config: {}", e))
+    }
+
+    /// Read the config file at exactly the given path, failing if it doesn't
+    /// exist.
+    pub fn read(path: &str) -> Result<Config, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read config file {}: {}", path, e))?;
+        Config::parse(&contents).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// The languages of the config, to be merged with the built-in ones.
+    pub fn supported_languages(&self) -> Vec<SupportedLanguage> {
+        self.languages
+            .iter()
+            .map(LanguageConfig::to_supported_language)
+            .collect()
+    }
+
+    /// Read the `.todo_finder.toml` in the given directory, if there is one.
+    /// It's not an error for there to be none, but it is for it to be invalid.
+    pub fn discover(dir: &std::path::Path) -> Result<Option<Config>, String> {
+        let path = dir.join(CONFIG_FILE_NAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let path = path
+            .to_str()
+            .ok_or_else(|| format!("could not convert config path {:?}", path))?;
+        Config::read(path).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_read_an_explicit_config_file() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        let path = dir.join("shared.toml");
+        std::fs::write(
+            &path,
+            "exclude = [\"target/**\"]\ntags = [\"TODO\", \"REVIEW\"]\noutput = \"markdown\"\n\
+             test_patterns = [\"*_check.rs\"]\n",
+        )
+        .unwrap();
+
+        let config = Config::read(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            config,
+            Config {
+                exclude: vec!["target/**".into()],
+                label: None,
+                tags: vec!["TODO".into(), "REVIEW".into()],
+                output: Some("markdown".into()),
+                languages: vec![],
+                test_patterns: vec!["*_check.rs".into()],
+                emoji_labels: BTreeMap::new(),
+                ignore_titles: vec![],
+                assignee_map: BTreeMap::new(),
+            }
+        );
+
+        let missing = dir.join("missing.toml");
+        assert!(Config::read(missing.to_str().unwrap())
+            .unwrap_err()
+            .starts_with("could not read config file"));
+        std::fs::write(&path, "labels = \"typo\"\n").unwrap();
+        assert!(Config::read(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn can_discover_a_config_file_in_a_directory() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        assert_eq!(Config::discover(dir), Ok(None));
+
+        std::fs::write(dir.join(CONFIG_FILE_NAME), "label = \"tech-debt\"\n").unwrap();
+        assert_eq!(
+            Config::discover(dir),
+            Ok(Some(Config {
+                label: Some("tech-debt".into()),
+                ..Config::default()
+            }))
+        );
+
+        std::fs::write(dir.join(CONFIG_FILE_NAME), "labels = \"typo\"\n").unwrap();
+        assert!(Config::discover(dir).is_err());
+    }
+}
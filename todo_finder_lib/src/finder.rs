@@ -1,14 +1,357 @@
 //! todo_finder is our broadphase TODO detector.
 pub mod parse;
 mod rg;
-pub use rg::PossibleTodosInFile;
+mod walk;
+pub use rg::{PossibleTodosInFile, MIN_RG_VERSION};
+
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    message::Message,
+    parser::{diff, source::TodoTags},
+    utils,
+};
+
+/// Globs of the files and directories that hold tests by common convention,
+/// to exclude with `--exclude-tests`.
+pub const TEST_PATTERNS: [&str; 5] = [
+    "**/tests/**",
+    "*_test.*",
+    "test_*.*",
+    "**/__tests__/**",
+    "*.spec.*",
+];
+
+/// How files are searched for possible TODOs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SearchBackend {
+    /// Use `rg`, falling back to the built-in walker if it isn't installed.
+    #[default]
+    Auto,
+    /// Use `rg`, failing if it isn't installed.
+    Ripgrep,
+    /// Walk the files without `rg`. Extra `rg` arguments are ignored.
+    Builtin,
+}
+
+impl SearchBackend {
+    pub fn from_arg(arg: &str) -> Result<Self, String> {
+        match arg {
+            "auto" => Ok(SearchBackend::Auto),
+            "rg" => Ok(SearchBackend::Ripgrep),
+            "builtin" => Ok(SearchBackend::Builtin),
+            _ => Err(format!("invalid search backend '{}'", arg)),
+        }
+    }
+}
 
 pub struct FileSearcher;
 
 impl FileSearcher {
-    /// Find the locations of possible TODOs at the given path.
-    pub fn find(path: &str, excludes: &Vec<String>) -> Result<Vec<PossibleTodosInFile>, String> {
-        let output = rg::get_rg_output_with_common_patterns(path, excludes)?;
-        rg::parse_rg_output(&output)
+    /// Find the locations of possible TODOs with the given tags at the given
+    /// path with the given backend, passing any extra arguments along to `rg`.
+    pub fn find(
+        path: &str,
+        backend: SearchBackend,
+        tags: &TodoTags,
+        excludes: &[String],
+        rg_args: &[String],
+    ) -> Result<Vec<PossibleTodosInFile>, String> {
+        if backend == SearchBackend::Builtin {
+            return walk::find_possible_todos(path, tags, excludes);
+        }
+        match rg::get_rg_output_with_common_patterns(path, tags, excludes, rg_args) {
+            Ok(output) => rg::parse_rg_output(&output),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && backend == SearchBackend::Auto => {
+                Message::RipgrepMissing.send();
+                walk::find_possible_todos(path, tags, excludes)
+            }
+            Err(e) => Err(format!("error using rg: {}", e)),
+        }
+    }
+
+    /// All the files at the given path that a search for TODOs would read,
+    /// whether or not they hold any, eg. to tell what share of them do.
+    pub fn files(path: &str, excludes: &[String]) -> Result<Vec<String>, String> {
+        walk::find_files(path, excludes)
+    }
+
+    /// Whether a search of the given path would skip the file for matching one
+    /// of the `excludes`, eg. for a file named in a diff.
+    pub fn is_excluded(path: &str, file: &str, excludes: &[String]) -> Result<bool, String> {
+        walk::is_excluded(path, file, excludes)
+    }
+
+    /// The numbers of the lines in some contents that may hold a TODO with the
+    /// given tags, found the way a search of files would find them.
+    ///
+    /// ```rust
+    /// use todo_finder_lib::{finder::FileSearcher, parser::source::TodoTags};
+    ///
+    /// let contents = "fn a() {}\n// TODO: Do b.\n// FIXME: Do c.\n";
+    /// assert_eq!(
+    ///     FileSearcher::find_in_contents(contents, &TodoTags::default()),
+    ///     vec![2, 3]
+    /// );
+    /// ```
+    pub fn find_in_contents(contents: &str, tags: &TodoTags) -> Vec<usize> {
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| tags.keywords.iter().any(|tag| line.contains(tag.as_str())))
+            .map(|(n, _)| n + 1)
+            .collect()
+    }
+
+    /// List the files git tracks at the given path, joined onto it so they
+    /// compare equal to the paths `rg` reports.
+    pub fn git_tracked_files(path: &str) -> Result<HashSet<PathBuf>, String> {
+        let output = utils::command("git", &["ls-files", "-z"], path)?;
+        Ok(output
+            .split('\0')
+            .filter(|file| !file.is_empty())
+            .map(|file| Path::new(path).join(file))
+            .collect())
+    }
+
+    /// List the files with uncommitted changes at the given path, including
+    /// untracked ones, relative to the root of the repo.
+    pub fn git_changed_files(path: &str) -> Result<Vec<String>, String> {
+        let output = utils::command("git", &["status", "--porcelain", "-z"], path)?;
+        Ok(parse_porcelain_status(&output))
+    }
+
+    /// Map the files at the given path to the numbers of their lines added or
+    /// changed since the given revision, including uncommitted changes. Files
+    /// are joined onto the path so they compare equal to the paths `rg`
+    /// reports. Untracked files aren't included.
+    pub fn git_added_lines(
+        path: &str,
+        since: &str,
+    ) -> Result<HashMap<PathBuf, Vec<usize>>, String> {
+        let output = utils::command(
+            "git",
+            &[
+                "diff",
+                "--unified=0",
+                "--relative",
+                "--no-color",
+                "--no-ext-diff",
+                since,
+                "--",
+            ],
+            path,
+        )?;
+        Ok(diff::added_lines(&output)
+            .into_iter()
+            .map(|(file, lines)| (Path::new(path).join(file), lines))
+            .collect())
+    }
+
+    /// Map the files git detects as renamed since the given commit to their new
+    /// names, both relative to the root of the repo.
+    pub fn git_renames(path: &str, since: &str) -> Result<HashMap<String, String>, String> {
+        let output = utils::cached_command(
+            "git",
+            &["diff", "--name-status", "-M", "-z", since, "--"],
+            path,
+        )?;
+        let mut renames = HashMap::new();
+        let mut fields = output.split('\0').filter(|field| !field.is_empty());
+        while let Some(status) = fields.next() {
+            // Renames and copies list the old and new paths, the rest just one
+            if status.starts_with('R') || status.starts_with('C') {
+                let (old, new) = fields
+                    .next()
+                    .zip(fields.next())
+                    .ok_or_else(|| format!("missing paths after {} in git diff output", status))?;
+                if status.starts_with('R') {
+                    renames.insert(old.to_string(), new.to_string());
+                }
+            } else {
+                let _ = fields.next();
+            }
+        }
+        Ok(renames)
+    }
+
+    /// Read the possible TODOs from the output of an `rg --json` run
+    /// elsewhere, eg. earlier in a CI pipeline, instead of searching again.
+    /// Any pattern may have been searched for, since each match is parsed.
+    pub fn parse_rg_json(output: &str) -> Result<Vec<PossibleTodosInFile>, String> {
+        rg::parse_rg_json(output)
+    }
+
+    /// Check that `rg` is installed and recent enough, returning its version.
+    pub fn check_rg_version() -> Result<(u32, u32, u32), String> {
+        rg::check_rg_version()
+    }
+}
+
+/// Parse the paths out of `git status --porcelain -z` output. Each entry is
+/// two status letters, a space and a path, where renames and copies are
+/// followed by their original path, which is skipped.
+///
+/// ```rust
+/// use todo_finder_lib::finder::parse_porcelain_status;
+///
+/// let output = " M src/lib.rs\0R  new.rs\0old.rs\0?? notes.txt\0";
+/// assert_eq!(
+///     parse_porcelain_status(output),
+///     vec!["src/lib.rs", "new.rs", "notes.txt"]
+/// );
+/// ```
+pub fn parse_porcelain_status(output: &str) -> Vec<String> {
+    let mut files = vec![];
+    let mut entries = output.split('\0').filter(|entry| !entry.is_empty());
+    while let Some(entry) = entries.next() {
+        // The leading space of an unstaged first entry may have been trimmed
+        let start = if entry.as_bytes().get(2) == Some(&b' ') {
+            3
+        } else {
+            2
+        };
+        let (status, file) = entry.split_at(start.min(entry.len()));
+        if !file.is_empty() {
+            files.push(file.to_string());
+        }
+        if status.contains(['R', 'C']) {
+            let _ = entries.next();
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn untracked_files_are_not_git_tracked() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/lib.rs"), "// TODO: Tracked.\n").unwrap();
+        fs::write(dir.join("generated.rs"), "// TODO: Untracked.\n").unwrap();
+        let dir_str = dir.to_str().unwrap();
+        utils::command("git", &["init", "-q"], dir_str).unwrap();
+        utils::command("git", &["add", "src/lib.rs"], dir_str).unwrap();
+
+        let tracked = FileSearcher::git_tracked_files(dir_str).unwrap();
+        assert_eq!(tracked, HashSet::from([dir.join("src/lib.rs")]));
+
+        let options = crate::parser::ScanOptions {
+            git_tracked_only: true,
+            search_backend: SearchBackend::Builtin,
+            ..Default::default()
+        };
+        let todos =
+            crate::parser::IssueMap::from_files_in_directory_with_options(dir_str, &options)
+                .unwrap();
+        assert_eq!(todos.todos.keys().collect::<Vec<_>>(), vec!["Tracked."]);
+    }
+
+    #[test]
+    fn test_patterns_exclude_tests() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        for sub in ["src/__tests__", "tests", "web"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        for file in [
+            "src/lib.rs",
+            "src/__tests__/a.js",
+            "src/parse_test.go",
+            "tests/foo.rs",
+            "web/test_app.py",
+            "web/app.spec.ts",
+        ] {
+            fs::write(dir.join(file), "// TODO: Here.\n").unwrap();
+        }
+        let excludes = TEST_PATTERNS
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .collect::<Vec<_>>();
+        let lib = dir.join("src/lib.rs").display().to_string();
+
+        for backend in [SearchBackend::Builtin, SearchBackend::Ripgrep] {
+            if backend == SearchBackend::Ripgrep && FileSearcher::check_rg_version().is_err() {
+                eprintln!("skipping rg, it isn't installed");
+                continue;
+            }
+            let todos = FileSearcher::find(
+                dir.to_str().unwrap(),
+                backend,
+                &TodoTags::default(),
+                &excludes,
+                &[],
+            )
+            .unwrap();
+            assert_eq!(
+                todos,
+                vec![PossibleTodosInFile::new(&lib, vec![1])],
+                "{:?}",
+                backend
+            );
+        }
+    }
+
+    #[test]
+    fn moved_files_are_git_renames() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        let contents = "fn a() {}\n// TODO: Follow me.\nfn b() {}\n";
+        fs::write(dir.join("src/old.rs"), contents).unwrap();
+        fs::write(dir.join("src/kept.rs"), "// TODO: Stay.\n").unwrap();
+        let dir_str = dir.to_str().unwrap();
+        let git = |args: &[&str]| utils::command("git", args, dir_str).unwrap();
+        git(&["init", "-q"]);
+        git(&["add", "."]);
+        git(&[
+            "-c",
+            "user.name=test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "-qm",
+            "init",
+        ]);
+        let since = git(&["rev-parse", "HEAD"]);
+        git(&["mv", "src/old.rs", "src/new.rs"]);
+        fs::write(dir.join("src/kept.rs"), "// TODO: Stay put.\n").unwrap();
+
+        let renames = FileSearcher::git_renames(dir_str, &since).unwrap();
+        assert_eq!(
+            renames,
+            vec![("src/old.rs".to_string(), "src/new.rs".to_string())]
+                .into_iter()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn dirty_files_are_parsed_from_git_status() {
+        // As returned by `utils::command`, which trims the leading space of
+        // the first, unstaged entry
+        let output = "M src/lib.rs\0MM src/main.rs\0A  src/new.rs\0\
+                      R  src/moved.rs\0src/old.rs\0 D gone.rs\0?? notes with spaces.txt\0";
+        assert_eq!(
+            parse_porcelain_status(output),
+            vec![
+                "src/lib.rs",
+                "src/main.rs",
+                "src/new.rs",
+                "src/moved.rs",
+                "gone.rs",
+                "notes with spaces.txt"
+            ]
+        );
+        assert!(parse_porcelain_status("").is_empty());
     }
 }
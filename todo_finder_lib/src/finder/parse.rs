@@ -1,35 +1,73 @@
 use nom::{
-    branch, bytes::complete as bytes, character::complete as character, combinator, multi, IResult,
+    branch, bytes::complete as bytes, character::complete as character, combinator, error, multi,
+    sequence, IResult,
 };
 
+/// Parse the owner and repo out of a git remote url. The owner is the whole
+/// namespace of the repo, so for GitLab subgroups, like
+/// `https://gitlab.com/group/subgroup/repo.git`, it's `group/subgroup`.
 pub fn parse_owner_and_repo_from_config(i: &str) -> IResult<&str, (&str, &str)> {
     let (i, (owner, repo)) = branch::alt((
         parse_owner_and_repo_from_config_git,
+        parse_owner_and_repo_from_config_ssh,
         parse_owner_and_repo_from_config_http,
     ))(i)?;
     Ok((i, (owner.trim(), repo.trim())))
 }
 
+/// Eat the path of a repo, like `group/subgroup/repo.git`, returning what's
+/// before its last `/` as the owner and what's after as the repo. A trailing
+/// `.git` or `/` is left uneaten.
+fn parse_namespace_and_repo(i: &str) -> IResult<&str, (&str, &str)> {
+    let (_, path) = bytes::take_till(|c: char| c.is_whitespace())(i)?;
+    let path = path
+        .strip_suffix(".git")
+        .unwrap_or(path)
+        .trim_end_matches('/');
+    match path.rsplit_once('/') {
+        Some((owner, repo)) if !owner.is_empty() && !repo.is_empty() => {
+            Ok((&i[path.len()..], (owner, repo)))
+        }
+        _ => Err(nom::Err::Error(error::Error::new(
+            i,
+            error::ErrorKind::Verify,
+        ))),
+    }
+}
+
+/// Eat the host of an `ssh://` or `http(s)://` url, with its optional user and
+/// port, up to the `/` that starts the path.
+fn parse_url_host(i: &str) -> IResult<&str, &str> {
+    let (i, _) = combinator::opt(sequence::terminated(
+        bytes::take_till1(|c| c == '@' || c == '/'),
+        character::char('@'),
+    ))(i)?;
+    let (i, host) = bytes::take_till1(|c| c == ':' || c == '/')(i)?;
+    let (i, _) = combinator::opt(character::char(':'))(i)?;
+    let (i, _) = character::digit0(i)?;
+    let (i, _) = character::char('/')(i)?;
+    Ok((i, host))
+}
+
 pub fn parse_owner_and_repo_from_config_git(i: &str) -> IResult<&str, (&str, &str)> {
     let (i, _) = bytes::tag("git@")(i)?;
     let (i, _) = bytes::take_till(|c| c == ':')(i)?;
     let (i, _) = character::char(':')(i)?;
-    let (i, owner) = bytes::take_till(|c| c == '/')(i)?;
-    let (i, _) = character::char('/')(i)?;
-    let (i, repo) = bytes::take_till(|c| c == '.')(i)?;
-    Ok((i, (owner, repo)))
+    parse_namespace_and_repo(i)
+}
+
+pub fn parse_owner_and_repo_from_config_ssh(i: &str) -> IResult<&str, (&str, &str)> {
+    let (i, _) = bytes::tag("ssh://")(i)?;
+    let (i, _) = parse_url_host(i)?;
+    parse_namespace_and_repo(i)
 }
 
 pub fn parse_owner_and_repo_from_config_http(i: &str) -> IResult<&str, (&str, &str)> {
     let (i, _) = bytes::tag("http")(i)?;
     let (i, _) = combinator::opt(character::char('s'))(i)?;
     let (i, _) = bytes::tag("://")(i)?;
-    let (i, _) = bytes::take_till(|c| c == '/')(i)?;
-    let (i, _) = character::char('/')(i)?;
-    let (i, owner) = bytes::take_till(|c| c == '/')(i)?;
-    let (i, _) = character::char('/')(i)?;
-    let (i, repo) = bytes::take_till(|c| c == '.')(i)?;
-    Ok((i, (owner, repo)))
+    let (i, _) = parse_url_host(i)?;
+    parse_namespace_and_repo(i)
 }
 
 /// Eat a whole line and optionally its ending but don't return that ending.
@@ -58,11 +96,26 @@ pub fn parse_rg(i: &str) -> IResult<&str, Vec<(&str, Vec<usize>)>> {
     multi::many1(parse_rg_file)(i)
 }
 
+fn parse_version_number(i: &str) -> IResult<&str, u32> {
+    combinator::map_res(character::digit1, str::parse)(i)
+}
+
+/// Parse the version out of the output of `rg --version`.
+pub fn parse_rg_version(i: &str) -> IResult<&str, (u32, u32, u32)> {
+    let (i, _) = bytes::tag("ripgrep ")(i)?;
+    let (i, major) = parse_version_number(i)?;
+    let (i, _) = character::char('.')(i)?;
+    let (i, minor) = parse_version_number(i)?;
+    let (i, _) = character::char('.')(i)?;
+    let (i, patch) = parse_version_number(i)?;
+    Ok((i, (major, minor, patch)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    const OUTPUT:&'static [u8] = b"\
+    const OUTPUT: &[u8] = b"\
 test_data/two.rs
 1:This is another test file. The following is some garbage from my dayjob, with TODO tags sprinkled in.
 13:// TODO: Here is an actual todo.
@@ -90,6 +143,24 @@ test_data/one.rs
         );
     }
 
+    #[test]
+    fn can_parse_rg_version() {
+        let output = "ripgrep 13.0.0 (rev af6b6c543b)\n-SIMD -AVX (compiled)\n+SIMD +AVX \
+                      (runtime)\n";
+        assert_eq!(
+            parse_rg_version(output).map(|(_, version)| version),
+            Ok((13, 0, 0))
+        );
+
+        let output = "ripgrep 14.1.1\n\nfeatures:+pcre2\nsimd(compile):+SSE2,-SSSE3\n";
+        assert_eq!(
+            parse_rg_version(output).map(|(_, version)| version),
+            Ok((14, 1, 1))
+        );
+
+        assert!(parse_rg_version("grep (GNU grep) 3.7\n").is_err());
+    }
+
     #[test]
     fn can_parse_git_config_owner_repo() {
         assert_eq!(
@@ -101,5 +172,42 @@ test_data/one.rs
             parse_owner_and_repo_from_config("https://github.com/schell/todo_sync"),
             Ok(("", ("schell", "todo_sync")))
         );
+
+        assert_eq!(
+            parse_owner_and_repo_from_config("https://github.com/schell/todo_sync.rs.git\n"),
+            Ok((".git\n", ("schell", "todo_sync.rs")))
+        );
+
+        assert!(parse_owner_and_repo_from_config("https://github.com/todo_sync").is_err());
+    }
+
+    #[test]
+    fn can_parse_git_config_owner_repo_with_ports_and_subgroups() {
+        assert_eq!(
+            parse_owner_and_repo_from_config("ssh://git@github.com:2222/schell/todo_sync.git"),
+            Ok((".git", ("schell", "todo_sync")))
+        );
+
+        assert_eq!(
+            parse_owner_and_repo_from_config("ssh://gitlab.example.com/schell/todo_sync"),
+            Ok(("", ("schell", "todo_sync")))
+        );
+
+        assert_eq!(
+            parse_owner_and_repo_from_config("git@gitlab.com:group/subgroup/todo_sync.git"),
+            Ok((".git", ("group/subgroup", "todo_sync")))
+        );
+
+        assert_eq!(
+            parse_owner_and_repo_from_config("ssh://git@gitlab.com:2222/group/subgroup/todo_sync"),
+            Ok(("", ("group/subgroup", "todo_sync")))
+        );
+
+        assert_eq!(
+            parse_owner_and_repo_from_config(
+                "https://user@gitlab.example.com:8443/group/subgroup/todo_sync.git"
+            ),
+            Ok((".git", ("group/subgroup", "todo_sync")))
+        );
     }
 }
@@ -1,7 +1,30 @@
 //! Running ripgrep to find TODOs.
-use std::process::Command;
+use serde_json::Value;
+use std::{collections::BTreeMap, io, process::Command};
 
 use super::parse;
+use crate::{message::Message, parser::source::TodoTags, utils};
+
+/// The oldest ripgrep known to produce the `--heading` output that
+/// `parse::parse_rg` expects.
+pub const MIN_RG_VERSION: (u32, u32, u32) = (11, 0, 0);
+
+/// Run `rg --version` and return the version, warning if it is older than
+/// `MIN_RG_VERSION`.
+pub(crate) fn check_rg_version() -> Result<(u32, u32, u32), String> {
+    let output = utils::command("rg", &["--version"], ".")?;
+    let (_, version) = parse::parse_rg_version(&output)
+        .map_err(|e| format!("could not parse rg version from '{}': {}", output, e))?;
+    eprintln!("using ripgrep {}.{}.{}", version.0, version.1, version.2);
+    if version < MIN_RG_VERSION {
+        Message::OldRipgrep {
+            version,
+            minimum: MIN_RG_VERSION,
+        }
+        .send();
+    }
+    Ok(version)
+}
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct PossibleTodosInFile {
@@ -19,41 +42,60 @@ impl PossibleTodosInFile {
 }
 
 /// Run `rg` with the path and pattern given, returning the result bytes if
-/// successful.
+/// successful. Any extra `rg_args` are passed along after the `--heading` and
+/// `--line-number` flags we rely on. Errors `rg` reports while searching,
+/// eg. for an unreadable file, are sent as warnings. Fails with
+/// `io::ErrorKind::NotFound` if `rg` isn't installed, or with the command
+/// line to run it again if it doesn't exit normally.
 pub(crate) fn get_rg_output(
     path: &str,
     pattern: &str,
-    excludes: &Vec<String>,
-) -> Result<Vec<u8>, String> {
-    let mut cmd = Command::new("rg".to_string());
-    let _ = cmd
-        .arg("--heading".to_string())
-        .arg("--line-number".to_string());
+    excludes: &[String],
+    rg_args: &[String],
+) -> io::Result<Vec<u8>> {
+    let mut args = vec!["--heading".to_string(), "--line-number".to_string()];
+    args.extend(rg_args.iter().cloned());
     for exclude in excludes.iter() {
-        cmd.arg("-g").arg(format!("!{}", exclude));
+        args.push("-g".into());
+        args.push(format!("!{}", exclude));
     }
-    let _ = cmd.arg(pattern).arg(path);
-
-    println!("running rg:\n{:#?}", cmd);
-
-    let output = cmd
-        .output()
-        .map_err(|e| format!("error using rg: {:#?}", e))?;
-    if output.status.success() {
-        Ok(output.stdout)
-    } else {
-        // For some reason rg returns an error when there are no results...
-        Ok(vec![])
+    args.push(pattern.into());
+    args.push(path.into());
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let argv = utils::shell_line("rg", &args);
+    let output = Command::new("rg").args(&args).output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match output.status.code() {
+        Some(0) => Ok(output.stdout),
+        // rg exits with 1 when nothing matches
+        Some(1) => Ok(vec![]),
+        // and with 2 on errors, eg. an unreadable file, whether it found
+        // anything or not
+        Some(2) => {
+            Message::RipgrepErrors {
+                command: argv,
+                stderr: stderr.trim().to_string(),
+            }
+            .send();
+            Ok(output.stdout)
+        }
+        _ => Err(io::Error::other(format!(
+            "`{}` failed ({}): '{}'",
+            argv,
+            output.status,
+            stderr.trim()
+        ))),
     }
 }
 
 /// Parse the output of `rg` into a map of file to possible todo locations.
-pub(crate) fn parse_rg_output(output: &Vec<u8>) -> Result<Vec<PossibleTodosInFile>, String> {
-    let rg_output = std::str::from_utf8(output)
-        .map_err(|e| format!("could not convert rg output to utf8: {:#?}", e))?;
+pub(crate) fn parse_rg_output(output: &[u8]) -> Result<Vec<PossibleTodosInFile>, String> {
+    // Matches in binary or otherwise non-UTF-8 files shouldn't stop us from
+    // finding the rest, those files are skipped when they're read.
+    let rg_output = String::from_utf8_lossy(output);
 
     let (_, files) =
-        parse::parse_rg(rg_output).map_err(|e| format!("rg nom parse error: {:#?}", e))?;
+        parse::parse_rg(&rg_output).map_err(|e| format!("rg nom parse error: {:#?}", e))?;
 
     let mut todos: Vec<_> = files
         .into_iter()
@@ -64,20 +106,65 @@ pub(crate) fn parse_rg_output(output: &Vec<u8>) -> Result<Vec<PossibleTodosInFil
     Ok(todos)
 }
 
-/// Run `rg` with the path and some commonly used TODO patterns, returning the
-/// result bytes if successful.
-pub(crate) fn get_rg_output_with_common_patterns(
-    path: &str,
-    excludes: &Vec<String>,
-) -> Result<Vec<u8>, String> {
-    let patterns = ["TODO", "@todo", "FIXME"];
-
-    let mut todos = vec![];
-    for pattern in patterns.iter() {
-        todos.extend(get_rg_output(path, pattern, excludes)?);
+/// Parse the JSON lines of `rg --json` into a map of file to possible todo
+/// locations. Only `match` events are used, and files whose paths aren't
+/// UTF-8 are skipped.
+pub(crate) fn parse_rg_json(output: &str) -> Result<Vec<PossibleTodosInFile>, String> {
+    let mut lines_by_file: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (n, line) in output.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event: Value = serde_json::from_str(line)
+            .map_err(|e| format!("invalid rg JSON on line {}: {}", n + 1, e))?;
+        if event["type"] != "match" {
+            continue;
+        }
+        let data = &event["data"];
+        let line_number = data["line_number"]
+            .as_u64()
+            .ok_or_else(|| format!("rg match without a line number on line {}", n + 1))?;
+        if let Some(path) = data["path"]["text"].as_str() {
+            lines_by_file
+                .entry(path.to_string())
+                .or_default()
+                .push(line_number as usize);
+        }
     }
+    Ok(lines_by_file
+        .into_iter()
+        .map(|(file, mut lines)| {
+            lines.sort_unstable();
+            lines.dedup();
+            PossibleTodosInFile {
+                file,
+                lines_to_search: lines,
+            }
+        })
+        .collect())
+}
 
-    Ok(todos)
+/// The regex `rg` searches for to find possible todos with the given tags,
+/// matching any of them as plain text, so that tags like `C++` don't match
+/// anything else. Macros like `todo!` are searched for as tags, see
+/// `ScanOptions::search_tags`.
+pub(crate) fn rg_pattern(tags: &TodoTags) -> String {
+    tags.keywords
+        .iter()
+        .map(|keyword| regex::escape(keyword))
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+/// Run `rg` with the path and the TODO tags, returning the result bytes if
+/// successful.
+pub(crate) fn get_rg_output_with_common_patterns(
+    path: &str,
+    tags: &TodoTags,
+    excludes: &[String],
+    rg_args: &[String],
+) -> io::Result<Vec<u8>> {
+    get_rg_output(path, &rg_pattern(tags), excludes, rg_args)
 }
 
 #[cfg(test)]
@@ -85,6 +172,51 @@ mod tests {
     use super::*;
     use std::process::Command;
 
+    #[test]
+    fn tags_are_searched_as_plain_text() {
+        let tags = TodoTags::new(["TODO", "XXX+", "C++", "todo!"]);
+        assert_eq!(rg_pattern(&tags), r"TODO|XXX\+|C\+\+|todo!");
+    }
+
+    #[test]
+    fn binary_matches_dont_stop_parsing() {
+        let output = b"src/blob.bin\n1:\xffTODO\x00\n\nsrc/lib.rs\n3:// TODO: Do it.\n";
+        assert_eq!(
+            parse_rg_output(output),
+            Ok(vec![
+                PossibleTodosInFile::new("src/blob.bin", vec![1]),
+                PossibleTodosInFile::new("src/lib.rs", vec![3]),
+            ])
+        );
+    }
+
+    #[test]
+    fn can_parse_rg_json() {
+        let output = r##"{"type":"begin","data":{"path":{"text":"src/lib.rs"}}}
+{"type":"match","data":{"path":{"text":"src/lib.rs"},"lines":{"text":"// TODO: One.\n"},"line_number":3,"absolute_offset":20,"submatches":[{"match":{"text":"TODO"},"start":3,"end":7}]}}
+{"type":"match","data":{"path":{"text":"src/lib.rs"},"lines":{"text":"// FIXME: Two.\n"},"line_number":1,"absolute_offset":0,"submatches":[{"match":{"text":"FIXME"},"start":3,"end":8}]}}
+{"type":"end","data":{"path":{"text":"src/lib.rs"},"binary_offset":null,"stats":{"matches":2}}}
+{"type":"match","data":{"path":{"bytes":"c3JjL2NhZsOp"},"lines":{"text":"TODO\n"},"line_number":1,"absolute_offset":0,"submatches":[]}}
+
+{"type":"match","data":{"path":{"text":"a.py"},"lines":{"text":"# TODO: Three. TODO: Four.\n"},"line_number":9,"absolute_offset":80,"submatches":[]}}
+{"type":"match","data":{"path":{"text":"src/lib.rs"},"lines":{"text":"// TODO: One.\n"},"line_number":3,"absolute_offset":20,"submatches":[]}}
+{"type":"summary","data":{"elapsed_total":{"secs":0,"nanos":1,"human":"0s"},"stats":{"matches":5}}}
+"##;
+        assert_eq!(
+            parse_rg_json(output),
+            Ok(vec![
+                PossibleTodosInFile::new("a.py", vec![9]),
+                PossibleTodosInFile::new("src/lib.rs", vec![1, 3]),
+            ])
+        );
+        assert_eq!(
+            parse_rg_json("{\"type\":\"match\"\n"),
+            Err(
+                "invalid rg JSON on line 1: EOF while parsing an object at line 1 column 15".into()
+            )
+        );
+    }
+
     #[test]
     fn can_search_todos_in_files() {
         let may_output = Command::new("rg")
@@ -0,0 +1,135 @@
+//! Finding TODOs without ripgrep, by walking the files ourselves.
+use ignore::{
+    overrides::{Override, OverrideBuilder},
+    DirEntry, WalkBuilder,
+};
+
+use super::{FileSearcher, PossibleTodosInFile};
+use crate::{message::Message, parser::source::TodoTags};
+
+/// The `excludes` as globs of files for a walk of the given path to skip.
+fn exclude_overrides(path: &str, excludes: &[String]) -> Result<Override, String> {
+    let mut overrides = OverrideBuilder::new(path);
+    for exclude in excludes.iter() {
+        let _ = overrides
+            .add(&format!("!{}", exclude))
+            .map_err(|e| format!("invalid exclude glob '{}': {}", exclude, e))?;
+    }
+    overrides
+        .build()
+        .map_err(|e| format!("could not build exclude globs: {}", e))
+}
+
+/// Whether a search of the given path would skip the file for matching one
+/// of the `excludes`.
+pub(crate) fn is_excluded(path: &str, file: &str, excludes: &[String]) -> Result<bool, String> {
+    Ok(exclude_overrides(path, excludes)?
+        .matched(file, false)
+        .is_ignore())
+}
+
+/// Walk the files at the given path that a search for TODOs would read,
+/// skipping hidden files, those ignored by `.gitignore`, `.ignore` and
+/// `.rgignore` files and those matching the `excludes`. Entries that can't be
+/// read are reported and skipped, as `rg` does.
+fn walk_files(path: &str, excludes: &[String]) -> Result<impl Iterator<Item = DirEntry>, String> {
+    let walk = WalkBuilder::new(path)
+        .add_custom_ignore_filename(".rgignore")
+        .overrides(exclude_overrides(path, excludes)?)
+        .build();
+    Ok(walk
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                Message::UnreadableEntry {
+                    reason: e.to_string(),
+                }
+                .send();
+                None
+            }
+        })
+        .filter(|entry| entry.file_type().is_some_and(|ty| ty.is_file())))
+}
+
+/// All the files at the given path that a search for TODOs would read,
+/// sorted, skipping the same files as `find_possible_todos`.
+pub(crate) fn find_files(path: &str, excludes: &[String]) -> Result<Vec<String>, String> {
+    let mut files = walk_files(path, excludes)?
+        .map(|entry| entry.path().display().to_string())
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+/// Search the files at the given path for the TODO tags the way
+/// `rg::get_rg_output_with_common_patterns` does, returning the same results
+/// as parsing its output with `rg::parse_rg_output`.
+///
+/// The files are those of `walk_files`, like the ones `rg` searches. The
+/// patterns are matched as plain text rather than as regexes.
+pub(crate) fn find_possible_todos(
+    path: &str,
+    tags: &TodoTags,
+    excludes: &[String],
+) -> Result<Vec<PossibleTodosInFile>, String> {
+    let mut todos = vec![];
+    for entry in walk_files(path, excludes)? {
+        let file = entry.path().display().to_string();
+        let bytes = match std::fs::read(entry.path()) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                Message::SkippedFile {
+                    path: file,
+                    reason: e.to_string(),
+                }
+                .send();
+                continue;
+            }
+        };
+        // Binary files are reported and skipped when they're parsed
+        let contents = String::from_utf8_lossy(&bytes);
+        // `rg` is run once with all the tags, so a file is listed once with
+        // the lines that match any of them
+        let lines = FileSearcher::find_in_contents(&contents, tags);
+        if !lines.is_empty() {
+            todos.push(PossibleTodosInFile::new(&file, lines));
+        }
+    }
+    todos.sort();
+
+    Ok(todos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils;
+    use std::fs;
+
+    #[test]
+    fn walking_respects_gitignore_and_excludes() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("target")).unwrap();
+        fs::create_dir_all(dir.join("vendor")).unwrap();
+        fs::write(
+            dir.join("src/lib.rs"),
+            "// TODO: One.\nfn a() {}\n// FIXME: Two.\nfn b() { todo!() }\n",
+        )
+        .unwrap();
+        fs::write(dir.join("src/none.rs"), "fn c() {}\n").unwrap();
+        fs::write(dir.join("target/out.rs"), "// TODO: Ignored.\n").unwrap();
+        fs::write(dir.join("vendor/dep.rs"), "// TODO: Excluded.\n").unwrap();
+        fs::write(dir.join(".hidden.rs"), "// TODO: Hidden.\n").unwrap();
+        fs::write(dir.join(".gitignore"), "target/\n").unwrap();
+        let dir_str = dir.to_str().unwrap();
+        utils::command("git", &["init", "-q"], dir_str).unwrap();
+
+        // Along with the tags, scans search for macros like `todo!`
+        let tags = crate::parser::ScanOptions::default().search_tags();
+        let todos = find_possible_todos(dir_str, &tags, &["vendor/**".into()]).unwrap();
+        let lib = dir.join("src/lib.rs").display().to_string();
+        assert_eq!(todos, vec![PossibleTodosInFile::new(&lib, vec![1, 3, 4])]);
+    }
+}
@@ -0,0 +1,1084 @@
+//! An abstraction over the forges (GitHub, GitLab, etc.) that todos can be
+//! synced with, so they can all share the same patch pipeline.
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::prelude::*,
+    path::PathBuf,
+};
+
+use super::parser::{todo_id, FileTodoLocation, Issue, IssueBody, IssueMap};
+// The markers are parsed along with issue bodies, but are part of what a
+// forge writes
+pub use super::parser::issue::{
+    keep_unmanaged_text, managed_body, with_managed_markers, MANAGED_END, MANAGED_START,
+};
+
+/// The changes needed to bring a forge's issues in line with the local todos,
+/// see `IssueMap::prepare_patch_following_moves`.
+pub struct Patch<Id> {
+    /// Local todos that have no issue yet.
+    pub create: IssueMap<(), FileTodoLocation>,
+    /// Issues that still have todos, updated with the local todos.
+    pub edit: IssueMap<Id, FileTodoLocation>,
+    /// Issues whose todos are gone.
+    pub delete: Vec<Id>,
+    /// Issues whose todos are marked won't fix, to be closed as not planned.
+    pub wontfix: Vec<Id>,
+    /// Issues closed as not planned whose todos are no longer marked won't
+    /// fix, to be reopened. These are edited too.
+    pub reopen: Vec<Id>,
+}
+
+impl<Id> Patch<Id> {
+    /// The number of issues the patch creates, edits, closes or reopens.
+    pub fn num_operations(&self) -> usize {
+        self.create.todos.len()
+            + self.edit.todos.len()
+            + self.delete.len()
+            + self.wontfix.len()
+            + self.reopen.len()
+    }
+
+    /// Fail if the patch would do more than `max_operations`, so a first sync
+    /// of a repo with lots of todos doesn't open hundreds of issues by
+    /// accident.
+    pub fn check_operations(&self, max_operations: Option<usize>) -> Result<(), String> {
+        match max_operations {
+            Some(max) if self.num_operations() > max => Err(format!(
+                "refusing to create {}, edit {}, close {} and reopen {} issues, which is more \
+                 than the maximum of {} operations (pass --force to do it anyway)",
+                self.create.todos.len(),
+                self.edit.todos.len(),
+                self.delete.len() + self.wontfix.len(),
+                self.reopen.len(),
+                max
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The fields of an issue that a sync writes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IssueFields {
+    pub title: String,
+    pub body: String,
+    pub assignees: Vec<String>,
+    pub labels: Vec<String>,
+}
+
+/// A field of an issue that an edit changes, with its old and new values.
+/// Lists are joined with commas.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+impl IssueFields {
+    /// The fields that differ from the `new` ones. Assignees and labels are
+    /// compared regardless of order.
+    ///
+    /// ```rust
+    /// use todo_finder_lib::forge::{FieldChange, IssueFields};
+    ///
+    /// let old = IssueFields {
+    ///     title: "Do it.".into(),
+    ///     labels: vec!["todo".into(), "backend".into()],
+    ///     ..IssueFields::default()
+    /// };
+    /// let new = IssueFields {
+    ///     title: "Do it now.".into(),
+    ///     labels: vec!["backend".into(), "todo".into()],
+    ///     ..IssueFields::default()
+    /// };
+    /// assert_eq!(
+    ///     old.changes(&new),
+    ///     vec![FieldChange {
+    ///         field: "title",
+    ///         old: "Do it.".into(),
+    ///         new: "Do it now.".into()
+    ///     }]
+    /// );
+    /// ```
+    pub fn changes(&self, new: &IssueFields) -> Vec<FieldChange> {
+        let sorted = |list: &[String]| {
+            let mut list = list.to_vec();
+            list.sort();
+            list.join(", ")
+        };
+        vec![
+            ("title", self.title.clone(), new.title.clone()),
+            ("body", self.body.replace("\r\n", "\n"), new.body.clone()),
+            ("assignees", sorted(&self.assignees), sorted(&new.assignees)),
+            ("labels", sorted(&self.labels), sorted(&new.labels)),
+        ]
+        .into_iter()
+        .filter(|(_, old, new)| old != new)
+        .map(|(field, old, new)| FieldChange { field, old, new })
+        .collect()
+    }
+}
+
+/// Diff the lines of two texts, marking each line ` `, `-` or `+` for kept,
+/// removed or added.
+///
+/// ```rust
+/// use todo_finder_lib::forge::diff_lines;
+///
+/// assert_eq!(
+///     diff_lines("a\nb\nc", "a\nc\nd"),
+///     vec![(' ', "a"), ('-', "b"), (' ', "c"), ('+', "d")]
+/// );
+/// ```
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<(char, &'a str)> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    // The length of the longest common subsequence of the lines after each
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut lines = vec![];
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+impl FieldChange {
+    /// Render the change as a diff of its lines, in red and green if `color`.
+    pub fn render(&self, color: bool) -> String {
+        let mut lines = vec![format!("{}:", self.field)];
+        for (sign, line) in diff_lines(&self.old, &self.new) {
+            let line = format!("  {} {}", sign, line);
+            lines.push(match (color, sign) {
+                (true, '-') => format!("\x1b[31m{}\x1b[0m", line),
+                (true, '+') => format!("\x1b[32m{}\x1b[0m", line),
+                _ => line,
+            });
+        }
+        lines.join("\n")
+    }
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
+}
+
+/// A place todos are kept as issues.
+// Todos are synced from a single task, so the futures don't need to be `Send`.
+#[allow(async_fn_in_trait)]
+pub trait Forge {
+    /// Identifies an issue at the forge, eg. its number.
+    type Id: Copy + PartialEq + Serialize + DeserializeOwned;
+    /// A todo location as parsed back out of an issue body.
+    type Location: Eq;
+
+    /// List the open todo issues.
+    async fn list_issues(&self) -> Result<IssueMap<Self::Id, Self::Location>, String>;
+
+    /// Find open issues that aren't managed yet but have the title of one of
+    /// the given todos, so they can be adopted. By default none are.
+    async fn find_unmanaged_issues(
+        &self,
+        _titles: &[&str],
+    ) -> Result<Vec<Issue<Self::Id, Self::Location>>, String> {
+        Ok(vec![])
+    }
+
+    /// Create an issue for a local todo, returning its id.
+    async fn create_issue(&self, issue: &Issue<(), FileTodoLocation>) -> Result<Self::Id, String>;
+
+    /// Update an existing issue with the local todo.
+    async fn update_issue(&self, issue: &Issue<Self::Id, FileTodoLocation>) -> Result<(), String>;
+
+    /// Fetch the current fields of an issue.
+    async fn issue_fields(&self, id: Self::Id) -> Result<IssueFields, String>;
+
+    /// The fields an issue is given when it's updated with the local todo.
+    fn fields_of(&self, issue: &Issue<Self::Id, FileTodoLocation>) -> Result<IssueFields, String>;
+
+    /// Close an issue whose todos are gone, returning its title if known.
+    async fn close_issue(&self, id: Self::Id) -> Result<Option<String>, String>;
+
+    /// Close an issue whose todos are marked won't fix as not planned,
+    /// returning its title if known. By default it's closed like any other.
+    async fn close_issue_as_not_planned(&self, id: Self::Id) -> Result<Option<String>, String> {
+        self.close_issue(id).await
+    }
+
+    /// Find issues that were closed as not planned and have the title of one
+    /// of the given todos, so they can be reopened now that the todos are no
+    /// longer marked won't fix. By default none are.
+    async fn find_not_planned_issues(
+        &self,
+        _titles: &[&str],
+    ) -> Result<Vec<Issue<Self::Id, Self::Location>>, String> {
+        Ok(vec![])
+    }
+
+    /// Reopen an issue found by `find_not_planned_issues`.
+    async fn reopen_issue(&self, id: Self::Id) -> Result<(), String>;
+
+    /// A permanent link to a todo location.
+    fn permalink(&self, loc: &FileTodoLocation) -> Result<String, String>;
+
+    /// A link to an issue, eg. to open it in a browser.
+    fn issue_url(&self, id: Self::Id) -> String;
+
+    /// Whether the todo at a location in an issue moved to the local location,
+    /// so an issue whose title no longer matches a todo can follow it. By
+    /// default todos are only matched by title.
+    fn is_moved_todo(&self, _remote: &Self::Location, _local: &FileTodoLocation) -> bool {
+        false
+    }
+
+    /// How similar the title of an issue must be to that of an unmatched todo
+    /// for the todo to keep the issue when it was reworded in place, see
+    /// `IssueMap::prepare_patch_following_edits`. By default todos are only
+    /// matched by title.
+    fn min_title_similarity(&self) -> Option<f64> {
+        None
+    }
+
+    /// Parse the descriptions and locations of todos back out of an issue body.
+    fn parse_body(&self, body: &str) -> Option<IssueBody<Self::Location>>;
+
+    /// Write the body of an issue, with each description followed by a
+    /// permalink to its location.
+    fn write_body(&self, body: &IssueBody<FileTodoLocation>) -> Result<String, String> {
+        let mut lines: Vec<String> = vec![];
+        for (desc_lines, loc) in body.descs_and_srcs.iter() {
+            lines.push([desc_lines.join("\n"), self.permalink(loc)?].join("\n"));
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// An operation of a sync that went through, as recorded in a `SyncLog`.
+/// Todos are identified by their id, see `Issue::id`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum LoggedOperation<Id> {
+    Create { todo: String, issue: Id },
+    Edit { todo: String, issue: Id },
+    Close { issue: Id },
+    Wontfix { issue: Id },
+    Reopen { issue: Id },
+}
+
+/// The operations of a sync, appended to a file as each one goes through so
+/// that a sync interrupted midway, eg. by a dropped connection, can be
+/// resumed without doing them again. The file is removed once a sync
+/// finishes.
+#[derive(Debug)]
+pub struct SyncLog<Id> {
+    path: Option<PathBuf>,
+    done: Vec<LoggedOperation<Id>>,
+}
+
+impl<Id> Default for SyncLog<Id> {
+    /// A log kept in memory only, so nothing can be resumed.
+    fn default() -> Self {
+        SyncLog {
+            path: None,
+            done: vec![],
+        }
+    }
+}
+
+impl<Id: PartialEq + Serialize + DeserializeOwned> SyncLog<Id> {
+    /// Open the log at the given path, reading the operations of an
+    /// interrupted sync if there was one.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let done = match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| {
+                    serde_json::from_str(line)
+                        .map_err(|e| format!("could not parse sync log {}: {}", path.display(), e))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(format!("could not read sync log {}: {}", path.display(), e)),
+        };
+        Ok(SyncLog {
+            path: Some(path),
+            done,
+        })
+    }
+
+    /// Whether an interrupted sync already did the operation.
+    pub fn contains(&self, op: &LoggedOperation<Id>) -> bool {
+        self.done.contains(op)
+    }
+
+    /// Whether an interrupted sync already created an issue for the todo.
+    fn created(&self, todo: &str) -> bool {
+        self.done.iter().any(|op| match op {
+            LoggedOperation::Create { todo: logged, .. } => logged == todo,
+            _ => false,
+        })
+    }
+
+    /// Record an operation that went through.
+    fn append(&mut self, op: LoggedOperation<Id>) -> Result<(), String> {
+        if let Some(path) = self.path.as_ref() {
+            let line = serde_json::to_string(&op)
+                .map_err(|e| format!("could not serialize sync operation: {}", e))?;
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{}", line))
+                .map_err(|e| format!("could not write sync log {}: {}", path.display(), e))?;
+        }
+        self.done.push(op);
+        Ok(())
+    }
+
+    /// Remove the log after a sync finished.
+    fn finish(self) -> Result<(), String> {
+        match self.path {
+            Some(path) if path.exists() => fs::remove_file(&path)
+                .map_err(|e| format!("could not remove sync log {}: {}", path.display(), e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Diff the forge's issues, and any unmanaged ones it adopts or not planned
+/// ones it reopens, against the local todos.
+async fn prepare_sync<F: Forge>(
+    forge: &F,
+    local_issues: IssueMap<(), FileTodoLocation>,
+) -> Result<Patch<F::Id>, String> {
+    let mut remote_issues = forge
+        .list_issues()
+        .await?
+        .with_key(local_issues.key.clone());
+    let unmatched = |remote_issues: &IssueMap<F::Id, F::Location>| {
+        local_issues
+            .todos
+            .iter()
+            .filter(|(key, issue)| !issue.head.wontfix && !remote_issues.todos.contains_key(*key))
+            .map(|(_, issue)| issue.head.title.as_str())
+            .collect::<Vec<_>>()
+    };
+    for issue in forge
+        .find_unmanaged_issues(&unmatched(&remote_issues))
+        .await?
+    {
+        println!("adopting '{}'", issue.head.title);
+        remote_issues.insert(issue);
+    }
+    let mut reopen = vec![];
+    for issue in forge
+        .find_not_planned_issues(&unmatched(&remote_issues))
+        .await?
+    {
+        reopen.push(issue.head.external_id);
+        remote_issues.insert(issue);
+    }
+    let mut patch = remote_issues.prepare_patch_following_edits(
+        local_issues,
+        |remote, local| forge.is_moved_todo(remote, local),
+        forge.min_title_similarity(),
+    );
+    patch.reopen = reopen;
+    Ok(patch)
+}
+
+/// The changes a sync would make to an issue it edits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IssueEdit<Id> {
+    pub id: Id,
+    pub title: String,
+    pub changes: Vec<FieldChange>,
+}
+
+/// Work out what a sync would do without doing it: the patch, and the field
+/// changes of each issue it would edit, compared with the issue's current
+/// fields. Edits that change nothing are left out.
+pub async fn preview_sync<F: Forge>(
+    forge: &F,
+    local_issues: IssueMap<(), FileTodoLocation>,
+) -> Result<(Patch<F::Id>, Vec<IssueEdit<F::Id>>), String> {
+    let patch = prepare_sync(forge, local_issues).await?;
+    let mut issues = patch.edit.todos.values().collect::<Vec<_>>();
+    issues.sort_by(|a, b| a.head.title.cmp(&b.head.title));
+    let mut edits = vec![];
+    for issue in issues.into_iter() {
+        let id = issue.head.external_id;
+        let current = forge.issue_fields(id).await?;
+        let new = forge.fields_of(issue)?;
+        // Bodies edited on the web may have CRLF line endings, which
+        // `changes` ignores
+        let new = IssueFields {
+            body: keep_unmanaged_text(&current.body.replace("\r\n", "\n"), &new.body),
+            ..new
+        };
+        let changes = current.changes(&new);
+        if !changes.is_empty() {
+            edits.push(IssueEdit {
+                id,
+                title: issue.head.title.clone(),
+                changes,
+            });
+        }
+    }
+    Ok((patch, edits))
+}
+
+/// Sync local todos with the forge: create issues for new todos, update
+/// the issues of existing ones and close the issues of todos that are gone.
+/// Nothing is changed if that would take more than `max_operations`.
+pub async fn sync_issues<F: Forge>(
+    forge: &F,
+    local_issues: IssueMap<(), FileTodoLocation>,
+    max_operations: Option<usize>,
+) -> Result<(), String> {
+    sync_issues_logged(forge, local_issues, max_operations, SyncLog::default()).await
+}
+
+/// Like `sync_issues`, but each operation is recorded in the log as it goes
+/// through, and operations already in the log from an interrupted sync are
+/// skipped.
+pub async fn sync_issues_logged<F: Forge>(
+    forge: &F,
+    local_issues: IssueMap<(), FileTodoLocation>,
+    max_operations: Option<usize>,
+    mut log: SyncLog<F::Id>,
+) -> Result<(), String> {
+    let patch = prepare_sync(forge, local_issues).await?;
+    patch.check_operations(max_operations)?;
+
+    // Creates are logged by the id of their key, which is the issue's own id
+    // unless todos are told apart by more than their title
+    let mut creates = patch.create.todos.iter().collect::<Vec<_>>();
+    creates.sort_by_key(|(key, _)| *key);
+    println!("creating {} issues", creates.len());
+    for (key, issue) in creates.into_iter() {
+        let todo = todo_id(key);
+        if log.created(&todo) {
+            println!("already created '{}'", issue.head.title);
+            continue;
+        }
+        let id = forge.create_issue(issue).await?;
+        log.append(LoggedOperation::Create { todo, issue: id })?;
+        println!("created '{}'", issue.head.title);
+    }
+
+    println!("reopening {} issues", patch.reopen.len());
+    for id in patch.reopen.into_iter() {
+        let op = LoggedOperation::Reopen { issue: id };
+        if log.contains(&op) {
+            continue;
+        }
+        forge.reopen_issue(id).await?;
+        log.append(op)?;
+    }
+
+    println!("editing {} issues", patch.edit.todos.len());
+    for (key, issue) in patch.edit.todos.iter() {
+        let op = LoggedOperation::Edit {
+            todo: todo_id(key),
+            issue: issue.head.external_id,
+        };
+        if log.contains(&op) {
+            println!("already edited '{}'", issue.head.title);
+            continue;
+        }
+        println!("editing '{}'", issue.head.title);
+        forge.update_issue(issue).await?;
+        log.append(op)?;
+    }
+
+    println!("deleting {} issues", patch.delete.len());
+    for id in patch.delete.into_iter() {
+        let op = LoggedOperation::Close { issue: id };
+        if log.contains(&op) {
+            continue;
+        }
+        let closed = forge.close_issue(id).await?;
+        log.append(op)?;
+        if let Some(title) = closed {
+            println!("closed '{}'", title);
+        }
+    }
+
+    println!("closing {} issues as not planned", patch.wontfix.len());
+    for id in patch.wontfix.into_iter() {
+        let op = LoggedOperation::Wontfix { issue: id };
+        if log.contains(&op) {
+            continue;
+        }
+        let closed = forge.close_issue_as_not_planned(id).await?;
+        log.append(op)?;
+        if let Some(title) = closed {
+            println!("closed '{}' as not planned", title);
+        }
+    }
+
+    log.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{source::ParsedTodo, ParsingSource, TodoKey};
+    use std::{cell::RefCell, collections::BTreeMap};
+
+    /// An issue kept by the mock forge.
+    #[derive(Clone, Debug, PartialEq)]
+    struct MockIssue {
+        title: String,
+        body: String,
+        open: bool,
+        /// Whether it was closed as not planned.
+        not_planned: bool,
+    }
+
+    /// A forge that keeps its issues in memory.
+    #[derive(Default)]
+    struct MockForge {
+        issues: RefCell<BTreeMap<u64, MockIssue>>,
+        /// Ids of issues not managed by us.
+        unmanaged: RefCell<Vec<u64>>,
+        /// Ids of issues that aren't listed yet, like GitHub's search lagging
+        /// behind.
+        unlisted: RefCell<Vec<u64>>,
+        /// The title of a todo whose issue fails to be created, like a
+        /// dropped connection.
+        fail_to_create: RefCell<Option<String>>,
+        /// Whether todos reworded in place keep their issues.
+        follow_moves: RefCell<bool>,
+        /// What tells issues apart when listing them.
+        key: RefCell<TodoKey>,
+        /// How similar titles must be for a reworded todo to keep its issue.
+        min_title_similarity: RefCell<Option<f64>>,
+        /// Whether bodies mark the part written by syncs, like GitHub's.
+        managed_markers: RefCell<bool>,
+    }
+
+    impl MockForge {
+        fn open_titles(&self) -> Vec<String> {
+            let mut titles = self
+                .issues
+                .borrow()
+                .values()
+                .filter(|issue| issue.open)
+                .map(|issue| issue.title.clone())
+                .collect::<Vec<_>>();
+            titles.sort();
+            titles
+        }
+
+        fn body_of(&self, issue: &IssueBody<FileTodoLocation>) -> Result<String, String> {
+            let body = self.write_body(issue)?;
+            Ok(if *self.managed_markers.borrow() {
+                with_managed_markers(&body)
+            } else {
+                body
+            })
+        }
+    }
+
+    impl Forge for MockForge {
+        type Id = u64;
+        type Location = FileTodoLocation;
+
+        async fn list_issues(&self) -> Result<IssueMap<u64, FileTodoLocation>, String> {
+            let mut map =
+                IssueMap::new(ParsingSource::SourceCode).with_key(self.key.borrow().clone());
+            let unmanaged = self.unmanaged.borrow();
+            for (id, mock) in self.issues.borrow().iter().filter(|(id, m)| {
+                m.open && !unmanaged.contains(id) && !self.unlisted.borrow().contains(id)
+            }) {
+                let mut issue = Issue::new(*id, mock.title.clone());
+                issue.body = self.parse_body(&mock.body).ok_or("bad body")?;
+                map.insert(issue);
+            }
+            Ok(map)
+        }
+
+        async fn find_unmanaged_issues(
+            &self,
+            titles: &[&str],
+        ) -> Result<Vec<Issue<u64, FileTodoLocation>>, String> {
+            let unmanaged = self.unmanaged.borrow();
+            Ok(self
+                .issues
+                .borrow()
+                .iter()
+                .filter(|(id, m)| {
+                    m.open && unmanaged.contains(id) && titles.contains(&m.title.as_str())
+                })
+                .map(|(id, m)| Issue::new(*id, m.title.clone()))
+                .collect())
+        }
+
+        async fn create_issue(&self, issue: &Issue<(), FileTodoLocation>) -> Result<u64, String> {
+            if self.fail_to_create.borrow().as_ref() == Some(&issue.head.title) {
+                return Err("connection reset".into());
+            }
+            let body = self.body_of(&issue.body)?;
+            let mut issues = self.issues.borrow_mut();
+            let id = issues.len() as u64 + 1;
+            issues.insert(
+                id,
+                MockIssue {
+                    title: issue.head.title.clone(),
+                    body,
+                    open: true,
+                    not_planned: false,
+                },
+            );
+            Ok(id)
+        }
+
+        async fn update_issue(&self, issue: &Issue<u64, FileTodoLocation>) -> Result<(), String> {
+            let body = self.body_of(&issue.body)?;
+            let mut issues = self.issues.borrow_mut();
+            let mock = issues
+                .get_mut(&issue.head.external_id)
+                .ok_or("no such issue")?;
+            mock.body = keep_unmanaged_text(&mock.body, &body);
+            self.unmanaged
+                .borrow_mut()
+                .retain(|id| *id != issue.head.external_id);
+            Ok(())
+        }
+
+        async fn issue_fields(&self, id: u64) -> Result<IssueFields, String> {
+            let issues = self.issues.borrow();
+            let mock = issues.get(&id).ok_or("no such issue")?;
+            Ok(IssueFields {
+                title: mock.title.clone(),
+                body: mock.body.clone(),
+                ..IssueFields::default()
+            })
+        }
+
+        fn fields_of(&self, issue: &Issue<u64, FileTodoLocation>) -> Result<IssueFields, String> {
+            Ok(IssueFields {
+                title: issue.head.title.clone(),
+                body: self.body_of(&issue.body)?,
+                ..IssueFields::default()
+            })
+        }
+
+        async fn close_issue(&self, id: u64) -> Result<Option<String>, String> {
+            let mut issues = self.issues.borrow_mut();
+            let mock = issues.get_mut(&id).ok_or("no such issue")?;
+            mock.open = false;
+            Ok(Some(mock.title.clone()))
+        }
+
+        async fn close_issue_as_not_planned(&self, id: u64) -> Result<Option<String>, String> {
+            let mut issues = self.issues.borrow_mut();
+            let mock = issues.get_mut(&id).ok_or("no such issue")?;
+            mock.open = false;
+            mock.not_planned = true;
+            Ok(Some(mock.title.clone()))
+        }
+
+        async fn find_not_planned_issues(
+            &self,
+            titles: &[&str],
+        ) -> Result<Vec<Issue<u64, FileTodoLocation>>, String> {
+            let mut found = vec![];
+            for (id, mock) in self.issues.borrow().iter() {
+                if mock.not_planned && titles.contains(&mock.title.as_str()) {
+                    let mut issue = Issue::new(*id, mock.title.clone());
+                    issue.body = self.parse_body(&mock.body).ok_or("bad body")?;
+                    found.push(issue);
+                }
+            }
+            Ok(found)
+        }
+
+        async fn reopen_issue(&self, id: u64) -> Result<(), String> {
+            let mut issues = self.issues.borrow_mut();
+            let mock = issues.get_mut(&id).ok_or("no such issue")?;
+            mock.open = true;
+            mock.not_planned = false;
+            Ok(())
+        }
+
+        fn is_moved_todo(&self, remote: &FileTodoLocation, local: &FileTodoLocation) -> bool {
+            *self.follow_moves.borrow() && remote == local
+        }
+
+        fn min_title_similarity(&self) -> Option<f64> {
+            *self.min_title_similarity.borrow()
+        }
+
+        fn permalink(&self, loc: &FileTodoLocation) -> Result<String, String> {
+            Ok(format!("mock://{}#L{}", loc.file, loc.src_span.0))
+        }
+
+        fn issue_url(&self, id: u64) -> String {
+            format!("mock://issues/{}", id)
+        }
+
+        fn parse_body(&self, body: &str) -> Option<IssueBody<FileTodoLocation>> {
+            let mut descs_and_srcs = vec![];
+            let mut descs = vec![];
+            for line in managed_body(body).lines() {
+                if let Some(link) = line.strip_prefix("mock://") {
+                    let (file, line) = link.split_once("#L")?;
+                    let loc = FileTodoLocation {
+                        file: file.into(),
+                        src_span: (line.parse().ok()?, None),
+                    };
+                    descs_and_srcs.push((std::mem::take(&mut descs), loc));
+                } else if !line.is_empty() {
+                    descs.push(line.to_string());
+                }
+            }
+            Some(IssueBody {
+                descs_and_srcs,
+                branches: vec![],
+            })
+        }
+    }
+
+    fn local_todos(todos: &[(&str, &str, usize)]) -> IssueMap<(), FileTodoLocation> {
+        let mut map = IssueMap::new_source_todos();
+        for (title, file, line) in todos.iter() {
+            map.add_parsed_todo(
+                &ParsedTodo {
+                    desc_lines: vec!["Some detail."],
+                    ..ParsedTodo::new(*title)
+                },
+                FileTodoLocation {
+                    file: file.to_string(),
+                    src_span: (*line, None),
+                },
+            );
+        }
+        map
+    }
+
+    #[tokio::test]
+    async fn can_sync_issues_with_a_forge() {
+        let forge = MockForge::default();
+
+        let local = local_todos(&[("Do A.", "a.rs", 1), ("Do B.", "b.rs", 2)]);
+        sync_issues(&forge, local, None).await.unwrap();
+        assert_eq!(forge.open_titles(), vec!["Do A.", "Do B."]);
+
+        let local = local_todos(&[("Do B.", "b.rs", 20), ("Do C.", "c.rs", 3)]);
+        sync_issues(&forge, local, None).await.unwrap();
+        assert_eq!(forge.open_titles(), vec!["Do B.", "Do C."]);
+        let issues = forge.issues.borrow().clone();
+        let b = issues
+            .values()
+            .find(|issue| issue.title == "Do B.")
+            .unwrap();
+        assert_eq!(b.body, "Some detail.\nmock://b.rs#L20");
+
+        // Syncing again changes nothing
+        let before = forge.issues.borrow().clone();
+        let local = local_todos(&[("Do B.", "b.rs", 20), ("Do C.", "c.rs", 3)]);
+        sync_issues(&forge, local, None).await.unwrap();
+        assert_eq!(*forge.issues.borrow(), before);
+    }
+
+    #[tokio::test]
+    async fn can_adopt_unmanaged_issues() {
+        let forge = MockForge::default();
+        for (id, title) in [(1, "Do A."), (2, "Something else.")].iter() {
+            forge.issues.borrow_mut().insert(
+                *id,
+                MockIssue {
+                    title: title.to_string(),
+                    body: "Written by hand.".into(),
+                    open: true,
+                    not_planned: false,
+                },
+            );
+            forge.unmanaged.borrow_mut().push(*id);
+        }
+
+        let local = local_todos(&[("Do A.", "a.rs", 1)]);
+        sync_issues(&forge, local, None).await.unwrap();
+
+        let issues = forge.issues.borrow();
+        // The matching issue was adopted rather than duplicated
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[&1].body, "Some detail.\nmock://a.rs#L1");
+        assert_eq!(*forge.unmanaged.borrow(), vec![2]);
+        // The unrelated one was left alone
+        assert!(issues[&2].open);
+        assert_eq!(issues[&2].body, "Written by hand.");
+    }
+
+    #[tokio::test]
+    async fn syncs_over_the_operation_limit_are_refused() {
+        let forge = MockForge::default();
+        let local = local_todos(&[("Do A.", "a.rs", 1), ("Do B.", "b.rs", 2)]);
+        sync_issues(&forge, local, None).await.unwrap();
+
+        let local = local_todos(&[("Do C.", "c.rs", 1), ("Do D.", "d.rs", 2)]);
+        assert_eq!(
+            sync_issues(&forge, local, Some(3)).await,
+            Err(
+                "refusing to create 2, edit 0, close 2 and reopen 0 issues, which is more than the \
+                 maximum of 3 operations (pass --force to do it anyway)"
+                    .into()
+            )
+        );
+        assert_eq!(forge.open_titles(), vec!["Do A.", "Do B."]);
+
+        let local = local_todos(&[("Do A.", "a.rs", 1), ("Do C.", "c.rs", 1)]);
+        sync_issues(&forge, local, Some(3)).await.unwrap();
+        assert_eq!(forge.open_titles(), vec!["Do A.", "Do C."]);
+    }
+
+    #[tokio::test]
+    async fn interrupted_syncs_can_be_resumed() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        let path = dir.join("sync.log");
+        let forge = MockForge::default();
+        let todos = [
+            ("Do A.", "a.rs", 1),
+            ("Do B.", "b.rs", 2),
+            ("Do C.", "c.rs", 3),
+        ];
+
+        // The connection drops after the first issue is created
+        *forge.fail_to_create.borrow_mut() = Some("Do B.".into());
+        let log = SyncLog::open(&path).unwrap();
+        assert_eq!(
+            sync_issues_logged(&forge, local_todos(&todos), None, log).await,
+            Err("connection reset".into())
+        );
+        assert_eq!(forge.open_titles(), vec!["Do A."]);
+        let log = SyncLog::open(&path).unwrap();
+        assert!(log.contains(&LoggedOperation::Create {
+            todo: crate::parser::todo_id("Do A."),
+            issue: 1,
+        }));
+
+        // On resuming, the issue created before isn't listed yet, but it isn't
+        // created again
+        *forge.fail_to_create.borrow_mut() = None;
+        forge.unlisted.borrow_mut().push(1);
+        sync_issues_logged(&forge, local_todos(&todos), None, log)
+            .await
+            .unwrap();
+        assert_eq!(forge.open_titles(), vec!["Do A.", "Do B.", "Do C."]);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn previews_show_the_fields_an_edit_changes() {
+        let forge = MockForge::default();
+        let local = local_todos(&[("Do A.", "a.rs", 1), ("Do B.", "b.rs", 2)]);
+        sync_issues(&forge, local, None).await.unwrap();
+
+        // A todo reworded in place, whose issue was edited by hand, and a
+        // todo that moved
+        *forge.follow_moves.borrow_mut() = true;
+        forge.issues.borrow_mut().get_mut(&1).unwrap().body = "Old detail.\nmock://a.rs#L1".into();
+        let local = local_todos(&[("Do A now.", "a.rs", 1), ("Do B.", "b.rs", 20)]);
+
+        let (patch, edits) = preview_sync(&forge, local).await.unwrap();
+        assert_eq!(patch.edit.todos.len(), 2);
+        assert_eq!(
+            edits,
+            vec![
+                IssueEdit {
+                    id: 1,
+                    title: "Do A now.".into(),
+                    changes: vec![
+                        FieldChange {
+                            field: "title",
+                            old: "Do A.".into(),
+                            new: "Do A now.".into(),
+                        },
+                        FieldChange {
+                            field: "body",
+                            old: "Old detail.\nmock://a.rs#L1".into(),
+                            new: "Some detail.\nmock://a.rs#L1".into(),
+                        },
+                    ],
+                },
+                IssueEdit {
+                    id: 2,
+                    title: "Do B.".into(),
+                    changes: vec![FieldChange {
+                        field: "body",
+                        old: "Some detail.\nmock://b.rs#L2".into(),
+                        new: "Some detail.\nmock://b.rs#L20".into(),
+                    }],
+                },
+            ]
+        );
+        assert_eq!(
+            edits[1].changes[0].to_string(),
+            "body:\n    Some detail.\n  - mock://b.rs#L2\n  + mock://b.rs#L20"
+        );
+        // Nothing was changed
+        assert_eq!(forge.open_titles(), vec!["Do A.", "Do B."]);
+    }
+
+    #[tokio::test]
+    async fn text_added_around_managed_bodies_is_kept() {
+        let forge = MockForge::default();
+        *forge.managed_markers.borrow_mut() = true;
+        *forge.follow_moves.borrow_mut() = true;
+        let local = local_todos(&[("Do A.", "a.rs", 1)]);
+        sync_issues(&forge, local, None).await.unwrap();
+        let managed = |line| with_managed_markers(&format!("Some detail.\nmock://a.rs#L{}", line));
+        assert_eq!(forge.issues.borrow()[&1].body, managed(1));
+
+        // A teammate discusses the todo in its issue
+        let discussed = |line| format!("Is this still needed?\n{}\nYes, see #2.", managed(line));
+        forge.issues.borrow_mut().get_mut(&1).unwrap().body = discussed(1);
+        let local = local_todos(&[("Do A.", "a.rs", 5)]);
+        let (_, edits) = preview_sync(&forge, local.clone()).await.unwrap();
+        assert_eq!(
+            edits[0].changes,
+            vec![FieldChange {
+                field: "body",
+                old: discussed(1),
+                new: discussed(5),
+            }]
+        );
+        sync_issues(&forge, local.clone(), None).await.unwrap();
+        assert_eq!(forge.issues.borrow()[&1].body, discussed(5));
+
+        // Only the managed part is parsed, so syncing again changes nothing
+        let (patch, edits) = preview_sync(&forge, local).await.unwrap();
+        assert_eq!(patch.num_operations(), 1);
+        assert!(edits.is_empty());
+    }
+
+    #[tokio::test]
+    async fn wontfix_todos_close_their_issues_as_not_planned() {
+        let forge = MockForge::default();
+        let local = local_todos(&[("Do A.", "a.rs", 1), ("Do B.", "b.rs", 2)]);
+        sync_issues(&forge, local, None).await.unwrap();
+
+        let wontfix = |mut local: IssueMap<(), FileTodoLocation>| {
+            local.add_parsed_todo(
+                &ParsedTodo {
+                    assignee: Some("wontfix"),
+                    ..ParsedTodo::new("Do B.")
+                },
+                FileTodoLocation {
+                    file: "b.rs".into(),
+                    src_span: (2, None),
+                },
+            );
+            local
+        };
+        let local = wontfix(local_todos(&[("Do A.", "a.rs", 1), ("Do C.", "c.rs", 3)]));
+        assert!(local.todos["Do B."].head.wontfix);
+        assert!(local.todos["Do B."].head.assignees.is_empty());
+        sync_issues(&forge, local, None).await.unwrap();
+        assert_eq!(forge.open_titles(), vec!["Do A.", "Do C."]);
+        assert!(forge.issues.borrow()[&2].not_planned);
+
+        // Syncing again changes nothing, and a new won't fix todo gets no issue
+        let local = wontfix(local_todos(&[("Do A.", "a.rs", 1), ("Do C.", "c.rs", 3)]));
+        let patch = prepare_sync(&forge, local).await.unwrap();
+        assert!(patch.create.todos.is_empty());
+        assert!(patch.wontfix.is_empty() && patch.reopen.is_empty());
+
+        // Removing the marker reopens the issue rather than making a new one
+        let local = local_todos(&[
+            ("Do A.", "a.rs", 1),
+            ("Do B.", "b.rs", 2),
+            ("Do C.", "c.rs", 3),
+        ]);
+        sync_issues(&forge, local, None).await.unwrap();
+        assert_eq!(forge.open_titles(), vec!["Do A.", "Do B.", "Do C."]);
+        assert_eq!(forge.issues.borrow().len(), 3);
+        assert!(!forge.issues.borrow()[&2].not_planned);
+    }
+
+    #[tokio::test]
+    async fn todos_with_the_same_title_can_be_kept_apart() {
+        let forge = MockForge::default();
+        *forge.key.borrow_mut() = TodoKey::FirstLine;
+        let local = || {
+            let mut local = IssueMap::new_source_todos().with_key(TodoKey::FirstLine);
+            for (desc, file) in [("In the parser.", "a.rs"), ("In the finder.", "b.rs")] {
+                local.add_parsed_todo(
+                    &ParsedTodo {
+                        desc_lines: vec![desc],
+                        ..ParsedTodo::new("Fix this.")
+                    },
+                    FileTodoLocation {
+                        file: file.into(),
+                        src_span: (1, None),
+                    },
+                );
+            }
+            local
+        };
+        assert_eq!(local().todos.len(), 2);
+        sync_issues(&forge, local(), None).await.unwrap();
+        assert_eq!(forge.open_titles(), vec!["Fix this.", "Fix this."]);
+
+        // Each issue is matched with its own todo on the next sync
+        let patch = prepare_sync(&forge, local()).await.unwrap();
+        assert!(patch.create.todos.is_empty() && patch.delete.is_empty());
+        let mut edited = patch
+            .edit
+            .todos
+            .values()
+            .map(|issue| {
+                (
+                    issue.head.external_id,
+                    issue.body.descs_and_srcs[0].1.file.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        edited.sort();
+        let mut created = forge
+            .issues
+            .borrow()
+            .iter()
+            .map(|(id, mock)| (*id, mock.body.contains("a.rs")))
+            .collect::<Vec<_>>();
+        created.sort();
+        assert_eq!(
+            edited
+                .iter()
+                .map(|(id, file)| (*id, file == "a.rs"))
+                .collect::<Vec<_>>(),
+            created
+        );
+    }
+}
@@ -1,18 +1,133 @@
 use super::{
-    finder::parse::parse_owner_and_repo_from_config,
-    parser::{issue::*, FileTodoLocation, IssueMap},
+    finder::{parse::parse_owner_and_repo_from_config, FileSearcher, PossibleTodosInFile},
+    forge::{
+        keep_unmanaged_text, preview_sync, sync_issues_logged, Forge, IssueFields, Patch, SyncLog,
+        MANAGED_START,
+    },
+    message::Message,
+    parser::{
+        issue::*, FileTodoLocation, Issue, IssueBody, IssueMap, LinkStyle, ScanOptions, TodoKey,
+    },
+    tracker::TrackerChecklist,
+    utils::cached_command,
 };
 use hyper::{
     body::{Body, HttpBody},
     Client, Request, Response,
 };
 use hyper_tls::HttpsConnector;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
 use serde_json::{json, Value};
-use std::process::Command;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::OnceLock,
+    time::Duration,
+};
 
+/// Why an issue whose todos are gone is closed, which GitHub shows on it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CloseReason {
+    /// The work was done, as a removed todo usually means.
+    #[default]
+    Completed,
+    /// The work won't be done.
+    NotPlanned,
+}
+
+impl CloseReason {
+    pub fn from_arg(arg: &str) -> Result<Self, String> {
+        match arg {
+            "completed" => Ok(CloseReason::Completed),
+            "not-planned" => Ok(CloseReason::NotPlanned),
+            _ => Err(format!("invalid close reason '{}'", arg)),
+        }
+    }
+
+    /// The issue's `state_reason` in GitHub's API.
+    ///
+    /// ```rust
+    /// use todo_finder_lib::github::CloseReason;
+    ///
+    /// assert_eq!(CloseReason::default().state_reason(), "completed");
+    /// assert_eq!(
+    ///     CloseReason::from_arg("not-planned").map(|reason| reason.state_reason()),
+    ///     Ok("not_planned")
+    /// );
+    /// assert!(CloseReason::from_arg("done").is_err());
+    /// ```
+    pub fn state_reason(&self) -> &'static str {
+        match self {
+            CloseReason::Completed => "completed",
+            CloseReason::NotPlanned => "not_planned",
+        }
+    }
+}
+
+/// Options for syncing todos with GitHub issues.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GitHubOptions {
+    /// Adopt open issues without the label whose title matches a todo.
+    pub adopt_unlabeled: bool,
+    /// Assign created issues without assignees to the authenticated user.
+    pub self_assign: bool,
+    /// Wrap each location in an issue body in a collapsible `<details>` block.
+    pub collapsible: bool,
+    /// Refuse to sync if it would create, edit and close more issues than this.
+    pub max_operations: Option<usize>,
+    /// Follow todos into files git detects as renamed since the checkout an
+    /// issue links to, so a todo moved and reworded keeps its issue.
+    pub rename_detection: bool,
+    /// Let a todo whose title was edited keep the issue of a single location
+    /// whose title is at least this similar, from 0.0 to 1.0, instead of the
+    /// issue being closed and another opened, see `title_similarity`.
+    pub min_title_similarity: Option<f64>,
+    /// The number of a GitHub Project (v2) of the repo's owner to add created
+    /// issues to.
+    pub project: Option<u64>,
+    /// The number or title of a milestone of the repo to create issues in,
+    /// see `resolve_milestone`.
+    pub milestone: Option<String>,
+    /// The web url of a GitHub Enterprise Server to sync with instead of
+    /// public GitHub, eg. `https://github.example.com`.
+    pub github_url: Option<String>,
+    /// GitHub logins to assign instead of the assignees of todos, eg. of
+    /// email addresses, see `map_assignees`.
+    pub assignee_map: BTreeMap<String, String>,
+    /// The number of lines of code around a todo to include in the links to
+    /// it, see `FileTodoLocation::with_context`.
+    pub context_lines: usize,
+    /// How the links to todos in issue bodies are written. Issues are pages
+    /// of the repo, so links relative to it work there too.
+    pub link_style: LinkStyle,
+    /// A file to log each operation of the sync to, so an interrupted sync
+    /// can be resumed without repeating them, see `SyncLog`.
+    pub sync_log: Option<String>,
+    /// What tells todos apart, which must be what the local todos were keyed
+    /// by so issues that share a title are kept apart too.
+    pub todo_key: TodoKey,
+    /// Why issues whose todos are gone are closed. Issues of todos marked
+    /// won't fix are always closed as not planned.
+    pub close_reason: CloseReason,
+    /// Refuse to sync from a working tree with uncommitted changes, whose
+    /// permalinks to the HEAD checkout may not match the todos.
+    pub require_clean: bool,
+}
+
+impl GitHubOptions {
+    /// The web url of the GitHub to sync with, public GitHub by default.
+    pub fn github_url(&self) -> &str {
+        self.github_url.as_deref().unwrap_or(GITHUB_URL)
+    }
+}
+
+/// The repo, auth and options of a sync with GitHub, made by
+/// `github_config`.
 #[derive(Deserialize)]
-struct GitHubConfig {
+pub struct GitHubConfig {
     // Label to use for filtering TODO issues
     issue_label: String,
     // Github token
@@ -27,6 +142,23 @@ struct GitHubConfig {
     checkout_hash: String,
     // The root project directory
     root_project_dir: String,
+    // How to sync
+    #[serde(default)]
+    options: GitHubOptions,
+    // The login of the authenticated user, once it has been looked up
+    #[serde(skip)]
+    login: OnceLock<String>,
+    // The files renamed since each checkout, once they have been looked up
+    #[serde(skip)]
+    renames: RefCell<HashMap<String, HashMap<String, String>>>,
+    // The node id of the project to add created issues to, once it has been
+    // looked up
+    #[serde(skip)]
+    project_id: OnceLock<String>,
+    // The number of the milestone to create issues in, once it has been
+    // looked up
+    #[serde(skip)]
+    milestone_number: OnceLock<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -46,92 +178,578 @@ pub struct GitHubUser {
     pub login: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitHubMilestone {
+    pub number: u64,
+    pub title: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GitHubIssue {
     pub id: u64,
     pub number: u64,
     pub title: String,
+    /// Issues created by hand may have no body at all.
+    #[serde(default, deserialize_with = "null_as_empty")]
     pub body: String,
     pub state: String,
     pub labels: Vec<GitHubLabel>,
     pub assignees: Vec<GitHubAssignee>,
     pub user: GitHubUser,
+    /// Pull requests show up in issue listings with this set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_request: Option<Value>,
+    /// Why a closed issue was closed, eg. `not_planned`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_reason: Option<String>,
+}
+
+impl GitHubIssue {
+    /// Whether the issue has the label, ignoring case, as GitHub does.
+    pub fn has_label(&self, label: &str) -> bool {
+        self.labels
+            .iter()
+            .any(|l| l.name.eq_ignore_ascii_case(label))
+    }
+}
+
+fn null_as_empty<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+pub type GitHubPatch = Patch<u64>;
+
+/// The web url of public GitHub, used unless another one is configured.
+pub const GITHUB_URL: &str = "https://github.com";
+
+/// Whether a web url, without its trailing slash, is public GitHub's, however
+/// its scheme, `www.` and case are written.
+fn is_public_github(github_url: &str) -> bool {
+    let host = github_url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    host.eq_ignore_ascii_case(GITHUB_URL.trim_start_matches("https://"))
+}
+
+/// The url of the REST API of the GitHub at the given web url, either public
+/// GitHub or a GitHub Enterprise Server.
+///
+/// ```rust
+/// use todo_finder_lib::github::{github_api_url, GITHUB_URL};
+///
+/// assert_eq!(github_api_url(GITHUB_URL), "https://api.github.com");
+/// assert_eq!(github_api_url("http://www.GitHub.com/"), "https://api.github.com");
+/// assert_eq!(
+///     github_api_url("https://github.example.com/"),
+///     "https://github.example.com/api/v3"
+/// );
+/// ```
+pub fn github_api_url(github_url: &str) -> String {
+    let github_url = github_url.trim_end_matches('/');
+    if is_public_github(github_url) {
+        "https://api.github.com".to_string()
+    } else {
+        format!("{}/api/v3", github_url)
+    }
+}
+
+/// The url of the GraphQL API of the GitHub at the given web url, see
+/// `github_api_url`.
+///
+/// ```rust
+/// use todo_finder_lib::github::{github_graphql_url, GITHUB_URL};
+///
+/// assert_eq!(github_graphql_url(GITHUB_URL), "https://api.github.com/graphql");
+/// assert_eq!(github_graphql_url("http://github.com/"), "https://api.github.com/graphql");
+/// assert_eq!(
+///     github_graphql_url("https://github.example.com"),
+///     "https://github.example.com/api/graphql"
+/// );
+/// ```
+pub fn github_graphql_url(github_url: &str) -> String {
+    let github_url = github_url.trim_end_matches('/');
+    if is_public_github(github_url) {
+        "https://api.github.com/graphql".to_string()
+    } else {
+        format!("{}/api/graphql", github_url)
+    }
+}
+
+/// The web url of a repo, which links to its files start with.
+pub fn github_repo_url(github_url: &str, owner: &str, repo: &str) -> String {
+    format!("{}/{}/{}", github_url.trim_end_matches('/'), owner, repo)
+}
+
+pub fn github_issues_url(api: &str, owner: &str, repo: &str) -> String {
+    format!("{}/repos/{}/{}/issues", api, owner, repo)
+}
+
+/// Percent-encode a query parameter value.
+fn encode_query_value(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// The url to list open issues, optionally only those with the given label.
+///
+/// ```rust
+/// use todo_finder_lib::github::github_open_issues_url;
+///
+/// assert_eq!(
+///     github_open_issues_url(
+///         "https://api.github.com",
+///         "schell",
+///         "repo",
+///         Some("good first todo")
+///     ),
+///     "https://api.github.com/repos/schell/repo/issues?state=open&per_page=100&labels=good%20first%20todo"
+/// );
+/// ```
+pub fn github_open_issues_url(api: &str, owner: &str, repo: &str, label: Option<&str>) -> String {
+    github_issues_in_state_url(api, owner, repo, "open", label)
+}
+
+/// The url to list closed issues, optionally only those with the given label.
+pub fn github_closed_issues_url(api: &str, owner: &str, repo: &str, label: Option<&str>) -> String {
+    github_issues_in_state_url(api, owner, repo, "closed", label)
+}
+
+fn github_issues_in_state_url(
+    api: &str,
+    owner: &str,
+    repo: &str,
+    state: &str,
+    label: Option<&str>,
+) -> String {
+    let mut url = format!(
+        "{}?state={}&per_page=100",
+        github_issues_url(api, owner, repo),
+        state
+    );
+    if let Some(label) = label {
+        url.push_str("&labels=");
+        url.push_str(&encode_query_value(label));
+    }
+    url
 }
 
-pub struct GitHubPatch {
-    pub create: IssueMap<(), FileTodoLocation>,
-    pub edit: IssueMap<u64, FileTodoLocation>,
-    pub delete: Vec<u64>,
+/// Find the open issues that don't have the label but are titled like one of
+/// the given todos, so they can be adopted. Pull requests are never adopted.
+pub fn adoptable_issues(
+    issues: &[GitHubIssue],
+    issue_label: &str,
+    titles: &[&str],
+) -> Vec<Issue<u64, GitHubTodoLocation>> {
+    issues
+        .iter()
+        .filter(|issue| {
+            issue.pull_request.is_none()
+                && !issue.has_label(issue_label)
+                && titles.contains(&issue.title.as_str())
+        })
+        .map(|github_issue| {
+            let mut issue = Issue::new(github_issue.number, github_issue.title.clone());
+            if let Ok((_, body)) = issue_body(&github_issue.body) {
+                issue.body = body;
+            }
+            issue
+        })
+        .collect()
 }
 
-pub fn github_issues_url(owner: &str, repo: &str) -> String {
-    format!("https://api.github.com/repos/{}/{}/issues", owner, repo)
+/// Find the issues with the label that were closed as not planned and are
+/// titled like one of the given todos, so they can be reopened.
+pub fn not_planned_issues(
+    issues: &[GitHubIssue],
+    issue_label: &str,
+    titles: &[&str],
+) -> Vec<Issue<u64, GitHubTodoLocation>> {
+    issues
+        .iter()
+        .filter(|issue| {
+            issue.pull_request.is_none()
+                && issue.has_label(issue_label)
+                && issue.state == "closed"
+                && issue.state_reason.as_deref() == Some("not_planned")
+                && titles.contains(&issue.title.as_str())
+        })
+        .map(|github_issue| {
+            let mut issue = Issue::new(github_issue.number, github_issue.title.clone());
+            if let Ok((_, body)) = issue_body(&github_issue.body) {
+                issue.body = body;
+            }
+            issue
+        })
+        .collect()
 }
 
-pub fn github_issues_update_url(owner: &str, repo: &str, id: u64) -> String {
+/// The milestones of a repo, open and closed.
+pub fn github_milestones_url(api: &str, owner: &str, repo: &str) -> String {
     format!(
-        "https://api.github.com/repos/{}/{}/issues/{}",
-        owner, repo, id
+        "{}/repos/{}/{}/milestones?state=all&per_page=100",
+        api, owner, repo
     )
 }
 
-/// git config --get remote.origin.url
-pub fn git_origin() -> Result<String, String> {
-    let output = Command::new("git")
-        .arg("config")
-        .arg("--get")
-        .arg("remote.origin.url")
-        .output()
-        .map_err(|e| format!("could not determine the git origin: {}", e))?;
+/// The url of the next page of a listing, from the `Link` header of the
+/// response with the current page, if there is one.
+///
+/// ```rust
+/// use todo_finder_lib::github::next_page_url;
+///
+/// let link = "<https://api.github.com/repos/a/b/issues?page=2>; rel=\"next\", \
+///             <https://api.github.com/repos/a/b/issues?page=5>; rel=\"last\"";
+/// assert_eq!(
+///     next_page_url(link),
+///     Some("https://api.github.com/repos/a/b/issues?page=2".to_string())
+/// );
+/// assert_eq!(
+///     next_page_url("<https://api.github.com/repos/a/b/issues?page=1>; rel=\"prev\""),
+///     None
+/// );
+/// ```
+pub fn next_page_url(link: &str) -> Option<String> {
+    link.split(',').find_map(|part| {
+        let (url, params) = part.split_once(';')?;
+        let is_next = params
+            .split(';')
+            .any(|param| param.trim() == "rel=\"next\"");
+        let url = url.trim().strip_prefix('<')?.strip_suffix('>')?;
+        Some(url.to_string()).filter(|_| is_next)
+    })
+}
+
+/// Find the number of a milestone by its number or its title, failing with
+/// the titles of the milestones there are if none match.
+///
+/// ```rust
+/// use todo_finder_lib::github::{resolve_milestone, GitHubMilestone};
+///
+/// let milestones = vec![
+///     GitHubMilestone { number: 1, title: "v1.0".into() },
+///     GitHubMilestone { number: 2, title: "v2.0".into() },
+/// ];
+/// assert_eq!(resolve_milestone(&milestones, "v2.0"), Ok(2));
+/// assert_eq!(resolve_milestone(&milestones, "1"), Ok(1));
+/// assert_eq!(
+///     resolve_milestone(&milestones, "v3.0"),
+///     Err("no milestone 'v3.0', the milestones are: 'v1.0', 'v2.0'".to_string())
+/// );
+/// ```
+pub fn resolve_milestone(milestones: &[GitHubMilestone], milestone: &str) -> Result<u64, String> {
+    let by_number = milestone.parse::<u64>().ok();
+    milestones
+        .iter()
+        .find(|m| Some(m.number) == by_number)
+        .or_else(|| milestones.iter().find(|m| m.title == milestone))
+        .map(|m| m.number)
+        .ok_or_else(|| {
+            if milestones.is_empty() {
+                return format!("no milestone '{}', the repo has none", milestone);
+            }
+            let titles = milestones
+                .iter()
+                .map(|m| format!("'{}'", m.title))
+                .collect::<Vec<_>>();
+            format!(
+                "no milestone '{}', the milestones are: {}",
+                milestone,
+                titles.join(", ")
+            )
+        })
+}
 
-    if !output.status.success() {
-        let output = String::from_utf8_lossy(&output.stderr).to_string();
-        return Err(format!("git config --get remote.origin.url: '{}'", output));
+/// The request body that creates an issue, in the given milestone if any.
+pub fn new_issue_json(
+    title: &str,
+    body: &str,
+    assignees: &[&str],
+    labels: &[String],
+    milestone: Option<u64>,
+) -> Value {
+    let mut issue = json!({
+      "title": title,
+      "body": body,
+      "assignees": assignees,
+      "labels": labels
+    });
+    if let Some(milestone) = milestone {
+        issue["milestone"] = json!(milestone);
     }
+    issue
+}
+
+pub fn github_user_url(api: &str) -> String {
+    format!("{}/user", api)
+}
+
+/// The GraphQL query for the node id of a user's or organization's project.
+pub const PROJECT_ID_QUERY: &str = "query($owner: String!, $number: Int!) {
+  repositoryOwner(login: $owner) {
+    ... on Organization { projectV2(number: $number) { id } }
+    ... on User { projectV2(number: $number) { id } }
+  }
+}";
+
+/// The GraphQL mutation adding an issue or pull request to a project.
+pub const ADD_TO_PROJECT_MUTATION: &str = "mutation($project: ID!, $content: ID!) {
+  addProjectV2ItemById(input: {projectId: $project, contentId: $content}) {
+    item { id }
+  }
+}";
+
+/// Turn the errors of a GraphQL response, if any, into one message. Missing
+/// projects and missing permissions are explained.
+fn graphql_errors(response: &Value, doing: &str) -> Result<(), String> {
+    let errors = match response.get("errors").and_then(Value::as_array) {
+        Some(errors) if !errors.is_empty() => errors,
+        _ => return Ok(()),
+    };
+    let messages = errors
+        .iter()
+        .map(|error| {
+            let message = error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown error");
+            match error.get("type").and_then(Value::as_str) {
+                Some("NOT_FOUND") => format!("not found: {}", message),
+                Some("FORBIDDEN") | Some("INSUFFICIENT_SCOPES") => format!(
+                    "permission denied, the token needs the 'project' scope: {}",
+                    message
+                ),
+                _ => message.to_string(),
+            }
+        })
+        .collect::<Vec<_>>();
+    Err(format!("error {}: {}", doing, messages.join("; ")))
+}
+
+/// Read the node id of a project out of the response to `PROJECT_ID_QUERY`.
+pub fn project_id_from_response(
+    owner: &str,
+    number: u64,
+    response: &Value,
+) -> Result<String, String> {
+    let doing = format!("looking up project {} of {}", number, owner);
+    graphql_errors(response, &doing)?;
+    response
+        .pointer("/data/repositoryOwner/projectV2/id")
+        .and_then(Value::as_str)
+        .map(String::from)
+        .ok_or_else(|| format!("error {}: not found", doing))
+}
+
+/// Read the id of the project item out of the response to
+/// `ADD_TO_PROJECT_MUTATION`.
+pub fn project_item_from_response(response: &Value) -> Result<String, String> {
+    let doing = "adding an issue to the project";
+    graphql_errors(response, doing)?;
+    response
+        .pointer("/data/addProjectV2ItemById/item/id")
+        .and_then(Value::as_str)
+        .map(String::from)
+        .ok_or_else(|| format!("error {}: no item in response {}", doing, response))
+}
+
+pub fn github_issues_update_url(api: &str, owner: &str, repo: &str, id: u64) -> String {
+    format!("{}/repos/{}/{}/issues/{}", api, owner, repo, id)
+}
+
+/// A `StaleReference` for each location of a todo that references one of the
+/// given issues that is closed, with paths relative to `cwd`.
+pub fn stale_references(
+    cwd: &str,
+    refs: &BTreeMap<u64, Vec<FileTodoLocation>>,
+    issues: &[GitHubIssue],
+) -> Vec<Message> {
+    issues
+        .iter()
+        .filter(|issue| issue.state == "closed")
+        .flat_map(|issue| {
+            refs.get(&issue.number)
+                .into_iter()
+                .flatten()
+                .map(move |loc| {
+                    let path = Path::new(&loc.file);
+                    Message::StaleReference {
+                        path: path.strip_prefix(cwd).unwrap_or(path).display().to_string(),
+                        line: loc.src_span.0,
+                        issue: issue.number,
+                    }
+                })
+        })
+        .collect()
+}
 
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+/// Whether the assignee is a team handle, like `org/team`, rather than a user.
+pub fn is_team(assignee: &str) -> bool {
+    assignee.contains('/')
+}
+
+/// Whether the assignee is an email address, like `alice@example.com`,
+/// rather than a user, as GitHub can't assign issues by email.
+///
+/// ```rust
+/// use todo_finder_lib::github::is_email;
+///
+/// assert!(is_email("alice@example.com"));
+/// assert!(!is_email("alice"));
+/// assert!(!is_email("org/team"));
+/// assert!(!is_email("@alice.b"));
+/// assert!(!is_email("alice@example."));
+/// ```
+pub fn is_email(assignee: &str) -> bool {
+    match assignee.split_once('@') {
+        Some((user, domain)) => {
+            !user.is_empty()
+                && domain
+                    .split_once('.')
+                    .is_some_and(|(host, rest)| !host.is_empty() && !rest.is_empty())
+        }
+        None => false,
+    }
+}
+
+/// Replace the assignees found in the map with their GitHub logins, eg. to
+/// assign the issues of `TODO(alice@example.com)` to `alice`.
+///
+/// ```rust
+/// use std::collections::BTreeMap;
+/// use todo_finder_lib::github::map_assignees;
+///
+/// let map = BTreeMap::from([("alice@example.com".to_string(), "alice".to_string())]);
+/// let assignees = vec!["alice@example.com".to_string(), "bob".to_string()];
+/// assert_eq!(map_assignees(&assignees, &map), vec!["alice", "bob"]);
+/// ```
+pub fn map_assignees(assignees: &[String], assignee_map: &BTreeMap<String, String>) -> Vec<String> {
+    assignees
+        .iter()
+        .map(|assignee| assignee_map.get(assignee).unwrap_or(assignee).clone())
+        .collect()
+}
+
+/// Split assignees into users, who can be assigned to an issue, and teams and
+/// email addresses, who can only be mentioned in it.
+pub fn users_and_mentions(assignees: &[String]) -> (Vec<&str>, Vec<&str>) {
+    assignees
+        .iter()
+        .map(|s| s.as_str())
+        .partition(|assignee| !is_team(assignee) && !is_email(assignee))
+}
+
+/// The users to assign a new issue to. An issue with no assignees at all is
+/// assigned to the given login, if any.
+pub fn users_or_self<'a>(
+    users: Vec<&'a str>,
+    mentions: &[&str],
+    self_login: Option<&'a str>,
+) -> Vec<&'a str> {
+    match self_login {
+        Some(login) if users.is_empty() && mentions.is_empty() => vec![login],
+        _ => users,
+    }
+}
+
+/// Mention the given teams and email addresses at the top of an issue body,
+/// inside the part of it that syncs write, so the mentions change along with
+/// the assignees.
+pub fn mention(body: String, mentions: &[&str]) -> String {
+    if mentions.is_empty() {
+        body
+    } else {
+        let mentions = mentions
+            .iter()
+            .map(|mention| {
+                if is_email(mention) {
+                    mention.to_string()
+                } else {
+                    format!("@{}", mention)
+                }
+            })
+            .collect::<Vec<_>>();
+        match body.strip_prefix(MANAGED_START) {
+            Some(rest) => format!("{}\ncc {}{}", MANAGED_START, mentions.join(" "), rest),
+            None => format!("cc {}\n{}", mentions.join(" "), body),
+        }
+    }
+}
+
+/// The labels of an issue: the configured issue label followed by the
+/// todo's hashtags and its priority, if any, like `priority:1`.
+pub fn issue_labels(issue_label: &str, tags: &[String], priority: Option<u8>) -> Vec<String> {
+    let mut labels = vec![issue_label.to_string()];
+    for tag in tags.iter() {
+        if !labels.contains(tag) {
+            labels.push(tag.clone());
+        }
+    }
+    if let Some(priority) = priority {
+        labels.push(format!("priority:{}", priority));
+    }
+    labels
+}
+
+/// git config --get remote.origin.url
+///
+/// The result is cached per directory, see `utils::cached_command`.
+pub fn git_origin(dir: &str) -> Result<String, String> {
+    cached_command("git", &["config", "--get", "remote.origin.url"], dir)
+        .map_err(|e| format!("could not determine the git origin: {}", e))
 }
 
 /// git rev-parse HEAD
-pub fn git_hash() -> Result<String, String> {
-    let output = Command::new("git")
-        .arg("rev-parse")
-        .arg("HEAD")
-        .output()
-        .map_err(|e| format!("could not run git rev-parse HEAD: {}", e))?;
+///
+/// The result is cached per directory, see `utils::cached_command`. If HEAD
+/// moves, the old hash may be returned for up to `utils::COMMAND_CACHE_TTL`.
+pub fn git_hash(dir: &str) -> Result<String, String> {
+    cached_command("git", &["rev-parse", "HEAD"], dir)
+        .map_err(|e| format!("could not determine the git hash: {}", e))
+}
 
-    if !output.status.success() {
-        return Err("git rev-parse HEAD erred".into());
+/// Warn with a `DirtyWorkingTree` if there are uncommitted changes at the
+/// given path, as links to the HEAD checkout may not show the todos found in
+/// them, or refuse with an error if `require_clean`.
+pub fn check_working_tree(dir: &str, require_clean: bool) -> Result<(), String> {
+    let changed_files = FileSearcher::git_changed_files(dir)?;
+    if changed_files.is_empty() {
+        return Ok(());
     }
+    let message = Message::DirtyWorkingTree { changed_files };
+    if require_clean {
+        return Err(format!(
+            "refusing to link to a dirty working tree, commit or stash the changes first\n{}",
+            message
+        ));
+    }
+    message.send();
+    Ok(())
+}
 
-    let s: String = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    Ok(s)
+/// Fetch the open issues, only those with the label if one is given, from
+/// every page of the listing.
+async fn fetch_github_issues(
+    cfg: &GitHubConfig,
+    label: Option<&str>,
+) -> Result<Vec<GitHubIssue>, String> {
+    let url = github_open_issues_url(&cfg.api_url(), &cfg.owner, &cfg.repo, label);
+    eprintln!("  {}", url);
+    get_all_pages(cfg, url, "fetching github issues").await
 }
 
 async fn get_github_issues(
     cfg: &GitHubConfig,
 ) -> Result<IssueMap<u64, GitHubTodoLocation>, String> {
-    let url = github_issues_url(&cfg.owner, &cfg.repo);
-    println!("  {}", url);
-    let req = github_req(
-        cfg,
-        "GET",
-        &url,
-        json!({
-          "labels": vec![&cfg.issue_label],
-          "state": "open"
-        }),
-    )?;
-
-    let https = HttpsConnector::new();
-    let client = Client::builder().build::<_, hyper::Body>(https);
-
-    let res = client
-        .request(req)
-        .await
-        .map_err(|e| format!("error fetching github issues: {}", e))?;
-    let github_issues: Vec<GitHubIssue> = get_json_response(res).await?;
-    let mut issues = IssueMap::new_github_todos();
+    let github_issues = fetch_github_issues(cfg, Some(&cfg.issue_label)).await?;
+    let mut issues = IssueMap::new_github_todos().with_key(cfg.options.todo_key.clone());
     for issue in github_issues.iter() {
         issues.add_issue(issue);
     }
@@ -177,112 +795,580 @@ async fn get_json_response<T: DeserializeOwned>(mut res: Response<Body>) -> Resu
     })
 }
 
-async fn apply_patch(cfg: &GitHubConfig, patch: GitHubPatch) -> Result<(), String> {
+/// Send a request to GitHub, returning its JSON response.
+async fn send_github_req<T: DeserializeOwned>(
+    req: Request<Body>,
+    doing: &str,
+) -> Result<T, String> {
     let https = HttpsConnector::new();
     let client = Client::builder().build::<_, hyper::Body>(https);
-    let url = github_issues_url(&cfg.owner, &cfg.repo);
+    let res = client
+        .request(req)
+        .await
+        .map_err(|e| format!("error {}: {}", doing, e))?;
+    get_json_response(res).await
+}
 
-    // Create
-    println!("creating {} issues", patch.create.todos.len());
-    for (_, issue) in patch.create.todos.iter() {
-        let req = github_req(
-            &cfg,
-            "POST",
-            &url,
-            json!({
-              "title": issue.head.title,
-              "body": issue.body.to_github_string(
-                &cfg.root_project_dir,
-                &cfg.owner,
-                &cfg.repo,
-                &cfg.checkout_hash
-              )?,
-              "assignees": issue.head.assignees,
-              "labels": vec![&cfg.issue_label]
-            }),
-        )?;
-        let res: Response<Body> = client
+/// Send a GET request for each page of a listing, following the `Link`
+/// headers of the responses, see `next_page_url`, returning the items of
+/// every page.
+async fn get_all_pages<T: DeserializeOwned>(
+    cfg: &GitHubConfig,
+    url: String,
+    doing: &str,
+) -> Result<Vec<T>, String> {
+    let https = HttpsConnector::new();
+    let client = Client::builder().build::<_, hyper::Body>(https);
+    let mut items = vec![];
+    let mut next = Some(url);
+    while let Some(url) = next {
+        let req = github_req(cfg, "GET", &url, json!({}))?;
+        let res = client
             .request(req)
             .await
-            .map_err(|e| format!("error creating github issue: {}", e))?;
+            .map_err(|e| format!("error {}: {}", doing, e))?;
+        next = res
+            .headers()
+            .get("link")
+            .and_then(|link| link.to_str().ok())
+            .and_then(next_page_url);
+        let page: Vec<T> = get_json_response(res).await?;
+        items.extend(page);
+    }
+    Ok(items)
+}
+
+/// The login of the authenticated user, looked up once per run.
+async fn authenticated_login(cfg: &GitHubConfig) -> Result<String, String> {
+    if let Some(login) = cfg.login.get() {
+        return Ok(login.clone());
+    }
+    let req = github_req(cfg, "GET", &github_user_url(&cfg.api_url()), json!({}))?;
+    let user: GitHubUser = send_github_req(req, "getting the authenticated github user").await?;
+    println!("self-assigning to '{}'", user.login);
+    Ok(cfg.login.get_or_init(|| user.login).clone())
+}
+
+/// The node id of the project created issues are added to, looked up once per
+/// run, at the start of a sync.
+async fn project_id(cfg: &GitHubConfig, number: u64) -> Result<String, String> {
+    if let Some(id) = cfg.project_id.get() {
+        return Ok(id.clone());
+    }
+    let req = github_req(
+        cfg,
+        "POST",
+        &github_graphql_url(cfg.options.github_url()),
+        json!({
+            "query": PROJECT_ID_QUERY,
+            "variables": { "owner": cfg.owner, "number": number }
+        }),
+    )?;
+    let response: Value = send_github_req(req, "looking up the github project").await?;
+    let id = project_id_from_response(&cfg.owner, number, &response)?;
+    Ok(cfg.project_id.get_or_init(|| id).clone())
+}
 
-        let _val: Value = get_json_response(res).await?;
-        println!("created '{}':", issue.head.title);
-        //println!("{:#?}", val);
+/// The number of the milestone created issues are put in, looked up once per
+/// run, at the start of a sync.
+async fn milestone_number(cfg: &GitHubConfig, milestone: &str) -> Result<u64, String> {
+    if let Some(number) = cfg.milestone_number.get() {
+        return Ok(*number);
     }
+    let milestones: Vec<GitHubMilestone> = get_all_pages(
+        cfg,
+        github_milestones_url(&cfg.api_url(), &cfg.owner, &cfg.repo),
+        "getting the github milestones",
+    )
+    .await?;
+    let number = resolve_milestone(&milestones, milestone)
+        .map_err(|e| format!("{} in {}/{}", e, cfg.owner, cfg.repo))?;
+    Ok(*cfg.milestone_number.get_or_init(|| number))
+}
 
-    // Edit
-    println!("editing {} issues", patch.edit.todos.len());
-    for (_, issue) in patch.edit.todos.iter() {
-        println!("editing '{}'", issue.head.title);
-        let id = issue.head.external_id;
-        let body = issue
+/// Add the issue with the given node id to the configured project.
+async fn add_to_project(cfg: &GitHubConfig, number: u64, node_id: &str) -> Result<(), String> {
+    let project = project_id(cfg, number).await?;
+    let req = github_req(
+        cfg,
+        "POST",
+        &github_graphql_url(cfg.options.github_url()),
+        json!({
+            "query": ADD_TO_PROJECT_MUTATION,
+            "variables": { "project": project, "content": node_id }
+        }),
+    )?;
+    let response: Value = send_github_req(req, "adding an issue to the github project").await?;
+    project_item_from_response(&response).map(|_| ())
+}
+
+impl Forge for GitHubConfig {
+    type Id = u64;
+    type Location = GitHubTodoLocation;
+
+    async fn list_issues(&self) -> Result<IssueMap<u64, GitHubTodoLocation>, String> {
+        println!("Getting remote issues for {}/{}", self.owner, self.repo);
+        get_github_issues(self).await
+    }
+
+    async fn find_unmanaged_issues(
+        &self,
+        titles: &[&str],
+    ) -> Result<Vec<Issue<u64, GitHubTodoLocation>>, String> {
+        if !self.options.adopt_unlabeled || titles.is_empty() {
+            return Ok(vec![]);
+        }
+        println!("Looking for unlabeled issues to adopt");
+        let issues = fetch_github_issues(self, None).await?;
+        Ok(adoptable_issues(&issues, &self.issue_label, titles))
+    }
+
+    async fn create_issue(&self, issue: &Issue<(), FileTodoLocation>) -> Result<u64, String> {
+        let assignees = map_assignees(&issue.head.assignees, &self.options.assignee_map);
+        let (users, mentions) = users_and_mentions(&assignees);
+        let self_login = if self.options.self_assign && issue.head.assignees.is_empty() {
+            Some(authenticated_login(self).await?)
+        } else {
+            None
+        };
+        let users = users_or_self(users, &mentions, self_login.as_deref());
+        let body = self.write_body(&issue.body)?;
+        let body = mention(body, &mentions);
+        let milestone = match self.options.milestone.as_ref() {
+            Some(milestone) => Some(milestone_number(self, milestone).await?),
+            None => None,
+        };
+        let req = github_req(
+            self,
+            "POST",
+            &github_issues_url(&self.api_url(), &self.owner, &self.repo),
+            new_issue_json(
+                &issue.head.title,
+                &body,
+                &users,
+                &issue_labels(&self.issue_label, &issue.head.tags, issue.head.priority),
+                milestone,
+            ),
+        )?;
+        let created: Value = send_github_req(req, "creating github issue").await?;
+        let number = created
+            .get("number")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| format!("created github issue has no number: {}", created))?;
+        if let Some(project) = self.options.project {
+            let node_id = created
+                .get("node_id")
+                .and_then(Value::as_str)
+                .ok_or_else(|| format!("created github issue has no node id: {}", created))?;
+            add_to_project(self, project, node_id).await?;
+            println!("added #{} to project {}", number, project);
+        }
+        Ok(number)
+    }
+
+    async fn update_issue(&self, issue: &Issue<u64, FileTodoLocation>) -> Result<(), String> {
+        let fields = self.fields_of(issue)?;
+        // Anything added to the body around the todos by hand is kept
+        let current = self.issue_fields(issue.head.external_id).await?;
+        let body = keep_unmanaged_text(&current.body, &fields.body);
+        let print_body = fields
             .body
-            .to_github_string(
-                &cfg.root_project_dir,
-                &cfg.owner,
-                &cfg.repo,
-                &cfg.checkout_hash,
-            )
-            .map_err(|e| format!("could not convert issue body to description: {}", e))?;
-        let print_body = body
             .lines()
-            .map(|s| vec!["  ".into(), s].concat())
+            .map(|s| ["  ", s].concat())
             .collect::<Vec<_>>()
             .join("\n");
         println!("{}", print_body);
 
         let req = github_req(
-            &cfg,
+            self,
             "PATCH",
-            &github_issues_update_url(&cfg.owner, &cfg.repo, id),
+            &github_issues_update_url(
+                &self.api_url(),
+                &self.owner,
+                &self.repo,
+                issue.head.external_id,
+            ),
             json!({
-              "title": issue.head.title,
+              "title": fields.title,
               "body": body,
-              "assignees": issue.head.assignees,
-              "labels": vec![&cfg.issue_label]
+              "assignees": fields.assignees,
+              "labels": fields.labels
             }),
         )?;
-        let res: Response<Body> = client
-            .request(req)
-            .await
-            .map_err(|e| format!("error editing github issue: {}", e))?;
+        let _: Value = send_github_req(req, "editing github issue").await?;
+        Ok(())
+    }
+
+    async fn issue_fields(&self, id: u64) -> Result<IssueFields, String> {
+        let req = github_req(
+            self,
+            "GET",
+            &github_issues_update_url(&self.api_url(), &self.owner, &self.repo, id),
+            json!({}),
+        )?;
+        let issue: GitHubIssue =
+            send_github_req(req, &format!("getting github issue #{}", id)).await?;
+        Ok(IssueFields {
+            title: issue.title,
+            body: issue.body,
+            assignees: issue.assignees.into_iter().map(|a| a.login).collect(),
+            labels: issue.labels.into_iter().map(|l| l.name).collect(),
+        })
+    }
+
+    fn fields_of(&self, issue: &Issue<u64, FileTodoLocation>) -> Result<IssueFields, String> {
+        let body = self
+            .write_body(&issue.body)
+            .map_err(|e| format!("could not convert issue body to description: {}", e))?;
+        let assignees = map_assignees(&issue.head.assignees, &self.options.assignee_map);
+        let (users, mentions) = users_and_mentions(&assignees);
+        Ok(IssueFields {
+            title: issue.head.title.clone(),
+            body: mention(body, &mentions),
+            assignees: users.into_iter().map(String::from).collect(),
+            labels: issue_labels(&self.issue_label, &issue.head.tags, issue.head.priority),
+        })
+    }
 
-        let _: Value = get_json_response(res).await?;
+    async fn close_issue(&self, id: u64) -> Result<Option<String>, String> {
+        close_github_issue(self, id, self.options.close_reason).await
     }
 
-    // Delete
-    println!("deleting {} issues", patch.delete.len());
-    for id in patch.delete.iter() {
+    async fn close_issue_as_not_planned(&self, id: u64) -> Result<Option<String>, String> {
+        close_github_issue(self, id, CloseReason::NotPlanned).await
+    }
+
+    async fn find_not_planned_issues(
+        &self,
+        titles: &[&str],
+    ) -> Result<Vec<Issue<u64, GitHubTodoLocation>>, String> {
+        if titles.is_empty() {
+            return Ok(vec![]);
+        }
+        println!("Looking for issues closed as not planned to reopen");
+        let url = github_closed_issues_url(
+            &self.api_url(),
+            &self.owner,
+            &self.repo,
+            Some(&self.issue_label),
+        );
+        println!("  {}", url);
+        let issues = get_all_pages(self, url, "fetching closed github issues").await?;
+        Ok(not_planned_issues(&issues, &self.issue_label, titles))
+    }
+
+    async fn reopen_issue(&self, id: u64) -> Result<(), String> {
         let req = github_req(
-            &cfg,
+            self,
             "PATCH",
-            &github_issues_update_url(&cfg.owner, &cfg.repo, *id),
-            json!({"state":"closed"}),
+            &github_issues_update_url(&self.api_url(), &self.owner, &self.repo, id),
+            json!({"state": "open"}),
         )?;
-        let res = client
-            .request(req)
-            .await
-            .map_err(|e| format!("error closing github issue: {}", e))?;
-
-        let json: Value = get_json_response(res).await?;
-        let title = json
-            .as_object()
-            .map(|obj| obj.get("title").map(|s| s.as_str()).flatten())
-            .flatten();
-        if let Some(title) = title {
-            println!("closed '{}'", title);
+        let _: Value = send_github_req(req, "reopening github issue").await?;
+        Ok(())
+    }
+
+    fn permalink(&self, loc: &FileTodoLocation) -> Result<String, String> {
+        self.linked_location(loc).to_github_link_with_style(
+            &self.root_project_dir,
+            &self.repo_url(),
+            &self.checkout_hash,
+            self.options.link_style,
+        )
+    }
+
+    fn issue_url(&self, id: u64) -> String {
+        format!("{}/issues/{}", self.repo_url(), id)
+    }
+
+    fn is_moved_todo(&self, remote: &GitHubTodoLocation, local: &FileTodoLocation) -> bool {
+        if !self.options.rename_detection || remote.src_span != self.linked_location(local).src_span
+        {
+            return false;
         }
+        let mut renames = self.renames.borrow_mut();
+        let renames = renames.entry(remote.checkout.clone()).or_insert_with(|| {
+            FileSearcher::git_renames(&self.root_project_dir, &remote.checkout).unwrap_or_else(
+                |e| {
+                    println!("could not detect renames since {}: {}", remote.checkout, e);
+                    HashMap::new()
+                },
+            )
+        });
+        renames.get(&remote.file).is_some_and(|file| {
+            Path::new(&self.root_project_dir).join(file) == Path::new(&local.file)
+        })
     }
 
+    fn min_title_similarity(&self) -> Option<f64> {
+        self.options.min_title_similarity
+    }
+
+    fn parse_body(&self, body: &str) -> Option<IssueBody<GitHubTodoLocation>> {
+        issue_body(body).ok().map(|(_, body)| body)
+    }
+
+    fn write_body(&self, body: &IssueBody<FileTodoLocation>) -> Result<String, String> {
+        body.to_github_string(
+            &self.root_project_dir,
+            &self.repo_url(),
+            &self.checkout_hash,
+            self.options.collapsible,
+            self.options.context_lines,
+        )
+    }
+}
+
+impl GitHubConfig {
+    /// The url of the REST API to sync with.
+    fn api_url(&self) -> String {
+        github_api_url(self.options.github_url())
+    }
+
+    /// The web url of the repo, which links to todos start with.
+    fn repo_url(&self) -> String {
+        github_repo_url(self.options.github_url(), &self.owner, &self.repo)
+    }
+
+    /// The location a link to a local todo points to, widened by the
+    /// context lines.
+    fn linked_location(&self, loc: &FileTodoLocation) -> FileTodoLocation {
+        loc.with_context_in_file(self.options.context_lines)
+    }
+
+    /// Find the open issue of the todo at a location, eg. the line of an
+    /// editor's cursor, returning its number and url. Only the location's
+    /// file is parsed, and the todo spanning its line is matched with the open
+    /// issues by title, or by the key of `GitHubOptions::todo_key`. Errors
+    /// are reported and count as no issue, see `find_issue_for_location` to
+    /// tell them apart.
+    pub async fn lookup_issue_for_location(&self, loc: &FileTodoLocation) -> Option<(u64, String)> {
+        self.find_issue_for_location(loc).await.unwrap_or_else(|e| {
+            eprintln!(
+                "could not look up the issue of {}:{}: {}",
+                loc.file, loc.src_span.0, e
+            );
+            None
+        })
+    }
+
+    /// Like `lookup_issue_for_location`, but returning errors, eg. from
+    /// reading the file or listing the issues, instead of reporting them.
+    pub async fn find_issue_for_location(
+        &self,
+        loc: &FileTodoLocation,
+    ) -> Result<Option<(u64, String)>, String> {
+        let path = Path::new(&self.root_project_dir).join(&loc.file);
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let options = ScanOptions {
+            todo_key: self.options.todo_key.clone(),
+            ..ScanOptions::default()
+        };
+        let lines = FileSearcher::find_in_contents(&contents, &options.search_tags());
+        let local = IssueMap::from_possible_todos_in_directory(
+            &self.root_project_dir,
+            vec![PossibleTodosInFile::new(&path.display().to_string(), lines)],
+            &options,
+        )?;
+        let line = loc.src_span.0;
+        let key = local.todos.iter().find_map(|(key, issue)| {
+            issue
+                .body
+                .descs_and_srcs
+                .iter()
+                .any(|(_, todo_loc)| {
+                    (todo_loc.src_span.0..=todo_loc.src_span.1.unwrap_or(todo_loc.src_span.0))
+                        .contains(&line)
+                })
+                .then_some(key)
+        });
+        let key = match key {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        let remote = get_github_issues(self).await?;
+        Ok(remote.todos.get(key).map(|issue| {
+            let id = issue.head.external_id;
+            (id, self.issue_url(id))
+        }))
+    }
+}
+
+/// Close an issue for the given reason, returning its title if known.
+async fn close_github_issue(
+    cfg: &GitHubConfig,
+    id: u64,
+    reason: CloseReason,
+) -> Result<Option<String>, String> {
+    let req = github_req(
+        cfg,
+        "PATCH",
+        &github_issues_update_url(&cfg.api_url(), &cfg.owner, &cfg.repo, id),
+        json!({"state": "closed", "state_reason": reason.state_reason()}),
+    )?;
+    let doing = format!("closing github issue as {}", reason.state_reason());
+    let json: Value = send_github_req(req, &doing).await?;
+    Ok(json
+        .get("title")
+        .and_then(Value::as_str)
+        .map(|s| s.to_string()))
+}
+
+/// The config of a sync with the GitHub repo that the git origin of `cwd`
+/// points to, at its HEAD checkout.
+pub fn github_config(
+    auth_token: String,
+    issue_label: String,
+    cwd: String,
+    options: GitHubOptions,
+) -> Result<GitHubConfig, String> {
+    let origin = git_origin(&cwd)?;
+    eprintln!("origin: {}", origin);
+    let (owner, repo) = parse_owner_and_repo_from_config(&origin)
+        .map_err(|_| "could not parse owner/repo from git config".to_string())?
+        .1;
+    eprintln!("owner: '{}', repo: '{}'", owner, repo);
+    let checkout_hash = git_hash(&cwd)?;
+    Ok(GitHubConfig {
+        issue_label,
+        auth_token,
+        _search_in_directory: None,
+        owner: owner.into(),
+        repo: repo.into(),
+        checkout_hash,
+        root_project_dir: cwd,
+        options,
+        login: OnceLock::new(),
+        renames: RefCell::new(HashMap::new()),
+        project_id: OnceLock::new(),
+        milestone_number: OnceLock::new(),
+    })
+}
+
+/// Sync all local TODOs into the task list of one tracking issue with the
+/// given title, creating the issue if need be.
+pub async fn run_tracker_github(
+    auth_token: String,
+    issue_label: String,
+    cwd: String,
+    tracker_title: String,
+    local_issues: IssueMap<(), FileTodoLocation>,
+    github_url: Option<String>,
+) -> Result<(), String> {
+    let options = GitHubOptions {
+        github_url,
+        ..GitHubOptions::default()
+    };
+    let cfg = github_config(auth_token, issue_label, cwd, options)?;
+    check_working_tree(&cfg.root_project_dir, false)?;
+
+    println!("Getting tracker issue '{}'", tracker_title);
+    let tracker = fetch_github_issues(&cfg, Some(&cfg.issue_label))
+        .await?
+        .into_iter()
+        .find(|issue| issue.title == tracker_title);
+    let mut checklist = tracker
+        .as_ref()
+        .map(|issue| TrackerChecklist::parse(&issue.body))
+        .unwrap_or_default();
+
+    let patch = checklist.as_issue_map().prepare_patch(local_issues);
+    println!(
+        "adding {}, updating {} and checking off {} tracker items",
+        patch.create.todos.len(),
+        patch.edit.todos.len(),
+        patch.delete.len()
+    );
+    checklist.apply_patch(patch, |loc| {
+        loc.to_github_link_with_style(
+            &cfg.root_project_dir,
+            &cfg.repo_url(),
+            &cfg.checkout_hash,
+            cfg.options.link_style,
+        )
+    })?;
+
+    let body = json!({
+      "title": tracker_title,
+      "body": checklist.as_github_string(),
+      "labels": vec![&cfg.issue_label]
+    });
+    let req = match tracker.as_ref() {
+        Some(issue) => github_req(
+            &cfg,
+            "PATCH",
+            &github_issues_update_url(&cfg.api_url(), &cfg.owner, &cfg.repo, issue.number),
+            body,
+        )?,
+        None => github_req(
+            &cfg,
+            "POST",
+            &github_issues_url(&cfg.api_url(), &cfg.owner, &cfg.repo),
+            body,
+        )?,
+    };
+
+    let https = HttpsConnector::new();
+    let client = Client::builder().build::<_, hyper::Body>(https);
+    let res = client
+        .request(req)
+        .await
+        .map_err(|e| format!("error syncing tracker issue: {}", e))?;
+    let _: Value = get_json_response(res).await?;
+    println!(
+        "{} '{}'",
+        if tracker.is_some() {
+            "updated"
+        } else {
+            "created"
+        },
+        tracker_title
+    );
+
     Ok(())
 }
 
+/// Look up every issue referenced by the local todos, like `TODO(#123): ...`,
+/// returning a `StaleReference` for each todo that references a closed one.
+pub async fn run_stale_refs_github(
+    auth_token: String,
+    cwd: String,
+    local_issues: &IssueMap<(), FileTodoLocation>,
+    github_url: Option<String>,
+) -> Result<Vec<Message>, String> {
+    let options = GitHubOptions {
+        github_url,
+        ..GitHubOptions::default()
+    };
+    let cfg = github_config(auth_token, String::new(), cwd, options)?;
+    let refs = local_issues.referenced_issues();
+    eprintln!("Checking {} referenced issues", refs.len());
+    let mut issues = vec![];
+    for number in refs.keys() {
+        let req = github_req(
+            &cfg,
+            "GET",
+            &github_issues_update_url(&cfg.api_url(), &cfg.owner, &cfg.repo, *number),
+            json!({}),
+        )?;
+        let response: Value =
+            send_github_req(req, &format!("getting referenced issue #{}", number)).await?;
+        // Numbers that aren't issues here come back as an error message
+        match serde_json::from_value::<GitHubIssue>(response) {
+            Ok(issue) => issues.push(issue),
+            Err(_) => eprintln!("could not find referenced issue #{}, skipping it", number),
+        }
+    }
+    Ok(stale_references(&cfg.root_project_dir, &refs, &issues))
+}
+
 pub async fn run_ts_github(
     auth_token: String,
     issue_label: String,
     cwd: String,
-    excludes: &Vec<String>,
+    options: GitHubOptions,
+    local_issues: IssueMap<(), FileTodoLocation>,
 ) -> Result<(), String> {
     //let path = Path::new(config_path_str);
     //let mut file: File = File::open(path).expect("could not open config file");
@@ -294,39 +1380,654 @@ pub async fn run_ts_github(
     //let config: ConfigFile = serde_yaml::from_str(&contents)
     //  .map_err(|e| format!("could not read config: {}", e))?;
 
-    let origin = git_origin()?;
-    println!("origin: {}", origin);
-    let (owner, repo) = parse_owner_and_repo_from_config(&origin)
-        .map_err(|_| "could not parse owner/repo from git config".to_string())?
-        .1;
-    println!("owner: '{}', repo: '{}'", owner, repo);
-    let checkout_hash = git_hash()?;
-    let local_issues = IssueMap::from_files_in_directory(&cwd, excludes).unwrap();
+    let cfg = github_config(auth_token, issue_label, cwd, options)?;
+    check_working_tree(&cfg.root_project_dir, cfg.options.require_clean)?;
+    // Before any issue is created, so a bad project or milestone doesn't
+    // stop the sync halfway through
+    if let Some(project) = cfg.options.project {
+        project_id(&cfg, project).await?;
+    }
+    if let Some(milestone) = cfg.options.milestone.as_ref() {
+        milestone_number(&cfg, milestone).await?;
+    }
     let num_issues = local_issues.distinct_len();
     if num_issues > 0 {
         println!("Found {} distinct local TODOs", num_issues);
     }
 
-    // Find the issues at the issue provider
-    let cfg = GitHubConfig {
-        issue_label,
-        auth_token,
-        _search_in_directory: None,
-        owner: owner.into(),
-        repo: repo.into(),
-        checkout_hash,
-        root_project_dir: cwd,
+    let max_operations = cfg.options.max_operations;
+    let log = match cfg.options.sync_log.as_ref() {
+        Some(path) => SyncLog::open(path)?,
+        None => SyncLog::default(),
+    };
+    sync_issues_logged(&cfg, local_issues, max_operations, log).await
+}
+
+/// Find the open GitHub issue of the todo at a location, eg. for an editor
+/// to jump to it, returning its number and url, see
+/// `GitHubConfig::find_issue_for_location`.
+pub async fn run_lookup_issue_github(
+    auth_token: String,
+    issue_label: String,
+    cwd: String,
+    options: GitHubOptions,
+    loc: &FileTodoLocation,
+) -> Result<Option<(u64, String)>, String> {
+    let cfg = github_config(auth_token, issue_label, cwd, options)?;
+    cfg.find_issue_for_location(loc).await
+}
+
+/// Print what syncing with GitHub would do without doing it, showing the
+/// field changes of each issue that would be edited, in color if `color`.
+pub async fn run_dry_run_diff_github(
+    auth_token: String,
+    issue_label: String,
+    cwd: String,
+    options: GitHubOptions,
+    local_issues: IssueMap<(), FileTodoLocation>,
+    color: bool,
+) -> Result<(), String> {
+    let cfg = github_config(auth_token, issue_label, cwd, options)?;
+    check_working_tree(&cfg.root_project_dir, cfg.options.require_clean)?;
+    let (patch, edits) = preview_sync(&cfg, local_issues).await?;
+    println!(
+        "would create {}, edit {}, close {} and reopen {} issues",
+        patch.create.todos.len(),
+        edits.len(),
+        patch.delete.len() + patch.wontfix.len(),
+        patch.reopen.len()
+    );
+    let mut creates = patch
+        .create
+        .todos
+        .values()
+        .map(|issue| &issue.head.title)
+        .collect::<Vec<_>>();
+    creates.sort();
+    for title in creates.into_iter() {
+        println!("+ '{}'", title);
+    }
+    for edit in edits.iter() {
+        println!("~ #{} '{}'", edit.id, edit.title);
+        for change in edit.changes.iter() {
+            for line in change.render(color).lines() {
+                println!("  {}", line);
+            }
+        }
+    }
+    for id in patch.delete.iter() {
+        println!("- #{}", id);
+    }
+    for id in patch.wontfix.iter() {
+        println!("- #{} as not planned", id);
+    }
+    for id in patch.reopen.iter() {
+        println!("+ #{} reopened", id);
+    }
+    Ok(())
+}
+
+/// How long to wait between the requests of a link check, so GitHub doesn't
+/// rate limit it.
+pub const LINK_CHECK_DELAY: Duration = Duration::from_millis(250);
+
+/// At most `n` of the items, spread evenly from first to last.
+///
+/// ```rust
+/// use todo_finder_lib::github::sample_evenly;
+///
+/// assert_eq!(sample_evenly(&[1, 2, 3, 4, 5, 6], 3), vec![1, 3, 5]);
+/// assert_eq!(sample_evenly(&[1, 2], 3), vec![1, 2]);
+/// assert!(sample_evenly(&[1, 2], 0).is_empty());
+/// ```
+pub fn sample_evenly<T: Clone>(items: &[T], n: usize) -> Vec<T> {
+    if n >= items.len() {
+        return items.to_vec();
+    }
+    (0..n).map(|i| items[i * items.len() / n].clone()).collect()
+}
+
+/// HEAD-request each link to a todo, waiting `delay` between requests, and
+/// report each that doesn't resolve. Redirects count as resolving.
+pub async fn check_links(links: &[(FileTodoLocation, String)], delay: Duration) -> Vec<Message> {
+    let https = HttpsConnector::new();
+    let client = Client::builder().build::<_, hyper::Body>(https);
+    let mut broken = vec![];
+    for (i, (loc, link)) in links.iter().enumerate() {
+        if i > 0 {
+            tokio::time::delay_for(delay).await;
+        }
+        let req = Request::builder()
+            .method("HEAD")
+            .uri(link)
+            .header("User-Agent", "todo_finder")
+            .body(Body::empty());
+        let status = match req {
+            Err(e) => Some(e.to_string()),
+            Ok(req) => match client.request(req).await {
+                Ok(res) if res.status().is_success() || res.status().is_redirection() => None,
+                Ok(res) => Some(res.status().to_string()),
+                Err(e) => Some(e.to_string()),
+            },
+        };
+        if let Some(status) = status {
+            broken.push(Message::BrokenLink {
+                path: loc.file.clone(),
+                line: loc.src_span.0,
+                link: link.clone(),
+                status,
+            });
+        }
+    }
+    broken
+}
+
+/// Check that the permalinks an issue sync would write resolve, or only an
+/// even sample of `sample` of them, returning how many were checked and the
+/// broken ones. This catches links to commits that weren't pushed. The links
+/// are made with the options of the sync, eg. its `github_url` and
+/// `context_lines`.
+pub async fn run_link_check_github(
+    cwd: String,
+    local_issues: &IssueMap<(), FileTodoLocation>,
+    sample: Option<usize>,
+    options: GitHubOptions,
+) -> Result<(usize, Vec<Message>), String> {
+    // Only full permalinks can be fetched
+    let options = GitHubOptions {
+        link_style: LinkStyle::Absolute,
+        ..options
     };
+    let cfg = github_config(String::new(), String::new(), cwd, options)?;
+    check_working_tree(&cfg.root_project_dir, false)?;
+    let mut issues = local_issues.todos.values().collect::<Vec<_>>();
+    issues.sort_by(|a, b| a.head.title.cmp(&b.head.title));
+    let mut links = vec![];
+    for issue in issues.into_iter() {
+        for (_, loc) in issue.body.descs_and_srcs.iter() {
+            links.push((loc.clone(), cfg.permalink(loc)?));
+        }
+    }
+    if let Some(n) = sample {
+        links = sample_evenly(&links, n);
+    }
+    eprintln!("Checking {} permalinks", links.len());
+    Ok((links.len(), check_links(&links, LINK_CHECK_DELAY).await))
+}
 
-    println!("Getting remote issues for {}/{}", owner, repo);
-    let remote_issues = get_github_issues(&cfg).await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        forge::with_managed_markers,
+        parser::{issue::issue_body, source::TodoParserConfig},
+    };
 
-    let patch = remote_issues.prepare_patch(local_issues);
+    /// Serve a mock GitHub with the JSON of each path and query, each linked
+    /// to the next one like the pages of a listing, returning its web url.
+    fn serve_pages(pages: Vec<(&'static str, &'static str)>) -> String {
+        use hyper::{
+            service::{make_service_fn, service_fn},
+            Server,
+        };
 
-    println!("Patching remote issues");
-    apply_patch(&cfg, patch).await?;
+        let make_service = make_service_fn(move |_| {
+            let pages = pages.clone();
+            async move {
+                Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
+                    let found = pages.iter().position(|(path, _)| {
+                        req.uri().path_and_query().map(|pq| pq.as_str()) == Some(*path)
+                    });
+                    let host = req.headers()["host"].to_str().unwrap().to_string();
+                    let mut res = Response::builder();
+                    if let Some(next) = found.and_then(|n| pages.get(n + 1)) {
+                        res = res
+                            .header("Link", format!("<http://{}{}>; rel=\"next\"", host, next.0));
+                    }
+                    let res = match found {
+                        Some(n) => res.status(200).body(Body::from(pages[n].1)),
+                        None => res.status(404).body(Body::from("{}")),
+                    };
+                    async move { res }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        url
+    }
 
-    Ok(())
+    /// The config of a sync of schell/repo with the GitHub at the url.
+    fn config_at(github_url: String) -> GitHubConfig {
+        GitHubConfig {
+            issue_label: "todo".into(),
+            auth_token: "token".into(),
+            _search_in_directory: None,
+            owner: "schell".into(),
+            repo: "repo".into(),
+            checkout_hash: "abc123".into(),
+            root_project_dir: ".".into(),
+            options: GitHubOptions {
+                github_url: Some(github_url),
+                ..GitHubOptions::default()
+            },
+            login: OnceLock::new(),
+            renames: RefCell::new(HashMap::new()),
+            project_id: OnceLock::new(),
+            milestone_number: OnceLock::new(),
+        }
+    }
+
+    #[test]
+    fn teams_are_mentioned_not_assigned() {
+        let assignees = vec![
+            "schell".to_string(),
+            "org/backend".to_string(),
+            "mitchellwrosen".to_string(),
+        ];
+        let (users, teams) = users_and_mentions(&assignees);
+        assert_eq!(users, vec!["schell", "mitchellwrosen"]);
+        assert_eq!(teams, vec!["org/backend"]);
+
+        let body = "Some description.\nhttps://github.com/schell/repo/blob/abc/src/lib.rs#L3";
+        let mentioned = mention(body.to_string(), &teams);
+        assert_eq!(
+            mentioned,
+            "cc @org/backend\nSome description.\nhttps://github.com/schell/repo/blob/abc/src/lib.rs#L3"
+        );
+        // The issue body can still be parsed back
+        assert!(issue_body(&mentioned).is_ok());
+
+        // Mentions go inside the part of a body that syncs write
+        let marked = mention(with_managed_markers(body), &teams);
+        assert_eq!(marked, with_managed_markers(&mentioned));
+        assert_eq!(issue_body(&marked), issue_body(&mentioned));
+    }
+
+    #[test]
+    fn email_assignees_are_mapped_or_mentioned() {
+        let parser = crate::parser::source::parse_todo(TodoParserConfig {
+            singles: vec!["//".into()],
+            ..TodoParserConfig::new()
+        });
+        let (_, todo) = parser("// TODO(alice@example.com): Do it.\n").unwrap();
+        assert_eq!(todo.assignee, Some("alice@example.com"));
+
+        let assignees = vec![
+            "alice@example.com".to_string(),
+            "bob@example.com".to_string(),
+            "org/backend".to_string(),
+            "carol".to_string(),
+        ];
+        let map = BTreeMap::from([("alice@example.com".to_string(), "alice".to_string())]);
+        let assignees = map_assignees(&assignees, &map);
+        let (users, mentions) = users_and_mentions(&assignees);
+        assert_eq!(users, vec!["alice", "carol"]);
+        assert_eq!(mentions, vec!["bob@example.com", "org/backend"]);
+        assert_eq!(
+            mention("Do it.".into(), &mentions),
+            "cc bob@example.com @org/backend\nDo it."
+        );
+        // An issue assigned by email only isn't assigned to its creator
+        assert!(users_or_self(vec![], &["bob@example.com"], Some("schell")).is_empty());
+    }
+
+    #[test]
+    fn users_only_are_assigned() {
+        let assignees = vec!["schell".to_string()];
+        let (users, teams) = users_and_mentions(&assignees);
+        assert_eq!(users, vec!["schell"]);
+        assert!(teams.is_empty());
+        assert_eq!(mention("body".into(), &teams), "body");
+    }
+
+    #[test]
+    fn unlabeled_issues_with_todo_titles_are_adoptable() {
+        let issue = |number: u64, title: &str, label: &str, pr: bool| {
+            let mut issue = json!({
+                "id": number,
+                "number": number,
+                "title": title,
+                "body": null,
+                "state": "open",
+                "labels": [{"id": 1, "name": label, "description": null}],
+                "assignees": [],
+                "user": {"login": "schell"}
+            });
+            if pr {
+                issue["pull_request"] = json!({});
+            }
+            serde_json::from_value::<GitHubIssue>(issue).unwrap()
+        };
+        let issues = vec![
+            issue(1, "Do A.", "bug", false),
+            issue(2, "Do B.", "todo", false),
+            issue(3, "Do C.", "bug", true),
+            issue(4, "Something else.", "bug", false),
+        ];
+
+        let adopted = adoptable_issues(&issues, "todo", &["Do A.", "Do B.", "Do C."]);
+        assert_eq!(
+            adopted
+                .iter()
+                .map(|issue| (issue.head.external_id, issue.head.title.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(1, "Do A.")]
+        );
+        assert!(adopted[0].body.descs_and_srcs.is_empty());
+
+        let closed = |number: u64, title: &str, reason: &str| {
+            let mut issue = issue(number, title, "todo", false);
+            issue.state = "closed".into();
+            issue.state_reason = Some(reason.into());
+            issue
+        };
+        let issues = vec![
+            closed(5, "Do A.", "not_planned"),
+            closed(6, "Do B.", "completed"),
+            closed(7, "Something else.", "not_planned"),
+        ];
+        let reopened = not_planned_issues(&issues, "TODO", &["Do A.", "Do B."]);
+        assert_eq!(
+            reopened
+                .iter()
+                .map(|issue| issue.head.external_id)
+                .collect::<Vec<_>>(),
+            vec![5]
+        );
+    }
+
+    #[tokio::test]
+    async fn unassigned_issues_can_be_self_assigned() {
+        let cfg = config_at(serve_pages(vec![(
+            "/api/v3/user",
+            r#"{"login": "schell", "id": 1, "type": "User", "site_admin": false}"#,
+        )]));
+        let login = authenticated_login(&cfg).await.unwrap();
+        assert_eq!(cfg.login.get(), Some(&login));
+        let login = Some(login.as_str());
+
+        assert_eq!(users_or_self(vec![], &[], login), vec!["schell"]);
+        assert_eq!(
+            users_or_self(vec!["mitchellwrosen"], &[], login),
+            vec!["mitchellwrosen"]
+        );
+        // An issue for a team is not orphaned
+        assert!(users_or_self(vec![], &["org/backend"], login).is_empty());
+        assert!(users_or_self(vec![], &[], None).is_empty());
+    }
+
+    #[tokio::test]
+    async fn broken_links_are_reported() {
+        use hyper::{
+            service::{make_service_fn, service_fn},
+            Server,
+        };
+
+        // A 404 for /missing and for anything but a HEAD
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, hyper::Error>(service_fn(|req: Request<Body>| async move {
+                let status = if req.method() == "HEAD" && req.uri().path() != "/missing" {
+                    200
+                } else {
+                    404
+                };
+                Response::builder().status(status).body(Body::empty())
+            }))
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let at = |line, path: &str| {
+            (
+                FileTodoLocation {
+                    file: "src/lib.rs".into(),
+                    src_span: (line, None),
+                },
+                format!("http://{}{}", addr, path),
+            )
+        };
+        let links = vec![at(1, "/found"), at(2, "/missing"), at(3, "/found")];
+        assert_eq!(
+            check_links(&links, Duration::from_millis(1)).await,
+            vec![Message::BrokenLink {
+                path: "src/lib.rs".into(),
+                line: 2,
+                link: format!("http://{}/missing", addr),
+                status: "404 Not Found".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn enterprise_urls_are_built_from_the_github_url() {
+        let options = GitHubOptions::default();
+        assert_eq!(options.github_url(), "https://github.com");
+        assert_eq!(
+            github_issues_url(&github_api_url(options.github_url()), "schell", "repo"),
+            "https://api.github.com/repos/schell/repo/issues"
+        );
+
+        let options = GitHubOptions {
+            github_url: Some("https://github.example.com".into()),
+            ..GitHubOptions::default()
+        };
+        let api = github_api_url(options.github_url());
+        assert_eq!(
+            github_closed_issues_url(&api, "schell", "repo", Some("todo")),
+            "https://github.example.com/api/v3/repos/schell/repo/issues?state=closed&per_page=100&\
+             labels=todo"
+        );
+        assert_eq!(
+            github_user_url(&api),
+            "https://github.example.com/api/v3/user"
+        );
+        let loc = FileTodoLocation {
+            file: "/repo/src/lib.rs".into(),
+            src_span: (3, None),
+        };
+        let repo_url = github_repo_url(options.github_url(), "schell", "repo");
+        let link = loc.to_github_link("/repo", &repo_url, "abc").unwrap();
+        assert_eq!(
+            link,
+            "https://github.example.com/schell/repo/blob/abc/src/lib.rs#L3"
+        );
+        // Issue bodies with enterprise links can be parsed back
+        let (_, body) = issue_body(&format!("Do it.\n{}\n", link)).unwrap();
+        assert_eq!(body.descs_and_srcs[0].1.file, "src/lib.rs");
+    }
+
+    #[test]
+    fn permalinks_are_widened_by_the_context_lines() {
+        let mut cfg = config_at(GITHUB_URL.into());
+        cfg.options.context_lines = 2;
+        let loc = FileTodoLocation {
+            file: "./src/missing.rs".into(),
+            src_span: (10, None),
+        };
+        assert_eq!(
+            cfg.permalink(&loc),
+            Ok("https://github.com/schell/repo/blob/abc123/src/missing.rs#L8-L12".into())
+        );
+        cfg.options.link_style = LinkStyle::RepoRelative;
+        assert_eq!(
+            cfg.permalink(&loc),
+            Ok("../blob/abc123/src/missing.rs#L8-L12".into())
+        );
+    }
+
+    #[test]
+    fn created_issues_can_be_put_in_a_milestone() {
+        let milestones: Vec<GitHubMilestone> = serde_json::from_str(
+            r#"[
+                {"number": 3, "title": "v1.0", "state": "closed", "open_issues": 0},
+                {"number": 7, "title": "v1.1", "state": "open", "open_issues": 12}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(resolve_milestone(&milestones, "v1.1"), Ok(7));
+        assert_eq!(resolve_milestone(&milestones, "3"), Ok(3));
+        assert!(resolve_milestone(&milestones, "7.0").is_err());
+        assert_eq!(
+            resolve_milestone(&[], "v1.0"),
+            Err("no milestone 'v1.0', the repo has none".to_string())
+        );
+
+        let labels = vec!["todo".to_string()];
+        let issue = new_issue_json("Do it.", "body", &["schell"], &labels, Some(7));
+        assert_eq!(
+            issue,
+            json!({
+                "title": "Do it.",
+                "body": "body",
+                "assignees": ["schell"],
+                "labels": ["todo"],
+                "milestone": 7
+            })
+        );
+        let issue = new_issue_json("Do it.", "body", &[], &labels, None);
+        assert!(issue.get("milestone").is_none());
+    }
+
+    #[tokio::test]
+    async fn milestones_are_found_on_any_page() {
+        let cfg = config_at(serve_pages(vec![
+            (
+                "/api/v3/repos/schell/repo/milestones?state=all&per_page=100",
+                r#"[{"number": 3, "title": "v1.0"}]"#,
+            ),
+            (
+                "/api/v3/repos/schell/repo/milestones?state=all&per_page=100&page=2",
+                r#"[{"number": 7, "title": "v1.1"}]"#,
+            ),
+        ]));
+        assert_eq!(milestone_number(&cfg, "v1.1").await, Ok(7));
+
+        let cfg = config_at(cfg.options.github_url().to_string());
+        assert_eq!(
+            milestone_number(&cfg, "v2.0").await,
+            Err("no milestone 'v2.0', the milestones are: 'v1.0', 'v1.1' in schell/repo".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn not_planned_issues_are_found_on_any_page() {
+        let cfg = config_at(serve_pages(vec![
+            (
+                "/api/v3/repos/schell/repo/issues?state=closed&per_page=100&labels=todo",
+                r#"[{"id": 1, "number": 1, "title": "Do A.", "body": null,
+                     "state": "closed", "state_reason": "completed",
+                     "labels": [{"id": 1, "name": "todo", "description": null}],
+                     "assignees": [], "user": {"login": "schell"}}]"#,
+            ),
+            (
+                "/api/v3/repos/schell/repo/issues?state=closed&per_page=100&labels=todo&page=2",
+                r#"[{"id": 2, "number": 2, "title": "Do A.", "body": null,
+                     "state": "closed", "state_reason": "not_planned",
+                     "labels": [{"id": 1, "name": "Todo", "description": null}],
+                     "assignees": [], "user": {"login": "schell"}}]"#,
+            ),
+        ]));
+        let reopened = cfg.find_not_planned_issues(&["Do A."]).await.unwrap();
+        assert_eq!(
+            reopened
+                .iter()
+                .map(|issue| issue.head.external_id)
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
+    #[tokio::test]
+    async fn issues_are_listed_from_every_page() {
+        let cfg = config_at(serve_pages(vec![
+            (
+                "/api/v3/repos/schell/repo/issues?state=open&per_page=100&labels=todo",
+                r#"[{"id": 1, "number": 1, "title": "Do A.", "body": null,
+                     "state": "open",
+                     "labels": [{"id": 1, "name": "todo", "description": null}],
+                     "assignees": [], "user": {"login": "schell"}}]"#,
+            ),
+            (
+                "/api/v3/repos/schell/repo/issues?state=open&per_page=100&labels=todo&page=2",
+                r#"[{"id": 2, "number": 2, "title": "Do B.", "body": null,
+                     "state": "open",
+                     "labels": [{"id": 1, "name": "todo", "description": null}],
+                     "assignees": [], "user": {"login": "schell"}}]"#,
+            ),
+        ]));
+        let issues = fetch_github_issues(&cfg, Some("todo")).await.unwrap();
+        assert_eq!(
+            issues.iter().map(|issue| issue.number).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+    }
+
+    #[tokio::test]
+    async fn the_issue_of_a_todo_can_be_looked_up_by_location() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        std::fs::write(
+            dir.join("lib.rs"),
+            "// TODO: Do A.\nfn a() {}\n/* TODO: Do B.\n   Across lines. */\n// TODO: Do C.\n",
+        )
+        .unwrap();
+        let mut cfg = config_at(serve_pages(vec![(
+            "/api/v3/repos/schell/repo/issues?state=open&per_page=100&labels=todo",
+            r#"[{"id": 10, "number": 1, "title": "Do A.",
+                 "body": "\nhttps://github.com/schell/repo/blob/abc123/lib.rs#L1",
+                 "state": "open",
+                 "labels": [{"id": 1, "name": "todo", "description": null}],
+                 "assignees": [], "user": {"login": "schell"}},
+                {"id": 11, "number": 2, "title": "Do B.",
+                 "body": "Across lines.\nhttps://github.com/schell/repo/blob/abc123/lib.rs#L3-L4",
+                 "state": "open",
+                 "labels": [{"id": 1, "name": "todo", "description": null}],
+                 "assignees": [], "user": {"login": "schell"}}]"#,
+        )]));
+        cfg.root_project_dir = dir.display().to_string();
+        let repo_url = cfg.repo_url();
+        let at = |line: usize| FileTodoLocation {
+            file: "lib.rs".into(),
+            src_span: (line, None),
+        };
+
+        assert_eq!(
+            cfg.lookup_issue_for_location(&at(1)).await,
+            Some((1, format!("{}/issues/1", repo_url)))
+        );
+        // Anywhere in a todo's span
+        assert_eq!(
+            cfg.lookup_issue_for_location(&at(4)).await,
+            Some((2, format!("{}/issues/2", repo_url)))
+        );
+        // No todo there
+        assert_eq!(cfg.lookup_issue_for_location(&at(2)).await, None);
+        // A todo without an open issue
+        assert_eq!(cfg.lookup_issue_for_location(&at(5)).await, None);
+        // Failures are errors rather than no issue
+        let missing = FileTodoLocation {
+            file: "missing.rs".into(),
+            src_span: (1, None),
+        };
+        assert!(cfg.find_issue_for_location(&missing).await.is_err());
+        assert_eq!(cfg.lookup_issue_for_location(&missing).await, None);
+    }
+
+    #[test]
+    fn tags_become_labels() {
+        let tags = vec!["frontend".to_string(), "todo".to_string(), "p2".to_string()];
+        assert_eq!(
+            issue_labels("todo", &tags, None),
+            vec!["todo", "frontend", "p2"]
+        );
+        assert_eq!(
+            issue_labels("todo", &[], Some(1)),
+            vec!["todo", "priority:1"]
+        );
+    }
 }
 
 #[cfg(test)]
@@ -410,4 +2111,126 @@ mod regression {
     fn can_deserialize_github_issues() {
         serde_json::from_str::<Vec<GitHubIssue>>(GITHUB_ISSUE_TEXT).unwrap();
     }
+
+    #[test]
+    fn can_read_project_graphql_responses() {
+        let found = json!({"data": {"repositoryOwner": {"projectV2": {"id": "PVT_kwDOAB"}}}});
+        assert_eq!(
+            project_id_from_response("schell", 3, &found),
+            Ok("PVT_kwDOAB".to_string())
+        );
+
+        let not_found = json!({
+            "data": {"repositoryOwner": {"projectV2": null}},
+            "errors": [{
+                "type": "NOT_FOUND",
+                "path": ["repositoryOwner", "projectV2"],
+                "message": "Could not resolve to a ProjectV2 with the number 3."
+            }]
+        });
+        assert_eq!(
+            project_id_from_response("schell", 3, &not_found),
+            Err(
+                "error looking up project 3 of schell: not found: Could not resolve to a \
+                 ProjectV2 with the number 3."
+                    .to_string()
+            )
+        );
+        // A user or organization that doesn't exist has no owner at all
+        let no_owner = json!({"data": {"repositoryOwner": null}});
+        assert_eq!(
+            project_id_from_response("nobody", 3, &no_owner),
+            Err("error looking up project 3 of nobody: not found".to_string())
+        );
+
+        let added = json!({"data": {"addProjectV2ItemById": {"item": {"id": "PVTI_lADOAB"}}}});
+        assert_eq!(
+            project_item_from_response(&added),
+            Ok("PVTI_lADOAB".to_string())
+        );
+        let forbidden = json!({
+            "data": {"addProjectV2ItemById": null},
+            "errors": [{
+                "type": "INSUFFICIENT_SCOPES",
+                "message": "Your token has not been granted the required scopes."
+            }]
+        });
+        assert_eq!(
+            project_item_from_response(&forbidden),
+            Err(
+                "error adding an issue to the project: permission denied, the token needs the \
+                 'project' scope: Your token has not been granted the required scopes."
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn todos_referencing_closed_issues_are_stale() {
+        let mut local = IssueMap::new_source_todos();
+        for (title, assignee, reference, file, line) in [
+            (
+                "Remove the workaround.",
+                None,
+                Some(12),
+                "/repo/src/lib.rs",
+                3,
+            ),
+            (
+                "Remove the workaround.",
+                None,
+                Some(12),
+                "/repo/src/main.rs",
+                9,
+            ),
+            ("Wait on #13.", None, None, "/repo/src/lib.rs", 20),
+            ("Unrelated.", Some("schell"), None, "/repo/src/lib.rs", 30),
+        ] {
+            local.add_parsed_todo(
+                &crate::parser::source::ParsedTodo {
+                    assignee,
+                    reference,
+                    ..crate::parser::source::ParsedTodo::new(title)
+                },
+                FileTodoLocation {
+                    file: file.into(),
+                    src_span: (line, None),
+                },
+            );
+        }
+        let refs = local.referenced_issues();
+        assert_eq!(refs.keys().copied().collect::<Vec<_>>(), vec![12, 13]);
+
+        let response = |number: u64, state: &str| {
+            json!({
+                "id": 1000 + number,
+                "number": number,
+                "title": "An issue",
+                "body": null,
+                "state": state,
+                "labels": [],
+                "assignees": [],
+                "user": { "login": "schell" },
+            })
+        };
+        let issues = vec![response(12, "closed"), response(13, "open")]
+            .into_iter()
+            .map(|value| serde_json::from_value::<GitHubIssue>(value).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stale_references("/repo", &refs, &issues),
+            vec![
+                Message::StaleReference {
+                    path: "src/lib.rs".into(),
+                    line: 3,
+                    issue: 12,
+                },
+                Message::StaleReference {
+                    path: "src/main.rs".into(),
+                    line: 9,
+                    issue: 12,
+                },
+            ]
+        );
+    }
 }
@@ -1,6 +1,16 @@
+pub mod actions;
+pub mod baseline;
+pub mod cache;
+pub mod config;
 pub mod finder;
+pub mod forge;
 pub mod github;
+pub mod message;
 pub mod parser;
+pub mod schema;
+pub mod stats;
+pub mod tracker;
+pub mod utils;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,221 @@
+//! Messages reported to the user while finding and syncing TODOs.
+use std::{collections::BTreeMap, fmt};
+
+use super::{
+    parser::MalformedTodo,
+    stats::{AssigneeStats, DuplicateTodos},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    /// Who owns the most TODOs and how many are owned by nobody.
+    AssigneeStats(AssigneeStats),
+    /// TODOs found in more than one location.
+    DuplicateTodos(DuplicateTodos),
+    /// A file took too long to read and parse, so it was skipped.
+    FileTimedOut { path: String },
+    /// A file with possible TODOs turned out to be binary, so it was skipped.
+    BinaryFile { path: String },
+    /// A file with possible TODOs couldn't be read, eg. because it isn't
+    /// UTF-8, so it was skipped.
+    SkippedFile { path: String, reason: String },
+    /// An entry couldn't be read while walking the files to search, eg.
+    /// because of its permissions, so it was skipped.
+    UnreadableEntry { reason: String },
+    /// A possible TODO couldn't be parsed, eg. its comment never ends.
+    UnparsedTodo {
+        path: String,
+        line: usize,
+        reason: String,
+    },
+    /// TODOs that were found but look malformed, eg. with no title.
+    MalformedTodos { entries: Vec<MalformedTodo> },
+    /// TODOs with the same title have different assignees in different
+    /// locations, so they may be different tasks merged by accident.
+    ConflictingAssignees {
+        title: String,
+        path: String,
+        line: usize,
+        assignees: Vec<String>,
+        others: Vec<String>,
+    },
+    /// A TODO references an issue that has been closed, so it may be stale.
+    StaleReference {
+        path: String,
+        line: usize,
+        issue: u64,
+    },
+    /// A permalink to a TODO doesn't resolve, eg. because its commit wasn't
+    /// pushed.
+    BrokenLink {
+        path: String,
+        line: usize,
+        link: String,
+        status: String,
+    },
+    /// The titles of the TODOs a run would create, update and delete, eg.
+    /// in `todos.md`.
+    PreparedPatch {
+        create: Vec<String>,
+        update: Vec<String>,
+        delete: Vec<String>,
+    },
+    /// The working tree has uncommitted changes, so permalinks to the HEAD
+    /// checkout may not show the code the TODOs were found in.
+    DirtyWorkingTree { changed_files: Vec<String> },
+    /// A file with possible TODOs is in an unsupported language, so it was
+    /// skipped.
+    UnsupportedFile { path: String },
+    /// How many files with possible TODOs were skipped for being in an
+    /// unsupported language, by extension, eg. to pick which language to add.
+    UnsupportedSummary { counts: BTreeMap<String, usize> },
+    /// The share of the source files scanned that hold at least one TODO.
+    TodoCoverage {
+        files_with_todos: usize,
+        total_files: usize,
+        pct: f32,
+    },
+    /// ripgrep reported errors, eg. for a file it couldn't read, but went on
+    /// searching the rest.
+    RipgrepErrors { command: String, stderr: String },
+    /// The installed ripgrep is older than the oldest one known to work.
+    OldRipgrep {
+        version: (u32, u32, u32),
+        minimum: (u32, u32, u32),
+    },
+    /// ripgrep isn't installed, so files are searched with the built-in
+    /// walker instead.
+    RipgrepMissing,
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Message::AssigneeStats(stats) => write!(f, "{}", stats),
+            Message::DuplicateTodos(duplicates) => write!(f, "{}", duplicates),
+            Message::FileTimedOut { path } => {
+                write!(f, "timed out parsing {}, skipping it", path)
+            }
+            Message::BinaryFile { path } => write!(f, "skipping binary file {}", path),
+            Message::SkippedFile { path, reason } => {
+                write!(f, "skipping {}: {}", path, reason)
+            }
+            Message::UnreadableEntry { reason } => {
+                write!(f, "warning: skipping an unreadable entry: {}", reason)
+            }
+            Message::UnparsedTodo { path, line, reason } => {
+                write!(
+                    f,
+                    "could not parse the TODO at {}:{}: {}",
+                    path, line, reason
+                )
+            }
+            Message::MalformedTodos { entries } => {
+                write!(f, "TODOs that look malformed:")?;
+                for todo in entries.iter() {
+                    write!(f, "\n  {}:{}: {}", todo.path, todo.line, todo.reason)?;
+                }
+                Ok(())
+            }
+            Message::ConflictingAssignees {
+                title,
+                path,
+                line,
+                assignees,
+                others,
+            } => write!(
+                f,
+                "warning: '{}' is assigned to {} at {}:{} but to {} elsewhere, are these \
+                 different TODOs?",
+                title,
+                assignees.join(", "),
+                path,
+                line,
+                others.join(", ")
+            ),
+            Message::StaleReference { path, line, issue } => write!(
+                f,
+                "warning: the TODO at {}:{} references closed issue #{}, is it stale?",
+                path, line, issue
+            ),
+            Message::BrokenLink {
+                path,
+                line,
+                link,
+                status,
+            } => write!(
+                f,
+                "the link to the TODO at {}:{} is broken ({}): {}",
+                path, line, status, link
+            ),
+            Message::PreparedPatch {
+                create,
+                update,
+                delete,
+            } => {
+                write!(
+                    f,
+                    "would create {}, update {} and delete {} TODOs",
+                    create.len(),
+                    update.len(),
+                    delete.len()
+                )?;
+                for (sign, titles) in [("+", create), ("~", update), ("-", delete)].iter() {
+                    for title in titles.iter() {
+                        write!(f, "\n  {} {}", sign, title)?;
+                    }
+                }
+                Ok(())
+            }
+            Message::RipgrepErrors { command, stderr } => {
+                write!(f, "warning: `{}` reported errors: '{}'", command, stderr)
+            }
+            Message::OldRipgrep { version, minimum } => write!(
+                f,
+                "warning: ripgrep {}.{}.{} is older than {}.{}.{}, its output may not parse",
+                version.0, version.1, version.2, minimum.0, minimum.1, minimum.2
+            ),
+            Message::RipgrepMissing => {
+                write!(f, "rg is not installed, searching with the built-in walker")
+            }
+            Message::TodoCoverage {
+                files_with_todos,
+                total_files,
+                pct,
+            } => write!(
+                f,
+                "{:.0}% of source files have TODOs ({} of {})",
+                pct, files_with_todos, total_files
+            ),
+            Message::UnsupportedFile { path } => {
+                write!(f, "possible TODO found in unsupported file {}", path)
+            }
+            Message::UnsupportedSummary { counts } => {
+                let total = counts.values().sum::<usize>();
+                write!(f, "{} files with possible TODOs are unsupported:", total)?;
+                let mut counts = counts.iter().collect::<Vec<_>>();
+                counts.sort_by_key(|(ext, count)| (std::cmp::Reverse(**count), *ext));
+                let width = counts.iter().map(|(ext, _)| ext.len()).max().unwrap_or(0);
+                for (ext, count) in counts {
+                    write!(f, "\n  {:<width$}  {}", ext, count, width = width)?;
+                }
+                Ok(())
+            }
+            Message::DirtyWorkingTree { changed_files } => write!(
+                f,
+                "warning: {} files have uncommitted changes, links to them may not match \
+                 the TODOs: {}",
+                changed_files.len(),
+                changed_files.join(", ")
+            ),
+        }
+    }
+}
+
+impl Message {
+    /// Report the message to the user, on stderr so it never gets mixed into
+    /// output like `-o json`.
+    pub fn send(&self) {
+        eprintln!("{}", self);
+    }
+}
@@ -1,82 +1,355 @@
 use nom::{bytes::complete as bytes, character::complete as character, combinator, IResult};
 
 use super::{
-    finder::FileSearcher,
-    github::{GitHubIssue, GitHubPatch},
+    cache::{self, ScanCache},
+    finder::{FileSearcher, PossibleTodosInFile, SearchBackend},
+    forge::Patch,
+    github::{self, GitHubIssue},
+    message::Message,
+    stats::{AssigneeStats, DuplicateTodos},
+    utils,
+};
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs::File,
+    io::prelude::*,
+    path::Path,
+    sync::{
+        atomic::{self, AtomicBool},
+        mpsc, Arc, Condvar, Mutex,
+    },
+    thread,
+    time::Duration,
 };
-use serde::Deserialize;
-use std::{collections::HashMap, fs::File, io::prelude::*, path::Path};
 
+pub mod diff;
 pub mod issue;
 pub mod langs;
+pub mod markdown;
 pub mod source;
 
-use issue::GitHubTodoLocation;
+use issue::{
+    managed_body, managed_span, with_managed_markers, GitHubTodoLocation, MANAGED_END,
+    MANAGED_START,
+};
+use langs::SupportedLanguage;
 use source::ParsedTodo;
 
 /// Eat a whole line and optionally its ending but don't return that ending.
+///
+/// Lines end in `\n` or `\r\n`, as `rg` counts them, so a lone `\r` is part
+/// of its line.
+///
+/// ```rust
+/// use todo_finder_lib::parser::take_to_eol;
+///
+/// assert_eq!(take_to_eol("a\r\nb"), Ok(("b", "a")));
+/// assert_eq!(take_to_eol("a\rb\nc"), Ok(("c", "a\rb")));
+/// ```
 pub fn take_to_eol(i: &str) -> IResult<&str, &str> {
-    let (i, ln) = bytes::take_till(|c| c == '\r' || c == '\n')(i)?;
-    let (i, _) = combinator::opt(character::line_ending)(i)?;
-    Ok((i, ln))
+    let (i, ln) = bytes::take_till(|c| c == '\n')(i)?;
+    let (i, _) = combinator::opt(character::char('\n'))(i)?;
+    Ok((i, ln.strip_suffix('\r').unwrap_or(ln)))
+}
+
+/// Count the lines of some text as `take_to_eol` eats them, so spans agree
+/// with the lines seeked past to find a todo.
+fn count_lines(mut text: &str) -> usize {
+    let mut n = 0;
+    while !text.is_empty() {
+        text = take_to_eol(text).map(|(rest, _)| rest).unwrap_or("");
+        n += 1;
+    }
+    n
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub enum IssueProvider {
     GitHub,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ParsingSource {
     MarkdownFile,
     SourceCode,
     IssueAt(IssueProvider),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IssueHead<K> {
     pub title: String,
     pub assignees: Vec<String>,
     pub external_id: K,
+    /// The keyword the todo was tagged with in source, eg. `FIXME`, if known.
+    pub tag: Option<String>,
+    /// Labels and hashtags found in the todo, eg. `backend` for
+    /// `TODO[backend]` or `frontend` for `#frontend`.
+    pub tags: Vec<String>,
+    /// The most urgent priority of any of the todo's locations, if any, see
+    /// `source::todo_priority`.
+    pub priority: Option<u8>,
+    /// Whether the todo is kept in code but won't be done, as marked by
+    /// `TODO(wontfix): ...`, see `is_wontfix_marker`. Its issue is closed as
+    /// not planned.
+    pub wontfix: bool,
+    /// The issue the todo references in place of an assignee, as in
+    /// `TODO(#123): ...`, see `source::issue_reference`.
+    pub reference: Option<u64>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct IssueBody<T> {
     pub descs_and_srcs: Vec<(Vec<String>, T)>,
     pub branches: Vec<String>,
 }
 
 impl IssueBody<FileTodoLocation> {
+    /// Write the body of a GitHub issue, with each description followed by a
+    /// link to its location in the repo at `repo_url`, eg.
+    /// `https://github.com/owner/repo`, widened by `context_lines` on each
+    /// side. If `collapsible`, each of those is wrapped in a `<details>`
+    /// block summarized by its file and line, which `issue::issue_body` can
+    /// still parse. The whole is wrapped in the markers of the part of a body
+    /// that syncs write, see `issue::keep_unmanaged_text`.
     pub fn to_github_string(
         &self,
         cwd: &str,
-        owner: &str,
-        repo: &str,
+        repo_url: &str,
         checkout: &str,
+        collapsible: bool,
+        context_lines: usize,
     ) -> Result<String, String> {
         let mut lines: Vec<String> = vec![];
         for (desc_lines, loc) in self.descs_and_srcs.iter() {
             let desc = desc_lines.clone().join("\n");
-            let link = loc.to_github_link(cwd, owner, repo, checkout)?;
-            lines.push(vec![desc, link].join("\n"));
+            let link = loc
+                .with_context_in_file(context_lines)
+                .to_github_link(cwd, repo_url, checkout)?;
+            if collapsible {
+                let path = Path::new(&loc.file);
+                let file = path.strip_prefix(cwd).unwrap_or(path);
+                let summary = format!(
+                    "<details><summary>{}:{}</summary>\n",
+                    file.display(),
+                    loc.src_span.0
+                );
+                let mut block = vec![summary];
+                if !desc.is_empty() {
+                    block.push(desc);
+                }
+                block.push(link);
+                block.push("\n</details>\n".into());
+                lines.push(block.join("\n"));
+            } else {
+                lines.push([desc, link].join("\n"));
+            }
         }
-        Ok(lines.join("\n"))
+        Ok(with_managed_markers(&lines.join("\n")))
+    }
+}
+
+/// A short id for the todo with the given key, for linking to it, eg. to the
+/// anchors in `IssueMap::as_markdown`. Todos are matched by key, by default
+/// their title, so the id is a hash of the key alone: it's the same on every
+/// run and machine as long as the key doesn't change, even if the todo moves,
+/// see `Issue::id_by`.
+///
+/// ```rust
+/// use todo_finder_lib::parser::todo_id;
+///
+/// assert_eq!(todo_id("Do it."), todo_id("Do it."));
+/// assert_ne!(todo_id("Do it."), todo_id("Do it!"));
+/// assert_eq!(todo_id("Do it.").len(), 8);
+/// ```
+pub fn todo_id(title: &str) -> String {
+    // FNV-1a, as the std hashers may change between releases
+    let hash = title.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:08x}", (hash >> 32) ^ (hash & 0xffff_ffff))
+}
+
+/// How similar two titles are, from 0.0 for nothing in common to 1.0 for the
+/// same title: one minus their Levenshtein distance in characters over the
+/// length of the longer title.
+///
+/// ```rust
+/// use todo_finder_lib::parser::title_similarity;
+///
+/// assert_eq!(title_similarity("Do it.", "Do it."), 1.0);
+/// assert_eq!(title_similarity("Fix the parser.", "Fix the parsers."), 0.9375);
+/// assert_eq!(title_similarity("abc", "xyz"), 0.0);
+/// assert_eq!(title_similarity("", ""), 1.0);
+/// ```
+pub fn title_similarity(a: &str, b: &str) -> f64 {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    // The distances from a prefix of `a` to each prefix of `b`, a row at a time
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    1.0 - row[b.len()] as f64 / longest as f64
+}
+
+/// The issue numbers referenced in some text, like `123` in `see #123.`.
+///
+/// ```rust
+/// use todo_finder_lib::parser::issue_numbers;
+///
+/// assert_eq!(issue_numbers("#12"), vec![12]);
+/// assert_eq!(issue_numbers("Blocked on #3, see #45."), vec![3, 45]);
+/// assert!(issue_numbers("Tag it #frontend or a#1.").is_empty());
+/// ```
+pub fn issue_numbers(text: &str) -> Vec<u64> {
+    text.split(|c: char| c.is_whitespace() || c == '(' || c == '[')
+        .filter_map(|word| {
+            let digits = word.strip_prefix('#')?;
+            let end = digits
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(digits.len());
+            // `#12abc` is a hashtag, not a reference
+            match digits[end..].chars().next() {
+                Some(c) if c.is_alphanumeric() || c == '_' => None,
+                _ => digits[..end].parse().ok(),
+            }
+        })
+        .collect()
+}
+
+/// The todos found by a scan, along with the messages about them, eg.
+/// skipped files, for the caller to report.
+pub type ScannedTodos = (IssueMap<(), FileTodoLocation>, Vec<Message>);
+
+/// Send the messages of a scan, returning what was scanned.
+fn send_messages<T>(scanned: Result<(T, Vec<Message>), String>) -> Result<T, String> {
+    let (scanned, messages) = scanned?;
+    for message in messages.iter() {
+        message.send();
+    }
+    Ok(scanned)
+}
+
+/// What an unsupported file is counted as: its extension, like `.proto`, or
+/// its name if it has none, like `Makefile`.
+///
+/// ```rust
+/// use std::path::Path;
+/// use todo_finder_lib::parser::unsupported_kind;
+///
+/// assert_eq!(unsupported_kind(Path::new("api/v1.proto")), ".proto");
+/// assert_eq!(unsupported_kind(Path::new("src/Makefile")), "Makefile");
+/// ```
+pub fn unsupported_kind(path: &Path) -> String {
+    match path.extension() {
+        Some(ext) => format!(".{}", ext.to_string_lossy()),
+        None => path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+/// The more urgent of two priorities, where lower is more urgent.
+fn most_urgent(a: Option<u8>, b: Option<u8>) -> Option<u8> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Issue<ExternalId, TodoLocation: PartialEq + Eq> {
     pub head: IssueHead<ExternalId>,
     pub body: IssueBody<TodoLocation>,
 }
 
+/// Serialized with its `id` alongside the head and body. In an `IssueMap`
+/// the id is by the map's key, see `Issue::id_by`.
+impl<ExId: Serialize, Loc: PartialEq + Eq + Serialize> Serialize for Issue<ExId, Loc> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        IdentifiedIssue {
+            id: self.id(),
+            issue: self,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// An issue serialized with the given id.
+struct IdentifiedIssue<'a, ExId, Loc: PartialEq + Eq> {
+    id: String,
+    issue: &'a Issue<ExId, Loc>,
+}
+
+impl<ExId: Serialize, Loc: PartialEq + Eq + Serialize> Serialize
+    for IdentifiedIssue<'_, ExId, Loc>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut issue = serializer.serialize_struct("Issue", 3)?;
+        issue.serialize_field("id", &self.id)?;
+        issue.serialize_field("head", &self.issue.head)?;
+        issue.serialize_field("body", &self.issue.body)?;
+        issue.end()
+    }
+}
+
 impl<ExId, Loc: PartialEq + Eq> Issue<ExId, Loc> {
+    /// A stable id for linking to this todo when todos are told apart by
+    /// title, see `todo_id`.
+    pub fn id(&self) -> String {
+        self.id_by(&TodoKey::Title)
+    }
+
+    /// A stable id for linking to this todo when todos are told apart by the
+    /// given key, so todos that share a title but not a key, eg. with
+    /// `--dedupe-by first-line`, get different ids.
+    ///
+    /// ```rust
+    /// use todo_finder_lib::parser::{FileTodoLocation, Issue, TodoKey};
+    ///
+    /// let issue = |desc: &str| {
+    ///     let mut issue = Issue::<(), FileTodoLocation>::new((), "Fix this.".into());
+    ///     let loc = FileTodoLocation {
+    ///         file: "src/lib.rs".into(),
+    ///         src_span: (1, None),
+    ///     };
+    ///     issue.body.descs_and_srcs.push((vec![desc.to_string()], loc));
+    ///     issue
+    /// };
+    /// let (a, b) = (issue("In the parser."), issue("In the finder."));
+    /// assert_eq!(a.id(), b.id());
+    /// assert_ne!(a.id_by(&TodoKey::FirstLine), b.id_by(&TodoKey::FirstLine));
+    /// assert_eq!(a.id_by(&TodoKey::Title), a.id());
+    /// ```
+    pub fn id_by(&self, key: &TodoKey) -> String {
+        todo_id(&key.key_of(self))
+    }
+
     pub fn new(id: ExId, title: String) -> Self {
         Issue {
             head: IssueHead {
                 title,
                 assignees: vec![],
                 external_id: id,
+                tag: None,
+                tags: vec![],
+                priority: None,
+                wontfix: false,
+                reference: None,
             },
             body: IssueBody {
                 descs_and_srcs: vec![],
@@ -86,20 +359,537 @@ impl<ExId, Loc: PartialEq + Eq> Issue<ExId, Loc> {
     }
 }
 
-#[derive(Debug, Clone)]
+/// What tells todos apart, so that todos with the same key are merged into
+/// one issue. By default todos are told apart by title alone.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TodoKey {
+    #[default]
+    Title,
+    /// The title and the first line of the description, so `TODO: Fix this.`
+    /// in different places with different descriptions are different todos.
+    FirstLine,
+    /// The title and the whole description.
+    Description,
+}
+
+impl TodoKey {
+    pub fn from_arg(arg: &str) -> Result<Self, String> {
+        match arg {
+            "title" => Ok(TodoKey::Title),
+            "first-line" => Ok(TodoKey::FirstLine),
+            "description" => Ok(TodoKey::Description),
+            _ => Err(format!("invalid todo key '{}'", arg)),
+        }
+    }
+
+    /// The key of a todo with the given title and description. A todo with
+    /// no description is keyed by its title in any case.
+    ///
+    /// ```rust
+    /// use todo_finder_lib::parser::TodoKey;
+    ///
+    /// let desc = vec!["In the parser.".to_string(), "Soon.".to_string()];
+    /// assert_eq!(TodoKey::Title.key("Fix this.", &desc), "Fix this.");
+    /// assert_ne!(
+    ///     TodoKey::FirstLine.key("Fix this.", &desc),
+    ///     TodoKey::FirstLine.key("Fix this.", &["In the finder.".to_string()])
+    /// );
+    /// assert_eq!(
+    ///     TodoKey::FirstLine.key("Fix this.", &desc),
+    ///     TodoKey::FirstLine.key("Fix this.", &desc[..1])
+    /// );
+    /// assert_eq!(TodoKey::Description.key("Fix this.", &[]), "Fix this.");
+    /// ```
+    pub fn key(&self, title: &str, desc_lines: &[String]) -> String {
+        let desc = match self {
+            TodoKey::Title => None,
+            TodoKey::FirstLine => desc_lines.first().cloned(),
+            TodoKey::Description => Some(desc_lines.join("\n")),
+        };
+        match desc {
+            Some(desc) if !desc.is_empty() => format!("{} ({})", title, todo_id(&desc)),
+            _ => title.to_string(),
+        }
+    }
+
+    /// The key of an issue, by its title and the description of its first
+    /// location.
+    pub fn key_of<ExId, Loc: PartialEq + Eq>(&self, issue: &Issue<ExId, Loc>) -> String {
+        let desc_lines = issue
+            .body
+            .descs_and_srcs
+            .first()
+            .map(|(desc_lines, _)| desc_lines.as_slice())
+            .unwrap_or_default();
+        self.key(&issue.head.title, desc_lines)
+    }
+}
+
+/// How severe a todo's tag is, where lower is more severe. Tags that aren't
+/// built in come last.
+///
+/// ```rust
+/// use todo_finder_lib::parser::tag_severity;
+///
+/// assert!(tag_severity(Some("BUG")) < tag_severity(Some("TODO")));
+/// assert!(tag_severity(Some("NOTE")) < tag_severity(Some("REVIEW")));
+/// ```
+pub fn tag_severity(tag: Option<&str>) -> usize {
+    const BY_SEVERITY: [&str; 8] = [
+        "BUG", "FIXME", "XXX", "HACK", "TODO", "@todo", "OPTIMIZE", "NOTE",
+    ];
+    tag.and_then(|tag| BY_SEVERITY.iter().position(|known| *known == tag))
+        .unwrap_or(BY_SEVERITY.len())
+}
+
+/// What todos are sorted by in the outputs that list them, see `SortOrder`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Title,
+    /// The file and then the line of a todo's first location.
+    File,
+    /// The line and then the file of a todo's first location.
+    Line,
+    /// The first assignee, ignoring case, with unassigned todos last.
+    Assignee,
+    /// The most urgent priority first and then the most severe tag, see
+    /// `tag_severity`.
+    Severity,
+    /// The most locations first, so the most duplicated todos lead.
+    Occurrences,
+}
+
+impl SortKey {
+    pub fn from_arg(arg: &str) -> Result<Self, String> {
+        match arg {
+            "title" => Ok(SortKey::Title),
+            "file" => Ok(SortKey::File),
+            "line" => Ok(SortKey::Line),
+            "assignee" => Ok(SortKey::Assignee),
+            "severity" => Ok(SortKey::Severity),
+            "occurrences" => Ok(SortKey::Occurrences),
+            _ => Err(format!("invalid sort key '{}'", arg)),
+        }
+    }
+}
+
+/// How the todos of the markdown output are grouped, see `MarkdownOptions`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GroupBy {
+    /// One list of todos, each with all of its locations.
+    #[default]
+    Todo,
+    /// A section for each source file, listing the todos in it with only
+    /// their locations in that file.
+    File,
+}
+
+impl GroupBy {
+    pub fn from_arg(arg: &str) -> Result<Self, String> {
+        match arg {
+            "todo" => Ok(GroupBy::Todo),
+            "file" => Ok(GroupBy::File),
+            _ => Err(format!("invalid grouping '{}'", arg)),
+        }
+    }
+}
+
+/// How the markdown output is laid out, see `IssueMap::write_markdown_with`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MarkdownOptions {
+    pub order: SortOrder,
+    pub group_by: GroupBy,
+    /// The template anchoring the lines of each location in its link, if
+    /// any, see `line_anchor`.
+    pub anchor_format: Option<String>,
+}
+
+/// How todos are sorted: by a key, then by title and location to break ties,
+/// and maybe reversed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SortOrder {
+    pub key: SortKey,
+    pub reverse: bool,
+}
+
+impl SortOrder {
+    pub fn by(key: SortKey) -> Self {
+        SortOrder {
+            key,
+            reverse: false,
+        }
+    }
+
+    /// Compare two todos, each at one of its locations, eg. its first. A
+    /// single location can't tell how many others its todo has, so
+    /// `SortKey::Occurrences` only breaks ties here, see `sort_issues`.
+    ///
+    /// ```rust
+    /// use std::cmp::Ordering;
+    /// use todo_finder_lib::parser::{FileTodoLocation, IssueHead, SortKey, SortOrder};
+    ///
+    /// let head = |title: &str, tag: &str| IssueHead {
+    ///     title: title.into(),
+    ///     assignees: vec![],
+    ///     external_id: (),
+    ///     tag: Some(tag.into()),
+    ///     tags: vec![],
+    ///     priority: None,
+    ///     wontfix: false,
+    ///     reference: None,
+    /// };
+    /// let loc = |file: &str| FileTodoLocation {
+    ///     file: file.into(),
+    ///     src_span: (1, None),
+    /// };
+    /// let (bug, todo) = (head("Fix b.", "BUG"), head("Do a.", "TODO"));
+    /// let (a, b) = ((&bug, Some(&loc("b.rs"))), (&todo, Some(&loc("a.rs"))));
+    /// assert_eq!(SortOrder::by(SortKey::Title).compare(a, b), Ordering::Greater);
+    /// assert_eq!(SortOrder::by(SortKey::Severity).compare(a, b), Ordering::Less);
+    /// ```
+    pub fn compare<K>(
+        &self,
+        a: (&IssueHead<K>, Option<&FileTodoLocation>),
+        b: (&IssueHead<K>, Option<&FileTodoLocation>),
+    ) -> Ordering {
+        type Span = (usize, Option<usize>);
+        fn by_file(loc: Option<&FileTodoLocation>) -> Option<(&str, Span)> {
+            loc.map(|loc| (loc.file.as_str(), loc.src_span))
+        }
+        fn by_line(loc: Option<&FileTodoLocation>) -> Option<(Span, &str)> {
+            loc.map(|loc| (loc.src_span, loc.file.as_str()))
+        }
+        let assignee = |head: &IssueHead<K>| head.assignees.first().map(|a| a.to_lowercase());
+        let severity = |head: &IssueHead<K>| {
+            (
+                head.priority.unwrap_or(u8::MAX),
+                tag_severity(head.tag.as_deref()),
+            )
+        };
+        let ordering = match self.key {
+            SortKey::Title => Ordering::Equal,
+            SortKey::File => by_file(a.1).cmp(&by_file(b.1)),
+            SortKey::Line => by_line(a.1).cmp(&by_line(b.1)),
+            SortKey::Assignee => match (assignee(a.0), assignee(b.0)) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+            SortKey::Severity => severity(a.0).cmp(&severity(b.0)),
+            SortKey::Occurrences => Ordering::Equal,
+        }
+        .then_with(|| a.0.title.cmp(&b.0.title))
+        .then_with(|| by_file(a.1).cmp(&by_file(b.1)));
+        if self.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+
+    /// Sort todos, each at its first location by file and line.
+    pub fn sort_issues<K>(&self, issues: &mut [&Issue<K, FileTodoLocation>]) {
+        fn first<K>(issue: &Issue<K, FileTodoLocation>) -> Option<&FileTodoLocation> {
+            issue
+                .body
+                .descs_and_srcs
+                .iter()
+                .map(|(_, loc)| loc)
+                .min_by(|a, b| (&a.file, a.src_span).cmp(&(&b.file, b.src_span)))
+        }
+        issues.sort_by(|a, b| {
+            let occurrences = if self.key == SortKey::Occurrences {
+                let ordering = b
+                    .body
+                    .descs_and_srcs
+                    .len()
+                    .cmp(&a.body.descs_and_srcs.len());
+                if self.reverse {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            } else {
+                Ordering::Equal
+            };
+            occurrences.then_with(|| self.compare((&a.head, first(a)), (&b.head, first(b))))
+        });
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct IssueMap<ExternalId, TodoLocation: PartialEq + Eq> {
     pub parsed_from: ParsingSource,
+    /// The todos, by their key.
     pub todos: HashMap<String, Issue<ExternalId, TodoLocation>>,
+    /// How the todos are keyed.
+    #[serde(skip)]
+    pub key: TodoKey,
+}
+
+/// Serialized with the `id` of each todo by the map's key, see `Issue::id_by`.
+impl<ExId: Serialize, Loc: PartialEq + Eq + Serialize> Serialize for IssueMap<ExId, Loc> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let todos = self
+            .todos
+            .iter()
+            .map(|(key, issue)| {
+                let id = issue.id_by(&self.key);
+                (key, IdentifiedIssue { id, issue })
+            })
+            .collect::<HashMap<_, _>>();
+        let mut map = serializer.serialize_struct("IssueMap", 2)?;
+        map.serialize_field("parsed_from", &self.parsed_from)?;
+        map.serialize_field("todos", &todos)?;
+        map.end()
+    }
+}
+
+/// How many files are read at once by default, well under the common limit
+/// of 1024 open files per process.
+pub const DEFAULT_FILE_CONCURRENCY: usize = 64;
+
+/// A bound on how many files are open at once, shared by its clones, so
+/// reading many files on many threads can't run out of file descriptors.
+#[derive(Clone, Debug)]
+pub struct FileLimit {
+    max: usize,
+    open: Arc<(Mutex<usize>, Condvar)>,
+}
+
+impl Default for FileLimit {
+    fn default() -> Self {
+        FileLimit::new(DEFAULT_FILE_CONCURRENCY)
+    }
+}
+
+impl FileLimit {
+    /// Allow at most `max` files open at once, or one if `max` is zero.
+    pub fn new(max: usize) -> Self {
+        FileLimit {
+            max: max.max(1),
+            open: Arc::new((Mutex::new(0), Condvar::new())),
+        }
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Wait until fewer than the max files are open, then count one more as
+    /// open until the returned guard is dropped.
+    pub fn acquire(&self) -> FileLimitGuard<'_> {
+        let (open, freed) = &*self.open;
+        let mut open = open.lock().unwrap_or_else(|e| e.into_inner());
+        while *open >= self.max {
+            open = freed.wait(open).unwrap_or_else(|e| e.into_inner());
+        }
+        *open += 1;
+        FileLimitGuard { limit: self }
+    }
+}
+
+/// Counts a file as open under a `FileLimit` while it's alive.
+pub struct FileLimitGuard<'a> {
+    limit: &'a FileLimit,
+}
+
+impl Drop for FileLimitGuard<'_> {
+    fn drop(&mut self) {
+        let (open, freed) = &*self.limit.open;
+        let mut open = open.lock().unwrap_or_else(|e| e.into_inner());
+        *open -= 1;
+        freed.notify_one();
+    }
+}
+
+/// Options for scanning a directory for todos.
+#[derive(Clone, Debug, Default)]
+pub struct ScanOptions {
+    /// Regexes of files or directories to ignore.
+    pub excludes: Vec<String>,
+    /// How long to spend reading and parsing any one file before skipping it.
+    pub timeout_per_file: Option<Duration>,
+    /// Extra arguments passed along to `rg`.
+    pub rg_args: Vec<String>,
+    /// Whether to search with `rg` or the built-in walker.
+    pub search_backend: SearchBackend,
+    /// Comment borders not to strip from todos, as pairs of a language name
+    /// or extension and the border, eg. `("swift", ":")`.
+    pub disabled_borders: Vec<(String, String)>,
+    /// Treat a comment block right after a todo, separated from it only by
+    /// blank lines, as more of that todo's description.
+    pub merge_adjacent: bool,
+    /// Strip redundant todo tags from the start of titles, as in
+    /// `// TODO: TODO: Do it.`.
+    pub strip_tag_from_title: bool,
+    /// How the titles of todos are found.
+    pub title_mode: source::TitleMode,
+    /// Only scan files tracked by git, skipping untracked ones even if they
+    /// aren't ignored.
+    pub git_tracked_only: bool,
+    /// Warn when todos with the same title have different assignees in
+    /// different locations, as they may be different tasks merged by accident.
+    pub warn_conflicting_assignees: bool,
+    /// The keywords that start a todo, used both to search with `rg` and to
+    /// parse.
+    pub tags: source::TodoTags,
+    /// Bounds how many files are read at once, including by the threads of
+    /// files that timed out.
+    pub file_limit: FileLimit,
+    /// How many threads read and parse files at once, by default one per
+    /// core. Never more than the `file_limit`.
+    pub jobs: Option<usize>,
+    /// What tells todos apart.
+    pub todo_key: TodoKey,
+    /// Languages known in addition to the built-in ones, eg. from a config
+    /// file.
+    pub custom_languages: Vec<SupportedLanguage>,
+    /// Skip todos in the fenced code examples of `///` and `//!` doc
+    /// comments, see `source::doc_example_lines`.
+    pub skip_doc_examples: bool,
+    /// Only find the todos on lines added or changed since this git
+    /// revision, see `FileSearcher::git_added_lines`.
+    pub since: Option<String>,
+    /// Assign todos without an assignee to the author of their first line,
+    /// by `git blame`, see `utils::blame_author`.
+    pub blame_assign: bool,
+    /// GitHub logins of commit authors by email, to assign blamed todos to,
+    /// eg. from `--assignee-map`.
+    pub assignee_map: BTreeMap<String, String>,
+    /// Report each file in an unsupported language, as well as their count by
+    /// extension, at the end of a scan.
+    pub report_unsupported_files: bool,
+    /// A file to cache the todos parsed from each file in between scans, so
+    /// the files that haven't changed aren't parsed again, see
+    /// `cache::ScanCache`.
+    pub cache: Option<String>,
+    /// Emoji that mark a todo's severity at the start of its title, and the
+    /// label each adds, eg. `("🔴", "critical")`, see `source::emoji_label`.
+    pub emoji_labels: Vec<(String, String)>,
+    /// Titles of todos to leave out of a scan, exactly or as globs, eg.
+    /// perennial todos in vendored example code, see
+    /// `source::title_is_ignored`.
+    pub ignore_titles: Vec<String>,
+    /// Count every source file in a supported language, not just those with
+    /// possible todos, to report the share of them holding a todo.
+    pub todo_coverage: bool,
+}
+
+impl ScanOptions {
+    /// The config to parse the todos of the given language with.
+    pub fn parser_config(&self, language: &SupportedLanguage) -> source::TodoParserConfig {
+        let mut cfg = language.as_todo_parser_config_without(&self.disabled_borders);
+        cfg.title_mode = self.title_mode.clone();
+        cfg.tags = self.tags.clone();
+        cfg.emoji_labels = self.emoji_labels.clone();
+        cfg
+    }
+
+    /// The title of a parsed todo, without redundant todo tags at its start
+    /// if `strip_tag_from_title`.
+    pub fn stripped_title<'a>(&self, title: Cow<'a, str>) -> Cow<'a, str> {
+        if !self.strip_tag_from_title {
+            return title;
+        }
+        match title {
+            Cow::Borrowed(title) => Cow::Borrowed(self.tags.strip_from_title(title)),
+            Cow::Owned(title) => Cow::Owned(self.tags.strip_from_title(&title).to_string()),
+        }
+    }
+
+    /// The tags searched for in files: the todo tags and the macros of every
+    /// language, see `langs::macros_with`. A macro is only parsed as a todo
+    /// in the languages that declare it.
+    pub fn search_tags(&self) -> source::TodoTags {
+        let mut tags = self.tags.clone();
+        tags.keywords
+            .extend(langs::macros_with(&self.custom_languages));
+        tags
+    }
+
+    /// The options that change how files are parsed, as a string to tell
+    /// whether a `cache::ScanCache` was written with the same ones.
+    pub fn cache_key(&self) -> String {
+        format!(
+            "{:?}",
+            (
+                &self.disabled_borders,
+                self.merge_adjacent,
+                self.strip_tag_from_title,
+                &self.title_mode,
+                &self.tags,
+                &self.emoji_labels,
+                &self.todo_key,
+                &self.custom_languages,
+                self.skip_doc_examples,
+                &self.since,
+                self.blame_assign,
+                &self.assignee_map,
+            )
+        )
+    }
 }
 
 /// A todo location in the local filesystem.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct FileTodoLocation {
     pub file: String,
     pub src_span: (usize, Option<usize>),
 }
 
+/// GitHub's anchor to the lines of a file, the default template of
+/// `line_anchor`.
+pub const GITHUB_ANCHOR_FORMAT: &str = "#L{start}-L{end}";
+
+/// The anchor to some lines of a file from a template like
+/// `GITHUB_ANCHOR_FORMAT`, where `{start}` and `{end}` are the first and last
+/// lines. For a single line, everything after `{start}` up to and including
+/// `{end}` is left out.
+///
+/// ```rust
+/// use todo_finder_lib::parser::{line_anchor, GITHUB_ANCHOR_FORMAT};
+///
+/// assert_eq!(line_anchor(GITHUB_ANCHOR_FORMAT, 10, Some(20)), "#L10-L20");
+/// assert_eq!(line_anchor(GITHUB_ANCHOR_FORMAT, 10, None), "#L10");
+/// assert_eq!(line_anchor("#lines-{start}:{end}", 10, Some(20)), "#lines-10:20");
+/// assert_eq!(line_anchor(":{start}", 10, Some(20)), ":10");
+/// ```
+pub fn line_anchor(format: &str, start: usize, end: Option<usize>) -> String {
+    let format = match (end, format.find("{start}"), format.find("{end}")) {
+        (None, Some(at_start), Some(at_end)) if at_start < at_end => Cow::Owned(
+            [
+                &format[..at_start + "{start}".len()],
+                &format[at_end + "{end}".len()..],
+            ]
+            .concat(),
+        ),
+        _ => Cow::Borrowed(format),
+    };
+    format
+        .replace("{start}", &start.to_string())
+        .replace("{end}", &end.unwrap_or(start).to_string())
+}
+
+/// How links to a todo's location are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkStyle {
+    /// A full permalink, eg. `https://github.com/owner/repo/blob/...`.
+    #[default]
+    Absolute,
+    /// A link relative to a page of the same repo, eg. a pull request review
+    /// comment, like `../blob/...`.
+    RepoRelative,
+}
+
 impl FileTodoLocation {
+    /// Write a permalink to this location in the repo at `repo_url`, see
+    /// `github::github_repo_url`.
+    ///
     /// ```rust
     /// use todo_finder_lib::parser::FileTodoLocation;
     ///
@@ -109,46 +899,178 @@ impl FileTodoLocation {
     /// };
     ///
     /// let string = loc
-    ///     .to_github_link("/total/path", "schell", "my_repo", "1234567890")
+    ///     .to_github_link(
+    ///         "/total/path",
+    ///         "https://github.com/schell/my_repo",
+    ///         "1234567890",
+    ///     )
     ///     .unwrap();
     ///
     /// assert_eq!(
     ///     &string,
     ///     "https://github.com/schell/my_repo/blob/1234567890/src/file.rs#L666-L1337"
     /// );
+    ///
+    /// let string = loc
+    ///     .to_github_link(
+    ///         "/total/path",
+    ///         "https://github.example.com/schell/my_repo",
+    ///         "1234567890",
+    ///     )
+    ///     .unwrap();
+    /// assert!(string.starts_with("https://github.example.com/schell/my_repo/blob/"));
     /// ```
     pub fn to_github_link(
         &self,
         cwd: &str,
-        owner: &str,
-        repo: &str,
+        repo_url: &str,
+        checkout: &str,
+    ) -> Result<String, String> {
+        self.to_github_link_with_style(cwd, repo_url, checkout, LinkStyle::Absolute)
+    }
+
+    /// Write a link to this location in the given style.
+    ///
+    /// ```rust
+    /// use todo_finder_lib::parser::{FileTodoLocation, LinkStyle};
+    ///
+    /// let loc = FileTodoLocation {
+    ///     file: "/total/path/src/file.rs".into(),
+    ///     src_span: (666, Some(1337)),
+    /// };
+    ///
+    /// let string = loc
+    ///     .to_github_link_with_style(
+    ///         "/total/path",
+    ///         "https://github.com/schell/my_repo",
+    ///         "1234567890",
+    ///         LinkStyle::RepoRelative,
+    ///     )
+    ///     .unwrap();
+    ///
+    /// assert_eq!(&string, "../blob/1234567890/src/file.rs#L666-L1337");
+    ///
+    /// let loc = FileTodoLocation {
+    ///     file: "/total/path/src/file.rs".into(),
+    ///     src_span: (666, None),
+    /// };
+    /// let string = loc
+    ///     .to_github_link_with_style(
+    ///         "/total/path",
+    ///         "https://github.com/schell/my_repo",
+    ///         "1234567890",
+    ///         LinkStyle::Absolute,
+    ///     )
+    ///     .unwrap();
+    ///
+    /// assert_eq!(
+    ///     &string,
+    ///     "https://github.com/schell/my_repo/blob/1234567890/src/file.rs#L666"
+    /// );
+    /// ```
+    pub fn to_github_link_with_style(
+        &self,
+        cwd: &str,
+        repo_url: &str,
+        checkout: &str,
+        style: LinkStyle,
+    ) -> Result<String, String> {
+        self.to_link(cwd, repo_url, checkout, style, GITHUB_ANCHOR_FORMAT)
+    }
+
+    /// Write a link to this location in the given style, with its lines
+    /// anchored by the template `anchor_format`, see `line_anchor`, for
+    /// renderers that don't understand GitHub's anchors.
+    ///
+    /// ```rust
+    /// use todo_finder_lib::parser::{FileTodoLocation, LinkStyle};
+    ///
+    /// let loc = FileTodoLocation {
+    ///     file: "/total/path/src/file.rs".into(),
+    ///     src_span: (10, Some(20)),
+    /// };
+    /// let link = |anchor_format| {
+    ///     loc.to_link(
+    ///         "/total/path",
+    ///         "https://git.example.com/schell/my_repo",
+    ///         "1234567890",
+    ///         LinkStyle::Absolute,
+    ///         anchor_format,
+    ///     )
+    ///     .unwrap()
+    /// };
+    ///
+    /// assert_eq!(
+    ///     link("#L{start}-{end}"),
+    ///     "https://git.example.com/schell/my_repo/blob/1234567890/src/file.rs#L10-20"
+    /// );
+    /// assert_eq!(
+    ///     link(":{start}"),
+    ///     "https://git.example.com/schell/my_repo/blob/1234567890/src/file.rs:10"
+    /// );
+    /// ```
+    pub fn to_link(
+        &self,
+        cwd: &str,
+        repo_url: &str,
         checkout: &str,
+        style: LinkStyle,
+        anchor_format: &str,
     ) -> Result<String, String> {
         let path: &Path = Path::new(&self.file);
         let relative: &Path = path
             .strip_prefix(cwd)
             .map_err(|e| format!("could not relativize path {:#?}: {}", path, e))?;
-        let file_and_range = vec![
-            format!("{}", relative.display()),
-            format!("#L{}", self.src_span.0),
-            if let Some(end) = self.src_span.1 {
-                format!("-L{}", end)
-            } else {
-                String::new()
-            },
+        let file_and_range = [
+            relative.display().to_string(),
+            line_anchor(anchor_format, self.src_span.0, self.src_span.1),
         ]
         .concat();
 
-        let parts = vec![
-            "https://github.com",
-            owner,
-            repo,
-            "blob",
-            checkout,
-            &file_and_range,
-        ];
+        let parts = match style {
+            LinkStyle::Absolute => vec![repo_url, "blob", checkout, &file_and_range],
+            LinkStyle::RepoRelative => vec!["..", "blob", checkout, &file_and_range],
+        };
         Ok(parts.join("/"))
     }
+
+    /// This location widened by `context_lines` on each side, clamped to the
+    /// first line and, if known, to the `num_lines` of the file. Links to the
+    /// widened location show some code around the todo.
+    ///
+    /// ```rust
+    /// use todo_finder_lib::parser::FileTodoLocation;
+    ///
+    /// let at = |src_span| FileTodoLocation {
+    ///     file: "src/file.rs".into(),
+    ///     src_span,
+    /// };
+    /// assert_eq!(at((50, None)).with_context(3, None), at((47, Some(53))));
+    /// assert_eq!(at((2, Some(4))).with_context(3, Some(100)), at((1, Some(7))));
+    /// assert_eq!(at((99, None)).with_context(3, Some(100)), at((96, Some(100))));
+    /// assert_eq!(at((1, None)).with_context(0, None), at((1, None)));
+    /// ```
+    pub fn with_context(&self, context_lines: usize, num_lines: Option<usize>) -> Self {
+        let (start, end) = self.src_span;
+        let end = end.unwrap_or(start) + context_lines;
+        let end = num_lines.map_or(end, |n| end.min(n.max(start)));
+        let start = start.saturating_sub(context_lines).max(1);
+        FileTodoLocation {
+            file: self.file.clone(),
+            src_span: (start, if end > start { Some(end) } else { None }),
+        }
+    }
+
+    /// Like `with_context`, clamped to the end of the file if it can be read.
+    pub fn with_context_in_file(&self, context_lines: usize) -> Self {
+        if context_lines == 0 {
+            return self.clone();
+        }
+        let num_lines = std::fs::read_to_string(&self.file)
+            .ok()
+            .map(|contents| count_lines(&contents));
+        self.with_context(context_lines, num_lines)
+    }
 }
 
 impl<K, V: Eq> IssueMap<K, V> {
@@ -156,216 +1078,2991 @@ impl<K, V: Eq> IssueMap<K, V> {
         IssueMap {
             parsed_from,
             todos: HashMap::new(),
+            key: TodoKey::default(),
+        }
+    }
+
+    /// Key the todos with the given key, see `TodoKey`.
+    pub fn with_key(mut self, key: TodoKey) -> Self {
+        self.todos = self
+            .todos
+            .into_values()
+            .map(|issue| (key.key_of(&issue), issue))
+            .collect();
+        self.key = key;
+        self
+    }
+
+    /// Leave out the todos whose titles are ignored, see
+    /// `source::title_is_ignored`.
+    pub fn without_ignored_titles(mut self, ignore_titles: &[String]) -> Self {
+        if !ignore_titles.is_empty() {
+            self.todos
+                .retain(|_, issue| !source::title_is_ignored(&issue.head.title, ignore_titles));
         }
+        self
+    }
+
+    /// Add an issue under its key, replacing any with the same key.
+    pub fn insert(&mut self, issue: Issue<K, V>) {
+        self.todos.insert(self.key.key_of(&issue), issue);
     }
 }
 
 impl IssueMap<u64, GitHubTodoLocation> {
     pub fn new_github_todos() -> Self {
-        IssueMap {
-            parsed_from: ParsingSource::IssueAt(IssueProvider::GitHub),
-            todos: HashMap::new(),
-        }
+        IssueMap::new(ParsingSource::IssueAt(IssueProvider::GitHub))
     }
 
     pub fn add_issue(&mut self, github_issue: &GitHubIssue) {
         if let Ok((_, body)) = issue::issue_body(&github_issue.body) {
             let mut issue = Issue::new(github_issue.number, github_issue.title.clone());
             issue.body = body;
-            self.todos.insert(github_issue.title.clone(), issue);
+            self.insert(issue);
         }
     }
+}
 
-    pub fn prepare_patch(&self, local: IssueMap<(), FileTodoLocation>) -> GitHubPatch {
-        let mut create = IssueMap::new_source_todos();
-        let mut edit: IssueMap<u64, FileTodoLocation> = IssueMap::new(ParsingSource::SourceCode);
-        let mut dont_delete = vec![];
+impl IssueMap<usize, FileTodoLocation> {
+    /// Parse the todos of a file written by `as_markdown`, keyed by their
+    /// number in it.
+    pub fn from_markdown(markdown: &str) -> Result<Self, String> {
+        IssueMap::from_markdown_anchored(markdown, None)
+    }
 
-        for (title, local_issue) in local.todos.into_iter() {
-            if let Some(remote_issue) = self.todos.get(&title) {
-                // They both have it
-                let id = remote_issue.head.external_id.clone();
-                dont_delete.push(id);
-                let issue = Issue {
-                    head: remote_issue.head.clone(),
-                    body: local_issue.body,
-                };
-                edit.todos.insert(title, issue);
-            } else {
-                // Must be created
-                create.todos.insert(title, local_issue);
+    /// Like `from_markdown`, for a file written by `write_markdown_with` with
+    /// the same `anchor_format`. A todo listed under more than one file, see
+    /// `GroupBy::File`, is parsed back as one with all of its locations. Only
+    /// the managed part of a file written by `update_markdown` is parsed.
+    pub fn from_markdown_anchored(
+        markdown: &str,
+        anchor_format: Option<&str>,
+    ) -> Result<Self, String> {
+        let (_, todos) = markdown::markdown_todos(managed_body(markdown))
+            .map_err(|e| format!("could not parse markdown todos: {}", e))?;
+        let mut map = IssueMap::new(ParsingSource::MarkdownFile);
+        for mut todo in todos.into_iter() {
+            if let Some(anchor_format) = anchor_format {
+                for (_, loc) in todo.body.descs_and_srcs.iter_mut() {
+                    let anchor = line_anchor(anchor_format, loc.src_span.0, loc.src_span.1);
+                    if let Some(file) = loc.file.strip_suffix(&anchor) {
+                        loc.file = file.to_string();
+                    }
+                }
+            }
+            match map.todos.get_mut(&todo.head.title) {
+                Some(listed) => listed.body.descs_and_srcs.extend(todo.body.descs_and_srcs),
+                None => {
+                    map.todos.insert(todo.head.title.clone(), todo);
+                }
             }
         }
+        Ok(map)
+    }
 
-        let delete = self
+    /// Summarize what writing the local todos over these would change,
+    /// without writing anything. Todos are matched by their key, see
+    /// `TodoKey`, and one is only updated if its locations, descriptions,
+    /// assignees, tags or priority changed.
+    pub fn preview_patch(&self, local: &IssueMap<(), FileTodoLocation>) -> Message {
+        let sorted = |body: &IssueBody<FileTodoLocation>| {
+            let mut descs_and_srcs = body.descs_and_srcs.clone();
+            descs_and_srcs
+                .sort_by(|a, b| (&a.1.file, a.1.src_span).cmp(&(&b.1.file, b.1.src_span)));
+            descs_and_srcs
+        };
+        // Won't fix todos are listed in the file like any other
+        let mut local = local.clone();
+        for issue in local.todos.values_mut() {
+            issue.head.wontfix = false;
+        }
+        let patch = self.prepare_patch(local.clone());
+        let deleted = patch.delete;
+        let mut create = patch
+            .create
             .todos
-            .values()
-            .filter_map(|issue| {
-                let id = issue.head.external_id;
-                if dont_delete.contains(&id) {
-                    None
-                } else {
-                    Some(id)
-                }
-            })
+            .into_values()
+            .map(|issue| issue.head.title)
             .collect::<Vec<_>>();
+        let mut update = patch
+            .edit
+            .todos
+            .into_iter()
+            .filter(|(key, issue)| {
+                let new = &local.todos[key];
+                self.todos
+                    .values()
+                    .find(|old| old.head.external_id == issue.head.external_id)
+                    .is_some_and(|old| {
+                        old.head.assignees != new.head.assignees
+                            || old.head.tags != new.head.tags
+                            || old.head.priority != new.head.priority
+                            || sorted(&old.body) != sorted(&new.body)
+                    })
+            })
+            .map(|(_, issue)| issue.head.title)
+            .collect::<Vec<_>>();
+        let mut delete = self
+            .todos
+            .values()
+            .filter(|issue| deleted.contains(&issue.head.external_id))
+            .map(|issue| issue.head.title.clone())
+            .collect::<Vec<_>>();
+        create.sort();
+        update.sort();
+        delete.sort();
+        Message::PreparedPatch {
+            create,
+            update,
+            delete,
+        }
+    }
+}
+
+impl<Id: Copy + PartialEq, Loc: Eq> IssueMap<Id, Loc> {
+    /// Diff these remote issues against the local todos, finding which todos
+    /// need an issue created, which issues need updating and which should be
+    /// closed, either because their todos are gone or because they are marked
+    /// won't fix. Issues are matched with todos by the key of the local todos,
+    /// see `TodoKey`.
+    pub fn prepare_patch(&self, local: IssueMap<(), FileTodoLocation>) -> Patch<Id> {
+        self.prepare_patch_following_moves(local, |_, _| false)
+    }
+
+    /// Like `prepare_patch`, but a local todo whose key matches no issue is
+    /// matched with an otherwise unmatched issue when `is_moved` says one of
+    /// the issue's locations moved to one of the todo's, eg. because its file
+    /// was renamed. The issue is then edited to the todo's new title instead
+    /// of being closed and opened anew.
+    pub fn prepare_patch_following_moves(
+        &self,
+        local: IssueMap<(), FileTodoLocation>,
+        is_moved: impl Fn(&Loc, &FileTodoLocation) -> bool,
+    ) -> Patch<Id> {
+        self.prepare_patch_following_edits(local, is_moved, None)
+    }
 
-        return GitHubPatch {
+    /// Like `prepare_patch_following_moves`, but when `min_title_similarity`
+    /// is given, a local todo still unmatched after following moves is also
+    /// matched with an otherwise unmatched issue of a single location whose
+    /// title is at least that similar to the todo's, see `title_similarity`.
+    /// The most similar issue wins, so a reworded todo keeps its issue, and
+    /// its comments and links, instead of it being closed and opened anew.
+    pub fn prepare_patch_following_edits(
+        &self,
+        local: IssueMap<(), FileTodoLocation>,
+        is_moved: impl Fn(&Loc, &FileTodoLocation) -> bool,
+        min_title_similarity: Option<f64>,
+    ) -> Patch<Id> {
+        let remote = self
+            .todos
+            .values()
+            .map(|issue| (local.key.key_of(issue), issue))
+            .collect::<HashMap<_, _>>();
+        let mut create = IssueMap::new_source_todos().with_key(local.key.clone());
+        let mut edit: IssueMap<Id, FileTodoLocation> =
+            IssueMap::new(ParsingSource::SourceCode).with_key(local.key.clone());
+        let mut dont_delete = vec![];
+        let mut wontfix = vec![];
+
+        for (key, local_issue) in local.todos.into_iter() {
+            if local_issue.head.wontfix {
+                // Its issue is closed as not planned, and none is created
+                if let Some(remote_issue) = remote.get(&key) {
+                    dont_delete.push(remote_issue.head.external_id);
+                    wontfix.push(remote_issue.head.external_id);
+                }
+            } else if let Some(remote_issue) = remote.get(&key) {
+                // They both have it
+                let id = remote_issue.head.external_id;
+                dont_delete.push(id);
+                let mut head = remote_issue.head.clone();
+                head.tags = local_issue.head.tags;
+                head.priority = local_issue.head.priority;
+                let issue = Issue {
+                    head,
+                    body: local_issue.body,
+                };
+                edit.todos.insert(key, issue);
+            } else {
+                // Must be created
+                create.todos.insert(key, local_issue);
+            }
+        }
+
+        let mut keys = create.todos.keys().cloned().collect::<Vec<_>>();
+        keys.sort();
+        for key in keys {
+            let local_issue = &create.todos[&key];
+            let moved = self.todos.values().find(|remote_issue| {
+                !dont_delete.contains(&remote_issue.head.external_id)
+                    && remote_issue
+                        .body
+                        .descs_and_srcs
+                        .iter()
+                        .any(|(_, remote_loc)| {
+                            local_issue
+                                .body
+                                .descs_and_srcs
+                                .iter()
+                                .any(|(_, local_loc)| is_moved(remote_loc, local_loc))
+                        })
+            });
+            if let Some(remote_issue) = moved {
+                let local_issue = create.todos.remove(&key).unwrap();
+                dont_delete.push(remote_issue.head.external_id);
+                let mut head = remote_issue.head.clone();
+                head.title = local_issue.head.title;
+                head.tags = local_issue.head.tags;
+                head.priority = local_issue.head.priority;
+                let issue = Issue {
+                    head,
+                    body: local_issue.body,
+                };
+                edit.todos.insert(key, issue);
+            }
+        }
+
+        if let Some(min_similarity) = min_title_similarity {
+            let mut remote_keys = self.todos.keys().collect::<Vec<_>>();
+            remote_keys.sort();
+            let mut keys = create.todos.keys().cloned().collect::<Vec<_>>();
+            keys.sort();
+            for key in keys {
+                let title = &create.todos[&key].head.title;
+                let mut retitled: Option<(f64, &Issue<Id, Loc>)> = None;
+                for remote_issue in remote_keys.iter().map(|k| &self.todos[*k]) {
+                    if dont_delete.contains(&remote_issue.head.external_id)
+                        || remote_issue.body.descs_and_srcs.len() != 1
+                    {
+                        continue;
+                    }
+                    let similarity = title_similarity(&remote_issue.head.title, title);
+                    if similarity >= min_similarity
+                        && retitled.is_none_or(|(most, _)| similarity > most)
+                    {
+                        retitled = Some((similarity, remote_issue));
+                    }
+                }
+                if let Some((_, remote_issue)) = retitled {
+                    let local_issue = create.todos.remove(&key).unwrap();
+                    dont_delete.push(remote_issue.head.external_id);
+                    let mut head = remote_issue.head.clone();
+                    head.title = local_issue.head.title;
+                    head.tags = local_issue.head.tags;
+                    head.priority = local_issue.head.priority;
+                    let issue = Issue {
+                        head,
+                        body: local_issue.body,
+                    };
+                    edit.todos.insert(key, issue);
+                }
+            }
+        }
+
+        let delete = self
+            .todos
+            .values()
+            .filter_map(|issue| {
+                let id = issue.head.external_id;
+                if dont_delete.contains(&id) {
+                    None
+                } else {
+                    Some(id)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Patch {
             create,
             edit,
             delete,
-        };
+            wontfix,
+            reopen: vec![],
+        }
+    }
+}
+
+/// A todo that was found but looks malformed, so its author may want to fix
+/// it, see `malformed_reason`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MalformedTodo {
+    pub path: String,
+    pub line: usize,
+    pub reason: String,
+}
+
+/// Why a todo with the given title and assignees looks malformed, if it does.
+///
+/// ```rust
+/// use todo_finder_lib::parser::malformed_reason;
+///
+/// assert_eq!(malformed_reason("Do the thing.", &[]), None);
+/// assert_eq!(malformed_reason("", &[]), Some("it has no title"));
+/// assert_eq!(malformed_reason("...", &[]), Some("its title has no words"));
+/// assert_eq!(
+///     malformed_reason("(alice: Do the thing.", &[]),
+///     Some("its assignee has no closing parenthesis")
+/// );
+/// assert_eq!(malformed_reason("(optional) Cache the result.", &[]), None);
+/// assert_eq!(malformed_reason("`parse` is slow.", &[]), None);
+/// ```
+pub fn malformed_reason(title: &str, assignees: &[String]) -> Option<&'static str> {
+    if title.trim().is_empty() {
+        Some("it has no title")
+    } else if is_unclosed_assignee(title) {
+        Some("its assignee has no closing parenthesis")
+    } else if title.starts_with('[') && !title.contains(']') {
+        Some("its labels have no closing bracket")
+    } else if assignees.iter().any(|assignee| assignee.trim().is_empty()) {
+        Some("it has an empty assignee")
+    } else if !title.chars().any(char::is_alphanumeric) {
+        Some("its title has no words")
+    } else if title.starts_with(|c: char| c.is_ascii_punctuation() && !"`\"'([".contains(c)) {
+        Some("its title starts with stray punctuation")
+    } else {
+        None
+    }
+}
+
+/// Whether a title starts like an assignee missing its `)`, as in
+/// `TODO(alice: ...`, rather than with a parenthesized aside.
+fn is_unclosed_assignee(title: &str) -> bool {
+    let rest = match title.strip_prefix('(') {
+        Some(rest) => rest.trim_start().trim_start_matches('@'),
+        None => return false,
+    };
+    let name_len = rest
+        .find(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == ':')
+        .unwrap_or(rest.len());
+    name_len > 0 && (rest[name_len..].is_empty() || rest[name_len..].starts_with(':'))
+}
+
+/// Whether the assignee of a todo is really a marker that it won't be done,
+/// like `TODO(wontfix): ...` or `TODO(nofix): ...`, ignoring case.
+///
+/// ```rust
+/// use todo_finder_lib::parser::is_wontfix_marker;
+///
+/// assert!(is_wontfix_marker("wontfix"));
+/// assert!(is_wontfix_marker("NoFix"));
+/// assert!(!is_wontfix_marker("schell"));
+/// ```
+pub fn is_wontfix_marker(assignee: &str) -> bool {
+    ["wontfix", "nofix"]
+        .iter()
+        .any(|marker| assignee.eq_ignore_ascii_case(marker))
+}
+
+/// Add an assignee unless it is already there, ignoring case. The first seen
+/// casing is kept.
+fn add_assignee(assignees: &mut Vec<String>, assignee: &str) {
+    let lowercase = assignee.to_lowercase();
+    if !assignees.iter().any(|a| a.to_lowercase() == lowercase) {
+        assignees.push(assignee.to_string());
     }
 }
 
 impl IssueMap<(), FileTodoLocation> {
     pub fn new_source_todos() -> Self {
-        IssueMap {
-            parsed_from: ParsingSource::SourceCode,
-            todos: HashMap::new(),
-        }
+        IssueMap::new(ParsingSource::SourceCode)
     }
 
     pub fn distinct_len(&self) -> usize {
         self.todos.len()
     }
 
-    pub fn add_parsed_todo(&mut self, todo: &ParsedTodo, loc: FileTodoLocation) {
-        let title = todo.title.to_string();
-        let issue = self
+    /// The number of todo locations, counting each place a todo is found.
+    ///
+    /// ```rust
+    /// use todo_finder_lib::parser::{langs, IssueMap, ScanOptions};
+    ///
+    /// let contents = "// TODO: Do a.\nfn a() {}\n// TODO: Do a.\n// TODO: Do b.\n";
+    /// let rust = &langs::language_map()["rs"];
+    /// let issues =
+    ///     IssueMap::from_file_contents("a.rs", contents, &[1, 3, 4], rust, &ScanOptions::default())
+    ///         .unwrap();
+    /// assert_eq!((issues.distinct_len(), issues.total_len()), (2, 3));
+    /// ```
+    pub fn total_len(&self) -> usize {
+        self.todos
+            .values()
+            .map(|issue| issue.body.descs_and_srcs.len())
+            .sum()
+    }
+
+    /// Every location of a todo without an assignee, as `file:line: title`
+    /// with the file relative to `cwd`, sorted by file and line.
+    pub fn unassigned_locations(&self, cwd: &str) -> Vec<String> {
+        let mut locs = self
+            .todos
+            .values()
+            .filter(|issue| issue.head.assignees.is_empty())
+            .flat_map(|issue| {
+                issue
+                    .body
+                    .descs_and_srcs
+                    .iter()
+                    .map(move |(_, loc)| (loc, issue.head.title.as_str()))
+            })
+            .collect::<Vec<_>>();
+        locs.sort_by(|a, b| (&a.0.file, a.0.src_span).cmp(&(&b.0.file, b.0.src_span)));
+        locs.into_iter()
+            .map(|(loc, title)| {
+                let path = Path::new(&loc.file);
+                let file = path.strip_prefix(cwd).unwrap_or(path);
+                format!("{}:{}: {}", file.display(), loc.src_span.0, title)
+            })
+            .collect()
+    }
+
+    /// Every location of a todo that looks malformed, with why, eg. a
+    /// `// TODO:` with no title. Paths are relative to `cwd`.
+    pub fn malformed_todos(&self, cwd: &str) -> Vec<MalformedTodo> {
+        let mut entries = self
             .todos
-            .entry(title.clone())
-            .or_insert(Issue::new((), title));
+            .values()
+            .filter_map(|issue| {
+                malformed_reason(&issue.head.title, &issue.head.assignees)
+                    .map(|reason| (issue, reason))
+            })
+            .flat_map(|(issue, reason)| {
+                issue.body.descs_and_srcs.iter().map(move |(_, loc)| {
+                    let path = Path::new(&loc.file);
+                    MalformedTodo {
+                        path: path.strip_prefix(cwd).unwrap_or(path).display().to_string(),
+                        line: loc.src_span.0,
+                        reason: reason.to_string(),
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| (&a.path, a.line).cmp(&(&b.path, b.line)));
+        entries
+    }
 
-        if let Some(assignee) = todo.assignee.map(|s| s.to_string()) {
-            if !issue.head.assignees.contains(&assignee) {
-                issue.head.assignees.push(assignee);
+    /// The locations of the todos that reference an issue by number, like
+    /// `TODO(#123): ...` or `... see #123.`, keyed by the issue number.
+    ///
+    /// ```rust
+    /// use todo_finder_lib::parser::{source::ParsedTodo, FileTodoLocation, IssueMap};
+    ///
+    /// let mut issues = IssueMap::new_source_todos();
+    /// issues.add_parsed_todo(
+    ///     &ParsedTodo {
+    ///         title: "Remove the workaround.".into(),
+    ///         assignee: None,
+    ///         desc_lines: vec!["Also see #7."],
+    ///         tag: "TODO",
+    ///         tags: vec![],
+    ///         priority: None,
+    ///         reference: Some(12),
+    ///     },
+    ///     FileTodoLocation {
+    ///         file: "src/lib.rs".into(),
+    ///         src_span: (3, None),
+    ///     },
+    /// );
+    /// let refs = issues.referenced_issues();
+    /// assert_eq!(refs.keys().copied().collect::<Vec<_>>(), vec![7, 12]);
+    /// assert_eq!(refs[&12][0].src_span, (3, None));
+    /// ```
+    pub fn referenced_issues(&self) -> BTreeMap<u64, Vec<FileTodoLocation>> {
+        let mut refs: BTreeMap<u64, Vec<FileTodoLocation>> = BTreeMap::new();
+        for issue in self.todos.values() {
+            for (desc_lines, loc) in issue.body.descs_and_srcs.iter() {
+                let mut numbers = std::iter::once(issue.head.title.as_str())
+                    .chain(desc_lines.iter().map(String::as_str))
+                    .flat_map(issue_numbers)
+                    .chain(issue.head.reference)
+                    .collect::<Vec<_>>();
+                numbers.sort_unstable();
+                numbers.dedup();
+                for number in numbers {
+                    refs.entry(number).or_default().push(loc.clone());
+                }
             }
         }
+        for locs in refs.values_mut() {
+            locs.sort_by(|a, b| (&a.file, a.src_span).cmp(&(&b.file, b.src_span)));
+        }
+        refs
+    }
 
+    pub fn add_parsed_todo(&mut self, todo: &ParsedTodo, loc: FileTodoLocation) {
+        let title = todo.title.to_string();
         let desc_lines = todo
             .desc_lines
             .iter()
             .map(|s| s.to_string())
             .collect::<Vec<_>>();
+        let issue = self
+            .todos
+            .entry(self.key.key(&title, &desc_lines))
+            .or_insert_with(|| Issue::new((), title));
+
+        if issue.head.tag.is_none() {
+            issue.head.tag = Some(todo.tag.to_string());
+        }
+
+        match todo.assignee {
+            Some(marker) if is_wontfix_marker(marker) => issue.head.wontfix = true,
+            Some(assignee) => add_assignee(&mut issue.head.assignees, assignee),
+            None => {}
+        }
+
+        for tag in todo.tags.iter() {
+            if !issue.head.tags.contains(tag) {
+                issue.head.tags.push(tag.clone());
+            }
+        }
+
+        issue.head.priority = most_urgent(issue.head.priority, todo.priority);
+        if issue.head.reference.is_none() {
+            issue.head.reference = todo.reference;
+        }
         issue.body.descs_and_srcs.push((desc_lines, loc));
     }
 
-    pub fn from_files_in_directory(
-        dir: &str,
-        excludes: &Vec<String>,
-    ) -> Result<IssueMap<(), FileTodoLocation>, String> {
-        let possible_todos = FileSearcher::find(dir, excludes)?;
-        let mut todos = IssueMap::new_source_todos();
-        let language_map = langs::language_map();
+    /// The assignees the todo with this key already has, if it has any and
+    /// they share none of the given assignees. Todos with the same key but
+    /// different assignees may really be different tasks. The key is the
+    /// todo's title unless the map is keyed otherwise, see `TodoKey`.
+    pub fn conflicting_assignees<S: AsRef<str>>(
+        &self,
+        key: &str,
+        assignees: &[S],
+    ) -> Option<&[String]> {
+        let issue = self.todos.get(key)?;
+        if assignees.is_empty() || issue.head.assignees.is_empty() {
+            return None;
+        }
+        let shares_any = assignees.iter().any(|assignee| {
+            let lowercase = assignee.as_ref().to_lowercase();
+            issue
+                .head
+                .assignees
+                .iter()
+                .any(|a| a.to_lowercase() == lowercase)
+        });
+        if shares_any {
+            None
+        } else {
+            Some(&issue.head.assignees)
+        }
+    }
 
-        for possible_todo in possible_todos.into_iter() {
-            let path = Path::new(&possible_todo.file);
+    /// A message if merging a todo with the given assignees at `loc` would
+    /// mix assignees from different locations.
+    fn conflicting_assignees_message<S: AsRef<str>>(
+        &self,
+        key: &str,
+        assignees: &[S],
+        loc: &FileTodoLocation,
+    ) -> Option<Message> {
+        self.conflicting_assignees(key, assignees)
+            .map(|others| Message::ConflictingAssignees {
+                title: self.todos[key].head.title.clone(),
+                path: loc.file.clone(),
+                line: loc.src_span.0,
+                assignees: assignees.iter().map(|a| a.as_ref().to_string()).collect(),
+                others: others.to_vec(),
+            })
+    }
 
-            // Get our parser for this extension
-            let ext: Option<_> = path.extension();
-            if ext.is_none() {
+    /// Assign each todo without an assignee to the author of its first
+    /// location's lines, by `git blame` in the given directory. Todos on lines
+    /// that aren't committed, or whose author has no known GitHub login, are
+    /// left as they are, see `utils::blame_author`.
+    pub fn assign_blamed(&mut self, dir: &str, assignee_map: &BTreeMap<String, String>) {
+        for issue in self.todos.values_mut() {
+            if !issue.head.assignees.is_empty() {
                 continue;
             }
-            let ext: &str = ext
-                .expect("impossible!")
-                .to_str()
-                .expect("could not get extension as str");
-            let languages = language_map.get(ext);
-            if languages.is_none() {
-                // TODO: Deadletter the file name as unsupported
-                println!("possible TODO found in unsupported file: {:#?}", path);
-                continue;
+            let loc = match issue.body.descs_and_srcs.first() {
+                Some((_, loc)) => loc,
+                None => continue,
+            };
+            let (start, end) = (loc.src_span.0, loc.src_span.1.unwrap_or(loc.src_span.0));
+            if let Ok(Some(author)) =
+                utils::git_blame_author(dir, &loc.file, start, end, assignee_map)
+            {
+                issue.head.assignees.push(author);
+            }
+        }
+    }
+
+    /// Merge the todos of another map into this one, eg. the todos found in
+    /// one file into the todos found so far.
+    pub fn merge(&mut self, other: IssueMap<(), FileTodoLocation>) {
+        for (key, other_issue) in other.todos.into_iter() {
+            let issue = self
+                .todos
+                .entry(key)
+                .or_insert_with(|| Issue::new((), other_issue.head.title.clone()));
+            if issue.head.tag.is_none() {
+                issue.head.tag = other_issue.head.tag;
             }
-            let languages = languages.expect("impossible!");
-
-            // Open the file and load the contents
-            let mut file = File::open(path)
-                .map_err(|e| format!("could not open file: {}\n{}", path.display(), e))?;
-            let mut contents = String::new();
-            file.read_to_string(&mut contents)
-                .map_err(|e| format!("could not read file {:#?}: {}", path, e))?;
-
-            let mut current_line = 1;
-            let mut i = contents.as_str();
-            for line in possible_todo.lines_to_search.into_iter() {
-                // Seek to the correct line...
-                while line > current_line {
-                    let (j, _) =
-                        take_to_eol(i).map_err(|e| format!("couldn't take line:\n{}", e))?;
-                    i = j;
-                    current_line += 1;
+            for assignee in other_issue.head.assignees.iter() {
+                add_assignee(&mut issue.head.assignees, assignee);
+            }
+            for tag in other_issue.head.tags.into_iter() {
+                if !issue.head.tags.contains(&tag) {
+                    issue.head.tags.push(tag);
                 }
+            }
+            issue.head.priority = most_urgent(issue.head.priority, other_issue.head.priority);
+            issue
+                .body
+                .descs_and_srcs
+                .extend(other_issue.body.descs_and_srcs);
+        }
+    }
+
+    /// Parse the todos on the given lines of one file's contents, sending any
+    /// messages about them, see `scan_file_contents`.
+    pub fn from_file_contents(
+        file: &str,
+        contents: &str,
+        lines_to_search: &[usize],
+        languages: &[SupportedLanguage],
+        options: &ScanOptions,
+    ) -> Result<IssueMap<(), FileTodoLocation>, String> {
+        send_messages(IssueMap::scan_file_contents(
+            file,
+            contents,
+            lines_to_search,
+            languages,
+            options,
+        ))
+    }
+
+    /// Parse the todos on the given lines of one file's contents, returning
+    /// them along with any messages about them instead of sending those.
+    pub fn scan_file_contents(
+        file: &str,
+        contents: &str,
+        lines_to_search: &[usize],
+        languages: &[SupportedLanguage],
+        options: &ScanOptions,
+    ) -> Result<ScannedTodos, String> {
+        IssueMap::scan_file_contents_until(
+            file,
+            contents,
+            lines_to_search,
+            languages,
+            options,
+            &AtomicBool::new(false),
+        )
+    }
+
+    /// Like `scan_file_contents`, but failing before the next line is parsed
+    /// once `cancelled` is set, eg. by a timeout on another thread.
+    fn scan_file_contents_until(
+        file: &str,
+        contents: &str,
+        lines_to_search: &[usize],
+        languages: &[SupportedLanguage],
+        options: &ScanOptions,
+        cancelled: &AtomicBool,
+    ) -> Result<ScannedTodos, String> {
+        let mut todos = IssueMap::new_source_todos().with_key(options.todo_key.clone());
+        let mut messages = vec![];
+        let doc_examples = if options.skip_doc_examples {
+            source::doc_example_lines(contents)
+        } else {
+            vec![]
+        };
+        let mut current_line = 1;
+        let mut i = contents;
+        for line in lines_to_search.iter().copied() {
+            if cancelled.load(atomic::Ordering::Relaxed) {
+                return Err("parsing was cancelled".to_string());
+            }
+            if doc_examples.binary_search(&line).is_ok() {
+                continue;
+            }
+            // Seek to the correct line...
+            while line > current_line {
+                let (j, _) = take_to_eol(i).map_err(|e| format!("couldn't take line:\n{}", e))?;
+                i = j;
+                current_line += 1;
+            }
 
-                // Try parsing in each language until we get a match
-                for language in languages.iter() {
-                    let parser_config = language.as_todo_parser_config();
-                    let parser = source::parse_todo(parser_config);
-                    if let Ok((j, parsed_todo)) = parser(i) {
-                        let num_lines = i.trim_end_matches(j).lines().fold(0, |n, _| n + 1);
-                        let loc = FileTodoLocation {
-                            file: possible_todo.file.to_string(),
-                            src_span: (
-                                line,
-                                if num_lines > 1 {
-                                    Some(line + num_lines - 1)
-                                } else {
-                                    None
-                                },
+            // Try parsing in each language until we get a match
+            for language in languages.iter() {
+                let parser_config = options.parser_config(language);
+                let singles = parser_config.singles.clone();
+                let parser = source::parse_todo(parser_config);
+                let res = parser(i);
+                if let Err(nom::Err::Failure(e)) = &res {
+                    if e.code == nom::error::ErrorKind::TooLarge {
+                        messages.push(Message::UnparsedTodo {
+                            path: file.to_string(),
+                            line,
+                            reason: format!(
+                                "no end of comment within {} bytes",
+                                source::MAX_MULTI_LINE_LOOKAHEAD
                             ),
+                        });
+                    }
+                }
+                if let Ok((mut j, mut parsed_todo)) = res {
+                    if options.merge_adjacent {
+                        if let Ok((k, lines)) = source::adjacent_comment_block(
+                            &singles,
+                            &options.tags,
+                            source::ADJACENT_BLOCK_MAX_GAP,
+                            j,
+                        ) {
+                            parsed_todo.desc_lines.extend(lines);
+                            j = k;
+                        }
+                    }
+                    parsed_todo.title = options.stripped_title(parsed_todo.title);
+                    let num_lines = count_lines(&i[..i.len() - j.len()]);
+                    let loc = FileTodoLocation {
+                        file: file.to_string(),
+                        src_span: (
+                            line,
+                            if num_lines > 1 {
+                                Some(line + num_lines - 1)
+                            } else {
+                                None
+                            },
+                        ),
+                    };
+                    if options.warn_conflicting_assignees {
+                        if let Some(assignee) = parsed_todo.assignee {
+                            let desc_lines = parsed_todo
+                                .desc_lines
+                                .iter()
+                                .map(|s| s.to_string())
+                                .collect::<Vec<_>>();
+                            let key = options.todo_key.key(&parsed_todo.title, &desc_lines);
+                            messages.extend(todos.conflicting_assignees_message(
+                                &key,
+                                &[assignee],
+                                &loc,
+                            ));
+                        }
+                    }
+                    todos.add_parsed_todo(&parsed_todo, loc);
+                }
+            }
+        }
+
+        Ok((todos, messages))
+    }
+
+    /// Parse the todos in the contents of a single file, eg. piped from an
+    /// editor, in the languages of the given extension. Nothing is searched
+    /// or read from disk.
+    pub fn from_contents_with_extension(
+        file: &str,
+        contents: &str,
+        ext: &str,
+        options: &ScanOptions,
+    ) -> Result<IssueMap<(), FileTodoLocation>, String> {
+        let languages = langs::language_map_with(&options.custom_languages)
+            .get(ext)
+            .cloned()
+            .ok_or_else(|| format!("unsupported file extension '{}'", ext))?;
+        let lines = FileSearcher::find_in_contents(contents, &options.search_tags());
+        IssueMap::from_file_contents(file, contents, &lines, &languages, options)
+            .map(|todos| todos.without_ignored_titles(&options.ignore_titles))
+    }
+
+    /// Parse the todos added in a unified diff, eg. of a pull request, with
+    /// the same options as a scan of the directory its paths are relative to.
+    /// Only the added lines are read, see `diff::parse_diff`.
+    pub fn from_diff(
+        dir: &str,
+        diff: &str,
+        options: &ScanOptions,
+    ) -> Result<IssueMap<(), FileTodoLocation>, String> {
+        let mut todos = IssueMap::new_source_todos().with_key(options.todo_key.clone());
+        for (todo, mut loc) in diff::parse_diff(diff, options) {
+            loc.file = Path::new(dir).join(&loc.file).display().to_string();
+            if !FileSearcher::is_excluded(dir, &loc.file, &options.excludes)? {
+                todos.add_parsed_todo(&todo, loc);
+            }
+        }
+        Ok(todos.without_ignored_titles(&options.ignore_titles))
+    }
+
+    /// Read the file of a possible todo and parse its todos, returning them
+    /// along with any messages about them, unless `cancelled` is set first,
+    /// see `scan_file_contents_until`.
+    fn scan_possible_todo(
+        possible_todo: &PossibleTodosInFile,
+        languages: &[SupportedLanguage],
+        options: &ScanOptions,
+        cancelled: &AtomicBool,
+    ) -> Result<ScannedTodos, String> {
+        let path = Path::new(&possible_todo.file);
+        let mut bytes = vec![];
+        {
+            let _open = options.file_limit.acquire();
+            let mut file = File::open(path).map_err(|e| format!("could not open it: {}", e))?;
+            file.read_to_end(&mut bytes)
+                .map_err(|e| format!("could not read it: {}", e))?;
+        }
+        // Like rg, consider any file with a NUL byte binary
+        if bytes.contains(&0) {
+            let message = Message::BinaryFile {
+                path: possible_todo.file.clone(),
+            };
+            return Ok((IssueMap::new_source_todos(), vec![message]));
+        }
+        let contents = String::from_utf8(bytes).map_err(|e| format!("not valid UTF-8: {}", e))?;
+        IssueMap::scan_file_contents_until(
+            &possible_todo.file,
+            &contents,
+            &possible_todo.lines_to_search,
+            languages,
+            options,
+            cancelled,
+        )
+    }
+
+    /// Read the file of a possible todo and parse its todos on another thread,
+    /// giving up if that takes longer than `timeout`.
+    ///
+    /// Returns `Ok(None)` if the timeout expired. The thread is then told to
+    /// stop, which it does before parsing its next todo.
+    pub fn from_possible_todo_with_timeout(
+        possible_todo: PossibleTodosInFile,
+        languages: Vec<SupportedLanguage>,
+        options: ScanOptions,
+        timeout: Duration,
+    ) -> Result<Option<IssueMap<(), FileTodoLocation>>, String> {
+        let scanned =
+            IssueMap::scan_possible_todo_with_timeout(possible_todo, languages, options, timeout)?;
+        scanned
+            .map(|scanned| send_messages(Ok(scanned)))
+            .transpose()
+    }
+
+    /// Like `from_possible_todo_with_timeout`, but returning any messages
+    /// about the todos instead of sending them.
+    pub fn scan_possible_todo_with_timeout(
+        possible_todo: PossibleTodosInFile,
+        languages: Vec<SupportedLanguage>,
+        options: ScanOptions,
+        timeout: Duration,
+    ) -> Result<Option<ScannedTodos>, String> {
+        let (tx, rx) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let cancel = cancelled.clone();
+        let _ = thread::spawn(move || {
+            let _ = tx.send(IssueMap::scan_possible_todo(
+                &possible_todo,
+                &languages,
+                &options,
+                &cancelled,
+            ));
+        });
+        match rx.recv_timeout(timeout) {
+            Ok(res) => res.map(Some),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                cancel.store(true, atomic::Ordering::Relaxed);
+                Ok(None)
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                Err("file parsing thread panicked".to_string())
+            }
+        }
+    }
+
+    pub fn from_files_in_directory(
+        dir: &str,
+        excludes: &[String],
+    ) -> Result<IssueMap<(), FileTodoLocation>, String> {
+        let options = ScanOptions {
+            excludes: excludes.to_vec(),
+            ..ScanOptions::default()
+        };
+        IssueMap::from_files_in_directory_with_options(dir, &options)
+    }
+
+    pub fn from_files_in_directory_with_options(
+        dir: &str,
+        options: &ScanOptions,
+    ) -> Result<IssueMap<(), FileTodoLocation>, String> {
+        send_messages(IssueMap::scan_files_in_directory(dir, options))
+    }
+
+    /// Search a directory and parse its todos, returning them along with the
+    /// messages about them, eg. skipped files, instead of sending those, for
+    /// programs embedding the scan.
+    ///
+    /// ```rust,no_run
+    /// use todo_finder_lib::parser::{IssueMap, ScanOptions};
+    ///
+    /// let (todos, messages) =
+    ///     IssueMap::scan_files_in_directory("src", &ScanOptions::default()).unwrap();
+    /// println!("{} todos, {} warnings", todos.distinct_len(), messages.len());
+    /// ```
+    pub fn scan_files_in_directory(
+        dir: &str,
+        options: &ScanOptions,
+    ) -> Result<ScannedTodos, String> {
+        let possible_todos = FileSearcher::find(
+            dir,
+            options.search_backend,
+            &options.search_tags(),
+            &options.excludes,
+            &options.rg_args,
+        )?;
+        IssueMap::scan_possible_todos_in_directory(dir, possible_todos, options)
+    }
+
+    /// Parse the possible todos found in the files of a directory, eg. read
+    /// with `FileSearcher::parse_rg_json`, as `from_files_in_directory_with_options`
+    /// does once it has searched the files.
+    pub fn from_possible_todos_in_directory(
+        dir: &str,
+        possible_todos: Vec<PossibleTodosInFile>,
+        options: &ScanOptions,
+    ) -> Result<IssueMap<(), FileTodoLocation>, String> {
+        send_messages(IssueMap::scan_possible_todos_in_directory(
+            dir,
+            possible_todos,
+            options,
+        ))
+    }
+
+    /// Read and parse one file of a directory scan, with the options' timeout
+    /// and blame assignment.
+    fn scan_possible_todo_in_directory(
+        dir: &str,
+        possible_todo: PossibleTodosInFile,
+        languages: &[SupportedLanguage],
+        options: &ScanOptions,
+    ) -> Result<Option<ScannedTodos>, String> {
+        let scanned = if let Some(timeout) = options.timeout_per_file {
+            IssueMap::scan_possible_todo_with_timeout(
+                possible_todo,
+                languages.to_vec(),
+                options.clone(),
+                timeout,
+            )?
+        } else {
+            Some(IssueMap::scan_possible_todo(
+                &possible_todo,
+                languages,
+                options,
+                &AtomicBool::new(false),
+            )?)
+        };
+        Ok(scanned.map(|(mut file_todos, file_messages)| {
+            if options.blame_assign {
+                file_todos.assign_blamed(dir, &options.assignee_map);
+            }
+            (file_todos, file_messages)
+        }))
+    }
+
+    /// Read and parse the files of a directory scan on a pool of the options'
+    /// `jobs` threads, no more than its `file_limit`, handing each file's
+    /// result to `merge` as soon as it and those before it are done. Results
+    /// are handed over in the order of the files given, however long each one
+    /// took, so merging them is deterministic, and only those that finish
+    /// ahead of a slower file are held in the meantime.
+    fn scan_possible_todos_in_parallel(
+        dir: &str,
+        files: Vec<(PossibleTodosInFile, &[SupportedLanguage])>,
+        options: &ScanOptions,
+        mut merge: impl FnMut(Result<Option<ScannedTodos>, String>),
+    ) {
+        let threads = options
+            .jobs
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+            .min(options.file_limit.max())
+            .min(files.len())
+            .max(1);
+        let queue = Mutex::new(files.into_iter().enumerate());
+        thread::scope(|scope| {
+            let (tx, rx) = mpsc::channel();
+            let workers = (0..threads)
+                .map(|_| {
+                    let tx = tx.clone();
+                    let queue = &queue;
+                    scope.spawn(move || loop {
+                        let next = queue.lock().unwrap_or_else(|e| e.into_inner()).next();
+                        let (index, (possible_todo, languages)) = match next {
+                            Some(next) => next,
+                            None => break,
                         };
-                        todos.add_parsed_todo(&parsed_todo, loc);
+                        let file_todos = IssueMap::scan_possible_todo_in_directory(
+                            dir,
+                            possible_todo,
+                            languages,
+                            options,
+                        );
+                        if tx.send((index, file_todos)).is_err() {
+                            break;
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            drop(tx);
+
+            let mut ahead = BTreeMap::new();
+            let mut next_index = 0;
+            for (index, file_todos) in rx {
+                ahead.insert(index, file_todos);
+                while let Some(file_todos) = ahead.remove(&next_index) {
+                    merge(file_todos);
+                    next_index += 1;
+                }
+            }
+            for worker in workers {
+                worker
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            }
+        });
+    }
+
+    /// Like `from_possible_todos_in_directory`, but returning the messages
+    /// about the todos instead of sending them.
+    pub fn scan_possible_todos_in_directory(
+        dir: &str,
+        mut possible_todos: Vec<PossibleTodosInFile>,
+        options: &ScanOptions,
+    ) -> Result<ScannedTodos, String> {
+        let mut messages = vec![];
+        let mut unsupported = BTreeMap::new();
+        let tracked = if options.git_tracked_only {
+            Some(FileSearcher::git_tracked_files(dir)?)
+        } else {
+            None
+        };
+        if let Some(tracked) = tracked.as_ref() {
+            possible_todos.retain(|todo| tracked.contains(Path::new(&todo.file)));
+        }
+        if let Some(since) = options.since.as_ref() {
+            let added = FileSearcher::git_added_lines(dir, since)?;
+            possible_todos.retain_mut(|todo| match added.get(Path::new(&todo.file)) {
+                Some(lines) => {
+                    todo.lines_to_search.retain(|line| lines.contains(line));
+                    !todo.lines_to_search.is_empty()
+                }
+                None => false,
+            });
+        }
+        let mut todos = IssueMap::new_source_todos().with_key(options.todo_key.clone());
+        let language_map = langs::language_map_with(&options.custom_languages);
+        let file_name_map = langs::file_name_map_with(&options.custom_languages);
+        let cached = options.cache.as_ref().map(|path| {
+            let checkout = github::git_hash(dir).unwrap_or_default();
+            ScanCache::read_or_new(path, &checkout, &options.cache_key())
+        });
+        let mut cache = cached
+            .as_ref()
+            .map(|cached| ScanCache::new(&cached.checkout, &cached.options));
+
+        // Each file's todos from the cache if it hasn't changed, or else
+        // parsed below, in the order they were found
+        let mut files_in_order = vec![];
+        let mut files = vec![];
+        let mut source_files = BTreeSet::new();
+        for possible_todo in possible_todos.into_iter() {
+            let path = Path::new(&possible_todo.file);
+
+            // Get our parser for this file name or extension, or for scripts
+            // without one, their shebang
+            let languages =
+                langs::find_languages(&language_map, &file_name_map, path).or_else(|| {
+                    if path.extension().is_some() {
+                        return None;
                     }
+                    let mut first_line = String::new();
+                    std::io::BufReader::new(File::open(path).ok()?)
+                        .read_line(&mut first_line)
+                        .ok()?;
+                    langs::find_languages_by_shebang(&language_map, &first_line)
+                });
+            let languages = match languages {
+                Some(languages) => languages,
+                None => {
+                    if options.report_unsupported_files {
+                        messages.push(Message::UnsupportedFile {
+                            path: possible_todo.file.clone(),
+                        });
+                    }
+                    *unsupported.entry(unsupported_kind(path)).or_insert(0) += 1;
+                    continue;
                 }
+            };
+            source_files.insert(possible_todo.file.clone());
+            let modified = cache::modified(&possible_todo.file);
+            let hit = cached
+                .as_ref()
+                .zip(modified)
+                .and_then(|(cached, modified)| {
+                    cached.get(
+                        &possible_todo.file,
+                        modified,
+                        &possible_todo.lines_to_search,
+                    )
+                })
+                .cloned();
+            let miss = hit.is_none();
+            files_in_order.push((
+                possible_todo.file.clone(),
+                possible_todo.lines_to_search.clone(),
+                modified,
+                hit,
+            ));
+            if miss {
+                files.push((possible_todo, languages.as_slice()));
             }
         }
 
-        Ok(todos)
+        // Merge each file's todos in the order they were found, the cached
+        // ones up to the next file being parsed, then that one once its scan
+        // is handed over. One bad file shouldn't stop the scan.
+        let mut files_in_order = files_in_order.into_iter().peekable();
+        let mut merge_in_order = |mut parsed: Option<Result<Option<ScannedTodos>, String>>| {
+            while let Some((_, _, _, hit)) = files_in_order.peek() {
+                if hit.is_none() && parsed.is_none() {
+                    return;
+                }
+                let (file, lines, modified, hit) = files_in_order.next().unwrap();
+                let file_todos = match hit {
+                    Some(hit) => Ok(Some((hit.with_key(options.todo_key.clone()), vec![]))),
+                    None => parsed.take().unwrap(),
+                };
+                let file_todos = match file_todos {
+                    Ok(Some((file_todos, file_messages))) => {
+                        // Files with messages are parsed again to report those
+                        if let (Some(cache), Some(modified)) = (cache.as_mut(), modified) {
+                            if file_messages.is_empty() {
+                                cache.insert(&file, modified, &lines, file_todos.clone());
+                            }
+                        }
+                        messages.extend(file_messages);
+                        file_todos
+                    }
+                    Ok(None) => {
+                        messages.push(Message::FileTimedOut { path: file });
+                        continue;
+                    }
+                    Err(reason) => {
+                        messages.push(Message::SkippedFile { path: file, reason });
+                        continue;
+                    }
+                };
+                if options.warn_conflicting_assignees {
+                    for (key, issue) in file_todos.todos.iter() {
+                        if let Some((_, loc)) = issue.body.descs_and_srcs.first() {
+                            messages.extend(todos.conflicting_assignees_message(
+                                key,
+                                &issue.head.assignees,
+                                loc,
+                            ));
+                        }
+                    }
+                }
+                todos.merge(file_todos);
+            }
+        };
+        IssueMap::scan_possible_todos_in_parallel(dir, files, options, |file_todos| {
+            merge_in_order(Some(file_todos))
+        });
+        merge_in_order(None);
+
+        if let (Some(path), Some(cache)) = (options.cache.as_ref(), cache) {
+            cache.write(path)?;
+        }
+        // After the cache, which keeps every todo in case the ignored titles
+        // change
+        let todos = todos.without_ignored_titles(&options.ignore_titles);
+        if options.todo_coverage {
+            // Files in a supported language by name or extension, along with
+            // the scripts found by their shebang above
+            for file in FileSearcher::files(dir, &options.excludes)? {
+                let path = Path::new(&file);
+                let tracked = tracked
+                    .as_ref()
+                    .is_none_or(|tracked| tracked.contains(path));
+                if tracked && langs::find_languages(&language_map, &file_name_map, path).is_some() {
+                    source_files.insert(file);
+                }
+            }
+            let files_with_todos = todos
+                .todos
+                .values()
+                .flat_map(|issue| issue.body.descs_and_srcs.iter())
+                .map(|(_, loc)| loc.file.as_str())
+                .collect::<BTreeSet<_>>()
+                .len();
+            let total_files = source_files.len();
+            messages.push(Message::TodoCoverage {
+                files_with_todos,
+                total_files,
+                pct: if total_files == 0 {
+                    0.0
+                } else {
+                    100.0 * files_with_todos as f32 / total_files as f32
+                },
+            });
+        }
+        if !unsupported.is_empty() {
+            messages.push(Message::UnsupportedSummary {
+                counts: unsupported,
+            });
+        }
+        Ok((todos, messages))
+    }
+
+    /// Write the todos as JSON for machines, eg. CI dashboards. Todos are
+    /// sorted by title and their locations by file then line, so the output
+    /// of two runs can be diffed. See `as_json_sorted` to sort todos by
+    /// something else.
+    ///
+    /// ```rust
+    /// use todo_finder_lib::parser::{source::ParsedTodo, FileTodoLocation, IssueMap};
+    ///
+    /// let mut issues = IssueMap::new_source_todos();
+    /// issues.add_parsed_todo(
+    ///     &ParsedTodo {
+    ///         title: "Do it.".into(),
+    ///         assignee: Some("schell"),
+    ///         desc_lines: vec!["Soon."],
+    ///         tag: "TODO",
+    ///         tags: vec![],
+    ///         priority: None,
+    ///         reference: None,
+    ///     },
+    ///     FileTodoLocation {
+    ///         file: "src/lib.rs".into(),
+    ///         src_span: (3, Some(4)),
+    ///     },
+    /// );
+    /// let json: serde_json::Value = serde_json::from_str(&issues.as_json()).unwrap();
+    /// assert_eq!(
+    ///     json,
+    ///     serde_json::json!({
+    ///         "todos": [{
+    ///             "id": "60aaf433",
+    ///             "title": "Do it.",
+    ///             "assignees": ["schell"],
+    ///             "tag": "TODO",
+    ///             "tags": [],
+    ///             "priority": null,
+    ///             "locations": [{
+    ///                 "file": "src/lib.rs",
+    ///                 "line": 3,
+    ///                 "end_line": 4,
+    ///                 "desc_lines": ["Soon."]
+    ///             }]
+    ///         }]
+    ///     })
+    /// );
+    /// ```
+    pub fn as_json(&self) -> String {
+        self.as_json_sorted(&SortOrder::default())
+    }
+
+    /// Like `as_json`, but with the todos in the given order.
+    pub fn as_json_sorted(&self, order: &SortOrder) -> String {
+        let mut todos = self.todos.values().collect::<Vec<_>>();
+        order.sort_issues(&mut todos);
+        let todos = todos
+            .into_iter()
+            .map(|issue| {
+                let mut locations = issue.body.descs_and_srcs.iter().collect::<Vec<_>>();
+                locations.sort_by(|a, b| (&a.1.file, a.1.src_span).cmp(&(&b.1.file, b.1.src_span)));
+                let locations = locations
+                    .into_iter()
+                    .map(|(desc_lines, loc)| {
+                        serde_json::json!({
+                            "file": loc.file,
+                            "line": loc.src_span.0,
+                            "end_line": loc.src_span.1,
+                            "desc_lines": desc_lines,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                serde_json::json!({
+                    "id": issue.id_by(&self.key),
+                    "title": issue.head.title,
+                    "assignees": issue.head.assignees,
+                    "tag": issue.head.tag,
+                    "tags": issue.head.tags,
+                    "priority": issue.head.priority,
+                    "locations": locations,
+                })
+            })
+            .collect::<Vec<_>>();
+        // Pretty printed
+        format!("{:#}", serde_json::json!({ "todos": todos }))
     }
 
     pub fn as_markdown(&self) -> String {
+        self.as_markdown_with(&MarkdownOptions::default())
+    }
+
+    /// Like `as_markdown`, but sorted, grouped and linked as given.
+    pub fn as_markdown_with(&self, options: &MarkdownOptions) -> String {
+        let mut markdown = vec![];
+        self.write_markdown_with(&mut markdown, options)
+            .expect("writing to a Vec can't fail");
+        String::from_utf8(markdown).expect("markdown is written from strings")
+    }
+
+    /// Like `as_markdown_with`, but wrapped in the managed markers, see
+    /// `issue::MANAGED_START`, and replacing only the managed part of the
+    /// `current` markdown, so notes written around it by hand are kept. A
+    /// file without markers is replaced whole, as there's no telling what to
+    /// keep.
+    ///
+    /// ```rust
+    /// use todo_finder_lib::parser::{IssueMap, MarkdownOptions, ParsingSource};
+    ///
+    /// let todos: IssueMap<(), _> = IssueMap::new(ParsingSource::MarkdownFile);
+    /// let options = MarkdownOptions::default();
+    /// let generated = todos.update_markdown("", &options);
+    /// let current = format!("Our notes.\n\n{}\n\nMore notes.\n", generated);
+    /// assert_eq!(todos.update_markdown(&current, &options), current);
+    /// ```
+    pub fn update_markdown(&self, current: &str, options: &MarkdownOptions) -> String {
+        let mut markdown = vec![];
+        self.write_updated_markdown(&mut markdown, current, options)
+            .expect("writing to a Vec can't fail");
+        String::from_utf8(markdown).expect("markdown is written from strings")
+    }
+
+    /// Write the same markdown as `update_markdown`, streaming the todos
+    /// like `write_markdown` does.
+    pub fn write_updated_markdown<W: Write>(
+        &self,
+        w: &mut W,
+        current: &str,
+        options: &MarkdownOptions,
+    ) -> std::io::Result<()> {
+        let current = current.replace("\r\n", "\n");
+        let (before, after) = match managed_span(&current) {
+            Some((start, end)) => (&current[..start], &current[end..]),
+            None => ("", ""),
+        };
+        writeln!(w, "{}{}", before, MANAGED_START)?;
+        self.write_markdown_with(&mut TrimmedEnd::new(&mut *w), options)?;
+        write!(w, "\n{}{}", MANAGED_END, after)
+    }
+
+    /// Write the same markdown as `as_markdown`, a todo at a time, so the
+    /// whole report is never held in memory.
+    pub fn write_markdown<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        self.write_markdown_sorted(w, &SortOrder::default())
+    }
+
+    /// Like `write_markdown`, but with the todos in the given order.
+    pub fn write_markdown_sorted<W: Write>(
+        &self,
+        w: &mut W,
+        order: &SortOrder,
+    ) -> std::io::Result<()> {
+        self.write_markdown_with(
+            w,
+            &MarkdownOptions {
+                order: *order,
+                ..MarkdownOptions::default()
+            },
+        )
+    }
+
+    /// Like `write_markdown`, but sorted, grouped and linked as given. With
+    /// an `anchor_format` the lines of each location are anchored in its
+    /// link, eg. `file://src/lib.rs:3 (line 3)` for `:{start}`.
+    pub fn write_markdown_with<W: Write>(
+        &self,
+        w: &mut W,
+        options: &MarkdownOptions,
+    ) -> std::io::Result<()> {
         let num_distinct = self.todos.len();
         let num_locs = self
             .todos
             .values()
             .fold(0, |n, todo| n + todo.body.descs_and_srcs.len());
 
-        let mut lines = vec![];
-
-        lines.push("# TODOs".into());
-        lines.push(format!(
-            "Found {} distinct TODOs in {} file locations.\n",
+        write!(w, "# TODOs")?;
+        write!(
+            w,
+            "\nFound {} distinct TODOs in {} file locations.\n",
             num_distinct, num_locs
-        ));
+        )?;
 
-        let mut todos = self.todos.clone().into_iter().collect::<Vec<_>>();
-        todos.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut todos = self.todos.values().collect::<Vec<_>>();
+        options.order.sort_issues(&mut todos);
+        let anchor_format = options.anchor_format.as_deref();
 
-        for ((title, issue), n) in todos.into_iter().zip(1..) {
-            lines.push(format!("{}. {}", n, title));
-            for (descs, loc) in issue.body.descs_and_srcs.into_iter() {
-                for line in descs.into_iter() {
-                    lines.push(format!("  {}", line));
+        match options.group_by {
+            GroupBy::Todo => {
+                for (issue, n) in todos.into_iter().zip(1..) {
+                    let locations = issue.body.descs_and_srcs.iter().collect::<Vec<_>>();
+                    let id = issue.id_by(&self.key);
+                    write_markdown_todo(w, n, Some(&id), issue, &locations, anchor_format)?;
+                }
+            }
+            GroupBy::File => {
+                let mut files = BTreeSet::new();
+                for issue in todos.iter() {
+                    files.extend(issue.body.descs_and_srcs.iter().map(|(_, loc)| &loc.file));
                 }
-                lines.push(format!(
-                    "  file://{} ({})",
-                    loc.file,
-                    if let Some(end) = loc.src_span.1 {
-                        format!("lines {} - {}", loc.src_span.0, end)
-                    } else {
-                        format!("line {}", loc.src_span.0)
+                let mut n = 1;
+                // A todo in more than one file is anchored in the first only,
+                // as HTML ids must be unique
+                let mut anchored = HashSet::new();
+                for file in files {
+                    write!(w, "\n## {}\n", file)?;
+                    for issue in todos.iter() {
+                        let locations = issue
+                            .body
+                            .descs_and_srcs
+                            .iter()
+                            .filter(|(_, loc)| &loc.file == file)
+                            .collect::<Vec<_>>();
+                        if !locations.is_empty() {
+                            let id = issue.id_by(&self.key);
+                            let anchor = Some(id.as_str()).filter(|_| anchored.insert(id.clone()));
+                            write_markdown_todo(w, n, anchor, issue, &locations, anchor_format)?;
+                            n += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        write!(w, "\n{}", AssigneeStats::from_issue_map(self).as_markdown())?;
+
+        let duplicates = DuplicateTodos::from_issue_map(self);
+        if !duplicates.is_empty() {
+            write!(w, "\n\n{}", duplicates.as_markdown())?;
+        }
+        Ok(())
+    }
+}
+
+/// A writer that holds back newlines until something else is written after
+/// them, so that what it writes doesn't end with any.
+struct TrimmedEnd<W> {
+    inner: W,
+    newlines: usize,
+}
+
+impl<W: Write> TrimmedEnd<W> {
+    fn new(inner: W) -> Self {
+        TrimmedEnd { inner, newlines: 0 }
+    }
+}
+
+impl<W: Write> Write for TrimmedEnd<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let trailing = buf.iter().rev().take_while(|b| **b == b'\n').count();
+        if trailing < buf.len() {
+            self.inner.write_all(&b"\n".repeat(self.newlines))?;
+            self.inner.write_all(&buf[..buf.len() - trailing])?;
+            self.newlines = 0;
+        }
+        self.newlines += trailing;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Write one numbered todo of the markdown output, with the given locations
+/// of it, and the anchor of its id if given.
+fn write_markdown_todo<K, W: Write>(
+    w: &mut W,
+    n: usize,
+    id: Option<&str>,
+    issue: &Issue<K, FileTodoLocation>,
+    locations: &[&(Vec<String>, FileTodoLocation)],
+    anchor_format: Option<&str>,
+) -> std::io::Result<()> {
+    write!(w, "\n{}. ", n)?;
+    if let Some(id) = id {
+        write!(w, "<a id=\"todo-{}\"></a>", id)?;
+    }
+    write!(w, "{}", issue.head.title)?;
+    for (descs, loc) in locations.iter() {
+        for line in descs.iter() {
+            write!(w, "\n  {}", line)?;
+        }
+        let anchor = anchor_format
+            .map(|format| line_anchor(format, loc.src_span.0, loc.src_span.1))
+            .unwrap_or_default();
+        write!(w, "\n  file://{}{} (", loc.file, anchor)?;
+        if let Some(end) = loc.src_span.1 {
+            write!(w, "lines {} - {})", loc.src_span.0, end)?;
+        } else {
+            write!(w, "line {})", loc.src_span.0)?;
+        }
+        writeln!(w)?;
+    }
+    if !issue.head.assignees.is_empty() {
+        write!(w, "\n  assignees: {}\n", issue.head.assignees.join(", "))?;
+    }
+    if let Some(priority) = issue.head.priority {
+        write!(w, "\n  priority: p{}\n", priority)?;
+    }
+    if !issue.head.tags.is_empty() {
+        let tags = issue
+            .head
+            .tags
+            .iter()
+            .map(|tag| format!("#{}", tag))
+            .collect::<Vec<_>>();
+        write!(w, "\n  tags: {}\n", tags.join(", "))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rust() -> Vec<SupportedLanguage> {
+        langs::language_map().get("rs").unwrap().clone()
+    }
+
+    #[test]
+    fn can_merge_todos_from_files() {
+        let contents = "// TODO(schell): Do the thing.\nfn main() {}\n";
+        let mut todos =
+            IssueMap::from_file_contents("a.rs", contents, &[1], &rust(), &ScanOptions::default())
+                .unwrap();
+        let contents = "fn main() {}\n// TODO(mitchellwrosen): Do the thing.\n";
+        todos.merge(
+            IssueMap::from_file_contents("b.rs", contents, &[2], &rust(), &ScanOptions::default())
+                .unwrap(),
+        );
+
+        let issue = todos.todos.get("Do the thing.").unwrap();
+        assert_eq!(issue.head.assignees, vec!["schell", "mitchellwrosen"]);
+        assert_eq!(
+            issue
+                .body
+                .descs_and_srcs
+                .iter()
+                .map(|(_, loc)| (loc.file.as_str(), loc.src_span.0))
+                .collect::<Vec<_>>(),
+            vec![("a.rs", 1), ("b.rs", 2)]
+        );
+    }
+
+    #[test]
+    fn assignees_are_deduplicated_ignoring_case() {
+        let contents =
+            "// TODO(Alice): Do the thing.\nfn main() {}\n// TODO(alice): Do the thing.\n";
+        let mut todos = IssueMap::from_file_contents(
+            "a.rs",
+            contents,
+            &[1, 3],
+            &rust(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        let contents = "// TODO(ALICE): Do the thing.\n// TODO(bob): Do the thing.\n";
+        todos.merge(
+            IssueMap::from_file_contents(
+                "b.rs",
+                contents,
+                &[1, 2],
+                &rust(),
+                &ScanOptions::default(),
+            )
+            .unwrap(),
+        );
+
+        let issue = todos.todos.get("Do the thing.").unwrap();
+        assert_eq!(issue.head.assignees, vec!["Alice", "bob"]);
+        assert_eq!(issue.body.descs_and_srcs.len(), 4);
+        assert!(todos.as_markdown().contains("  assignees: Alice, bob\n"));
+    }
+
+    #[test]
+    fn the_most_urgent_priority_wins() {
+        let contents = "// TODO(p2): Do the thing.\nfn main() {}\n// TODO!!: Do the thing.\n";
+        let mut todos = IssueMap::from_file_contents(
+            "a.rs",
+            contents,
+            &[1, 3],
+            &rust(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        let contents = "// TODO(P3): Do the thing.\n// TODO: Do another.\n";
+        todos.merge(
+            IssueMap::from_file_contents(
+                "b.rs",
+                contents,
+                &[1, 2],
+                &rust(),
+                &ScanOptions::default(),
+            )
+            .unwrap(),
+        );
+
+        let issue = todos.todos.get("Do the thing.").unwrap();
+        assert!(issue.head.assignees.is_empty());
+        assert_eq!(issue.head.priority, Some(1));
+        assert_eq!(todos.todos.get("Do another.").unwrap().head.priority, None);
+        assert!(todos.as_markdown().contains("  priority: p1\n"));
+    }
+
+    #[test]
+    fn moved_todos_keep_their_issues() {
+        let mut remote: IssueMap<u64, FileTodoLocation> = IssueMap::new(ParsingSource::SourceCode);
+        for (id, title, file, line) in [
+            (1, "Follow me.", "old.rs", 2),
+            (2, "Old wording.", "old.rs", 5),
+            (3, "Gone.", "gone.rs", 1),
+        ] {
+            remote.todos.insert(
+                title.into(),
+                Issue {
+                    head: IssueHead {
+                        title: title.into(),
+                        assignees: vec![],
+                        external_id: id,
+                        tag: None,
+                        tags: vec![],
+                        priority: None,
+                        wontfix: false,
+                        reference: None,
+                    },
+                    body: IssueBody {
+                        descs_and_srcs: vec![(
+                            vec![],
+                            FileTodoLocation {
+                                file: file.into(),
+                                src_span: (line, None),
+                            },
+                        )],
+                        branches: vec![],
+                    },
+                },
+            );
+        }
+        // old.rs was renamed to new.rs and one of its todos reworded
+        let contents = "fn a() {}\n// TODO: Follow me.\nfn b() {}\n\n// TODO: New wording.\n";
+        let local = || {
+            IssueMap::from_file_contents(
+                "new.rs",
+                contents,
+                &[2, 5],
+                &rust(),
+                &ScanOptions::default(),
+            )
+            .unwrap()
+        };
+        let summary = |patch: Patch<u64>| {
+            let mut create = patch.create.todos.into_keys().collect::<Vec<_>>();
+            create.sort();
+            let mut edit = patch
+                .edit
+                .todos
+                .values()
+                .map(|issue| (issue.head.title.clone(), issue.head.external_id))
+                .collect::<Vec<_>>();
+            edit.sort();
+            let mut delete = patch.delete;
+            delete.sort();
+            (create, edit, delete)
+        };
+
+        assert_eq!(
+            summary(remote.prepare_patch(local())),
+            (
+                vec!["New wording.".to_string()],
+                vec![("Follow me.".to_string(), 1)],
+                vec![2, 3]
+            )
+        );
+        let is_moved = |remote: &FileTodoLocation, local: &FileTodoLocation| {
+            remote.file == "old.rs" && local.file == "new.rs" && remote.src_span == local.src_span
+        };
+        assert_eq!(
+            summary(remote.prepare_patch_following_moves(local(), is_moved)),
+            (
+                vec![],
+                vec![
+                    ("Follow me.".to_string(), 1),
+                    ("New wording.".to_string(), 2)
+                ],
+                vec![3]
+            )
+        );
+    }
+
+    #[test]
+    fn retitled_todos_keep_similar_issues() {
+        let mut remote: IssueMap<u64, FileTodoLocation> = IssueMap::new(ParsingSource::SourceCode);
+        for (id, title, lines) in [
+            (1, "Fix the parser.", vec![1]),
+            (2, "Speed up the finder.", vec![2, 3]),
+        ] {
+            let mut issue = Issue::new(id, title.to_string());
+            for line in lines {
+                issue.body.descs_and_srcs.push((
+                    vec![],
+                    FileTodoLocation {
+                        file: "a.rs".into(),
+                        src_span: (line, None),
                     },
                 ));
-                lines.push("".into());
             }
-            if issue.head.assignees.len() > 0 {
-                lines.push(format!(
-                    "  assignees: {}\n",
-                    issue.head.assignees.join(", ")
-                ));
+            remote.insert(issue);
+        }
+        let contents = "// TODO: Fix the parsers.
+// TODO: Speed up the finders.
+";
+        let local = || {
+            IssueMap::from_file_contents(
+                "a.rs",
+                contents,
+                &[1, 2],
+                &rust(),
+                &ScanOptions::default(),
+            )
+            .unwrap()
+        };
+        let patch = |similarity| {
+            let patch = remote.prepare_patch_following_edits(local(), |_, _| false, similarity);
+            let mut create = patch.create.todos.into_keys().collect::<Vec<_>>();
+            create.sort();
+            let edit = patch
+                .edit
+                .todos
+                .into_values()
+                .map(|issue| (issue.head.title, issue.head.external_id))
+                .collect::<Vec<_>>();
+            let mut delete = patch.delete;
+            delete.sort();
+            (create, edit, delete)
+        };
+        let all_new = (
+            vec![
+                "Fix the parsers.".to_string(),
+                "Speed up the finders.".to_string(),
+            ],
+            vec![],
+            vec![1, 2],
+        );
+
+        assert_eq!(patch(None), all_new);
+        // Issues with more than one location never change hands
+        assert_eq!(
+            patch(Some(0.8)),
+            (
+                vec!["Speed up the finders.".to_string()],
+                vec![("Fix the parsers.".to_string(), 1)],
+                vec![2]
+            )
+        );
+        assert_eq!(patch(Some(0.99)), all_new);
+    }
+
+    #[test]
+    fn todo_ids_are_stable_and_unique() {
+        let contents = "// TODO: Do a.\n// TODO: Do b.\nfn main() {}\n// TODO: Do a.\n";
+        let scan = |file| {
+            IssueMap::from_file_contents(
+                file,
+                contents,
+                &[1, 2, 4],
+                &rust(),
+                &ScanOptions::default(),
+            )
+            .unwrap()
+        };
+        let ids = |issues: &IssueMap<(), FileTodoLocation>| {
+            let mut ids = issues
+                .todos
+                .values()
+                .map(|issue| (issue.head.title.clone(), issue.id()))
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        // Ids don't change between runs or when a todo moves
+        let todos = scan("a.rs");
+        assert_eq!(ids(&todos), ids(&scan("a.rs")));
+        assert_eq!(ids(&todos), ids(&scan("b.rs")));
+        assert_eq!(todo_id("Do a."), "41219525");
+
+        let markdown = todos.as_markdown();
+        let json = serde_json::to_value(&todos).unwrap();
+        for (title, id) in ids(&todos) {
+            assert!(markdown.contains(&format!("<a id=\"todo-{}\"></a>{}", id, title)));
+            assert_eq!(json["todos"][&title]["id"], id);
+        }
+
+        let ids = (0..10_000)
+            .map(|n| todo_id(&format!("Do {}.", n)))
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(ids.len(), 10_000);
+    }
+
+    #[test]
+    fn todos_told_apart_by_key_get_their_own_ids() {
+        let contents = "// TODO: Fix this.\n// In the parser.\nfn a() {}\n\
+                        // TODO: Fix this.\n// In the finder.\n";
+        let options = ScanOptions {
+            todo_key: TodoKey::FirstLine,
+            ..ScanOptions::default()
+        };
+        let todos =
+            IssueMap::from_file_contents("a.rs", contents, &[1, 4], &rust(), &options).unwrap();
+        assert_eq!(todos.todos.len(), 2);
+
+        let markdown = todos.as_markdown();
+        let json = serde_json::to_value(&todos).unwrap();
+        let mut ids = vec![];
+        for (key, issue) in todos.todos.iter() {
+            let id = issue.id_by(&TodoKey::FirstLine);
+            assert_eq!(id, todo_id(key));
+            assert!(
+                markdown.contains(&format!("<a id=\"todo-{}\">", id)),
+                "{}",
+                markdown
+            );
+            assert_eq!(json["todos"][key]["id"], id);
+            ids.push(id);
+        }
+        assert_ne!(ids[0], ids[1]);
+    }
+
+    #[test]
+    fn streamed_markdown_matches_the_string() {
+        /// A writer that takes at most a few bytes at a time, like a pipe.
+        struct Trickle(Vec<u8>);
+
+        impl Write for Trickle {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                let n = buf.len().min(3);
+                self.0.extend_from_slice(&buf[..n]);
+                Ok(n)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
             }
         }
 
-        lines.join("\n")
+        let options = ScanOptions::default();
+        let mut todos = IssueMap::from_file_contents(
+            "src/main.rs",
+            "// TODO!!(bob): Do A. #backend\n//   Carefully.\n/* FIXME: Fix B.\n * Now.\n */\n",
+            &[1, 3],
+            &rust(),
+            &options,
+        )
+        .unwrap();
+        todos.merge(
+            IssueMap::from_file_contents(
+                "src/lib.rs",
+                "// TODO: Fix B.\n",
+                &[1],
+                &rust(),
+                &options,
+            )
+            .unwrap(),
+        );
+
+        let mut streamed = Trickle(vec![]);
+        todos.write_markdown(&mut streamed).unwrap();
+        let streamed = String::from_utf8(streamed.0).unwrap();
+        assert_eq!(streamed, todos.as_markdown());
+        assert_eq!(
+            streamed,
+            format!(
+                "# TODOs\nFound 2 distinct TODOs in 3 file locations.\n\n\
+                 1. <a id=\"todo-{}\"></a>Do A.\n  Carefully.\n  file://src/main.rs (lines 1 - 2)\n\n\
+                 \x20 assignees: bob\n\n  priority: p1\n\n  tags: #backend\n\n\
+                 2. <a id=\"todo-{}\"></a>Fix B.\n  Now.\n  file://src/main.rs (lines 3 - 5)\n\n\
+                 \x20 file://src/lib.rs (line 1)\n\n\
+                 {}\n\n{}",
+                todo_id("Do A."),
+                todo_id("Fix B."),
+                AssigneeStats::from_issue_map(&todos).as_markdown(),
+                DuplicateTodos::from_issue_map(&todos).as_markdown()
+            )
+        );
+    }
+
+    #[test]
+    fn file_limit_bounds_open_files() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let limit = FileLimit::new(3);
+        let open = Arc::new(AtomicUsize::new(0));
+        let most_open = Arc::new(AtomicUsize::new(0));
+        let threads = (0..16)
+            .map(|_| {
+                let (limit, open, most_open) = (limit.clone(), open.clone(), most_open.clone());
+                thread::spawn(move || {
+                    let _open_file = limit.acquire();
+                    let n = open.fetch_add(1, Ordering::SeqCst) + 1;
+                    most_open.fetch_max(n, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(5));
+                    open.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        // How many threads overlap depends on scheduling, only the cap is fixed
+        let most_open = most_open.load(Ordering::SeqCst);
+        assert!(
+            (1..=3).contains(&most_open),
+            "{} files were open",
+            most_open
+        );
+
+        // Files left open by timed out threads count against the limit too
+        let options = ScanOptions {
+            file_limit: FileLimit::new(1),
+            ..ScanOptions::default()
+        };
+        let guard = options.file_limit.acquire();
+        let possible_todo =
+            PossibleTodosInFile::new(concat!(env!("CARGO_MANIFEST_DIR"), "/src/lib.rs"), vec![1]);
+        let res = IssueMap::from_possible_todo_with_timeout(
+            possible_todo.clone(),
+            rust(),
+            options.clone(),
+            Duration::from_millis(20),
+        );
+        assert!(matches!(res, Ok(None)));
+        drop(guard);
+        let res = IssueMap::from_possible_todo_with_timeout(
+            possible_todo,
+            rust(),
+            options,
+            Duration::from_secs(10),
+        );
+        assert!(matches!(res, Ok(Some(_))));
+    }
+
+    #[test]
+    fn json_is_sorted_by_title_then_location() {
+        let contents = "// TODO: Do b.\n// TODO: Do a.\nfn main() {}\n// TODO: Do a.\n";
+        let scan = |file| {
+            IssueMap::from_file_contents(
+                file,
+                contents,
+                &[1, 2, 4],
+                &rust(),
+                &ScanOptions::default(),
+            )
+            .unwrap()
+        };
+        let mut todos = scan("b.rs");
+        todos.merge(scan("a.rs"));
+        let json: serde_json::Value = serde_json::from_str(&todos.as_json()).unwrap();
+        let summary = json["todos"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|todo| {
+                let locations = todo["locations"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|loc| format!("{}:{}", loc["file"].as_str().unwrap(), loc["line"]))
+                    .collect::<Vec<_>>();
+                (todo["title"].as_str().unwrap().to_string(), locations)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "Do a.".to_string(),
+                    vec!["a.rs:2", "a.rs:4", "b.rs:2", "b.rs:4"]
+                        .into_iter()
+                        .map(String::from)
+                        .collect::<Vec<_>>()
+                ),
+                ("Do b.".to_string(), vec!["a.rs:1".into(), "b.rs:1".into()]),
+            ]
+        );
+        // The same todos found in another order are written the same
+        let mut todos_again = scan("a.rs");
+        todos_again.merge(scan("b.rs"));
+        assert_eq!(todos.as_json(), todos_again.as_json());
+    }
+
+    #[test]
+    fn json_and_markdown_can_be_sorted_by_each_key() {
+        let scan = |file, contents, lines: &[usize]| {
+            IssueMap::from_file_contents(file, contents, lines, &rust(), &ScanOptions::default())
+                .unwrap()
+        };
+        let mut todos = scan("a.rs", "fn a() {}\n// TODO(zed): Cache it.\n", &[2]);
+        todos.merge(scan("b.rs", "// BUG: Crashes on empty input.\n", &[1]));
+        todos.merge(scan(
+            "c.rs",
+            "// NOTE(amy): Add docs.\n\n// TODO(p0): Drop the old API.\n",
+            &[1, 3],
+        ));
+        let titles = |key, reverse| {
+            let order = SortOrder { key, reverse };
+            let json: serde_json::Value =
+                serde_json::from_str(&todos.as_json_sorted(&order)).unwrap();
+            let titles = json["todos"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|todo| todo["title"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>();
+            // Markdown lists the todos in the same order
+            let mut markdown = vec![];
+            todos.write_markdown_sorted(&mut markdown, &order).unwrap();
+            let markdown = String::from_utf8(markdown).unwrap();
+            let positions = titles
+                .iter()
+                .map(|title| markdown.find(title.as_str()).unwrap())
+                .collect::<Vec<_>>();
+            assert!(positions.windows(2).all(|w| w[0] < w[1]), "{:?}", titles);
+            titles
+        };
+
+        assert_eq!(
+            titles(SortKey::Title, false),
+            vec![
+                "Add docs.",
+                "Cache it.",
+                "Crashes on empty input.",
+                "Drop the old API."
+            ]
+        );
+        assert_eq!(
+            titles(SortKey::File, false),
+            vec![
+                "Cache it.",
+                "Crashes on empty input.",
+                "Add docs.",
+                "Drop the old API."
+            ]
+        );
+        assert_eq!(
+            titles(SortKey::Line, false),
+            vec![
+                "Crashes on empty input.",
+                "Add docs.",
+                "Cache it.",
+                "Drop the old API."
+            ]
+        );
+        assert_eq!(
+            titles(SortKey::Assignee, false),
+            vec![
+                "Add docs.",
+                "Cache it.",
+                "Crashes on empty input.",
+                "Drop the old API."
+            ]
+        );
+        assert_eq!(
+            titles(SortKey::Severity, false),
+            vec![
+                "Drop the old API.",
+                "Crashes on empty input.",
+                "Cache it.",
+                "Add docs."
+            ]
+        );
+        assert_eq!(
+            titles(SortKey::Title, true),
+            vec![
+                "Drop the old API.",
+                "Crashes on empty input.",
+                "Cache it.",
+                "Add docs."
+            ]
+        );
+        assert_eq!(todos.as_json(), todos.as_json_sorted(&SortOrder::default()));
+    }
+
+    #[test]
+    fn macro_tags_are_only_matched_in_their_language() {
+        let contents = "fn a() {\n    todo!(\"Write a.\")\n}\n";
+        let todos =
+            IssueMap::from_file_contents("a.rs", contents, &[2], &rust(), &ScanOptions::default())
+                .unwrap();
+        assert!(todos.todos.contains_key("Write a."));
+
+        let python = langs::language_map().get("py").unwrap().clone();
+        let contents = "def a():\n    todo!(\"Write a.\")\n";
+        let todos =
+            IssueMap::from_file_contents("a.py", contents, &[2], &python, &ScanOptions::default())
+                .unwrap();
+        assert!(todos.todos.is_empty());
+
+        // Languages from a config file may have macros of their own
+        let config = crate::config::Config::parse(
+            "[[languages]]\nname = \"Pipeline\"\nextensions = [\"pipe\"]\n\
+             single = [\"%%\"]\nmacros = [\"tbd!\"]\n",
+        )
+        .unwrap();
+        let options = ScanOptions {
+            custom_languages: config.supported_languages(),
+            ..ScanOptions::default()
+        };
+        let contents = "step = tbd!(\"Pick a step.\")\n";
+        let todos =
+            IssueMap::from_contents_with_extension("a.pipe", contents, "pipe", &options).unwrap();
+        assert!(todos.todos.contains_key("Pick a step."));
+        let todos =
+            IssueMap::from_contents_with_extension("a.py", contents, "py", &options).unwrap();
+        assert!(todos.todos.is_empty());
+    }
+
+    #[test]
+    fn can_find_todos_in_attribute_notes() {
+        let contents = "#[deprecated(note = \"TODO: remove in 2.0\")]\npub fn a() {}\n\
+                        #[allow(unused, reason = \"Not a todo.\")]\nfn b() {}\n";
+        let todos = IssueMap::from_file_contents(
+            "a.rs",
+            contents,
+            &[1, 3],
+            &rust(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            todos.todos.keys().collect::<Vec<_>>(),
+            vec!["remove in 2.0"]
+        );
+
+        let c = langs::language_map().get("c").unwrap().clone();
+        let todos =
+            IssueMap::from_file_contents("a.c", contents, &[1], &c, &ScanOptions::default())
+                .unwrap();
+        assert!(todos.todos.is_empty());
+    }
+
+    #[test]
+    fn can_find_conflicting_assignees() {
+        let todos = IssueMap::from_file_contents(
+            "a.rs",
+            "// TODO(alice): Do the thing.\n// TODO: Do another thing.\n",
+            &[1, 2],
+            &rust(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        let other = IssueMap::from_file_contents(
+            "b.rs",
+            "// TODO(bob): Do the thing.\n// TODO(carol): Do another thing.\n",
+            &[1, 2],
+            &rust(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+
+        let bobs = &other.todos["Do the thing."].head.assignees;
+        assert_eq!(
+            todos.conflicting_assignees("Do the thing.", bobs),
+            Some(&["alice".to_string()][..])
+        );
+        assert_eq!(
+            todos.conflicting_assignees("Do the thing.", &["Alice"]),
+            None
+        );
+        // Unassigned todos don't conflict with anyone
+        let carols = &other.todos["Do another thing."].head.assignees;
+        assert_eq!(
+            todos.conflicting_assignees("Do another thing.", carols),
+            None
+        );
+    }
+
+    #[test]
+    fn can_find_conflicting_assignees_by_key() {
+        let options = ScanOptions {
+            todo_key: TodoKey::FirstLine,
+            warn_conflicting_assignees: true,
+            ..ScanOptions::default()
+        };
+        let contents = "// TODO(alice): Do the thing.\n// In a.\n\
+                        // TODO(bob): Do the thing.\n// In a.\n";
+        let (todos, messages) =
+            IssueMap::scan_file_contents("a.rs", contents, &[1, 3], &rust(), &options).unwrap();
+        let key = TodoKey::FirstLine.key("Do the thing.", &["In a.".to_string()]);
+        assert_eq!(
+            todos.conflicting_assignees(&key, &["carol"]),
+            Some(&["alice".to_string(), "bob".to_string()][..])
+        );
+        // Reported with the todo's title rather than its key
+        assert_eq!(
+            messages,
+            vec![Message::ConflictingAssignees {
+                title: "Do the thing.".into(),
+                path: "a.rs".into(),
+                line: 3,
+                assignees: vec!["bob".into()],
+                others: vec!["alice".into()],
+            }]
+        );
+    }
+
+    #[test]
+    fn can_strip_doubled_tags_from_titles() {
+        let contents = "// TODO: TODO: Fix x.\n";
+        let todos =
+            IssueMap::from_file_contents("a.rs", contents, &[1], &rust(), &ScanOptions::default())
+                .unwrap();
+        assert!(todos.todos.contains_key("TODO: Fix x."));
+
+        let options = ScanOptions {
+            strip_tag_from_title: true,
+            ..ScanOptions::default()
+        };
+        let contents = "// TODO: TODO: Fix x.\n// FIXME FIXME(schell): Fix y.\n";
+        let todos =
+            IssueMap::from_file_contents("a.rs", contents, &[1, 2], &rust(), &options).unwrap();
+        let mut titles = todos.todos.keys().collect::<Vec<_>>();
+        titles.sort();
+        assert_eq!(titles, vec!["Fix x.", "Fix y."]);
+    }
+
+    #[test]
+    fn can_skip_todos_in_doc_examples() {
+        let contents = "/// Parses a config.\n\
+                        ///\n\
+                        /// ```\n\
+                        /// // TODO: Handle the error.\n\
+                        /// let cfg = parse(\"\").unwrap();\n\
+                        /// ```\n\
+                        ///\n\
+                        /// ```text\n\
+                        /// TODO: Write the docs.\n\
+                        /// ```\n\
+                        /// TODO: Support YAML.\n\
+                        fn parse(s: &str) {}\n";
+        let lines = [4, 9, 11];
+        let todos = IssueMap::from_file_contents(
+            "a.rs",
+            contents,
+            &lines,
+            &rust(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        assert!(todos.todos.contains_key("Write the docs."));
+
+        let options = ScanOptions {
+            skip_doc_examples: true,
+            ..ScanOptions::default()
+        };
+        let todos =
+            IssueMap::from_file_contents("a.rs", contents, &lines, &rust(), &options).unwrap();
+        assert_eq!(
+            todos.todos.keys().collect::<Vec<_>>(),
+            vec!["Support YAML."]
+        );
+    }
+
+    #[test]
+    fn can_merge_adjacent_comment_blocks() {
+        let contents = "// TODO: Split this up.\n// It is too long.\n\n// Into three parts.\nfn \
+                        main() {}\n";
+        let options = ScanOptions {
+            merge_adjacent: true,
+            ..ScanOptions::default()
+        };
+        let todos =
+            IssueMap::from_file_contents("a.rs", contents, &[1], &rust(), &options).unwrap();
+        let (descs, loc) = &todos.todos["Split this up."].body.descs_and_srcs[0];
+        assert_eq!(descs, &vec!["It is too long.", "Into three parts."]);
+        assert_eq!(loc.src_span, (1, Some(4)));
+
+        let todos =
+            IssueMap::from_file_contents("a.rs", contents, &[1], &rust(), &ScanOptions::default())
+                .unwrap();
+        let (descs, _) = &todos.todos["Split this up."].body.descs_and_srcs[0];
+        assert_eq!(descs, &vec!["It is too long."]);
+
+        let contents = "// TODO: Split this up.\nfn main() {}\n// Into three parts.\n";
+        let todos =
+            IssueMap::from_file_contents("a.rs", contents, &[1], &rust(), &options).unwrap();
+        let (descs, loc) = &todos.todos["Split this up."].body.descs_and_srcs[0];
+        assert!(descs.is_empty());
+        assert_eq!(loc.src_span, (1, None));
+    }
+
+    #[test]
+    fn skips_binary_files() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        let file = dir.join("blob.rs");
+        std::fs::write(&file, b"\x00\x01\xff// TODO: Not really a todo.\n\xfe").unwrap();
+        let possible_todo = PossibleTodosInFile::new(file.to_str().unwrap(), vec![1]);
+
+        let (todos, messages) = IssueMap::scan_possible_todo(
+            &possible_todo,
+            &rust(),
+            &ScanOptions::default(),
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+        assert!(todos.todos.is_empty());
+        assert_eq!(
+            messages,
+            vec![Message::BinaryFile {
+                path: possible_todo.file.clone()
+            }]
+        );
+    }
+
+    #[test]
+    fn bad_files_are_skipped() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        // "café" in latin-1
+        std::fs::write(dir.join("latin1.rs"), b"// TODO: Order a caf\xe9.\n").unwrap();
+        std::fs::write(dir.join("good.rs"), "// TODO: Keep going.\n").unwrap();
+        let options = ScanOptions {
+            search_backend: SearchBackend::Builtin,
+            ..ScanOptions::default()
+        };
+
+        let todos = IssueMap::from_files_in_directory_with_options(dir.to_str().unwrap(), &options)
+            .unwrap();
+        assert_eq!(todos.todos.keys().collect::<Vec<_>>(), vec!["Keep going."]);
+    }
+
+    #[test]
+    fn only_todos_added_since_a_revision_are_found() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/lib.rs"), "// TODO: Old.\nfn a() {}\n").unwrap();
+        std::fs::write(dir.join("src/same.rs"), "// TODO: Untouched.\n").unwrap();
+        let dir_str = dir.to_str().unwrap();
+        let git = |args: &[&str]| crate::utils::command("git", args, dir_str).unwrap();
+        git(&["init", "-q"]);
+        git(&["add", "."]);
+        git(&[
+            "-c",
+            "user.name=test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "-qm",
+            "init",
+        ]);
+        std::fs::write(
+            dir.join("src/lib.rs"),
+            "// TODO: Old.\nfn a() {}\n// TODO: New.\n",
+        )
+        .unwrap();
+        let options = ScanOptions {
+            search_backend: SearchBackend::Builtin,
+            since: Some("HEAD".into()),
+            ..ScanOptions::default()
+        };
+
+        let todos = IssueMap::from_files_in_directory_with_options(dir_str, &options).unwrap();
+        assert_eq!(todos.todos.keys().collect::<Vec<_>>(), vec!["New."]);
+        assert_eq!(
+            todos.todos["New."].body.descs_and_srcs[0].1.src_span,
+            (3, None)
+        );
+    }
+
+    #[test]
+    fn unassigned_todos_can_be_assigned_by_blame() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        let committed = "// TODO: Blamed.\n// TODO(bob): Assigned.\n";
+        std::fs::write(dir.join("lib.rs"), committed).unwrap();
+        let dir_str = dir.to_str().unwrap();
+        let git = |args: &[&str]| crate::utils::command("git", args, dir_str).unwrap();
+        git(&["init", "-q"]);
+        git(&["add", "."]);
+        git(&[
+            "-c",
+            "user.name=carol",
+            "-c",
+            "user.email=carol@example.com",
+            "commit",
+            "-qm",
+            "init",
+        ]);
+        std::fs::write(
+            dir.join("lib.rs"),
+            format!("{}// TODO: Uncommitted.\n", committed),
+        )
+        .unwrap();
+        let options = ScanOptions {
+            search_backend: SearchBackend::Builtin,
+            blame_assign: true,
+            ..ScanOptions::default()
+        };
+
+        let scan = |options: &ScanOptions| {
+            let todos = IssueMap::from_files_in_directory_with_options(dir_str, options).unwrap();
+            ["Blamed.", "Assigned.", "Uncommitted."]
+                .map(|title| todos.todos[title].head.assignees.clone())
+        };
+        // A name alone could be anyone's login
+        assert_eq!(scan(&options), [vec![], vec!["bob".to_string()], vec![]]);
+        let options = ScanOptions {
+            assignee_map: BTreeMap::from([("carol@example.com".into(), "carol-gh".into())]),
+            ..options
+        };
+        assert_eq!(
+            scan(&options),
+            [
+                vec!["carol-gh".to_string()],
+                vec!["bob".to_string()],
+                vec![]
+            ]
+        );
+    }
+
+    #[test]
+    fn scans_return_their_messages() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        std::fs::write(dir.join("latin1.rs"), b"// TODO: Order a caf\xe9.\n").unwrap();
+        std::fs::write(dir.join("good.rs"), "// TODO: Keep going.\n").unwrap();
+        let options = ScanOptions {
+            search_backend: SearchBackend::Builtin,
+            ..ScanOptions::default()
+        };
+
+        let (todos, messages) =
+            IssueMap::scan_files_in_directory(dir.to_str().unwrap(), &options).unwrap();
+        assert_eq!(todos.todos.keys().collect::<Vec<_>>(), vec!["Keep going."]);
+        match messages.as_slice() {
+            [Message::SkippedFile { path, reason }] => {
+                assert!(path.ends_with("latin1.rs"), "{}", path);
+                assert!(reason.contains("UTF-8"), "{}", reason);
+            }
+            messages => panic!("unexpected messages: {:?}", messages),
+        }
+    }
+
+    #[test]
+    fn parallel_scans_merge_files_in_order() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        for i in 0..40 {
+            let todos = format!(
+                "// TODO(dev{}): Shared.\n// {}\n// TODO: File {}.\n",
+                i % 3,
+                "x".repeat(i * 100),
+                i
+            );
+            std::fs::write(dir.join(format!("file{:02}.rs", i)), todos).unwrap();
+        }
+        std::fs::write(dir.join("latin1.rs"), b"// TODO: Order a caf\xe9.\n").unwrap();
+        let scan = |jobs| {
+            let options = ScanOptions {
+                search_backend: SearchBackend::Builtin,
+                jobs: Some(jobs),
+                warn_conflicting_assignees: true,
+                ..ScanOptions::default()
+            };
+            IssueMap::scan_files_in_directory(dir.to_str().unwrap(), &options).unwrap()
+        };
+
+        let (sequential, sequential_messages) = scan(1);
+        let (parallel, parallel_messages) = scan(8);
+        let files = |todos: &IssueMap<(), FileTodoLocation>| {
+            todos.todos["Shared."]
+                .body
+                .descs_and_srcs
+                .iter()
+                .map(|(_, loc)| loc.file.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(files(&sequential).len(), 40);
+        assert_eq!(files(&parallel), files(&sequential));
+        assert_eq!(parallel.as_json(), sequential.as_json());
+        assert!(sequential_messages.len() > 1);
+        assert_eq!(parallel_messages, sequential_messages);
+    }
+
+    #[test]
+    fn ignored_titles_are_left_out_of_scans() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        std::fs::write(
+            dir.join("example.rs"),
+            "// TODO: Implement this.\n\
+             // TODO: Example: handle errors.\n\
+             // TODO: Example: retry.\n\
+             // TODO: Implement this later.\n",
+        )
+        .unwrap();
+        let scan = |ignore_titles: &[&str]| {
+            let options = ScanOptions {
+                search_backend: SearchBackend::Builtin,
+                ignore_titles: ignore_titles.iter().map(|s| s.to_string()).collect(),
+                ..ScanOptions::default()
+            };
+            let todos =
+                IssueMap::from_files_in_directory_with_options(dir.to_str().unwrap(), &options)
+                    .unwrap();
+            let mut titles = todos.todos.keys().cloned().collect::<Vec<_>>();
+            titles.sort();
+            titles
+        };
+
+        assert_eq!(scan(&[]).len(), 4);
+        // Exact titles match however they're cased, spaced or punctuated
+        assert_eq!(
+            scan(&["implement  THIS"]),
+            vec![
+                "Example: handle errors.",
+                "Example: retry.",
+                "Implement this later."
+            ]
+        );
+        assert_eq!(
+            scan(&["Example: *", "implement this"]),
+            vec!["Implement this later."]
+        );
+        assert_eq!(
+            scan(&["implement this*"]),
+            vec!["Example: handle errors.", "Example: retry."]
+        );
+        assert_eq!(scan(&["Example: re???"]).len(), 3);
+
+        // Contents piped in are left out the same way
+        let options = ScanOptions {
+            ignore_titles: vec!["Example: *".into()],
+            ..ScanOptions::default()
+        };
+        let contents = "// TODO: Example: retry.\n// TODO: Retry.\n";
+        let todos = IssueMap::from_contents_with_extension("-", contents, "rs", &options).unwrap();
+        assert_eq!(todos.todos.keys().collect::<Vec<_>>(), vec!["Retry."]);
+    }
+
+    #[test]
+    fn todo_coverage_counts_every_source_file() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/a.rs"), "// TODO: Do it.\n").unwrap();
+        std::fs::write(dir.join("src/b.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(dir.join("src/c.rs"), "// TODO: Implement this.\n").unwrap();
+        std::fs::write(dir.join("lib.py"), "# TODO: Do it too.\n").unwrap();
+        std::fs::write(dir.join("notes.unknown"), "Not source.\n").unwrap();
+        let options = ScanOptions {
+            search_backend: SearchBackend::Builtin,
+            todo_coverage: true,
+            ignore_titles: vec!["Implement this.".into()],
+            ..ScanOptions::default()
+        };
+
+        let (todos, messages) =
+            IssueMap::scan_files_in_directory(dir.to_str().unwrap(), &options).unwrap();
+        assert_eq!(todos.distinct_len(), 2);
+        // An ignored todo doesn't count either
+        assert_eq!(
+            messages,
+            vec![Message::TodoCoverage {
+                files_with_todos: 2,
+                total_files: 4,
+                pct: 50.0,
+            }]
+        );
+        assert_eq!(
+            messages[0].to_string(),
+            "50% of source files have TODOs (2 of 4)"
+        );
+    }
+
+    #[test]
+    fn unsupported_files_are_counted_by_extension() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        for file in &["a.proto", "b.proto", "c.zig", "Jenkinsfile"] {
+            std::fs::write(dir.join(file), "// TODO: Support this.\n").unwrap();
+        }
+        std::fs::write(dir.join("good.rs"), "// TODO: Keep going.\n").unwrap();
+        let options = ScanOptions {
+            search_backend: SearchBackend::Builtin,
+            ..ScanOptions::default()
+        };
+
+        let (todos, messages) =
+            IssueMap::scan_files_in_directory(dir.to_str().unwrap(), &options).unwrap();
+        assert_eq!(todos.todos.keys().collect::<Vec<_>>(), vec!["Keep going."]);
+        let counts = match messages.as_slice() {
+            [Message::UnsupportedSummary { counts }] => counts,
+            messages => panic!("unexpected messages: {:?}", messages),
+        };
+        let expected = vec![(".proto", 2), (".zig", 1), ("Jenkinsfile", 1)];
+        assert_eq!(
+            counts
+                .iter()
+                .map(|(ext, count)| (ext.as_str(), *count))
+                .collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(
+            messages[0].to_string(),
+            "4 files with possible TODOs are unsupported:\n  \
+             .proto       2\n  \
+             .zig         1\n  \
+             Jenkinsfile  1"
+        );
+
+        // Verbose scans report each file too, before the summary
+        let options = ScanOptions {
+            report_unsupported_files: true,
+            ..options
+        };
+        let (_, verbose_messages) =
+            IssueMap::scan_files_in_directory(dir.to_str().unwrap(), &options).unwrap();
+        let files = verbose_messages
+            .iter()
+            .filter_map(|message| match message {
+                Message::UnsupportedFile { path } => Path::new(path).file_name(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(files, vec!["Jenkinsfile", "a.proto", "b.proto", "c.zig"]);
+        assert_eq!(verbose_messages.last(), messages.last());
+    }
+
+    #[test]
+    fn can_parse_todos_in_markdown_and_text() {
+        let markdown = langs::language_map()["md"].clone();
+        let contents = "# Design\n\
+                        \n\
+                        TODO: Decide on a wire format. JSON or CBOR.\n\
+                        \n\
+                        - Use a queue.\n\
+                        - TODO(alice): Benchmark the\n  \
+                        queue. Under load.\n\
+                        * Ship it.\n\
+                        > FIXME: Quote this properly.\n\
+                        - [ ] TODO: Add a changelog.\n\
+                        - [x] TODO: Write a README.\n";
+        let todos = IssueMap::from_file_contents(
+            "design.md",
+            contents,
+            &[3, 6, 9, 10, 11],
+            &markdown,
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        let mut titles = todos.todos.keys().collect::<Vec<_>>();
+        titles.sort();
+        assert_eq!(
+            titles,
+            vec![
+                "Add a changelog.",
+                "Benchmark the queue.",
+                "Decide on a wire format.",
+                "Quote this properly."
+            ]
+        );
+        let benchmark = &todos.todos["Benchmark the queue."];
+        assert_eq!(benchmark.head.assignees, vec!["alice".to_string()]);
+        let (descs, loc) = &benchmark.body.descs_and_srcs[0];
+        assert_eq!(descs, &vec!["Under load.".to_string()]);
+        assert_eq!(loc.src_span, (6, Some(7)));
+
+        let text = langs::language_map()["txt"].clone();
+        let todos = IssueMap::from_file_contents(
+            "notes.txt",
+            "Some notes.\n  TODO: Tidy these up.\n",
+            &[2],
+            &text,
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        assert!(todos.todos.contains_key("Tidy these up."));
+
+        // Named for their language, though they end in `.txt`
+        let (language_map, file_name_map) = (langs::language_map(), langs::file_name_map());
+        let cmake =
+            langs::find_languages(&language_map, &file_name_map, Path::new("CMakeLists.txt"));
+        assert_eq!(cmake.unwrap()[0].name, "Cmake");
+    }
+
+    #[test]
+    fn python_docstring_todos_are_located_in_the_file() {
+        let contents = "\"\"\"The module.\n\
+                        \n\
+                        TODO: Split this module.\n\
+                        It's big.\n\
+                        \"\"\"\n\
+                        import os\n\
+                        \n\
+                        \n\
+                        def f():\n    \
+                            \"\"\"Does f.\n\
+                        \n    \
+                            TODO(amy): Handle errors.\n    \
+                            \"\"\"\n    \
+                            pass\n\
+                        \n\
+                        \n\
+                        def g():\n    \
+                            '''TODO: Quote consistently.'''\n\
+                        \n\
+                        \n\
+                        def h():\n    \
+                            r\"\"\"TODO: Support \\d patterns.\n    \
+                            \"\"\"\n";
+        let python = langs::language_map()["py"].clone();
+        let lines = FileSearcher::find_in_contents(contents, &source::TodoTags::default());
+        let todos = IssueMap::from_file_contents(
+            "a.py",
+            contents,
+            &lines,
+            &python,
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        let span = |title: &str| todos.todos[title].body.descs_and_srcs[0].1.src_span;
+        assert_eq!(span("Split this module."), (3, Some(5)));
+        assert_eq!(
+            todos.todos["Split this module."].body.descs_and_srcs[0].0,
+            vec!["It's big.".to_string()]
+        );
+        assert_eq!(span("Handle errors."), (12, Some(13)));
+        assert_eq!(span("Quote consistently."), (18, None));
+        assert_eq!(span("Support \\d patterns."), (22, Some(23)));
+    }
+
+    #[test]
+    fn scripts_are_parsed_by_their_shebang() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        std::fs::write(
+            dir.join("deploy"),
+            "#!/usr/bin/env python3\nimport os\n\n# TODO: Deploy to staging first.\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("build"),
+            "#!/bin/bash\nset -e\n# TODO: Cache the build.\n#   It's slow.\nmake\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("notes"), "TODO: Not a script.\n").unwrap();
+        let options = ScanOptions {
+            search_backend: SearchBackend::Builtin,
+            ..ScanOptions::default()
+        };
+
+        let todos = IssueMap::from_files_in_directory_with_options(dir.to_str().unwrap(), &options)
+            .unwrap();
+        let location = |title: &str| {
+            let (desc, loc) = &todos.todos[title].body.descs_and_srcs[0];
+            let file = Path::new(&loc.file).file_name().unwrap().to_str().unwrap();
+            (file.to_string(), loc.src_span, desc.clone())
+        };
+        assert_eq!(todos.todos.len(), 2);
+        assert_eq!(
+            location("Deploy to staging first."),
+            ("deploy".into(), (4, None), vec![])
+        );
+        assert_eq!(
+            location("Cache the build."),
+            ("build".into(), (3, Some(4)), vec!["It's slow.".to_string()])
+        );
+    }
+
+    #[test]
+    fn spans_count_lines_like_the_seek() {
+        let contents = "// TODO: Feed the form.\x0c\n\
+                        //   Vertical\x0btab.\n\
+                        fn a() {}\rfn b() {}\r\n\
+                        /* TODO: Span it.\r\n\
+                        \x0c  Over lines. */\n";
+        let todos = IssueMap::from_file_contents(
+            "a.rs",
+            contents,
+            &[1, 4],
+            &rust(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        let span = |title: &str| todos.todos[title].body.descs_and_srcs[0].1.src_span;
+        assert_eq!(span("Feed the form."), (1, Some(2)));
+        assert_eq!(span("Span it."), (4, Some(5)));
+        assert_eq!(
+            todos.todos["Feed the form."].body.descs_and_srcs[0].0,
+            vec!["Vertical\x0btab."]
+        );
+    }
+
+    #[test]
+    fn can_time_out_on_huge_files() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+
+        let small = dir.join("small.rs");
+        std::fs::write(&small, "// TODO: Parse me.\n").unwrap();
+        let small = PossibleTodosInFile::new(small.to_str().unwrap(), vec![1]);
+        let todos = IssueMap::from_possible_todo_with_timeout(
+            small,
+            rust(),
+            ScanOptions::default(),
+            Duration::from_secs(10),
+        )
+        .unwrap()
+        .expect("small file should not time out");
+        assert!(todos.todos.contains_key("Parse me."));
+
+        // Far more todos than can be parsed in a millisecond
+        let huge = dir.join("huge.rs");
+        let contents = "// TODO: Parse me later.\n".repeat(100_000);
+        std::fs::write(&huge, &contents).unwrap();
+        let lines = (1..=100_000).collect::<Vec<_>>();
+        let huge = PossibleTodosInFile::new(huge.to_str().unwrap(), lines.clone());
+        let todos = IssueMap::from_possible_todo_with_timeout(
+            huge,
+            rust(),
+            ScanOptions::default(),
+            Duration::from_millis(1),
+        )
+        .unwrap();
+        assert!(todos.is_none());
+
+        // A timed out parse stops rather than running on in the background
+        let cancelled = AtomicBool::new(true);
+        let scanned = IssueMap::scan_file_contents_until(
+            "huge.rs",
+            &contents,
+            &lines,
+            &rust(),
+            &ScanOptions::default(),
+            &cancelled,
+        );
+        assert_eq!(scanned.err(), Some("parsing was cancelled".to_string()));
+    }
+
+    #[test]
+    fn can_classify_malformed_todos() {
+        let contents = "// TODO:\n\
+                        // TODO(alice: Do it.\n\
+                        // TODO(): Do it.\n\
+                        // TODO[backend: Do it.\n\
+                        // TODO: ...\n\
+                        // TODO -- do it\n\
+                        // TODO: Do it properly.\n";
+        let todos = IssueMap::from_file_contents(
+            "/repo/a.rs",
+            contents,
+            &[1, 2, 3, 4, 5, 6, 7],
+            &rust(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        let entries = todos
+            .malformed_todos("/repo")
+            .into_iter()
+            .map(|todo| (todo.path, todo.line, todo.reason))
+            .collect::<Vec<_>>();
+        let entry = |line: usize, reason: &str| ("a.rs".to_string(), line, reason.to_string());
+        assert_eq!(
+            entries,
+            vec![
+                entry(1, "it has no title"),
+                entry(2, "its assignee has no closing parenthesis"),
+                entry(3, "it has an empty assignee"),
+                entry(4, "its labels have no closing bracket"),
+                entry(5, "its title has no words"),
+                entry(6, "its title starts with stray punctuation"),
+            ]
+        );
+    }
+
+    #[test]
+    fn asides_and_quotes_are_not_malformed() {
+        for title in [
+            "(optional) cache the result",
+            "(see the docs) Do it.",
+            "[link] is broken.",
+            "`parse` is slow.",
+            "\"Soon\" means now.",
+            "'static bounds are too strict.",
+        ] {
+            assert_eq!(malformed_reason(title, &[]), None, "{}", title);
+        }
+    }
+
+    #[test]
+    fn diffs_are_parsed_with_the_scan_options() {
+        let diff = "\
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1 +1,4 @@
+ fn a() {}
++// REVIEW: REVIEW: Check b.
++// TODO: Not a tag.
++// REVIEW: Ignored.
+--- a/vendor/dep.rs
++++ b/vendor/dep.rs
+@@ -1 +1,2 @@
+ fn c() {}
++// REVIEW: Excluded.
+";
+        let options = ScanOptions {
+            tags: source::TodoTags::new(["REVIEW"]),
+            strip_tag_from_title: true,
+            excludes: vec!["vendor/**".into()],
+            ignore_titles: vec!["Ignored.".into()],
+            ..ScanOptions::default()
+        };
+        let todos = IssueMap::from_diff("/repo", diff, &options).unwrap();
+        let mut titles = todos.todos.keys().cloned().collect::<Vec<_>>();
+        titles.sort();
+        assert_eq!(titles, vec!["Check b."]);
+        let (_, loc) = &todos.todos["Check b."].body.descs_and_srcs[0];
+        assert_eq!(loc.file, "/repo/src/lib.rs");
+        assert_eq!(loc.src_span, (2, None));
+    }
+
+    #[test]
+    fn can_parse_contents_by_extension() {
+        let contents =
+            "fn a() {}\n// TODO(alice): Do b.\n//   Soon.\nfn b() { todo!(\"Do c.\") }\n";
+        let todos =
+            IssueMap::from_contents_with_extension("-", contents, "rs", &ScanOptions::default())
+                .unwrap();
+        let mut titles = todos.todos.keys().cloned().collect::<Vec<_>>();
+        titles.sort();
+        assert_eq!(titles, vec!["Do b.", "Do c."]);
+        assert_eq!(
+            todos.todos["Do b."].body.descs_and_srcs,
+            vec![(
+                vec!["Soon.".to_string()],
+                FileTodoLocation {
+                    file: "-".into(),
+                    src_span: (2, Some(3)),
+                }
+            )]
+        );
+        assert!(IssueMap::from_contents_with_extension(
+            "-",
+            contents,
+            "nope",
+            &ScanOptions::default()
+        )
+        .is_err());
     }
 }
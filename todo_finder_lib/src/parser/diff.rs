@@ -0,0 +1,267 @@
+//! # Parsing todos added in a unified diff.
+use std::{collections::HashMap, path::Path};
+
+use super::{
+    langs,
+    source::{parse_todo, ParsedTodo},
+    FileTodoLocation, ScanOptions,
+};
+
+/// Where we are in a unified diff.
+enum DiffState {
+    /// Between hunks, reading file headers.
+    Header,
+    /// In a hunk with this many old and new lines left.
+    Hunk { old: usize, new: usize },
+}
+
+/// Parse the counts of a hunk header like `@@ -1,3 +1,4 @@`, returning the
+/// old line count, new start line and new line count.
+fn hunk_header(line: &str) -> Option<(usize, usize, usize)> {
+    let mut ranges = line.strip_prefix("@@ ")?.split(' ');
+    let range = |r: Option<&str>, sign: char| -> Option<(usize, usize)> {
+        let r = r?.strip_prefix(sign)?;
+        match r.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((r.parse().ok()?, 1)),
+        }
+    };
+    let (_, old) = range(ranges.next(), '-')?;
+    let (start, new) = range(ranges.next(), '+')?;
+    Some((old, start, new))
+}
+
+/// The path in the new version of a file header like `+++ b/src/lib.rs`, or
+/// `None` if the file was deleted.
+fn new_path(header: &str) -> Option<&str> {
+    // Timestamps may follow the path, separated by a tab
+    let path = header.split('\t').next().unwrap_or(header).trim_end();
+    match path {
+        "/dev/null" => None,
+        path => Some(path.strip_prefix("b/").unwrap_or(path)),
+    }
+}
+
+/// The numbers of the lines added to each file in a unified diff, in the new
+/// version, eg. from `git diff --unified=0`.
+///
+/// ```rust
+/// use todo_finder_lib::parser::diff::added_lines;
+///
+/// let diff = "\
+/// --- a/src/lib.rs
+/// +++ b/src/lib.rs
+/// @@ -1,0 +2,2 @@
+/// +// TODO: Write b.
+/// +fn b() {}
+/// @@ -9 +11 @@ fn c() {
+/// -    old();
+/// +    new();
+/// ";
+/// assert_eq!(added_lines(diff)["src/lib.rs"], vec![2, 3, 11]);
+/// ```
+pub fn added_lines(diff: &str) -> HashMap<String, Vec<usize>> {
+    let mut lines: HashMap<String, Vec<usize>> = HashMap::new();
+    let mut state = DiffState::Header;
+    let mut file: Option<&str> = None;
+    let mut line_number = 0;
+
+    for line in diff.lines() {
+        match state {
+            DiffState::Header => {
+                if let Some(header) = line.strip_prefix("+++ ") {
+                    file = new_path(header);
+                } else if let Some((old, start, new)) = hunk_header(line) {
+                    line_number = start;
+                    state = DiffState::Hunk { old, new };
+                }
+            }
+            DiffState::Hunk {
+                ref mut old,
+                ref mut new,
+            } => {
+                if line.starts_with('+') {
+                    if let Some(file) = file {
+                        lines.entry(file.to_string()).or_default().push(line_number);
+                    }
+                    *new = new.saturating_sub(1);
+                    line_number += 1;
+                } else if line.starts_with('-') {
+                    *old = old.saturating_sub(1);
+                } else if line.starts_with('\\') {
+                    // "\ No newline at end of file"
+                } else {
+                    *old = old.saturating_sub(1);
+                    *new = new.saturating_sub(1);
+                    line_number += 1;
+                }
+                if *old == 0 && *new == 0 {
+                    state = DiffState::Header;
+                }
+            }
+        }
+    }
+
+    lines
+}
+
+/// Find the todos on the lines added in a unified diff, parsed with the
+/// given options and located by their file and line in the new version. Each
+/// file is parsed by the languages of its name or extension and files of
+/// unsupported languages are skipped.
+///
+/// Only the added line a todo starts on is parsed, so descriptions on the
+/// lines after it are not found.
+///
+/// ```rust
+/// use todo_finder_lib::parser::{diff::parse_diff, ScanOptions};
+///
+/// let diff = "\
+/// --- a/src/lib.rs
+/// +++ b/src/lib.rs
+/// @@ -1,2 +1,3 @@
+///  fn a() {}
+/// +// TODO: Write b.
+///  fn c() {}
+/// ";
+/// let todos = parse_diff(diff, &ScanOptions::default());
+/// assert_eq!(todos.len(), 1);
+/// assert_eq!(todos[0].0.title, "Write b.");
+/// assert_eq!(todos[0].1.file, "src/lib.rs");
+/// assert_eq!(todos[0].1.src_span, (2, None));
+/// ```
+pub fn parse_diff<'a>(
+    diff: &'a str,
+    options: &ScanOptions,
+) -> Vec<(ParsedTodo<'a>, FileTodoLocation)> {
+    let language_map = langs::language_map_with(&options.custom_languages);
+    let file_name_map = langs::file_name_map_with(&options.custom_languages);
+    let mut todos = vec![];
+    let mut state = DiffState::Header;
+    let mut file: Option<&str> = None;
+    let mut line_number = 0;
+
+    for line in diff.lines() {
+        match state {
+            DiffState::Header => {
+                if let Some(header) = line.strip_prefix("+++ ") {
+                    file = new_path(header);
+                } else if let Some((old, start, new)) = hunk_header(line) {
+                    line_number = start;
+                    state = DiffState::Hunk { old, new };
+                }
+            }
+            DiffState::Hunk {
+                ref mut old,
+                ref mut new,
+            } => {
+                if let Some(added) = line.strip_prefix('+') {
+                    if let Some(file) = file {
+                        let languages =
+                            langs::find_languages(&language_map, &file_name_map, Path::new(file));
+                        let todo = languages.into_iter().flatten().find_map(|language| {
+                            parse_todo(options.parser_config(language))(added.trim_start())
+                                .ok()
+                                .map(|(_, mut todo)| {
+                                    todo.title = options.stripped_title(todo.title);
+                                    todo
+                                })
+                        });
+                        if let Some(todo) = todo {
+                            let loc = FileTodoLocation {
+                                file: file.to_string(),
+                                src_span: (line_number, None),
+                            };
+                            todos.push((todo, loc));
+                        }
+                    }
+                    *new = new.saturating_sub(1);
+                    line_number += 1;
+                } else if line.starts_with('-') {
+                    *old = old.saturating_sub(1);
+                } else if line.starts_with('\\') {
+                    // "\ No newline at end of file"
+                } else {
+                    *old = old.saturating_sub(1);
+                    *new = new.saturating_sub(1);
+                    line_number += 1;
+                }
+                if *old == 0 && *new == 0 {
+                    state = DiffState::Header;
+                }
+            }
+        }
+    }
+
+    todos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_parse_todos_added_in_a_diff() {
+        let diff = "\
+diff --git a/src/lib.rs b/src/lib.rs
+index 3b18e51..a2c4d3e 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,4 @@
+ fn a() {}
+-// TODO: Removed.
++// TODO(schell): Write b. Soon.
++++x;
+ fn c() {}
+@@ -10,2 +11,3 @@ fn d() {
+     // TODO: Context isn't added.
++    // FIXME: Fix e.
+ }
+diff --git a/scripts/build.py b/scripts/build.py
+new file mode 100644
+--- /dev/null
++++ b/scripts/build.py
+@@ -0,0 +1,2 @@
++import os
++# TODO: Build it.
+diff --git a/notes.unknown b/notes.unknown
+--- a/notes.unknown
++++ b/notes.unknown
+@@ -1 +1 @@
+-a
++TODO: Unsupported.
+";
+        let todos = parse_diff(diff, &ScanOptions::default())
+            .into_iter()
+            .map(|(todo, loc)| {
+                (
+                    todo.title.to_string(),
+                    todo.assignee,
+                    todo.desc_lines,
+                    loc.file,
+                    loc.src_span.0,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            todos,
+            vec![
+                (
+                    "Write b.".to_string(),
+                    Some("schell"),
+                    vec!["Soon."],
+                    "src/lib.rs".to_string(),
+                    2
+                ),
+                ("Fix e.".into(), None, vec![], "src/lib.rs".into(), 12),
+                (
+                    "Build it.".into(),
+                    None,
+                    vec![],
+                    "scripts/build.py".into(),
+                    2
+                ),
+            ]
+        );
+    }
+}
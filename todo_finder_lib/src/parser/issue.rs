@@ -4,6 +4,11 @@ use std::collections::HashMap;
 
 use super::{take_to_eol, IssueBody};
 
+/// Parse a line number, failing on numbers too big to be one.
+fn line_number(i: &str) -> IResult<&str, usize> {
+    combinator::map_res(character::digit1, |ln_str: &str| ln_str.parse::<usize>())(i)
+}
+
 /// Version 0 of the source location parser.
 /// Parse the file and line location from an issue decription.
 pub fn src_location0(i: &str) -> IResult<&str, (&str, usize)> {
@@ -13,10 +18,7 @@ pub fn src_location0(i: &str) -> IResult<&str, (&str, usize)> {
     let (i, file) = bytes::take_till(|c| c == '"')(i)?;
     let (i, _) = dub_quote(i)?;
     let (i, _) = bytes::tag(" on line ")(i)?;
-    let (i, ln_str) = character::digit1(i)?;
-    let n = ln_str
-        .parse::<usize>()
-        .expect("could not convert line number: src_location0");
+    let (i, n) = line_number(i)?;
     Ok((i, (file, n)))
 }
 
@@ -39,32 +41,28 @@ pub fn repo_from_github_link(i: &str) -> IResult<&str, (&str, &str)> {
 /// ```
 pub fn span_from_github_link(i: &str) -> IResult<&str, (usize, Option<usize>)> {
     let (i, _) = bytes::tag("#L")(i)?;
-    let (i, ln_str) = character::digit1(i)?;
-    let start = ln_str
-        .parse::<usize>()
-        .expect("could not convert line number: span_from_github_link");
-    fn convert_line(ii: &str) -> IResult<&str, usize> {
-        let (ii, _) = bytes::tag("-L")(ii)?;
-        let (ii, ln_str) = character::digit1(ii)?;
-        let end = ln_str
-            .parse::<usize>()
-            .expect("could not convert line number: span_from_github_link::fn");
-        Ok((ii, end))
-    }
-    let (i, may_end) = combinator::opt(convert_line)(i)?;
+    let (i, start) = line_number(i)?;
+    // A bad end line fails the whole span rather than leaving it behind
+    let (i, may_end) = match combinator::opt(bytes::tag("-L"))(i)? {
+        (i, Some(_)) => combinator::map(line_number, Some)(i)?,
+        (i, None) => (i, None),
+    };
     Ok((i, (start, may_end)))
 }
 
 /// Uniquely identifies a todo location.
 #[derive(Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
 pub struct GitHubTodoLocation {
+    /// The owner and name of the repo, both empty for a link relative to the
+    /// repo, see `LinkStyle::RepoRelative`.
     pub repo: (String, String),
     pub checkout: String,
     pub file: String,
     pub src_span: (usize, Option<usize>),
 }
 
-/// Parses the location of a todo from a github link.
+/// Parses the location of a todo from a github link, to public GitHub or to
+/// a GitHub Enterprise host, or relative to the repo, like `../blob/...`.
 ///
 /// ```rust
 /// use todo_finder_lib::parser::issue::*;
@@ -85,9 +83,17 @@ pub struct GitHubTodoLocation {
 /// );
 /// ```
 pub fn todo_location_from_github_link(i: &str) -> IResult<&str, GitHubTodoLocation> {
-    let (i, _) = bytes::tag("https://github.com/")(i)?;
-    let (i, repo) = repo_from_github_link(i)?;
-    let (i, _) = character::char('/')(i)?;
+    let (i, relative) = combinator::opt(bytes::tag("../"))(i)?;
+    let (i, repo) = if relative.is_some() {
+        (i, ("", ""))
+    } else {
+        let (i, _) = bytes::tag("https://")(i)?;
+        let (i, _host) = bytes::take_till1(|c| c == '/' || c == ' ')(i)?;
+        let (i, _) = character::char('/')(i)?;
+        let (i, repo) = repo_from_github_link(i)?;
+        let (i, _) = character::char('/')(i)?;
+        (i, repo)
+    };
     let (i, _) = bytes::tag("blob")(i)?;
     let (i, _) = character::char('/')(i)?;
     let (i, checkout) = bytes::take_till(|c| c == '/')(i)?;
@@ -128,7 +134,9 @@ pub fn todo_location_from_github_link(i: &str) -> IResult<&str, GitHubTodoLocati
 /// ```
 pub fn todo_location_from_github_markdown_link(i: &str) -> IResult<&str, GitHubTodoLocation> {
     let (i, may_tloc) = combinator::opt(todo_location_from_github_link)(i)?;
-    if may_tloc.is_none() {
+    if let Some(tloc) = may_tloc {
+        Ok((i, tloc))
+    } else {
         let (i, _) = character::char('[')(i)?;
         let (i, _) = bytes::take_till(|c| c == ']')(i)?;
         let (i, _) = character::char(']')(i)?;
@@ -137,8 +145,6 @@ pub fn todo_location_from_github_markdown_link(i: &str) -> IResult<&str, GitHubT
         let (i, _) = bytes::take_till(|c| c == ')')(i)?;
         let (i, _) = character::char(')')(i)?;
         Ok((i, tloc))
-    } else {
-        Ok((i, may_tloc.unwrap()))
     }
 }
 
@@ -194,7 +200,7 @@ impl<'a> TodoStory<'a> {
 ///     ))
 /// );
 /// ```
-pub fn todo_story(i: &str) -> IResult<&str, TodoStory> {
+pub fn todo_story(i: &str) -> IResult<&str, TodoStory<'_>> {
     let (i, _) = character::char('*')(i)?;
     let alts = [
         (" Opened on ", false),
@@ -219,7 +225,7 @@ pub fn todo_story(i: &str) -> IResult<&str, TodoStory> {
 
 /// Collapse and filter the input stories into a vector of branches that the todo
 /// still exists on.
-pub fn branches_todo_is_found_on(stories: Vec<TodoStory>) -> Vec<&str> {
+pub fn branches_todo_is_found_on(stories: Vec<TodoStory<'_>>) -> Vec<&str> {
     let mut map: HashMap<&str, bool> = HashMap::new();
     for story in stories.into_iter() {
         map.insert(story.branch, story.is_closed);
@@ -242,7 +248,7 @@ pub fn branches_todo_is_found_on(stories: Vec<TodoStory>) -> Vec<&str> {
 }
 
 /// Parse a vector of TodoStory.
-pub fn todo_stories(i: &str) -> IResult<&str, Vec<TodoStory>> {
+pub fn todo_stories(i: &str) -> IResult<&str, Vec<TodoStory<'_>>> {
     multi::many1(todo_story)(i)
 }
 
@@ -252,16 +258,99 @@ pub fn issue_todo(i: &str) -> IResult<&str, (Vec<&str>, GitHubTodoLocation)> {
     multi::many_till(take_to_eol, todo_location_from_github_markdown_link)(i)
 }
 
-/// Parse the entire body of an issue.
+/// Marks the start of the part of an issue body that's written by syncs, so
+/// that anything added around it by hand is kept, see `keep_unmanaged_text`.
+pub const MANAGED_START: &str = "<!-- todo_finder:start -->";
+/// Marks the end of the part of an issue body that's written by syncs.
+pub const MANAGED_END: &str = "<!-- todo_finder:end -->";
+
+/// Where the managed part of a body is, including its markers.
+pub(crate) fn managed_span(body: &str) -> Option<(usize, usize)> {
+    let start = body.find(MANAGED_START)?;
+    let end = start + body[start..].find(MANAGED_END)? + MANAGED_END.len();
+    Some((start, end))
+}
+
+/// Wrap the todos written into an issue body in the managed markers.
+pub fn with_managed_markers(body: &str) -> String {
+    format!("{}\n{}\n{}", MANAGED_START, body, MANAGED_END)
+}
+
+/// The part of an issue body written by syncs, to parse the todos from, or
+/// the whole body of an issue written before there were markers.
+///
+/// ```rust
+/// use todo_finder_lib::parser::issue::{managed_body, with_managed_markers};
+///
+/// let body = format!("Notes.\n{}\nThanks!", with_managed_markers("Do it.\nlink"));
+/// assert_eq!(managed_body(&body), "Do it.\nlink");
+/// assert_eq!(managed_body("Do it.\nlink"), "Do it.\nlink");
+/// ```
+pub fn managed_body(body: &str) -> &str {
+    match managed_span(body) {
+        Some((start, end)) => {
+            body[start + MANAGED_START.len()..end - MANAGED_END.len()].trim_matches('\n')
+        }
+        None => body,
+    }
+}
+
+/// Replace the managed part of the `current` body of an issue with the one
+/// of the `new` body, keeping any text that was added around it, eg. notes
+/// from a teammate. If either has no managed part the `new` body is used as
+/// is, as there's no telling what to keep.
+///
+/// ```rust
+/// use todo_finder_lib::parser::issue::{keep_unmanaged_text, with_managed_markers};
+///
+/// let current = format!("Notes.\n{}\nThanks!", with_managed_markers("Old."));
+/// let new = with_managed_markers("New.");
+/// assert_eq!(
+///     keep_unmanaged_text(&current, &new),
+///     format!("Notes.\n{}\nThanks!", new)
+/// );
+/// assert_eq!(keep_unmanaged_text("Old.", &new), new);
+/// ```
+pub fn keep_unmanaged_text(current: &str, new: &str) -> String {
+    match (managed_span(current), managed_span(new)) {
+        (Some((start, end)), Some((new_start, new_end))) => {
+            [&current[..start], &new[new_start..new_end], &current[end..]].concat()
+        }
+        _ => new.to_string(),
+    }
+}
+
+/// Parse the entire body of an issue. If the part written by syncs is marked,
+/// see `MANAGED_START`, only that part is parsed, so text added around
+/// it by hand isn't mistaken for a todo's description. Bodies written before
+/// there were markers are parsed whole.
+pub fn issue_body(i: &str) -> IResult<&str, IssueBody<GitHubTodoLocation>> {
+    match managed_span(i) {
+        Some((_, end)) => {
+            let (_, body) = issue_todos(managed_body(i))?;
+            Ok((&i[end..], body))
+        }
+        None => issue_todos(i),
+    }
+}
+
+/// Parse the todos of an issue body.
 /// TODO: Remove stories entirely.
 /// We really only need to operate on one branch.
-pub fn issue_body(i: &str) -> IResult<&str, IssueBody<GitHubTodoLocation>> {
+fn issue_todos(i: &str) -> IResult<&str, IssueBody<GitHubTodoLocation>> {
     let mut ii = i;
     let mut descs_todos = vec![];
     let mut may_stories = None;
     'todos: loop {
-        let (j, desc_todo) = issue_todo(ii)?;
-        descs_todos.push(desc_todo);
+        let (j, (descs, loc)) = issue_todo(ii)?;
+        // Drop the start of a collapsible block, see `to_github_string`
+        let descs = descs
+            .into_iter()
+            .skip_while(|line| line.trim().is_empty() || line.starts_with("<details>"))
+            .collect::<Vec<_>>();
+        descs_todos.push((descs, loc));
+        let (j, _) = multi::many0(character::newline)(j)?;
+        let (j, _) = combinator::opt(bytes::tag("</details>"))(j)?;
         let (j, _) = multi::many0(character::newline)(j)?;
         let (j, my_may_stories) = combinator::opt(todo_stories)(j)?;
         ii = j;
@@ -282,7 +371,7 @@ pub fn issue_body(i: &str) -> IResult<&str, IssueBody<GitHubTodoLocation>> {
             )
         })
         .collect::<Vec<_>>();
-    descs_todos.sort_by(|(_, a_loc), (_, b_loc)| a_loc.cmp(&b_loc));
+    descs_todos.sort_by(|(_, a_loc), (_, b_loc)| a_loc.cmp(b_loc));
 
     let branches = if let Some(stories) = may_stories {
         let mut branches: Vec<String> = branches_todo_is_found_on(stories)
@@ -341,7 +430,30 @@ a1eb484c90f9e0b85ab5066b8950750a5bd4ab95/app/Main.hs#L3-L7";
                     src_span: (3, Some(7))
                 }
             ))
-        )
+        );
+
+        let bytes = "https://github.example.com/schell/src-of-truth/blob/abc/app/Main.hs#L3";
+        assert_eq!(
+            todo_location_from_github_link(bytes).map(|(_, loc)| (loc.repo, loc.file)),
+            Ok((
+                ("schell".into(), "src-of-truth".into()),
+                "app/Main.hs".into()
+            ))
+        );
+
+        let bytes = "../blob/abc/app/Main.hs#L3-L7";
+        assert_eq!(
+            todo_location_from_github_link(bytes),
+            Ok((
+                "",
+                GitHubTodoLocation {
+                    repo: ("".into(), "".into()),
+                    checkout: "abc".into(),
+                    file: "app/Main.hs".into(),
+                    src_span: (3, Some(7))
+                }
+            ))
+        );
     }
 
     #[test]
@@ -494,4 +606,141 @@ This is another description.
             ))
         );
     }
+
+    #[test]
+    fn github_strings_round_trip() {
+        let body = IssueBody {
+            descs_and_srcs: vec![
+                (
+                    vec!["This is the description.".to_string(), "More.".into()],
+                    crate::parser::FileTodoLocation {
+                        file: "/repo/src/File.hs".into(),
+                        src_span: (666, None),
+                    },
+                ),
+                (
+                    vec![],
+                    crate::parser::FileTodoLocation {
+                        file: "/repo/src/Other.hs".into(),
+                        src_span: (23, Some(25)),
+                    },
+                ),
+            ],
+            branches: vec![],
+        };
+        let expected = IssueBody {
+            descs_and_srcs: vec![
+                (
+                    vec!["This is the description.".to_string(), "More.".into()],
+                    GitHubTodoLocation {
+                        repo: ("schell".into(), "repo".into()),
+                        checkout: "abighash".into(),
+                        file: "src/File.hs".into(),
+                        src_span: (666, None),
+                    },
+                ),
+                (
+                    vec![],
+                    GitHubTodoLocation {
+                        repo: ("schell".into(), "repo".into()),
+                        checkout: "abighash".into(),
+                        file: "src/Other.hs".into(),
+                        src_span: (23, Some(25)),
+                    },
+                ),
+            ],
+            branches: vec![],
+        };
+
+        for collapsible in [false, true].iter() {
+            let string = body
+                .to_github_string(
+                    "/repo",
+                    "https://github.com/schell/repo",
+                    "abighash",
+                    *collapsible,
+                    0,
+                )
+                .unwrap();
+            assert_eq!(issue_body(&string).unwrap().1, expected, "{}", string);
+        }
+
+        let string = body
+            .to_github_string(
+                "/repo",
+                "https://github.com/schell/repo",
+                "abighash",
+                true,
+                0,
+            )
+            .unwrap();
+        assert!(string.starts_with(
+            "<!-- todo_finder:start -->\n<details><summary>src/File.hs:666</summary>\n\nThis is the description.\nMore.\n\
+             https://github.com/schell/repo/blob/abighash/src/File.hs#L666\n\n</details>\n"
+        ));
+    }
+
+    #[test]
+    fn only_the_marked_part_of_an_issue_body_is_parsed() {
+        let todos = "This is the description.\n\
+                     https://github.com/schell/repo/blob/abighash/src/File.hs#L666";
+        let marked = format!(
+            "Is this still needed? See\n\
+             https://github.com/schell/repo/blob/abighash/src/Other.hs#L1\n\
+             <!-- todo_finder:start -->\n{}\n<!-- todo_finder:end -->\nThanks!",
+            todos
+        );
+        let (rest, body) = issue_body(&marked).unwrap();
+        assert_eq!(rest, "\nThanks!");
+        assert_eq!(body, issue_body(todos).unwrap().1);
+        assert_eq!(
+            body.descs_and_srcs,
+            vec![(
+                vec!["This is the description.".to_string()],
+                GitHubTodoLocation {
+                    repo: ("schell".into(), "repo".into()),
+                    checkout: "abighash".into(),
+                    file: "src/File.hs".into(),
+                    src_span: (666, None),
+                },
+            )]
+        );
+
+        // Without the markers the link in the discussion is a todo too
+        let unmarked = marked
+            .replace("<!-- todo_finder:start -->\n", "")
+            .replace("\n<!-- todo_finder:end -->\nThanks!", "");
+        assert_eq!(issue_body(&unmarked).unwrap().1.descs_and_srcs.len(), 2);
+    }
+
+    #[test]
+    fn bad_line_numbers_are_skipped() {
+        let giant = "99999999999999999999999999";
+        assert!(src_location0(&format!("Located in \"a.rs\" on line {}", giant)).is_err());
+        assert!(span_from_github_link(&format!("#L{}", giant)).is_err());
+        assert!(span_from_github_link(&format!("#L1-L{}", giant)).is_err());
+        assert!(span_from_github_link("#Lx").is_err());
+
+        let bad = format!(
+            "https://github.com/schell/repo/blob/abighash/src/File.hs#L{}",
+            giant
+        );
+        let body = format!(
+            "Description.\n{}\nhttps://github.com/schell/repo/blob/abighash/src/File.hs#L3-L4\n",
+            bad
+        );
+        let (_, body) = issue_body(&body).unwrap();
+        assert_eq!(
+            body.descs_and_srcs,
+            vec![(
+                vec!["Description.".to_string(), bad],
+                GitHubTodoLocation {
+                    repo: ("schell".into(), "repo".into()),
+                    checkout: "abighash".into(),
+                    file: "src/File.hs".into(),
+                    src_span: (3, Some(4)),
+                },
+            )]
+        );
+    }
 }
@@ -1,4 +1,7 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
 
 use super::source::TodoParserConfig;
 
@@ -7,6 +10,17 @@ pub enum CommentStyle {
     Single(String),
     Multi(String, String),
     Border(String),
+    /// Rust's `#[doc = "..."]` and `#![doc = "..."]` attributes, which are
+    /// doc comments in attribute form.
+    DocAttribute,
+    /// Notes in Rust attributes like `#[deprecated(note = "...")]`.
+    AttributeNote,
+    /// A macro whose message is a todo, eg. Rust's `todo!`. These are only
+    /// looked for in the languages that have them.
+    Macro(String),
+    /// Prose, where any line may start a todo without a comment prefix, eg.
+    /// Markdown's list items and paragraphs.
+    Prose,
 }
 
 fn from_single(s: &str) -> CommentStyle {
@@ -26,12 +40,54 @@ pub struct SupportedLanguage {
     pub name: String,
     pub comment_styles: Vec<CommentStyle>,
     pub file_extensions: Vec<String>,
+    /// Whole file names for files that are known by name rather than by
+    /// extension, eg. `.env`.
+    pub file_names: Vec<String>,
 }
 
 impl SupportedLanguage {
     pub fn as_todo_parser_config(&self) -> TodoParserConfig {
         TodoParserConfig::from_comment_styles(self.comment_styles.clone())
     }
+
+    /// Like `as_todo_parser_config`, but without the borders disabled for this
+    /// language, see `parse_disabled_border`.
+    pub fn as_todo_parser_config_without(
+        &self,
+        disabled_borders: &[(String, String)],
+    ) -> TodoParserConfig {
+        let mut cfg = self.as_todo_parser_config();
+        cfg.borders.retain(|border| {
+            !disabled_borders
+                .iter()
+                .any(|(language, disabled)| disabled == border && self.is_called(language))
+        });
+        cfg
+    }
+
+    /// Whether the language goes by the given name or file extension, ignoring
+    /// case, eg. `swift` or `Swift`.
+    pub fn is_called(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || self
+                .file_extensions
+                .iter()
+                .any(|ext| ext.eq_ignore_ascii_case(name))
+    }
+
+    pub fn with_file_names(mut self, names: Vec<&str>) -> Self {
+        self.file_names = names.into_iter().map(|name| name.into()).collect();
+        self
+    }
+
+    /// The macros whose message is a todo in this language, see
+    /// `CommentStyle::Macro`.
+    pub fn macros(&self) -> impl Iterator<Item = &str> {
+        self.comment_styles.iter().filter_map(|style| match style {
+            CommentStyle::Macro(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
 }
 
 pub fn lang(name: &str, comment_styles: Vec<CommentStyle>, exts: Vec<&str>) -> SupportedLanguage {
@@ -39,6 +95,7 @@ pub fn lang(name: &str, comment_styles: Vec<CommentStyle>, exts: Vec<&str>) -> S
         name: name.into(),
         comment_styles,
         file_extensions: exts.into_iter().map(|ext| ext.into()).collect(),
+        file_names: vec![],
     }
 }
 
@@ -64,7 +121,25 @@ pub fn c_style() -> Vec<CommentStyle> {
 }
 
 pub fn rust_style() -> Vec<CommentStyle> {
-    c_style()
+    let mut c = c_style();
+    c.extend(vec![
+        CommentStyle::DocAttribute,
+        CommentStyle::AttributeNote,
+        CommentStyle::Macro("todo!".into()),
+        CommentStyle::Macro("unimplemented!".into()),
+    ]);
+    c
+}
+
+/// Any line may be a todo, after the `-` and `*` of a list item or the `>`
+/// of a quote.
+pub fn markdown_style() -> Vec<CommentStyle> {
+    vec![
+        CommentStyle::Prose,
+        from_border("-"),
+        from_border("*"),
+        from_border(">"),
+    ]
 }
 
 pub fn objc_style() -> Vec<CommentStyle> {
@@ -99,8 +174,19 @@ pub fn php_style() -> Vec<CommentStyle> {
     vec![from_single("//"), from_single("#"), from_multi("/*", "*/")]
 }
 
+pub fn ini_style() -> Vec<CommentStyle> {
+    vec![from_single(";"), from_single("#")]
+}
+
+/// Comments and docstrings, in either quotes and maybe raw, as in `r"""`.
 pub fn python_style() -> Vec<CommentStyle> {
-    vec![from_single("#"), from_multi("\"\"\"", "\"\"\"")]
+    vec![
+        from_single("#"),
+        from_multi("\"\"\"", "\"\"\""),
+        from_multi("'''", "'''"),
+        from_multi("r\"\"\"", "\"\"\""),
+        from_multi("r'''", "'''"),
+    ]
 }
 
 pub fn all_supported_langs() -> HashSet<SupportedLanguage> {
@@ -124,7 +210,8 @@ pub fn all_supported_langs() -> HashSet<SupportedLanguage> {
             ],
         ),
         lang("Clojure", lisp_style(), vec!["clj", "cljs", "cljc", "edn"]),
-        lang("Cmake", vec![from_single("#")], vec!["cmake"]),
+        lang("Cmake", vec![from_single("#")], vec!["cmake"])
+            .with_file_names(vec!["CMakeLists.txt"]),
         lang("CoffeeScript", coffee_style(), vec!["coffee", "litcoffee"]),
         lang("Cs", c_style(), vec!["cs"]),
         lang("CSS", vec![from_multi("/*", "*/")], vec!["css"]),
@@ -156,7 +243,8 @@ pub fn all_supported_langs() -> HashSet<SupportedLanguage> {
         ),
         lang("Haxe", c_style(), vec!["hx"]),
         lang("HTML", vec![from_multi("<!--", "-->")], vec!["html"]),
-        lang("Ini", vec![from_single(";")], vec!["ini"]),
+        lang("Dotenv", vec![from_single("#")], vec![]).with_file_names(vec![".env"]),
+        lang("Ini", ini_style(), vec!["ini"]),
         lang("Jade", vec![from_single("//-")], vec!["jade"]),
         lang("Jade", vec![from_single("//-")], vec!["pug"]),
         lang("Java", c_style(), vec!["java"]),
@@ -174,6 +262,7 @@ pub fn all_supported_langs() -> HashSet<SupportedLanguage> {
             vec!["lua"],
         ),
         lang("M4", vec![from_single("#")], vec!["m4"]),
+        lang("Markdown", markdown_style(), vec!["md", "markdown"]),
         lang(
             "Matlab",
             vec![from_single("%"), from_multi("%{", "%}")],
@@ -209,7 +298,7 @@ pub fn all_supported_langs() -> HashSet<SupportedLanguage> {
             vec![from_single("#"), from_multi("<#", "#>"), from_border("#")],
             vec!["ps1"],
         ),
-        lang("Properties", vec![from_single("#")], vec!["properties"]),
+        lang("Properties", ini_style(), vec!["properties"]),
         lang("Python", python_style(), vec!["py"]),
         lang(
             "R",
@@ -222,7 +311,7 @@ pub fn all_supported_langs() -> HashSet<SupportedLanguage> {
             vec![from_single("#"), from_multi("=begin", "=end")],
             vec!["rb"],
         ),
-        lang("Rust", c_style(), vec!["rs", "rc"]),
+        lang("Rust", rust_style(), vec!["rs", "rc"]),
         lang("Sbt", c_style(), vec!["sbt"]),
         lang("Scala", c_style(), vec!["sc", "scala"]),
         lang("Scss", vec![from_single("//")], vec!["scss"]),
@@ -232,6 +321,7 @@ pub fn all_supported_langs() -> HashSet<SupportedLanguage> {
         lang("Swift", swift_style(), vec!["swift"]),
         lang("Terraform", vec![from_single("#")], vec!["tf"]),
         lang("TeX", vec![from_single("%")], vec!["tex", "latex"]),
+        lang("Text", markdown_style(), vec!["txt"]),
         lang("Typescript", c_style(), vec!["ts"]),
         lang("Vala", vec![from_single("//")], vec!["vala", "vapi"]),
         lang(
@@ -254,9 +344,40 @@ pub fn all_supported_langs() -> HashSet<SupportedLanguage> {
     .collect()
 }
 
+/// The macros of the built-in languages and of the given ones, eg. from a
+/// config file, which are searched for along with the todo tags.
+///
+/// ```rust
+/// use todo_finder_lib::parser::langs::macros_with;
+///
+/// assert_eq!(macros_with(&[]), vec!["todo!", "unimplemented!"]);
+/// ```
+pub fn macros_with(custom: &[SupportedLanguage]) -> Vec<String> {
+    let languages = all_supported_langs();
+    let mut macros = languages
+        .iter()
+        .chain(custom)
+        .flat_map(SupportedLanguage::macros)
+        .map(String::from)
+        .collect::<Vec<_>>();
+    macros.sort();
+    macros.dedup();
+    macros
+}
+
 pub fn language_map() -> HashMap<String, Vec<SupportedLanguage>> {
+    language_map_with(&[])
+}
+
+/// Like `language_map`, but with more languages, eg. from a config file. A
+/// language whose extension is already known is added after the others for
+/// that extension, rather than replacing them.
+pub fn language_map_with(custom: &[SupportedLanguage]) -> HashMap<String, Vec<SupportedLanguage>> {
     let mut lang_map = HashMap::new();
-    for language in all_supported_langs().into_iter() {
+    let languages = all_supported_langs()
+        .into_iter()
+        .chain(custom.iter().cloned());
+    for language in languages {
         for ext in language.file_extensions.iter() {
             let langs_by_ext = lang_map.entry(ext.clone()).or_insert(vec![]);
             langs_by_ext.push(language.clone());
@@ -264,3 +385,204 @@ pub fn language_map() -> HashMap<String, Vec<SupportedLanguage>> {
     }
     lang_map
 }
+
+pub fn file_name_map() -> HashMap<String, Vec<SupportedLanguage>> {
+    file_name_map_with(&[])
+}
+
+/// Like `file_name_map`, but with more languages, see `language_map_with`.
+pub fn file_name_map_with(custom: &[SupportedLanguage]) -> HashMap<String, Vec<SupportedLanguage>> {
+    let mut name_map = HashMap::new();
+    let languages = all_supported_langs()
+        .into_iter()
+        .chain(custom.iter().cloned());
+    for language in languages {
+        for name in language.file_names.iter() {
+            let langs_by_name = name_map.entry(name.clone()).or_insert(vec![]);
+            langs_by_name.push(language.clone());
+        }
+    }
+    name_map
+}
+
+/// Parse a border to disable for a language, as `LANGUAGE=BORDER`, where the
+/// language is a name or file extension.
+///
+/// ```rust
+/// use todo_finder_lib::parser::langs::parse_disabled_border;
+///
+/// assert_eq!(
+///     parse_disabled_border("swift=:"),
+///     Ok(("swift".to_string(), ":".to_string()))
+/// );
+/// assert!(parse_disabled_border("swift").is_err());
+/// assert!(parse_disabled_border("=:").is_err());
+/// ```
+pub fn parse_disabled_border(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((language, border)) if !language.is_empty() && !border.is_empty() => {
+            Ok((language.to_string(), border.to_string()))
+        }
+        _ => Err(format!(
+            "invalid border '{}', expected LANGUAGE=BORDER, eg. swift=:",
+            arg
+        )),
+    }
+}
+
+/// Find the languages of the file at the given path, first by its whole file
+/// name and then by its extension.
+pub fn find_languages<'a>(
+    language_map: &'a HashMap<String, Vec<SupportedLanguage>>,
+    file_name_map: &'a HashMap<String, Vec<SupportedLanguage>>,
+    path: &Path,
+) -> Option<&'a Vec<SupportedLanguage>> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| file_name_map.get(name))
+        .or_else(|| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .and_then(|ext| language_map.get(ext))
+        })
+}
+
+/// The interpreter a script's first line says to run it with, if that line is
+/// a shebang. Versions are stripped from its name, and `env` is looked through.
+///
+/// ```rust
+/// use todo_finder_lib::parser::langs::shebang_interpreter;
+///
+/// assert_eq!(shebang_interpreter("#!/usr/bin/env python3"), Some("python"));
+/// assert_eq!(shebang_interpreter("#! /bin/bash -e"), Some("bash"));
+/// assert_eq!(shebang_interpreter("#!/usr/bin/env -S node --no-warnings"), Some("node"));
+/// assert_eq!(shebang_interpreter("#!/usr/bin/perl5.36"), Some("perl"));
+/// assert_eq!(shebang_interpreter("# TODO: Add a shebang."), None);
+/// ```
+pub fn shebang_interpreter(first_line: &str) -> Option<&str> {
+    let mut args = first_line.strip_prefix("#!")?.split_whitespace();
+    let mut program = args.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = args.find(|arg| !arg.starts_with('-') && !arg.contains('='))?;
+    }
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Find the languages of a script by the interpreter in its first line, for
+/// files without an extension, see `shebang_interpreter`.
+pub fn find_languages_by_shebang<'a>(
+    language_map: &'a HashMap<String, Vec<SupportedLanguage>>,
+    first_line: &str,
+) -> Option<&'a Vec<SupportedLanguage>> {
+    let ext = match shebang_interpreter(first_line)? {
+        "python" => "py",
+        "sh" | "bash" | "dash" | "ksh" | "zsh" => "sh",
+        "ruby" => "rb",
+        "node" | "nodejs" => "js",
+        "perl" => "pl",
+        _ => return None,
+    };
+    language_map.get(ext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::source::{parse_todo, ParsedTodo, TitleMode};
+
+    fn todo_title(language: &SupportedLanguage, bytes: &str) -> Option<String> {
+        parse_todo(language.as_todo_parser_config())(bytes)
+            .ok()
+            .map(|(_, todo): (_, ParsedTodo)| todo.title.to_string())
+    }
+
+    #[test]
+    fn ini_allows_both_comment_styles() {
+        let language_map = language_map();
+        let ini = &language_map.get("ini").unwrap()[0];
+        assert_eq!(
+            todo_title(ini, "; TODO: Use a real port.\nport = 80\n"),
+            Some("Use a real port.".into())
+        );
+        assert_eq!(
+            todo_title(ini, "# TODO: Use a real host.\nhost = localhost\n"),
+            Some("Use a real host.".into())
+        );
+    }
+
+    #[test]
+    fn can_find_languages_by_file_name() {
+        let (language_map, file_name_map) = (language_map(), file_name_map());
+        let find = |path: &str| {
+            find_languages(&language_map, &file_name_map, Path::new(path))
+                .map(|langs| langs[0].name.clone())
+        };
+        assert_eq!(find("project/.env"), Some("Dotenv".into()));
+        assert_eq!(find("project/settings.ini"), Some("Ini".into()));
+        assert_eq!(find("project/README"), None);
+    }
+
+    #[test]
+    fn borders_can_be_disabled() {
+        let language_map = language_map();
+        let swift = &language_map.get("swift").unwrap()[0];
+        let parse = |mut cfg: TodoParserConfig| {
+            cfg.title_mode = TitleMode::FirstLine;
+            parse_todo(cfg)("// TODO: :memo: Document the API\n//   :see: The guide.\n")
+                .map(|(_, todo)| (todo.title.to_string(), todo.desc_lines))
+                .unwrap()
+        };
+        assert_eq!(
+            parse(swift.as_todo_parser_config()),
+            (
+                "memo: Document the API".to_string(),
+                vec!["see: The guide."]
+            )
+        );
+
+        let disabled = vec![parse_disabled_border("swift=:").unwrap()];
+        assert_eq!(
+            parse(swift.as_todo_parser_config_without(&disabled)),
+            (
+                ":memo: Document the API".to_string(),
+                vec![":see: The guide."]
+            )
+        );
+        // Only for the given language
+        let objc = &language_map.get("mm").unwrap()[0];
+        let borders = objc
+            .as_todo_parser_config_without(&[("swift".into(), "!".into())])
+            .borders;
+        assert!(borders.contains(&"!".to_string()));
+    }
+
+    #[test]
+    fn custom_languages_add_to_the_built_in_ones() {
+        let dsl = lang(
+            "Pipeline",
+            vec![from_single("%%"), from_border("|")],
+            vec!["pipe", "h"],
+        );
+        let merged = language_map_with(std::slice::from_ref(&dsl));
+        assert_eq!(merged["pipe"], vec![dsl.clone()]);
+
+        // Header files are still C-like, and now maybe pipelines too
+        let headers = &merged["h"];
+        assert!(headers.len() > 1);
+        assert_eq!(headers.last(), Some(&dsl));
+        // The built-in languages come from a set, so their order may differ
+        let built_in = &language_map()["h"];
+        assert_eq!(headers.len() - 1, built_in.len());
+        assert!(built_in.iter().all(|language| headers.contains(language)));
+
+        assert_eq!(
+            todo_title(&dsl, "%% | TODO: Run in parallel.\n"),
+            Some("Run in parallel.".into())
+        );
+    }
+}
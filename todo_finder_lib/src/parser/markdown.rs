@@ -0,0 +1,336 @@
+//! # Parsing todos back out of a markdown file written by
+//! `IssueMap::as_markdown`.
+use nom::{
+    branch, bytes::complete as bytes, character::complete as character, combinator, sequence,
+    IResult,
+};
+
+use super::{take_to_eol, FileTodoLocation, Issue};
+
+/// Parse the heading of a todo, returning its number and title.
+///
+/// ```rust
+/// use todo_finder_lib::parser::markdown::*;
+///
+/// let bytes = "2. <a id=\"todo-abc\"></a>Do the thing.\n";
+/// assert_eq!(markdown_heading(bytes), Ok(("", (2, "Do the thing."))));
+/// ```
+pub fn markdown_heading(i: &str) -> IResult<&str, (usize, &str)> {
+    let (i, n) = combinator::map_res(character::digit1, |n: &str| n.parse::<usize>())(i)?;
+    let (i, _) = bytes::tag(". ")(i)?;
+    let (i, _) = combinator::opt(sequence::tuple((
+        bytes::tag("<a id=\""),
+        bytes::take_till(|c| c == '"'),
+        bytes::tag("\"></a>"),
+    )))(i)?;
+    take_to_eol(i).map(|(i, title)| (i, (n, title)))
+}
+
+/// Parse the line linking to one of a todo's locations.
+///
+/// ```rust
+/// use todo_finder_lib::parser::{markdown::*, FileTodoLocation};
+///
+/// let bytes = "  file://src/lib.rs (lines 3 - 5)";
+/// assert_eq!(
+///     markdown_location(bytes),
+///     Ok((
+///         "",
+///         FileTodoLocation {
+///             file: "src/lib.rs".into(),
+///             src_span: (3, Some(5))
+///         }
+///     ))
+/// );
+/// ```
+pub fn markdown_location(i: &str) -> IResult<&str, FileTodoLocation> {
+    let line_number = || combinator::map_res(character::digit1, |n: &str| n.parse::<usize>());
+    let (i, _) = bytes::tag("  file://")(i)?;
+    let (i, file) = bytes::take_until(" (line")(i)?;
+    let (i, src_span) = branch::alt((
+        sequence::delimited(
+            bytes::tag(" (lines "),
+            combinator::map(
+                sequence::separated_pair(line_number(), bytes::tag(" - "), line_number()),
+                |(start, end)| (start, Some(end)),
+            ),
+            character::char(')'),
+        ),
+        sequence::delimited(
+            bytes::tag(" (line "),
+            combinator::map(line_number(), |start| (start, None)),
+            character::char(')'),
+        ),
+    ))(i)?;
+    Ok((
+        i,
+        FileTodoLocation {
+            file: file.into(),
+            src_span,
+        },
+    ))
+}
+
+/// Parse one todo, from its heading to the heading of the next, keyed by its
+/// number in the file.
+pub fn markdown_todo(i: &str) -> IResult<&str, Issue<usize, FileTodoLocation>> {
+    let (mut i, (n, title)) = markdown_heading(i)?;
+    let mut issue = Issue::new(n, title.to_string());
+    let mut descs = vec![];
+    while !i.is_empty() && markdown_heading(i).is_err() {
+        let (j, line) = take_to_eol(i)?;
+        if let Ok((_, loc)) = markdown_location(line) {
+            issue
+                .body
+                .descs_and_srcs
+                .push((std::mem::take(&mut descs), loc));
+        } else if let Some(assignees) = line.strip_prefix("  assignees: ") {
+            issue.head.assignees = assignees.split(", ").map(String::from).collect();
+        } else if let Some(priority) = line.strip_prefix("  priority: p") {
+            issue.head.priority = priority.parse().ok();
+        } else if let Some(tags) = line.strip_prefix("  tags: ") {
+            issue.head.tags = tags
+                .split(", ")
+                .map(|tag| tag.trim_start_matches('#').to_string())
+                .collect();
+        } else if let Some(desc) = line.strip_prefix("  ") {
+            descs.push(desc.to_string());
+        } else if !line.is_empty() {
+            // The stats that end the file
+            break;
+        }
+        i = j;
+    }
+    Ok((i, issue))
+}
+
+/// Parse the heading of a file's section, when the todos are grouped by
+/// file, up to the first todo in it.
+///
+/// ```rust
+/// use todo_finder_lib::parser::markdown::*;
+///
+/// let bytes = "## src/lib.rs\n\n1. Do the thing.\n";
+/// assert_eq!(markdown_file_heading(bytes), Ok(("1. Do the thing.\n", "src/lib.rs")));
+/// assert!(markdown_file_heading("## Assignees\n\n| a | 1 |\n").is_err());
+/// ```
+pub fn markdown_file_heading(i: &str) -> IResult<&str, &str> {
+    let (i, _) = bytes::tag("## ")(i)?;
+    let (mut i, file) = take_to_eol(i)?;
+    while let Some(j) = i.strip_prefix('\n') {
+        i = j;
+    }
+    combinator::peek(markdown_heading)(i)?;
+    Ok((i, file))
+}
+
+/// Parse all the todos in the file, skipping the header before the first one,
+/// the headings of files between them and the stats after the last.
+pub fn markdown_todos(i: &str) -> IResult<&str, Vec<Issue<usize, FileTodoLocation>>> {
+    let mut i = i;
+    while !i.is_empty() && markdown_heading(i).is_err() {
+        i = take_to_eol(i)?.0;
+    }
+    let mut todos = vec![];
+    loop {
+        if let Ok((j, todo)) = markdown_todo(i) {
+            todos.push(todo);
+            i = j;
+        } else if let Ok((j, _)) = markdown_file_heading(i) {
+            i = j;
+        } else {
+            break;
+        }
+    }
+    Ok((i, todos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        message::Message,
+        parser::{langs, GroupBy, IssueMap, MarkdownOptions, ScanOptions, SortKey, SortOrder},
+    };
+
+    fn rust_todos(contents: &str, lines: &[usize]) -> IssueMap<(), FileTodoLocation> {
+        let rust = langs::language_map().get("rs").unwrap().clone();
+        IssueMap::from_file_contents(
+            "src/main.rs",
+            contents,
+            lines,
+            &rust,
+            &ScanOptions::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn markdown_round_trips() {
+        let contents = "// TODO(alice, p1): Do the thing. #backend\n//   Carefully.\n\
+                        fn main() {}\n/* FIXME: Fix it.\n * It's\n * broken.\n */\n\
+                        fn a() {}\n// TODO(alice, p1): Do the thing.\n";
+        let local = rust_todos(contents, &[1, 4, 9]);
+        let markdown = local.as_markdown();
+        let parsed = IssueMap::from_markdown(&markdown).unwrap();
+
+        assert_eq!(parsed.todos.len(), local.todos.len(), "{}", markdown);
+        for (title, issue) in local.todos.iter() {
+            let found = &parsed.todos[title];
+            assert_eq!(found.head.assignees, issue.head.assignees);
+            assert_eq!(found.head.tags, issue.head.tags);
+            assert_eq!(found.head.priority, issue.head.priority);
+            assert_eq!(found.body, issue.body, "{}", markdown);
+        }
+        assert_eq!(parsed.todos["Fix it."].head.external_id, 2);
+    }
+
+    #[test]
+    fn markdown_with_line_anchors_round_trips() {
+        let local = rust_todos(
+            "/* TODO: Span.\n * Lines.\n */\n// TODO: One line.\n",
+            &[1, 4],
+        );
+        for (anchor_format, span, line) in [
+            (
+                "#L{start}-{end}",
+                "src/main.rs#L1-3 (lines 1 - 3)",
+                "src/main.rs#L4 (line 4)",
+            ),
+            (
+                ":{start}",
+                "src/main.rs:1 (lines 1 - 3)",
+                "src/main.rs:4 (line 4)",
+            ),
+        ] {
+            let markdown = local.as_markdown_with(&MarkdownOptions {
+                anchor_format: Some(anchor_format.to_string()),
+                ..MarkdownOptions::default()
+            });
+            assert!(
+                markdown.contains(&format!("file://{}", span)),
+                "{}",
+                markdown
+            );
+            assert!(
+                markdown.contains(&format!("file://{}", line)),
+                "{}",
+                markdown
+            );
+
+            let parsed = IssueMap::from_markdown_anchored(&markdown, Some(anchor_format)).unwrap();
+            for (title, issue) in local.todos.iter() {
+                assert_eq!(parsed.todos[title].body, issue.body, "{}", markdown);
+            }
+        }
+    }
+
+    #[test]
+    fn markdown_grouped_by_file_round_trips() {
+        let mut local = rust_todos(
+            "// TODO: Once.\n// TODO: Twice.\n// TODO: Twice.\n",
+            &[1, 2, 3],
+        );
+        let lib = langs::language_map().get("rs").unwrap().clone();
+        local.merge(
+            IssueMap::from_file_contents(
+                "src/lib.rs",
+                "// TODO: Twice.\n",
+                &[1],
+                &lib,
+                &ScanOptions::default(),
+            )
+            .unwrap(),
+        );
+        let markdown = local.as_markdown_with(&MarkdownOptions {
+            order: SortOrder::by(SortKey::Occurrences),
+            group_by: GroupBy::File,
+            anchor_format: None,
+        });
+        let lib_at = markdown.find("## src/lib.rs").unwrap();
+        let main_at = markdown.find("## src/main.rs").unwrap();
+        assert!(lib_at < main_at, "{}", markdown);
+        // The most duplicated first, in each file
+        let twice_at = markdown[main_at..].find("Twice.").unwrap();
+        let once_at = markdown[main_at..].find("Once.").unwrap();
+        assert!(twice_at < once_at, "{}", markdown);
+        // Listed under both files but anchored once, as HTML ids are unique
+        assert_eq!(markdown.matches("></a>Twice.").count(), 1, "{}", markdown);
+        assert_eq!(markdown.matches(". Twice.").count(), 1, "{}", markdown);
+
+        let parsed = IssueMap::from_markdown(&markdown).unwrap();
+        assert_eq!(parsed.todos.len(), 2, "{}", markdown);
+        for (title, issue) in local.todos.iter() {
+            let mut locations = parsed.todos[title]
+                .body
+                .descs_and_srcs
+                .iter()
+                .map(|(_, loc)| loc.clone())
+                .collect::<Vec<_>>();
+            let mut expected = issue
+                .body
+                .descs_and_srcs
+                .iter()
+                .map(|(_, loc)| loc.clone())
+                .collect::<Vec<_>>();
+            locations.sort_by(|a, b| (&a.file, a.src_span).cmp(&(&b.file, b.src_span)));
+            expected.sort_by(|a, b| (&a.file, a.src_span).cmp(&(&b.file, b.src_span)));
+            assert_eq!(locations, expected, "{}", markdown);
+        }
+        assert!(markdown.contains("\n## Assignees"), "{}", markdown);
+    }
+
+    #[test]
+    fn markdown_updates_keep_hand_written_notes() {
+        let options = MarkdownOptions::default();
+        let old = rust_todos("// TODO: Keep.\n// TODO: Drop.\n", &[1, 2]);
+        let generated = old.update_markdown("", &options);
+        assert_eq!(generated, old.update_markdown(&old.as_markdown(), &options));
+        let intro = "# Our TODOs\n\nTriaged weekly:\n1. Not a todo.\n\n";
+        let notes = "\n\n## Notes\n\nDrop is blocked on the release.\n";
+        let edited = format!("{}{}{}", intro, generated, notes);
+        assert_eq!(old.update_markdown(&edited, &options), edited);
+
+        let new = rust_todos("// TODO: Keep.\n// TODO: Add.\n", &[1, 2]);
+        let updated = new.update_markdown(&edited.replace('\n', "\r\n"), &options);
+        assert_eq!(
+            updated,
+            format!("{}{}{}", intro, new.update_markdown("", &options), notes)
+        );
+        assert!(!updated.contains("Drop."), "{}", updated);
+
+        let parsed = IssueMap::from_markdown(&updated).unwrap();
+        let mut titles = parsed.todos.keys().cloned().collect::<Vec<_>>();
+        titles.sort();
+        assert_eq!(titles, vec!["Add.", "Keep."], "{}", updated);
+        assert_eq!(
+            parsed.preview_patch(&new).to_string(),
+            "would create 0, update 0 and delete 0 TODOs"
+        );
+    }
+
+    #[test]
+    fn can_preview_changes_to_markdown() {
+        let old = rust_todos(
+            "// TODO: Keep.\n// TODO: Move.\n// TODO: Drop.\n",
+            &[1, 2, 3],
+        );
+        let markdown = IssueMap::from_markdown(&old.as_markdown()).unwrap();
+        let new = rust_todos(
+            "// TODO: Keep.\nfn a() {}\n// TODO: Move.\n// TODO: Add.\n",
+            &[1, 3, 4],
+        );
+        assert_eq!(
+            markdown.preview_patch(&new),
+            Message::PreparedPatch {
+                create: vec!["Add.".into()],
+                update: vec!["Move.".into()],
+                delete: vec!["Drop.".into()],
+            }
+        );
+        assert_eq!(
+            markdown.preview_patch(&old).to_string(),
+            "would create 0, update 0 and delete 0 TODOs"
+        );
+    }
+}
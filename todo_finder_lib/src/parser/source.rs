@@ -6,9 +6,9 @@ use super::{
 
 use nom::{
     branch, bytes::complete as bytes, character::complete as character, combinator,
-    error::ErrorKind, multi, Err, IResult,
+    error::ErrorKind, multi, sequence, Err, IResult,
 };
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap, sync::OnceLock};
 
 #[cfg(test)]
 mod test_my_assumptions {
@@ -40,12 +40,12 @@ mod test_my_assumptions {
         if let Ok((i, ())) = combinator::not(todo_tag)(i) {
             assert_eq!(i, "blah1 blah2");
         } else {
-            assert!(false, "Failed");
+            panic!("Failed");
         }
 
         let i = "TODO: blah1 blah2";
         if let Ok((_, ())) = combinator::not(todo_tag)(i) {
-            assert!(false, "Failed");
+            panic!("Failed");
         }
     }
 
@@ -90,7 +90,10 @@ mod test_my_assumptions {
         let bytes = "-- TODO: This is a todo.\n\n\n-------------\n";
         assert_eq!(
             single_line_todo(vec![], "--".into())(bytes),
-            Ok(("\n\n-------------\n", (None, "This is a todo.", vec![])))
+            Ok((
+                "\n\n-------------\n",
+                (None, "This is a todo.".into(), vec![], "TODO")
+            ))
         );
 
         let bytes = "    # TODO: Let's have a byte to eat. Ok.\n    # TODO(): Nah, let's just \
@@ -100,8 +103,18 @@ mod test_my_assumptions {
             Ok((
                 "    \n",
                 vec![
-                    (None, "Let's have a byte to eat.", vec!["Ok.".into()]),
-                    (Some(""), "Nah, let's just have a nibble.", vec![])
+                    (
+                        None,
+                        "Let's have a byte to eat.".into(),
+                        vec!["Ok."],
+                        "TODO"
+                    ),
+                    (
+                        Some(""),
+                        "Nah, let's just have a nibble.".into(),
+                        vec![],
+                        "TODO"
+                    )
                 ]
             ))
         );
@@ -109,7 +122,10 @@ mod test_my_assumptions {
         let bytes = "    # TODO: Do A.\n    # TODO: Do B.\n";
         assert_eq!(
             single_line_todo(vec![], "#".into())(bytes),
-            Ok(("    # TODO: Do B.\n", (None, "Do A.", vec![])))
+            Ok((
+                "    # TODO: Do B.\n",
+                (None, "Do A.".into(), vec![], "TODO")
+            ))
         );
 
         let bytes = "    # TODO: aborted evaluations\n    # TODO: dependency failed without \
@@ -120,7 +136,48 @@ mod test_my_assumptions {
             Ok((
                 "    # TODO: dependency failed without propagated builds
    for tr in d('img[alt=\"Failed\"]').parents('tr'):\n",
-                (None, "aborted evaluations", vec![])
+                (None, "aborted evaluations".into(), vec![], "TODO")
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_wrapped_titles() {
+        let bytes = "// TODO: Make sure that titles which are wrapped\n// onto the next line are \
+                     whole. And then describe.\n// More description.\nfn main() {}\n";
+        assert_eq!(
+            single_line_todo(vec![], "//".into())(bytes),
+            Ok((
+                "fn main() {}\n",
+                (
+                    None,
+                    "Make sure that titles which are wrapped onto the next line are whole.".into(),
+                    vec!["And then describe.", "More description."],
+                    "TODO"
+                )
+            ))
+        );
+
+        let bytes = "// TODO: An unterminated title\n//\n// A description.\n";
+        assert_eq!(
+            single_line_todo(vec![], "//".into())(bytes),
+            Ok((
+                "",
+                (
+                    None,
+                    "An unterminated title".into(),
+                    vec!["A description."],
+                    "TODO"
+                )
+            ))
+        );
+
+        let bytes = "# TODO: An unterminated title\nprint('hello')\n";
+        assert_eq!(
+            single_line_todo(vec![], "#".into())(bytes),
+            Ok((
+                "print('hello')\n",
+                (None, "An unterminated title".into(), vec![], "TODO")
             ))
         );
     }
@@ -138,8 +195,9 @@ mod test_my_assumptions {
                 "\n",
                 (
                     None,
-                    "Make sure this comment gets turned",
-                    vec!["into a todo.",]
+                    "Make sure this comment gets turned".into(),
+                    vec!["into a todo.",],
+                    "TODO"
                 )
             ))
         );
@@ -147,7 +205,15 @@ mod test_my_assumptions {
         let bytes = "{- | TODO: List the steps to draw an owl. -}\n";
         assert_eq!(
             haskell_parser(bytes),
-            Ok(("", (None, "List the steps to draw an owl.", vec![])))
+            Ok((
+                "",
+                (
+                    None,
+                    "List the steps to draw an owl.".into(),
+                    vec![],
+                    "TODO"
+                )
+            ))
         );
 
         let bytes = "{- TODO: Figure out why duplicate tickets are being made.
@@ -162,22 +228,55 @@ mod test_my_assumptions {
                 "\n",
                 (
                     None,
-                    "Figure out why duplicate tickets are being made.",
+                    "Figure out why duplicate tickets are being made.".into(),
                     vec![
                         "The todo above \"Add log levels\" is getting re-created on each check-in.",
                         "Fix dis shizz!"
-                    ]
+                    ],
+                    "TODO"
                 )
             ))
         );
     }
 
+    #[test]
+    fn parse_banner_todos() {
+        use super::super::langs::{c_style, CommentStyle};
+
+        let mut styles = c_style();
+        styles.push(CommentStyle::Border("-=".into()));
+        let c_parser = parse_todo(TodoParserConfig::from_comment_styles(styles));
+        let parse = |bytes| {
+            c_parser(bytes)
+                .map(|(_, todo)| (todo.title.to_string(), todo.desc_lines))
+                .unwrap()
+        };
+
+        assert_eq!(
+            parse("/*** TODO: Fix the banner. ***/\n"),
+            ("Fix the banner.".into(), vec![])
+        );
+        assert_eq!(
+            parse("/* TODO: Close on the same line. */\n"),
+            ("Close on the same line.".into(), vec![])
+        );
+        assert_eq!(
+            parse("/*** TODO: Fix the banner.\n *** It's loud.\n *** Very. ***\n ***/\n"),
+            ("Fix the banner.".into(), vec!["It's loud.", "Very."])
+        );
+        assert_eq!(
+            parse("/* -=-= TODO: Fix the banner. -=-=\n *-=-= It's loud. -=-=*\n */\n"),
+            ("Fix the banner.".into(), vec!["It's loud."])
+        );
+    }
+
     #[test]
     fn parse_todos() {
         let c_parser = parse_todo(TodoParserConfig {
             singles: vec!["//".into()],
             multis: vec![("/*".into(), "*/".into())],
             borders: vec!["*".into()],
+            ..TodoParserConfig::new()
         });
 
         let bytes = "/** FIXME: C++ doc title.
@@ -189,12 +288,12 @@ mod test_my_assumptions {
             Ok((
                 "\n",
                 ParsedTodo {
-                    title: "C++ doc title.",
-                    assignee: None,
                     desc_lines: vec![
                         "C++ doc body. Here is some detail",
                         "that is really interesting."
-                    ]
+                    ],
+                    tag: "FIXME",
+                    ..ParsedTodo::new("C++ doc title.")
                 }
             ))
         );
@@ -203,6 +302,7 @@ mod test_my_assumptions {
             singles: vec!["#".into()],
             multis: vec![],
             borders: vec![],
+            ..TodoParserConfig::new()
         });
 
         let bytes = "    # TODO: aborted evaluations\n    # TODO: dependency failed without \
@@ -212,334 +312,1735 @@ mod test_my_assumptions {
             Ok((
                 "    # TODO: dependency failed without propagated builds\n    for tr in \
                  d('img[alt=\"Failed\"]').parents('tr'):\n",
+                ParsedTodo::new("aborted evaluations")
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_doc_attribute_todos() {
+        let rust_parser = parse_todo(TodoParserConfig::from_comment_styles(
+            super::super::langs::rust_style(),
+        ));
+
+        let bytes = "#![doc = \"TODO: Write the crate docs.\\nWith examples.\"]\n";
+        assert_eq!(
+            rust_parser(bytes),
+            Ok((
+                "",
+                ParsedTodo {
+                    desc_lines: vec!["With examples."],
+                    ..ParsedTodo::new("Write the crate docs.")
+                }
+            ))
+        );
+
+        let bytes = r#"    #[doc = "FIXME(schell): Don't \"panic\". Return an error."]
+    fn go() {}
+"#;
+        assert_eq!(
+            rust_parser(bytes),
+            Ok((
+                "    fn go() {}\n",
                 ParsedTodo {
-                    title: "aborted evaluations",
-                    assignee: None,
-                    desc_lines: vec![]
+                    assignee: Some("schell"),
+                    desc_lines: vec!["Return an error."],
+                    tag: "FIXME",
+                    ..ParsedTodo::new(r#"Don't \"panic\"."#)
                 }
             ))
         );
+
+        let c_parser = parse_todo(TodoParserConfig::from_comment_styles(
+            super::super::langs::c_style(),
+        ));
+        assert!(c_parser("#[doc = \"TODO: Not in C.\"]\n").is_err());
     }
-}
 
-/// Eat a single or multi line comment start.
-///
-/// ```rust
-/// use todo_finder_lib::parser::source::*;
-///
-/// let (borders, single) = (vec!["|".to_string()], "--".to_string());
-///
-/// assert_eq!(
-///     comment_start(borders, single)(" -- Here is a comment."),
-///     Ok(("Here is a comment.", ()))
-/// );
-/// ```
-pub fn comment_start(
-    // An ignorable border for comments that like to have outlines.
-    // Eg. "*" for C-like langs or "!" for Objective-C.
-    borders: Vec<String>,
-    // The comment prefix.
-    // Eg. "--" or "{-" for Haskell, "//" or "/*" for Rust.
-    prefix: String,
-) -> impl Fn(&str) -> IResult<&str, ()> {
-    move |i: &str| {
-        let (i, _) = character::space0(i)?;
-        let (i, _) = bytes::tag(prefix.as_str())(i)?;
-        let (i, _) = character::space0(i)?;
-        let i = {
-            let mut input_left = i;
-            'eat_borders: for border in borders.iter() {
-                let (input, ate) = combinator::opt(bytes::tag(border.as_str()))(input_left)?;
-                input_left = input;
-                match ate {
-                    Some(_) => {
-                        break 'eat_borders;
-                    }
-                    None => {}
+    #[test]
+    fn parse_trailing_hashtags() {
+        let python_parser = parse_todo(TodoParserConfig {
+            singles: vec!["#".into()],
+            ..TodoParserConfig::new()
+        });
+
+        let bytes = "# TODO: Fix the layout on small screens. #frontend #p2\n# See #123 #css\n";
+        assert_eq!(
+            python_parser(bytes),
+            Ok((
+                "",
+                ParsedTodo {
+                    desc_lines: vec!["See #123 #css"],
+                    tags: vec!["frontend".into(), "p2".into()],
+                    ..ParsedTodo::new("Fix the layout on small screens.")
                 }
-            }
-            input_left
+            ))
+        );
+
+        let bytes = "# TODO: Release it. #backend #p1\n";
+        assert_eq!(
+            python_parser(bytes),
+            Ok((
+                "",
+                ParsedTodo {
+                    tags: vec!["backend".into(), "p1".into()],
+                    ..ParsedTodo::new("Release it.")
+                }
+            ))
+        );
+
+        let c_parser = parse_todo(TodoParserConfig {
+            singles: vec!["//".into()],
+            ..TodoParserConfig::new()
+        });
+        let bytes = "// TODO: Drop the header. Then remove\n// #include <stdio.h>\n";
+        assert_eq!(
+            c_parser(bytes),
+            Ok((
+                "",
+                ParsedTodo {
+                    desc_lines: vec!["Then remove", "#include <stdio.h>"],
+                    ..ParsedTodo::new("Drop the header.")
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_priority_markers() {
+        let parser = parse_todo(TodoParserConfig {
+            singles: vec!["//".into()],
+            ..TodoParserConfig::new()
+        });
+        let parsed = |bytes| {
+            parser(bytes)
+                .map(|(_, todo)| (todo.tag, todo.assignee, todo.title, todo.priority))
+                .unwrap()
         };
-        let (i, _) = character::space0(i)?;
-        Ok((i, ()))
+        assert_eq!(
+            parsed("// TODO(p1): Ship it.\n"),
+            ("TODO", None, "Ship it.".into(), Some(1))
+        );
+        assert_eq!(
+            parsed("// FIXME!!! Fix it now.\n"),
+            ("FIXME", None, "Fix it now.".into(), Some(0))
+        );
+        assert_eq!(
+            parsed("// TODO!(schell): Soonish.\n"),
+            ("TODO", Some("schell"), "Soonish.".into(), Some(2))
+        );
+        assert_eq!(
+            parsed("// TODO(p1x): Not a priority.\n"),
+            ("TODO", Some("p1x"), "Not a priority.".into(), None)
+        );
     }
-}
 
-/// Eat an assigned name.
-///
-/// ```rust
-/// use todo_finder_lib::parser::source::*;
-///
-/// assert_eq!(assignee("(mitchellwrosen)"), Ok(("", "mitchellwrosen")))
-/// ```
-pub fn assignee(i: &str) -> IResult<&str, &str> {
-    let (i, _) = character::char('(')(i)?;
-    let (i, _) = character::space0(i)?;
-    let is_end = |input: char| input != '\r' && input != '\n' && input != ' ' && input != ')';
-    let (i, name) = bytes::take_while(is_end)(i)?;
-    let (i, _) = character::char(')')(i)?;
-    Ok((i, name))
-}
+    #[test]
+    fn issue_references_are_not_assignees() {
+        let parser = parse_todo(TodoParserConfig {
+            singles: vec!["//".into()],
+            ..TodoParserConfig::new()
+        });
+        let parsed = |bytes| {
+            parser(bytes)
+                .map(|(_, todo)| (todo.assignee, todo.title, todo.reference))
+                .unwrap()
+        };
+        assert_eq!(
+            parsed("// TODO(#123): Drop the shim.\n"),
+            (None, "Drop the shim.".into(), Some(123))
+        );
+        assert_eq!(
+            parsed("// TODO(@schell): Drop the shim.\n"),
+            (Some("schell"), "Drop the shim.".into(), None)
+        );
+    }
 
-/// Eat a todo tag. Currently supports `TODO`, `FIXME` and `@todo`.
-/// It will also eat any assigned name following the todo tag and return it.
-///
-/// ```rust
-/// use nom::multi;
-/// use todo_finder_lib::parser::source::*;
-///
-/// assert_eq!(todo_tag("@todo "), Ok(("", None)));
-/// assert_eq!(todo_tag("TODO "), Ok(("", None)));
-/// assert_eq!(todo_tag("TODO"), Ok(("", None)));
-/// assert_eq!(todo_tag("FIXME"), Ok(("", None)));
-///
-/// let all_text = "TODO(schell) FIXME (mitchellwrosen) @todo(imalsogreg)";
-/// let parsed = multi::many1(|i| todo_tag(i))(all_text);
-/// assert_eq!(
-///     parsed,
-///     Ok((
-///         "",
-///         vec![Some("schell"), Some("mitchellwrosen"), Some("imalsogreg")]
-///     ))
-/// );
-/// ```
-pub fn todo_tag(i: &str) -> IResult<&str, Option<&str>> {
-    let (i, _) = character::space0(i)?;
-    let tags = (bytes::tag("TODO"), bytes::tag("FIXME"), bytes::tag("@todo"));
-    let (i, _) = branch::alt(tags)(i)?;
-    let (i, _) = character::space0(i)?;
-    let (i, may_name) = combinator::opt(|i| assignee(i))(i)?;
-    let (i, _) = character::space0(i)?;
-    let (i, _) = combinator::opt(character::char(':'))(i)?;
-    let (i, _) = character::space0(i)?;
-    Ok((i, may_name))
-}
+    #[test]
+    fn parse_emoji_severity_labels() {
+        let parser = parse_todo(TodoParserConfig {
+            singles: vec!["//".into()],
+            emoji_labels: vec![
+                ("🔴".into(), "critical".into()),
+                ("🟡".into(), "minor".into()),
+            ],
+            ..TodoParserConfig::new()
+        });
+        let parsed = |bytes| {
+            parser(bytes)
+                .map(|(_, todo)| (todo.assignee, todo.title, todo.tags))
+                .unwrap()
+        };
+        assert_eq!(
+            parsed("// TODO: 🔴 Fix the crash on startup.\n"),
+            (
+                None,
+                "Fix the crash on startup.".into(),
+                vec!["critical".into()]
+            )
+        );
+        assert_eq!(
+            parsed("// FIXME[ui](schell): 🟡 Tidy the layout. #frontend\n"),
+            (
+                Some("schell"),
+                "Tidy the layout.".into(),
+                vec!["ui".into(), "minor".into(), "frontend".into()]
+            )
+        );
+        assert_eq!(
+            parsed("// TODO: 🟢 Not a configured emoji.\n"),
+            (None, "🟢 Not a configured emoji.".into(), vec![])
+        );
+    }
 
-/// Eat a sentence and its terminator and a space.
-/// Terminators must have an empty space after them to be considered valid,
-/// otherwise they could be a programming operator.
-/// The entire eaten sentence and terminators will be returned in a Vector of
-/// slices.
-///
-/// ```rust
-/// use todo_finder_lib::parser::source::*;
-///
-/// let bytes = "Sandy doesn't like fmap.fmap for some reason. Other sentence.\n";
-/// assert_eq!(
-///     sentence_and_terminator(bytes),
-///     Ok((
-///         "Other sentence.\n",
-///         "Sandy doesn't like fmap.fmap for some reason."
-///     ))
-/// );
-///
-/// let bytes = "Hey you, I bet you allocate\na lot of resources. Maybe not.";
-/// assert_eq!(
-///     sentence_and_terminator(bytes),
-///     Ok((
-///         "Maybe not.",
-///         "Hey you, I bet you allocate\na lot of resources."
-///     ))
-/// );
-/// ```
-pub fn sentence_and_terminator(i: &str) -> IResult<&str, &str> {
-    let is_terminator = |c: char| c == '.' || c == '?' || c == '!';
-    let mut n = 0;
-    let mut ii = i;
-    'eating_sentences: loop {
-        let (j, sentence) = bytes::take_till(is_terminator)(ii)?;
-        let (j, terminators) = bytes::take_while(is_terminator)(j)?;
-        let (j, space) = combinator::opt(character::char(' '))(j)?;
-        ii = j;
-        n += sentence.len();
-        n += terminators.len();
-        if space.is_some() || j == "" {
-            // Unless we get a space or are at the end, keep eating more
-            break 'eating_sentences;
-        }
+    #[test]
+    fn parse_bracketed_labels() {
+        let parser = parse_todo(TodoParserConfig {
+            singles: vec!["//".into()],
+            ..TodoParserConfig::new()
+        });
+        let parsed = |bytes| {
+            parser(bytes)
+                .map(|(_, todo)| (todo.assignee, todo.title, todo.tags))
+                .unwrap()
+        };
+        assert_eq!(
+            parsed("// TODO[backend,perf]: Cache the query. #db\n"),
+            (
+                None,
+                "Cache the query.".into(),
+                vec!["backend".into(), "perf".into(), "db".into()]
+            )
+        );
+        assert_eq!(
+            parsed("// FIXME!![ui](schell) Fix the layout.\n"),
+            (Some("schell"), "Fix the layout.".into(), vec!["ui".into()])
+        );
     }
-    let (sentence, i) = i.split_at(n);
-    let i = i.trim_start();
-    Ok((i, sentence))
-}
 
-/// Trim any source code borders off of the string. This only accounts for
-/// borders on the beginning and end of the string, not in the middle. To remove
-/// borders from the middle of a string, first break it into lines. Furthermore
-/// this function *will remove trailing whitespace, including line breaks*.
+    #[test]
+    fn parse_titles_in_each_mode() {
+        let parser = |title_mode| {
+            parse_todo(TodoParserConfig {
+                singles: vec!["//".into()],
+                multis: vec![("/*".into(), "*/".into())],
+                borders: vec!["*".into()],
+                title_mode,
+                ..TodoParserConfig::new()
+            })
+        };
+
+        let bytes = "// TODO: Support e.g. v1.2 of the spec. Soon\n// More detail.\n";
+        assert_eq!(
+            parser(TitleMode::Sentence)(bytes).map(|(_, todo)| (todo.title, todo.desc_lines)),
+            Ok((
+                "Support e.g.".into(),
+                vec!["v1.2 of the spec. Soon", "More detail."]
+            ))
+        );
+        assert_eq!(
+            parser(TitleMode::FirstLine)(bytes).map(|(_, todo)| (todo.title, todo.desc_lines)),
+            Ok((
+                "Support e.g. v1.2 of the spec. Soon".into(),
+                vec!["More detail."]
+            ))
+        );
+
+        let bytes = "// TODO: A wrapped\n// title.\n";
+        assert_eq!(
+            parser(TitleMode::Sentence)(bytes).map(|(_, todo)| (todo.title, todo.desc_lines)),
+            Ok(("A wrapped title.".into(), vec![]))
+        );
+        assert_eq!(
+            parser(TitleMode::FirstLine)(bytes).map(|(_, todo)| (todo.title, todo.desc_lines)),
+            Ok(("A wrapped".into(), vec!["title."]))
+        );
+
+        let bytes = "/* TODO: Use v2.0 now. */\n";
+        assert_eq!(
+            parser(TitleMode::FirstLine)(bytes).map(|(_, todo)| (todo.title, todo.desc_lines)),
+            Ok(("Use v2.0 now.".into(), vec![]))
+        );
+        let bytes = "/** TODO: Use v2.0 now.\n * Because.\n */\n";
+        assert_eq!(
+            parser(TitleMode::FirstLine)(bytes).map(|(_, todo)| (todo.title, todo.desc_lines)),
+            Ok(("Use v2.0 now.".into(), vec!["Because."]))
+        );
+    }
+
+    #[test]
+    fn parse_titles_with_a_separator() {
+        let parser = parse_todo(TodoParserConfig {
+            singles: vec!["//".into()],
+            multis: vec![("/*".into(), "*/".into())],
+            borders: vec!["*".into()],
+            title_mode: TitleMode::Separator("||".into()),
+            ..TodoParserConfig::new()
+        });
+
+        let bytes = "// TODO: Support e.g. v1.2 || The spec. Soon\n// More detail.\n";
+        assert_eq!(
+            parser(bytes).map(|(_, todo)| (todo.title, todo.desc_lines)),
+            Ok((
+                "Support e.g. v1.2".into(),
+                vec!["The spec. Soon", "More detail."]
+            ))
+        );
+
+        // Without the separator the whole line is the title
+        let bytes = "// TODO: Support e.g. v1.2. Soon\n// More detail.\n";
+        assert_eq!(
+            parser(bytes).map(|(_, todo)| (todo.title, todo.desc_lines)),
+            Ok(("Support e.g. v1.2. Soon".into(), vec!["More detail."]))
+        );
+
+        let bytes = "/* TODO: Use v2.0 || It's faster. */\nint a;\n";
+        assert_eq!(
+            parser(bytes).map(|(i, todo)| (i, todo.title, todo.desc_lines)),
+            Ok(("int a;\n", "Use v2.0".into(), vec!["It's faster."]))
+        );
+        let bytes = "/* TODO: Use v2.0 */\n";
+        assert_eq!(
+            parser(bytes).map(|(_, todo)| (todo.title, todo.desc_lines)),
+            Ok(("Use v2.0".into(), vec![]))
+        );
+    }
+
+    #[test]
+    fn parse_every_tag_kind() {
+        let lines = TODO_KEYWORDS
+            .iter()
+            .map(|keyword| (*keyword, format!("// {}(schell): Do it.\n", keyword)))
+            .collect::<Vec<_>>();
+        let parser = parse_todo(TodoParserConfig {
+            singles: vec!["//".into()],
+            macros: vec!["todo!".into()],
+            ..TodoParserConfig::new()
+        });
+        for (keyword, bytes) in lines.iter() {
+            assert_eq!(
+                parser(bytes).map(|(_, todo)| (todo.tag, todo.assignee, todo.title)),
+                Ok((*keyword, Some("schell"), "Do it.".into())),
+                "{}",
+                keyword
+            );
+        }
+
+        let bytes = "// NOTED: Not a todo.\n";
+        assert!(parser(bytes).is_err());
+
+        // Keywords in the message of a macro aren't its tag
+        let bytes = "todo!(\"Fix the BUG.\")\n";
+        assert_eq!(
+            parser(bytes).map(|(_, todo)| (todo.tag, todo.title)),
+            Ok(("TODO", "Fix the BUG.".into()))
+        );
+    }
+
+    #[test]
+    fn parse_macro_assignees() {
+        let parser = macro_todo("todo!".into(), TodoTags::default());
+        let parse = |bytes| parser(bytes).map(|(_, todo)| todo);
+        assert_eq!(
+            parse("todo!(\"(schell) fix the thing\")\n"),
+            Ok((Some("schell"), "fix the thing".into(), vec![], "TODO"))
+        );
+        assert_eq!(
+            parse("todo!(\"(@alice): Count. Carefully.\")\n"),
+            Ok((Some("alice"), "Count.".into(), vec!["Carefully."], "TODO"))
+        );
+        assert_eq!(
+            parse("todo!(\"TODO(bob): Count.\")\n"),
+            Ok((Some("bob"), "Count.".into(), vec![], "TODO"))
+        );
+        // Parentheses with spaces in them are part of the title
+        assert_eq!(
+            parse("todo!(\"(in a while) Count.\")\n"),
+            Ok((None, "(in a while) Count.".into(), vec![], "TODO"))
+        );
+        assert!(parse("todo!(\"(schell)\")\n").is_err());
+    }
+
+    #[test]
+    fn parse_macro_string_forms() {
+        let parser = macro_todo("todo!".into(), TodoTags::default());
+        let parse = |bytes| parser(bytes).map(|(_, todo)| todo);
+        let fixed = || {
+            Ok((
+                None,
+                Cow::from("Fix the thing."),
+                vec!["Carefully."],
+                "TODO",
+            ))
+        };
+
+        // Raw strings keep their backslashes and quotes
+        assert_eq!(parse("todo!(r\"Fix the thing. Carefully.\")\n"), fixed());
+        assert_eq!(
+            parse("todo!(r#\"TODO(schell): Quote \"it\". See C:\\n.\"#)\n"),
+            Ok((
+                Some("schell"),
+                Cow::from("Quote \"it\"."),
+                vec!["See C:\\n."],
+                "TODO"
+            ))
+        );
+        // Three literals, adjacent or concatenated
+        assert_eq!(
+            parse("todo!(\"Fix \" \"the thing.\" \" Carefully.\")\n"),
+            fixed()
+        );
+        assert_eq!(
+            parse("todo!(concat!(\"Fix \", r\"the \", \"thing. Carefully.\"))\n"),
+            fixed()
+        );
+        assert_eq!(
+            parse(
+                "todo!(\n    \"Fix the \"\n    \"thing.\\n\"\n    \"Carefully.\"\n);\nfn a() {}\n"
+            ),
+            fixed()
+        );
+        // Line continuations join lines, even in the title
+        assert_eq!(
+            parse("todo!(\"Fix the \\\n      thing. \\\n      Carefully.\")\n"),
+            fixed()
+        );
+        // Each literal of a description is a line of it
+        assert_eq!(
+            parse("todo!(\"Fix the thing.\" \" Carefully.\" \" Slowly.\")\n"),
+            Ok((
+                None,
+                Cow::from("Fix the thing."),
+                vec!["Carefully.", "Slowly."],
+                "TODO"
+            ))
+        );
+        assert!(parse("todo!(\"\" r\"  \")\n").is_err());
+    }
+
+    #[test]
+    fn parse_todos_with_custom_tags() {
+        let bytes = "// REVIEW(schell): Check the bounds.\n\
+                     // TODO: Not a tag here.\n\
+                     /* DEPRECATED: Use b.\n   It's faster. */\n\
+                     // REVIEWED: Not a tag either.\n\
+                     todo!(\"REVIEW: The message.\")\n";
+        let mut parser = super::parse_todos(TodoParserConfig {
+            singles: vec!["//".into()],
+            multis: vec![("/*".into(), "*/".into())],
+            macros: vec!["todo!".into()],
+            tags: TodoTags::new(["REVIEW", "DEPRECATED"]),
+            ..TodoParserConfig::new()
+        });
+        assert_eq!(
+            parser(bytes)
+                .into_iter()
+                .map(|todo| (todo.tag, todo.assignee, todo.title, todo.desc_lines))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "REVIEW",
+                    Some("schell"),
+                    "Check the bounds.".into(),
+                    vec!["TODO: Not a tag here."]
+                ),
+                ("DEPRECATED", None, "Use b.".into(), vec!["It's faster."]),
+                ("TODO", None, "The message.".into(), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_adjacent_comment_blocks() {
+        let singles = vec!["//".to_string()];
+        let tags = TodoTags::default();
+
+        let bytes = "\n\n    // More detail.\n    //\n    // Even more.\nlet a = 1;\n";
+        assert_eq!(
+            adjacent_comment_block(&singles, &tags, 2, bytes),
+            Ok(("let a = 1;\n", vec!["More detail.", "Even more."]))
+        );
+
+        let too_far = "\n\n\n// More detail.\n";
+        assert!(adjacent_comment_block(&singles, &tags, 2, too_far).is_err());
+
+        let code_between = "let a = 1;\n\n// Unrelated.\n";
+        assert!(adjacent_comment_block(&singles, &tags, 2, code_between).is_err());
+
+        let another_todo = "\n// TODO: Another one.\n";
+        assert!(adjacent_comment_block(&singles, &tags, 2, another_todo).is_err());
+    }
+
+    #[test]
+    fn unterminated_comments_stop_at_the_lookahead_limit() {
+        // This used to scan to the end of the input for each comment opener
+        let unterminated = format!(
+            "/* TODO: Close this.\n{}// TODO: Still found.\n",
+            "int a = 1;\n".repeat(MAX_MULTI_LINE_LOOKAHEAD / 8)
+        );
+        let c_parser = parse_todo(TodoParserConfig::from_comment_styles(
+            super::super::langs::c_style(),
+        ));
+        match c_parser(&unterminated) {
+            Err(Err::Failure(e)) => assert_eq!(e.code, ErrorKind::TooLarge),
+            res => panic!("expected a failure, got {:?}", res.map(|(_, todo)| todo)),
+        }
+        let todos = super::parse_todos(TodoParserConfig::from_comment_styles(
+            super::super::langs::c_style(),
+        ))(&unterminated);
+        assert_eq!(
+            todos
+                .iter()
+                .map(|todo| todo.title.as_ref())
+                .collect::<Vec<_>>(),
+            vec!["Still found."]
+        );
+
+        // Short unterminated comments are just not todos
+        match c_parser("/* TODO: Close this.\n") {
+            Err(Err::Error(_)) => {}
+            res => panic!("expected an error, got {:?}", res.map(|(_, todo)| todo)),
+        }
+    }
+}
+
+/// Eat a single or multi line comment start.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::*;
+///
+/// let (borders, single) = (vec!["|".to_string()], "--".to_string());
+///
+/// assert_eq!(
+///     comment_start(borders, single)(" -- Here is a comment."),
+///     Ok(("Here is a comment.", ()))
+/// );
+/// ```
+pub fn comment_start(
+    // An ignorable border for comments that like to have outlines.
+    // Eg. "*" for C-like langs or "!" for Objective-C.
+    borders: Vec<String>,
+    // The comment prefix.
+    // Eg. "--" or "{-" for Haskell, "//" or "/*" for Rust.
+    prefix: String,
+) -> impl Fn(&str) -> IResult<&str, ()> {
+    move |i: &str| {
+        let (i, _) = character::space0(i)?;
+        let (i, _) = bytes::tag(prefix.as_str())(i)?;
+        let (i, _) = character::space0(i)?;
+        // Eat as many borders as decorate the start, eg. the `**` of `/***`
+        let mut i = i;
+        while let Some(border) = longest_first(&borders)
+            .into_iter()
+            .find(|border| !border.is_empty() && i.starts_with(border))
+        {
+            i = &i[border.len()..];
+        }
+        let (i, _) = character::space0(i)?;
+        Ok((i, ()))
+    }
+}
+
+/// Eat an assigned name. Names may be GitHub style handles starting with `@`,
+/// which is dropped. Team handles contain a `/`, like `@org/team`.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::*;
+///
+/// assert_eq!(assignee("(mitchellwrosen)"), Ok(("", "mitchellwrosen")));
+/// assert_eq!(assignee("(@schell)"), Ok(("", "schell")));
+/// assert_eq!(assignee("(@org/backend)"), Ok(("", "org/backend")));
+/// ```
+pub fn assignee(i: &str) -> IResult<&str, &str> {
+    let (i, _) = character::char('(')(i)?;
+    let (i, _) = character::space0(i)?;
+    let (i, _) = combinator::opt(character::char('@'))(i)?;
+    let is_end = |input: char| input != '\r' && input != '\n' && input != ' ' && input != ')';
+    let (i, name) = bytes::take_while(is_end)(i)?;
+    let (i, _) = character::char(')')(i)?;
+    Ok((i, name))
+}
+
+/// Eat a bracketed, comma separated list of labels right after a todo's
+/// keyword, as in `TODO[backend,perf]: ...`, returning the labels.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::*;
+///
+/// assert_eq!(tag_labels("[backend, perf]: Go."), Ok((": Go.", vec!["backend", "perf"])));
+/// assert_eq!(tag_labels("[]"), Ok(("", vec![])));
+/// assert!(tag_labels("(schell)").is_err());
+/// ```
+pub fn tag_labels(i: &str) -> IResult<&str, Vec<&str>> {
+    let (i, _) = character::char('[')(i)?;
+    let (i, list) = bytes::take_till(|c| c == ']' || c == '\r' || c == '\n')(i)?;
+    let (i, _) = character::char(']')(i)?;
+    let labels = list
+        .split(',')
+        .map(str::trim)
+        .filter(|label| !label.is_empty())
+        .collect();
+    Ok((i, labels))
+}
+
+/// The keywords that start a todo.
+pub const TODO_KEYWORDS: [&str; 8] = [
+    "TODO", "FIXME", "HACK", "XXX", "NOTE", "BUG", "OPTIMIZE", "@todo",
+];
+
+/// The keywords that start a todo, eg. to also find `REVIEW:` comments.
+/// Defaults to the `TODO_KEYWORDS`.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::*;
+///
+/// let tags = TodoTags::new(["TODO", "REVIEW"]);
+/// assert_eq!(tags.todo_tag("REVIEW(schell): Check it."), Ok(("Check it.", Some("schell"))));
+/// assert_eq!(tags.todo_head("REVIEW: Check it."), Ok(("Check it.", ("REVIEW", None))));
+/// assert!(tags.todo_tag("FIXME: Fix it.").is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct TodoTags {
+    pub keywords: Vec<String>,
+}
+
+impl Default for TodoTags {
+    fn default() -> Self {
+        TodoTags::new(TODO_KEYWORDS)
+    }
+}
+
+impl TodoTags {
+    pub fn new<S: Into<String>>(keywords: impl IntoIterator<Item = S>) -> Self {
+        TodoTags {
+            keywords: keywords.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Eat one of the keywords, returning it. The longest keyword that
+    /// matches wins.
+    pub fn todo_keyword<'a>(&self, i: &'a str) -> IResult<&'a str, &'a str> {
+        let (i, _) = character::space0(i)?;
+        let len = self
+            .keywords
+            .iter()
+            .filter(|keyword| !keyword.is_empty() && i.starts_with(keyword.as_str()))
+            .map(String::len)
+            .max()
+            .ok_or(Err::Error(nom::error::Error {
+                input: i,
+                code: ErrorKind::Tag,
+            }))?;
+        Ok((&i[len..], &i[..len]))
+    }
+
+    /// Eat one of the keywords and any assigned name following it, returning
+    /// the name, see `todo_tag`.
+    pub fn todo_tag<'a>(&self, i: &'a str) -> IResult<&'a str, Option<&'a str>> {
+        let (i, (_, may_name)) = self.todo_head(i)?;
+        Ok((i, may_name))
+    }
+
+    /// Eat one of the keywords and any assigned name following it, returning
+    /// both.
+    pub fn todo_head<'a>(&self, i: &'a str) -> IResult<&'a str, (&'a str, Option<&'a str>)> {
+        let (i, keyword) = self.todo_keyword(i)?;
+        // Bangs mark the priority, as in `TODO!!!`, see `todo_priority`
+        let (i, _) = bytes::take_while(|c| c == '!')(i)?;
+        let (i, _) = combinator::opt(tag_labels)(i)?;
+        // Don't eat the start of a longer word, like `TODOs`
+        let (i, _) = combinator::not(bytes::take_while_m_n(1, 1, |c: char| {
+            c.is_alphanumeric() || c == '_'
+        }))(i)?;
+        let (i, _) = character::space0(i)?;
+        let (i, may_name) = combinator::opt(|i| assignee(i))(i)?;
+        let (i, _) = character::space0(i)?;
+        let (i, _) = combinator::opt(character::char(':'))(i)?;
+        let (i, _) = character::space0(i)?;
+        Ok((i, (keyword, may_name)))
+    }
+
+    /// Strip any redundant todo tags from the start of a title, see
+    /// `strip_tag_from_title`.
+    pub fn strip_from_title<'a>(&self, title: &'a str) -> &'a str {
+        let mut stripped = title;
+        while let Ok((rest, _)) = self.todo_tag(stripped) {
+            if rest.is_empty() {
+                break;
+            }
+            stripped = rest;
+        }
+        stripped
+    }
+}
+
+/// The default `TodoTags`.
+fn default_tags() -> &'static TodoTags {
+    static TAGS: OnceLock<TodoTags> = OnceLock::new();
+    TAGS.get_or_init(TodoTags::default)
+}
+
+/// Eat a todo keyword, returning it.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::*;
+///
+/// assert_eq!(todo_keyword(" FIXME: Fix it."), Ok((": Fix it.", "FIXME")));
+/// assert_eq!(todo_keyword("XXX: Fix it."), Ok((": Fix it.", "XXX")));
+/// ```
+pub fn todo_keyword(i: &str) -> IResult<&str, &str> {
+    default_tags().todo_keyword(i)
+}
+
+/// Eat a todo tag, which is any of the `TODO_KEYWORDS`, eg. `TODO`, `FIXME`,
+/// `HACK` or `@todo`.
+/// It will also eat any assigned name following the todo tag and return it.
+///
+/// ```rust
+/// use nom::multi;
+/// use todo_finder_lib::parser::source::*;
+///
+/// assert_eq!(todo_tag("@todo "), Ok(("", None)));
+/// assert_eq!(todo_tag("TODO "), Ok(("", None)));
+/// assert_eq!(todo_tag("TODO"), Ok(("", None)));
+/// assert_eq!(todo_tag("FIXME"), Ok(("", None)));
+/// assert_eq!(todo_tag("HACK(schell): "), Ok(("", Some("schell"))));
+/// assert_eq!(todo_tag("TODO!!: "), Ok(("", None)));
+/// assert!(todo_tag("TODOs are hard").is_err());
+/// assert!(todo_tag("BUGS are hard").is_err());
+///
+/// let all_text = "TODO(schell) FIXME (mitchellwrosen) @todo(imalsogreg)";
+/// let parsed = multi::many1(|i| todo_tag(i))(all_text);
+/// assert_eq!(
+///     parsed,
+///     Ok((
+///         "",
+///         vec![Some("schell"), Some("mitchellwrosen"), Some("imalsogreg")]
+///     ))
+/// );
+/// ```
+pub fn todo_tag(i: &str) -> IResult<&str, Option<&str>> {
+    default_tags().todo_tag(i)
+}
+
+/// Strip any redundant todo tags from the start of a title, as in
+/// `// TODO: TODO: Do it.`. A title that is nothing but a tag is left alone.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::*;
+///
+/// assert_eq!(strip_tag_from_title("TODO: FIXME Do it."), "Do it.");
+/// assert_eq!(strip_tag_from_title("TODOs are hard."), "TODOs are hard.");
+/// assert_eq!(strip_tag_from_title("TODO"), "TODO");
+/// ```
+pub fn strip_tag_from_title(title: &str) -> &str {
+    default_tags().strip_from_title(title)
+}
+
+/// Eat a sentence and its terminator and a space.
+/// Terminators must have an empty space after them to be considered valid,
+/// otherwise they could be a programming operator.
+/// The entire eaten sentence and terminators will be returned in a Vector of
+/// slices.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::*;
+///
+/// let bytes = "Sandy doesn't like fmap.fmap for some reason. Other sentence.\n";
+/// assert_eq!(
+///     sentence_and_terminator(bytes),
+///     Ok((
+///         "Other sentence.\n",
+///         "Sandy doesn't like fmap.fmap for some reason."
+///     ))
+/// );
+///
+/// let bytes = "Hey you, I bet you allocate\na lot of resources. Maybe not.";
+/// assert_eq!(
+///     sentence_and_terminator(bytes),
+///     Ok((
+///         "Maybe not.",
+///         "Hey you, I bet you allocate\na lot of resources."
+///     ))
+/// );
+/// ```
+pub fn sentence_and_terminator(i: &str) -> IResult<&str, &str> {
+    let is_terminator = |c: char| c == '.' || c == '?' || c == '!';
+    let mut n = 0;
+    let mut ii = i;
+    'eating_sentences: loop {
+        let (j, sentence) = bytes::take_till(is_terminator)(ii)?;
+        let (j, terminators) = bytes::take_while(is_terminator)(j)?;
+        let (j, space) = combinator::opt(character::char(' '))(j)?;
+        ii = j;
+        n += sentence.len();
+        n += terminators.len();
+        if space.is_some() || j.is_empty() {
+            // Unless we get a space or are at the end, keep eating more
+            break 'eating_sentences;
+        }
+    }
+    let (sentence, i) = i.split_at(n);
+    let i = i.trim_start();
+    Ok((i, sentence))
+}
+
+/// The borders sorted so that longer ones are tried first, so a multi-char
+/// border is eaten as a unit before any shorter border it starts with.
+fn longest_first(borders: &[String]) -> Vec<&str> {
+    let mut borders = borders
+        .iter()
+        .map(String::as_str)
+        .filter(|border| !border.is_empty())
+        .collect::<Vec<_>>();
+    borders.sort_by_key(|border| std::cmp::Reverse(border.len()));
+    borders
+}
+
+/// Trim any source code borders off of the string. This only accounts for
+/// borders on the beginning and end of the string, not in the middle. To remove
+/// borders from the middle of a string, first break it into lines. Furthermore
+/// this function *will remove trailing whitespace, including line breaks*.
+///
+/// Borders may be more than one char, eg. `-=`, in which case they are trimmed
+/// as whole units, and any mix of the borders is trimmed.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::*;
+///
+/// assert_eq!(
+///     trim_borders(&vec!["*".into()], " * I like veggies *\n"),
+///     "I like veggies"
+/// );
+/// assert_eq!(
+///     trim_borders(&vec!["*".into(), "-=".into()], "*** -=-= Banner -=-= ***"),
+///     "Banner"
+/// );
+/// ```
+pub fn trim_borders<'a>(borders: &[String], i: &'a str) -> &'a str {
+    let borders = longest_first(borders);
+    let mut i = i.trim();
+    loop {
+        let trimmed = borders.iter().fold(i, |i, border| {
+            i.trim_start_matches(border).trim_end_matches(border).trim()
+        });
+        if trimmed == i {
+            return i;
+        }
+        i = trimmed;
+    }
+}
+
+/// Eat a sentence and the rest of the line, if possible. The rest, in the case
+/// of a todo, is a portion of the description.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::*;
+///
+/// let bytes = "sleep for variable time depending on exact error? Ionno know.\n\n";
+/// assert_eq!(
+///     title_and_rest_till_eol(vec![])(bytes),
+///     Ok((
+///         "\n",
+///         (
+///             "sleep for variable time depending on exact error?",
+///             "Ionno know."
+///         )
+///     ))
+/// );
+/// ```
+pub fn title_and_rest_till_eol(
+    // An ignorable border for comments that like to have outlines.
+    // Eg. "*" for C-like langs or "!" for Objective-C.
+    borders: Vec<String>,
+) -> impl Fn(&str) -> IResult<&str, (&str, &str)> {
+    title_and_rest_till_eol_in_mode(borders, TitleMode::Sentence)
+}
+
+/// How the title of a todo is found.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TitleMode {
+    /// The title is the first sentence, which may be wrapped onto the
+    /// following lines.
+    #[default]
+    Sentence,
+    /// The title is the whole first line, verbatim.
+    FirstLine,
+    /// The title is the first line up to the given separator, and the rest of
+    /// the line starts the description, as in `TODO: Title || Description`.
+    /// Without the separator the whole first line is the title.
+    Separator(String),
+}
+
+impl TitleMode {
+    pub fn from_arg(arg: &str) -> Result<Self, String> {
+        match arg {
+            "sentence" => Ok(TitleMode::Sentence),
+            "firstline" => Ok(TitleMode::FirstLine),
+            _ => Err(format!("invalid title mode '{}'", arg)),
+        }
+    }
+}
+
+/// Eat a title and the rest of the line, finding the title in the given mode.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::*;
+///
+/// let bytes = "Use v2.0 of the API. Or later.\n";
+/// assert_eq!(
+///     title_and_rest_till_eol_in_mode(vec![], TitleMode::FirstLine)(bytes),
+///     Ok(("", ("Use v2.0 of the API. Or later.", "")))
+/// );
+///
+/// let bytes = "Use v2.0 || The API changed.\n";
+/// assert_eq!(
+///     title_and_rest_till_eol_in_mode(vec![], TitleMode::Separator("||".into()))(bytes),
+///     Ok(("", ("Use v2.0", "The API changed.")))
+/// );
+/// ```
+pub fn title_and_rest_till_eol_in_mode(
+    borders: Vec<String>,
+    mode: TitleMode,
+) -> impl Fn(&str) -> IResult<&str, (&str, &str)> {
+    move |i| {
+        let (i, ln) = take_to_eol(i)?;
+        match &mode {
+            TitleMode::Sentence => {
+                let (desc, title) = sentence_and_terminator(ln)?;
+                Ok((i, (title, trim_borders(&borders, desc))))
+            }
+            TitleMode::FirstLine => Ok((i, (trim_borders(&borders, ln), ""))),
+            TitleMode::Separator(separator) => match ln.split_once(separator.as_str()) {
+                Some((title, desc)) => Ok((i, (title.trim(), trim_borders(&borders, desc)))),
+                None => Ok((i, (trim_borders(&borders, ln), ""))),
+            },
+        }
+    }
+}
+
+/// Eat a single line comment. Fails if it hits a possible todo with one of the
+/// given tags and stops when it eats the end of a line.
+///
+/// ```rust
+/// use nom::CompareResult::Error;
+/// use todo_finder_lib::parser::source::*;
+///
+/// let bytes = "// Here is a whole single line comment.\n";
+/// assert_eq!(
+///     single_line_comment(vec![], "//".into(), TodoTags::default())(bytes),
+///     Ok(("", "Here is a whole single line comment."))
+/// );
+///
+/// let bytes = "// TODO: Here is a whole single line comment.\n";
+/// assert!(single_line_comment(vec![], "//".into(), TodoTags::default())(bytes).is_err());
+/// ```
+pub fn single_line_comment(
+    // An ignorable border for comments that like to have outlines.
+    // Eg. "*" for C-like langs or "!" for Objective-C.
+    borders: Vec<String>,
+    // The comment prefix.
+    // Eg. "--" for Haskell, "//" for Rust.
+    prefix: String,
+    // The tags of the todos that end the comment.
+    tags: TodoTags,
+) -> impl Fn(&str) -> IResult<&str, &str> {
+    let parse_comment_start = comment_start(borders, prefix);
+    move |i| {
+        let (i, _) = parse_comment_start(i)?;
+        let (i, _) = combinator::not(|i| tags.todo_tag(i))(i)?;
+        take_to_eol(i)
+    }
+}
+
+/// Returns whether the given title ends in a sentence terminator.
+fn is_terminated(title: &str) -> bool {
+    title.ends_with(['.', '?', '!'])
+}
+
+/// Continue eating a title that has been wrapped onto the following comment
+/// lines. Lines are eaten until the title's sentence is terminated, a blank
+/// comment line is found or the comment block ends. Any remainder of the last
+/// eaten line is returned as the first line of the description.
+fn wrapped_title<'a>(
+    parse_line: &impl Fn(&'a str) -> IResult<&'a str, &'a str>,
+    borders: &[String],
+    i: &'a str,
+    title: &'a str,
+    desc0: &'a str,
+) -> IResult<&'a str, (Cow<'a, str>, &'a str)> {
+    let mut ii = i;
+    let mut title: Cow<str> = title.trim_end().into();
+    let mut desc0 = desc0;
+    'eating_lines: while !is_terminated(&title) {
+        let (j, ln) = match parse_line(ii) {
+            Ok(res) => res,
+            Err(_) => break 'eating_lines,
+        };
+        let ln = trim_borders(borders, ln);
+        if ln.is_empty() {
+            break 'eating_lines;
+        }
+        let (rest, sentence) = sentence_and_terminator(ln)?;
+        title = if title.is_empty() {
+            sentence.trim_end().into()
+        } else {
+            format!("{} {}", title, sentence.trim_end()).into()
+        };
+        desc0 = rest;
+        ii = j;
+    }
+    Ok((ii, (title, desc0)))
+}
+
+/// The parts of a parsed todo: an optional assignee, the todo's title, its
+/// description lines and the keyword that started it.
+pub type TodoParts<'a> = (Option<&'a str>, Cow<'a, str>, Vec<&'a str>, &'a str);
+
+/// Eat a todo comprised of single line comments.
+/// Returns an assignee if possible, the todo's title and a vector of description
+/// lines.
+///
+/// ```rust
+/// use nom::multi;
+/// use todo_finder_lib::parser::source::*;
+///
+/// let bytes = "-- TODO: Hey there.\n--    Description.\n";
+/// assert_eq!(
+///     single_line_todo(vec![], "--".into())(bytes),
+///     Ok(("", (None, "Hey there.".into(), vec!["Description.".into()], "TODO")))
+/// );
+///
+/// let bytes = "-- TODO: A title that is\n-- wrapped. Description.\n";
+/// assert_eq!(
+///     single_line_todo(vec![], "--".into())(bytes),
+///     Ok((
+///         "",
+///         (
+///             None,
+///             "A title that is wrapped.".into(),
+///             vec!["Description.".into()],
+///             "TODO"
+///         )
+///     ))
+/// );
+/// ```
+pub fn single_line_todo(
+    // An ignorable border for comments that like to have outlines.
+    // Eg. "*" for C-like langs or "!" for Objective-C.
+    borders: Vec<String>,
+    // The comment prefix.
+    // Eg. "--" for Haskell, "//" for Rust.
+    prefix: String,
+) -> impl Fn(&str) -> IResult<&str, TodoParts<'_>> {
+    single_line_todo_in_mode(borders, prefix, TitleMode::Sentence, TodoTags::default())
+}
+
+/// Eat a todo comprised of single line comments that starts with one of the
+/// given tags, finding its title in the given mode.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::*;
+///
+/// let bytes = "-- TODO: Bump to v2.0 now. It's out.\n--    Description.\n";
+/// assert_eq!(
+///     single_line_todo_in_mode(vec![], "--".into(), TitleMode::FirstLine, TodoTags::default())(bytes),
+///     Ok((
+///         "",
+///         (None, "Bump to v2.0 now. It's out.".into(), vec!["Description."], "TODO")
+///     ))
+/// );
+/// ```
+pub fn single_line_todo_in_mode(
+    borders: Vec<String>,
+    prefix: String,
+    mode: TitleMode,
+    tags: TodoTags,
+) -> impl Fn(&str) -> IResult<&str, TodoParts<'_>> {
+    let parse_comment_start = comment_start(borders.clone(), prefix.clone());
+    let parse_title_desc = title_and_rest_till_eol_in_mode(borders.clone(), mode.clone());
+    move |i| {
+        let (i, _) = parse_comment_start(i)?;
+        let (i, (keyword, may_name)) = tags.todo_head(i)?;
+        let (i, (title, desc0)) = parse_title_desc(i)?;
+        let parse_single_line = single_line_comment(borders.clone(), prefix.clone(), tags.clone());
+        let (i, (title, desc0)) = match mode {
+            TitleMode::Sentence => wrapped_title(&parse_single_line, &borders, i, title, desc0)?,
+            TitleMode::FirstLine | TitleMode::Separator(_) => (i, (title.into(), desc0)),
+        };
+        let (i, mut desc_n) = multi::many0(parse_single_line)(i)?;
+        desc_n.insert(0, desc0);
+        desc_n.retain(|desc| !desc.is_empty());
+        Ok((i, (may_name, title, desc_n, keyword)))
+    }
+}
+
+/// Eat the borders that may start a line of prose, eg. the `-` of a Markdown
+/// list item or the `>` of a quote, in any number and along with the spaces
+/// around them, then the box of an unchecked task list item, `[ ]`. A checked
+/// one, `[x]`, is done, so isn't eaten and its line is no todo.
+fn prose_start<'a>(borders: &[String], i: &'a str) -> &'a str {
+    let mut i = i.trim_start_matches([' ', '\t']);
+    while let Some(border) = longest_first(borders)
+        .into_iter()
+        .find(|border| !border.is_empty() && i.starts_with(border))
+    {
+        i = i[border.len()..].trim_start_matches([' ', '\t']);
+    }
+    match i.strip_prefix("[ ]") {
+        Some(rest) => rest.trim_start_matches([' ', '\t']),
+        None => i,
+    }
+}
+
+/// Eat a line that continues a todo in prose. Fails at a blank line, a
+/// heading, a line that starts with a border, like another list item, or
+/// another todo.
+fn prose_line(borders: Vec<String>, tags: TodoTags) -> impl Fn(&str) -> IResult<&str, &str> {
+    move |i| {
+        let (j, _) = character::space0(i)?;
+        if j.is_empty()
+            || j.starts_with(['\r', '\n', '#'])
+            || borders
+                .iter()
+                .any(|border| !border.is_empty() && j.starts_with(border.as_str()))
+        {
+            return Err(Err::Error(nom::error::Error::new(i, ErrorKind::Verify)));
+        }
+        let (j, _) = combinator::not(|i| tags.todo_tag(i))(j)?;
+        take_to_eol(j)
+    }
+}
+
+/// Eat a todo in prose, like a Markdown or plain text file, where any line may
+/// start one with one of the given tags. So that sentences about todos aren't
+/// taken for them, the tag must end in a colon, as in `TODO:` or
+/// `TODO(name):`. The title, found in the given mode, may wrap
+/// onto the following lines and the description runs on until a blank line
+/// or the next list item, see `CommentStyle::Prose`.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::*;
+///
+/// let borders = vec!["-".to_string(), "*".to_string(), ">".to_string()];
+/// let bytes = "- TODO(schell): Document the\n  config file. It has options.\n- Done.\n";
+/// assert_eq!(
+///     prose_todo(borders.clone(), TitleMode::Sentence, TodoTags::default())(bytes),
+///     Ok((
+///         "- Done.\n",
+///         (
+///             Some("schell"),
+///             "Document the config file.".into(),
+///             vec!["It has options."],
+///             "TODO"
+///         )
+///     ))
+/// );
+/// assert!(prose_todo(borders, TitleMode::Sentence, TodoTags::default())("TODO lists are kept in issues.\n").is_err());
+/// ```
+pub fn prose_todo(
+    borders: Vec<String>,
+    mode: TitleMode,
+    tags: TodoTags,
+) -> impl Fn(&str) -> IResult<&str, TodoParts<'_>> {
+    let parse_title_desc = title_and_rest_till_eol_in_mode(borders.clone(), mode.clone());
+    move |i| {
+        let start = prose_start(&borders, i);
+        let (i, (keyword, may_name)) = tags.todo_head(start)?;
+        if !start[..start.len() - i.len()].trim_end().ends_with(':') {
+            return Err(Err::Error(nom::error::Error::new(start, ErrorKind::Verify)));
+        }
+        let (i, (title, desc0)) = parse_title_desc(i)?;
+        let parse_line = prose_line(borders.clone(), tags.clone());
+        let (i, (title, desc0)) = match mode {
+            TitleMode::Sentence => wrapped_title(&parse_line, &borders, i, title, desc0)?,
+            TitleMode::FirstLine | TitleMode::Separator(_) => (i, (title.into(), desc0)),
+        };
+        let (i, mut desc_n) = multi::many0(parse_line)(i)?;
+        desc_n.insert(0, desc0);
+        desc_n.iter_mut().for_each(|desc| *desc = desc.trim());
+        desc_n.retain(|desc| !desc.is_empty());
+        Ok((i, (may_name, title, desc_n, keyword)))
+    }
+}
+
+/// Eat a todo that lives in a multi-line comment block.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::*;
+///
+/// let haskell_parser = multi_line_todo(vec!["|".into()], "{-".into(), "-}".into());
+///
+/// let bytes = "{- | TODO: My todo title.
+///                   Description too. With more
+///                   sentences over more lines.
+///              -}\n";
+/// assert_eq!(
+///     haskell_parser(bytes),
+///     Ok((
+///         "\n",
+///         (
+///             None,
+///             "My todo title.".into(),
+///             vec!["Description too. With more", "sentences over more lines."],
+///             "TODO"
+///         )
+///     ))
+/// );
+/// ```
+pub fn multi_line_todo(
+    // An ignorable border for comments that like to have outlines.
+    // Eg. "*" for C-like langs or "!" for Objective-C.
+    borders: Vec<String>,
+    // The comment prefix.
+    // Eg. "{-" for Haskell, "/*" for Rust.
+    prefix: String,
+    // The comment suffix.
+    // Eg. "-}" for Haskell, "*/" for Rust.
+    suffix: String,
+) -> impl Fn(&str) -> IResult<&str, TodoParts<'_>> {
+    multi_line_todo_in_mode(
+        borders,
+        prefix,
+        suffix,
+        TitleMode::Sentence,
+        TodoTags::default(),
+    )
+}
+
+/// The furthest, in bytes, to look for the end of a multi-line comment.
+pub const MAX_MULTI_LINE_LOOKAHEAD: usize = 64 * 1024;
+
+/// Eat until the given tag, looking at most `limit` bytes ahead.
+///
+/// Fails with an error if the input ends before the tag is found, or with a
+/// `ErrorKind::TooLarge` failure if the limit is hit first, so a missing
+/// comment closer can't have us scan to the end of a huge file for every
+/// comment opener.
+///
+/// ```rust
+/// use nom::{error::ErrorKind, Err};
+/// use todo_finder_lib::parser::source::*;
+///
+/// assert_eq!(take_until_within("*/", 16, "a b */ c"), Ok(("*/ c", "a b ")));
+/// assert!(matches!(take_until_within("*/", 16, "a b"), Err(Err::Error(_))));
+/// assert!(matches!(
+///     take_until_within("*/", 4, "a b c d */"),
+///     Err(Err::Failure(e)) if e.code == ErrorKind::TooLarge
+/// ));
+/// ```
+pub fn take_until_within<'a>(tag: &str, limit: usize, i: &'a str) -> IResult<&'a str, &'a str> {
+    let mut end = limit.min(i.len());
+    while !i.is_char_boundary(end) {
+        end -= 1;
+    }
+    // Include a tag that straddles the limit
+    let mut window_end = (end + tag.len()).min(i.len());
+    while !i.is_char_boundary(window_end) {
+        window_end -= 1;
+    }
+    match i[..window_end].find(tag) {
+        Some(n) => Ok((&i[n..], &i[..n])),
+        None if end < i.len() => Err(Err::Failure(nom::error::Error {
+            input: i,
+            code: ErrorKind::TooLarge,
+        })),
+        None => Err(Err::Error(nom::error::Error {
+            input: i,
+            code: ErrorKind::TakeUntil,
+        })),
+    }
+}
+
+/// Eat a todo that lives in a multi-line comment block and starts with one of
+/// the given tags, finding its title in the given mode.
+pub fn multi_line_todo_in_mode(
+    borders: Vec<String>,
+    prefix: String,
+    suffix: String,
+    mode: TitleMode,
+    tags: TodoTags,
+) -> impl Fn(&str) -> IResult<&str, TodoParts<'_>> {
+    let parse_title_desc = title_and_rest_till_eol_in_mode(borders.clone(), mode.clone());
+    move |i| {
+        let (i, _) = character::space0(i)?;
+        let (i, _) = combinator::opt(comment_start(borders.clone(), prefix.clone()))(i)?;
+        let (i, (keyword, may_name)) = tags.todo_head(i)?;
+        let suffix = suffix.as_str();
+        // A comment that ends on its first line, eg. `/*** TODO: Title. ***/`.
+        // The suffix is stripped first, as its chars may also be borders.
+        let (rest, ln) = take_to_eol(i)?;
+        if let Some(ln) = ln.trim_end().strip_suffix(suffix) {
+            let (_, (title, desc0)) = parse_title_desc(ln)?;
+            let title = Cow::from(trim_borders(&borders, title));
+            let mut desc_n = vec![desc0];
+            desc_n.retain(|desc| !desc.is_empty());
+            return Ok((rest, (may_name, title, desc_n, keyword)));
+        }
+        let (i, (title, desc0)) = parse_title_desc(i)?;
+        // A whole first line may end the comment, too
+        let (title, desc0, ends_comment) = match (&mode, title.strip_suffix(suffix)) {
+            (TitleMode::Sentence, _) if desc0 == suffix => (title, "", true),
+            (TitleMode::Sentence, _) => (title, desc0, false),
+            (_, Some(title)) if desc0.is_empty() => (trim_borders(&borders, title), "", true),
+            (TitleMode::Separator(_), _) => match desc0.strip_suffix(suffix) {
+                Some(desc0) => (title, trim_borders(&borders, desc0), true),
+                None => (title, desc0, false),
+            },
+            _ => (title, desc0, false),
+        };
+        let title = Cow::from(title);
+        if ends_comment {
+            let mut desc_n = vec![desc0];
+            desc_n.retain(|desc| !desc.is_empty());
+            Ok((i, (may_name, title, desc_n, keyword)))
+        } else {
+            let (i, comment) = take_until_within(suffix, MAX_MULTI_LINE_LOOKAHEAD, i)?;
+            let (i, _) = bytes::tag(suffix)(i)?;
+            let mut desc_n = vec![desc0];
+            for line in comment.lines() {
+                let trimmed_line = trim_borders(&borders, line);
+                desc_n.push(trimmed_line);
+            }
+            desc_n.retain(|desc| !desc.is_empty());
+            Ok((i, (may_name, title, desc_n, keyword)))
+        }
+    }
+}
+
+/// Eat a Rust string literal, returning its contents. Escape sequences are
+/// left as they are.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::*;
+///
+/// assert_eq!(
+///     rust_string_literal(r#""A \"quoted\" string." rest"#),
+///     Ok((" rest", r#"A \"quoted\" string."#))
+/// );
+/// assert_eq!(rust_string_literal("\"\""), Ok(("", "")));
+/// ```
+pub fn rust_string_literal(i: &str) -> IResult<&str, &str> {
+    let (i, _) = character::char('"')(i)?;
+    let (i, content) = combinator::recognize(multi::many0(branch::alt((
+        bytes::is_not("\\\""),
+        combinator::recognize(sequence::preceded(
+            character::char('\\'),
+            character::anychar,
+        )),
+    ))))(i)?;
+    let (i, _) = character::char('"')(i)?;
+    Ok((i, content))
+}
+
+/// Eat a todo with one of the given tags from the contents of a Rust string
+/// literal. Lines may be broken
+/// by line breaks or by `\n` escapes.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::*;
+///
+/// assert_eq!(
+///     rust_todo_content(&TodoTags::default(), r"TODO: Title. Description.\nMore description."),
+///     Ok((
+///         "",
+///         (
+///             None,
+///             "Title.".into(),
+///             vec!["Description.", "More description."],
+///             "TODO"
+///         )
+///     ))
+/// );
+/// ```
+pub fn rust_todo_content<'a>(tags: &TodoTags, content: &'a str) -> IResult<&'a str, TodoParts<'a>> {
+    let (i, (keyword, may_name)) = tags.todo_head(content)?;
+    rust_todo_message(keyword, may_name, &[RustLiteral::Cooked(i)])
+}
+
+/// The contents of a Rust string literal, see `rust_string_literals`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RustLiteral<'a> {
+    /// A literal like `"..."`, whose escapes are left as they are.
+    Cooked(&'a str),
+    /// A raw literal like `r#"..."#`, which has no escapes.
+    Raw(&'a str),
+}
+
+impl<'a> RustLiteral<'a> {
+    fn contents(&self) -> &'a str {
+        match self {
+            RustLiteral::Cooked(contents) | RustLiteral::Raw(contents) => contents,
+        }
+    }
+}
+
+/// Eat a Rust raw string literal, returning its contents.
 ///
 /// ```rust
 /// use todo_finder_lib::parser::source::*;
 ///
+/// assert_eq!(rust_raw_string_literal(r#"r"C:\dir" rest"#), Ok((" rest", r"C:\dir")));
 /// assert_eq!(
-///     trim_borders(&vec!["*".into()], " * I like veggies *\n"),
-///     "I like veggies"
+///     rust_raw_string_literal(r###"r#"A "quoted" string."#"###),
+///     Ok(("", r#"A "quoted" string."#))
 /// );
 /// ```
-pub fn trim_borders<'a>(borders: &Vec<String>, i: &'a str) -> &'a str {
-    let i = i.trim();
-    let i = borders
-        .iter()
-        .fold(i, |i, border| i.trim_start_matches(border).trim());
-    borders
-        .iter()
-        .fold(i, |i, border| i.trim_end_matches(border).trim())
+pub fn rust_raw_string_literal(i: &str) -> IResult<&str, &str> {
+    let (i, _) = character::char('r')(i)?;
+    let (i, hashes) = bytes::take_while(|c| c == '#')(i)?;
+    let (i, _) = character::char('"')(i)?;
+    let end = format!("\"{}", hashes);
+    let (i, content) = bytes::take_until(end.as_str())(i)?;
+    let (i, _) = bytes::tag(end.as_str())(i)?;
+    Ok((i, content))
 }
 
-/// Eat a sentence and the rest of the line, if possible. The rest, in the case
-/// of a todo, is a portion of the description.
+/// Eat the Rust string literals of a message: a literal, adjacent literals
+/// like `"Part a. " "Part b."`, or the literals of a `concat!`. Literals may
+/// be raw and may span lines.
 ///
 /// ```rust
 /// use todo_finder_lib::parser::source::*;
 ///
-/// let bytes = "sleep for variable time depending on exact error? Ionno know.\n\n";
 /// assert_eq!(
-///     title_and_rest_till_eol(vec![])(bytes),
-///     Ok((
-///         "\n",
-///         (
-///             "sleep for variable time depending on exact error?",
-///             "Ionno know."
-///         )
-///     ))
+///     rust_string_literals("\"a\"\n    r#\"b\"# )"),
+///     Ok((" )", vec![RustLiteral::Cooked("a"), RustLiteral::Raw("b")]))
+/// );
+/// assert_eq!(
+///     rust_string_literals("concat!(\"a\", \"b\",), x"),
+///     Ok((", x", vec![RustLiteral::Cooked("a"), RustLiteral::Cooked("b")]))
 /// );
+/// assert!(rust_string_literals("format!(\"a\")").is_err());
 /// ```
-pub fn title_and_rest_till_eol(
-    // An ignorable border for comments that like to have outlines.
-    // Eg. "*" for C-like langs or "!" for Objective-C.
-    borders: Vec<String>,
-) -> impl Fn(&str) -> IResult<&str, (&str, &str)> {
-    move |i| {
-        let (i, ln) = take_to_eol(i)?;
-        let (desc, title) = sentence_and_terminator(ln)?;
-        Ok((i, (title, trim_borders(&borders, desc))))
+pub fn rust_string_literals(i: &str) -> IResult<&str, Vec<RustLiteral<'_>>> {
+    let literal = |i| {
+        branch::alt((
+            combinator::map(rust_raw_string_literal, RustLiteral::Raw),
+            combinator::map(rust_string_literal, RustLiteral::Cooked),
+        ))(i)
+    };
+    branch::alt((
+        sequence::delimited(
+            sequence::tuple((
+                bytes::tag("concat!"),
+                character::multispace0,
+                character::char('('),
+                character::multispace0,
+            )),
+            multi::separated_list1(
+                sequence::tuple((
+                    character::multispace0,
+                    character::char(','),
+                    character::multispace0,
+                )),
+                literal,
+            ),
+            sequence::tuple((
+                character::multispace0,
+                combinator::opt(character::char(',')),
+                character::multispace0,
+                character::char(')'),
+            )),
+        ),
+        multi::many1(sequence::preceded(character::multispace0, literal)),
+    ))(i)
+}
+
+/// Break the literals of a message into the fragments of its lines, each with
+/// whether it runs on into the next fragment without a line break. A line
+/// runs on at the end of a literal followed by another, and at a `\` line
+/// continuation, after which leading whitespace is skipped as Rust does.
+fn message_fragments<'a>(literals: &[RustLiteral<'a>]) -> Vec<(&'a str, bool)> {
+    let mut fragments = vec![];
+    let mut continued = false;
+    for (n, literal) in literals.iter().enumerate() {
+        let is_raw = matches!(literal, RustLiteral::Raw(_));
+        let lines = literal.contents().split('\n').collect::<Vec<_>>();
+        for (m, line) in lines.iter().enumerate() {
+            let line = if continued { line.trim_start() } else { line };
+            let is_last_line = m + 1 == lines.len();
+            let (line, runs_on) = match line.strip_suffix('\\') {
+                Some(line) if !is_raw && !is_last_line => (line, true),
+                _ => (line, is_last_line && n + 1 < literals.len()),
+            };
+            continued = runs_on && !is_last_line;
+            if is_raw {
+                fragments.push((line, runs_on));
+            } else {
+                let parts = line.split("\\n").collect::<Vec<_>>();
+                let num_parts = parts.len();
+                for (k, part) in parts.into_iter().enumerate() {
+                    fragments.push((part, runs_on && k + 1 == num_parts));
+                }
+            }
+        }
+    }
+    fragments
+}
+
+/// Split the rest of a todo in Rust string literals into its title and
+/// description. The title is the first sentence, which may run on over
+/// several literals. The rest of each line is a line of the description.
+fn rust_todo_message<'a>(
+    keyword: &'a str,
+    may_name: Option<&'a str>,
+    literals: &[RustLiteral<'a>],
+) -> IResult<&'a str, TodoParts<'a>> {
+    let fragments = message_fragments(literals);
+    let mut title: Cow<'a, str> = Cow::Borrowed("");
+    let mut desc_n = vec![];
+    let mut rest = fragments.into_iter();
+    for (fragment, runs_on) in rest.by_ref() {
+        let (desc0, sentence) = sentence_and_terminator(fragment)?;
+        title = if title.is_empty() {
+            Cow::Borrowed(sentence)
+        } else {
+            Cow::Owned([title.as_ref(), sentence].concat())
+        };
+        let is_ended = sentence.ends_with(['.', '?', '!']);
+        if is_ended || !runs_on {
+            desc_n.push(desc0.trim());
+            break;
+        }
     }
+    desc_n.extend(rest.map(|(fragment, _)| fragment.trim()));
+    desc_n.retain(|desc| !desc.is_empty());
+    let title = match title {
+        Cow::Borrowed(title) => Cow::Borrowed(title.trim_end()),
+        Cow::Owned(title) => Cow::Owned(title.trim_end().to_string()),
+    };
+    Ok(("", (may_name, title, desc_n, keyword)))
 }
 
-/// Eat a single line comment. Fails if it hits a possible todo and stops when it
-/// eats the end of a line.
+/// Eat a todo with one of the given tags that lives in a Rust doc attribute, eg. `#[doc = "TODO: ..."]`
+/// or `#![doc = "TODO: ..."]`.
 ///
 /// ```rust
-/// use nom::CompareResult::Error;
 /// use todo_finder_lib::parser::source::*;
 ///
-/// let bytes = "// Here is a whole single line comment.\n";
+/// let bytes = "#[doc = \"TODO(schell): Document this. It's public.\"]\nstruct A;\n";
 /// assert_eq!(
-///     single_line_comment(vec![], "//".into())(bytes),
-///     Ok(("", "Here is a whole single line comment."))
+///     doc_attribute_todo(&TodoTags::default(), bytes),
+///     Ok((
+///         "struct A;\n",
+///         (Some("schell"), "Document this.".into(), vec!["It's public."], "TODO")
+///     ))
 /// );
+/// ```
+pub fn doc_attribute_todo<'a>(tags: &TodoTags, i: &'a str) -> IResult<&'a str, TodoParts<'a>> {
+    let (i, _) = character::space0(i)?;
+    let (i, _) = character::char('#')(i)?;
+    let (i, _) = combinator::opt(character::char('!'))(i)?;
+    let (i, _) = character::char('[')(i)?;
+    let (i, _) = character::space0(i)?;
+    let (i, _) = bytes::tag("doc")(i)?;
+    let (i, _) = character::space0(i)?;
+    let (i, _) = character::char('=')(i)?;
+    let (i, _) = character::space0(i)?;
+    let (i, content) = rust_string_literal(i)?;
+    let (i, _) = character::multispace0(i)?;
+    let (i, _) = character::char(']')(i)?;
+    let (i, _) = take_to_eol(i)?;
+    let (_, parts) = rust_todo_content(tags, content)?;
+    Ok((i, parts))
+}
+
+/// The numbers of the lines, counting from 1, inside fenced code blocks of
+/// `///` and `//!` doc comments, whose todos are usually examples rather than
+/// real ones. A block ends at its closing fence or wherever the doc comment
+/// does.
 ///
-/// let bytes = "// TODO: Here is a whole single line comment.\n";
-/// assert!(single_line_comment(vec![], "//".into())(bytes).is_err());
+/// ```rust
+/// use todo_finder_lib::parser::source::doc_example_lines;
+///
+/// let code = "/// Do it.\n///\n/// ```\n/// // TODO: Example.\n/// ```\n// TODO: Real.\n";
+/// assert_eq!(doc_example_lines(code), vec![3, 4, 5]);
 /// ```
-pub fn single_line_comment(
-    // An ignorable border for comments that like to have outlines.
-    // Eg. "*" for C-like langs or "!" for Objective-C.
-    borders: Vec<String>,
-    // The comment prefix.
-    // Eg. "--" for Haskell, "//" for Rust.
-    prefix: String,
-) -> impl Fn(&str) -> IResult<&str, &str> {
-    let parse_comment_start = comment_start(borders, prefix);
-    move |i| {
-        let (i, _) = parse_comment_start(i)?;
-        let (i, _) = combinator::not(todo_tag)(i)?;
-        take_to_eol(i)
+pub fn doc_example_lines(contents: &str) -> Vec<usize> {
+    let mut lines = vec![];
+    let mut fence: Option<&str> = None;
+    for (n, line) in contents.lines().enumerate() {
+        let doc = line.trim_start();
+        let doc = match doc.strip_prefix("///").or_else(|| doc.strip_prefix("//!")) {
+            Some(doc) => doc.trim(),
+            None => {
+                fence = None;
+                continue;
+            }
+        };
+        let marker = ["```", "~~~"]
+            .iter()
+            .copied()
+            .find(|marker| doc.starts_with(marker));
+        match (fence, marker) {
+            (Some(open), Some(close)) if open == close => {
+                fence = None;
+            }
+            (Some(_), _) => {}
+            (None, Some(open)) => fence = Some(open),
+            (None, None) => continue,
+        }
+        lines.push(n + 1);
     }
+    lines
 }
 
-/// Eat a todo comprised of single line comments.
-/// Returns an assignee if possible, the todo's title and a vector of description
-/// lines.
+/// Attributes whose string arguments may hold todos, see `attribute_note_todo`.
+pub const NOTE_ATTRIBUTES: [&str; 5] = ["deprecated", "allow", "expect", "warn", "deny"];
+
+/// Eat a `note = "..."` or `reason = "..."` attribute argument, returning the
+/// string.
+fn note_argument(i: &str) -> IResult<&str, &str> {
+    let (i, _) = branch::alt((bytes::tag("note"), bytes::tag("reason")))(i)?;
+    let (i, _) = character::space0(i)?;
+    let (i, _) = character::char('=')(i)?;
+    let (i, _) = character::space0(i)?;
+    rust_string_literal(i)
+}
+
+/// Eat a todo in the note of a Rust attribute like `deprecated`, or in the
+/// reason of a lint attribute like `allow`. Only notes starting with one of the
+/// given tags are todos.
 ///
 /// ```rust
-/// use nom::multi;
 /// use todo_finder_lib::parser::source::*;
 ///
-/// let bytes = "-- TODO: Hey there.\n--    Description.\n";
+/// let bytes = "#[deprecated(since = \"1.2\", note = \"TODO: Remove in 2.0.\")]\nfn a() {}\n";
 /// assert_eq!(
-///     single_line_todo(vec![], "--".into())(bytes),
-///     Ok(("", (None, "Hey there.", vec!["Description.".into()])))
+///     attribute_note_todo(&TodoTags::default(), bytes),
+///     Ok(("fn a() {}\n", (None, "Remove in 2.0.".into(), vec![], "TODO")))
+/// );
+///
+/// let bytes = "#[allow(dead_code, reason = \"TODO(schell): Use this.\")]\n";
+/// assert_eq!(
+///     attribute_note_todo(&TodoTags::default(), bytes),
+///     Ok(("", (Some("schell"), "Use this.".into(), vec![], "TODO")))
 /// );
+///
+/// assert!(attribute_note_todo(&TodoTags::default(), "#[deprecated(note = \"Use b instead.\")]\n").is_err());
 /// ```
-pub fn single_line_todo(
-    // An ignorable border for comments that like to have outlines.
-    // Eg. "*" for C-like langs or "!" for Objective-C.
-    borders: Vec<String>,
-    // The comment prefix.
-    // Eg. "--" for Haskell, "//" for Rust.
-    prefix: String,
-) -> impl Fn(&str) -> IResult<&str, (Option<&str>, &str, Vec<&str>)> {
-    let parse_comment_start = comment_start(borders.clone(), prefix.clone());
-    let parse_title_desc = title_and_rest_till_eol(borders.clone());
-    move |i| {
-        let (i, _) = parse_comment_start(i)?;
-        let (i, may_name) = todo_tag(i)?;
-        let (i, (title, desc0)) = parse_title_desc(i)?;
-        let parse_single_line = single_line_comment(borders.clone(), prefix.clone());
-        let (i, mut desc_n) = multi::many0(parse_single_line)(i)?;
-        desc_n.insert(0, desc0);
-        desc_n.retain(|desc| !desc.is_empty());
-        Ok((i, (may_name, title, desc_n)))
+pub fn attribute_note_todo<'a>(tags: &TodoTags, i: &'a str) -> IResult<&'a str, TodoParts<'a>> {
+    let (i, _) = character::space0(i)?;
+    let (i, _) = character::char('#')(i)?;
+    let (i, _) = character::char('[')(i)?;
+    let (i, _) = character::space0(i)?;
+    let (i, name) = character::alphanumeric1(i)?;
+    if !NOTE_ATTRIBUTES.contains(&name) {
+        return Err(Err::Error(nom::error::Error {
+            input: i,
+            code: ErrorKind::Tag,
+        }));
     }
+    let (i, _) = character::space0(i)?;
+    let (i, content) = branch::alt((
+        // #[deprecated = "..."]
+        sequence::preceded(
+            sequence::pair(character::char('='), character::space0),
+            rust_string_literal,
+        ),
+        // #[deprecated(since = "...", note = "...")]
+        sequence::preceded(
+            character::char('('),
+            combinator::map(
+                multi::many_till(
+                    branch::alt((
+                        combinator::value((), rust_string_literal),
+                        combinator::value((), character::none_of("]")),
+                    )),
+                    note_argument,
+                ),
+                |(_, note)| note,
+            ),
+        ),
+    ))(i)?;
+    let (i, _) = take_to_eol(i)?;
+    let (_, parts) = rust_todo_content(tags, content)?;
+    Ok((i, parts))
 }
 
-/// Eat a todo that lives in a multi-line comment block.
+/// Eat a todo that is the message of a macro, eg. `todo!("Do it.")` in Rust.
+/// The macro may come after other code on the line, and its message may but
+/// needn't start with one of the given tags.
 ///
 /// ```rust
 /// use todo_finder_lib::parser::source::*;
 ///
-/// let haskell_parser = multi_line_todo(vec!["|".into()], "{-".into(), "-}".into());
-///
-/// let bytes = "{- | TODO: My todo title.
-///                   Description too. With more
-///                   sentences over more lines.
-///              -}\n";
+/// let bytes = "    let a = todo!(\"FIXME(schell): Count. Carefully.\");\nlet b = 1;\n";
 /// assert_eq!(
-///     haskell_parser(bytes),
+///     macro_todo("todo!".into(), TodoTags::default())(bytes),
 ///     Ok((
-///         "\n",
-///         (
-///             None,
-///             "My todo title.",
-///             vec!["Description too. With more", "sentences over more lines."]
-///         )
+///         "let b = 1;\n",
+///         (Some("schell"), "Count.".into(), vec!["Carefully."], "TODO")
 ///     ))
 /// );
+/// assert_eq!(
+///     macro_todo("todo!".into(), TodoTags::default())("todo!(\"(schell) Fix the thing.\")\n"),
+///     Ok(("", (Some("schell"), "Fix the thing.".into(), vec![], "TODO")))
+/// );
+/// assert!(macro_todo("todo!".into(), TodoTags::default())("my_todo!(\"Count.\")").is_err());
+/// assert!(macro_todo("todo!".into(), TodoTags::default())("todo!()").is_err());
 /// ```
-pub fn multi_line_todo(
-    // An ignorable border for comments that like to have outlines.
-    // Eg. "*" for C-like langs or "!" for Objective-C.
-    borders: Vec<String>,
-    // The comment prefix.
-    // Eg. "{-" for Haskell, "/*" for Rust.
-    prefix: String,
-    // The comment suffix.
-    // Eg. "-}" for Haskell, "*/" for Rust.
-    suffix: String,
-) -> impl Fn(&str) -> IResult<&str, (Option<&str>, &str, Vec<&str>)> {
-    let parse_title_desc = title_and_rest_till_eol(borders.clone());
+pub fn macro_todo(name: String, tags: TodoTags) -> impl Fn(&str) -> IResult<&str, TodoParts<'_>> {
     move |i| {
-        let (i, _) = character::space0(i)?;
-        let (i, _) = combinator::opt(comment_start(borders.clone(), prefix.clone()))(i)?;
-        let (i, may_name) = todo_tag(i)?;
-        let (i, (title, desc0)) = parse_title_desc(i)?;
-        if desc0 == &suffix {
-            Ok((i, (may_name, title, vec![])))
-        } else {
-            let (i, comment) = bytes::take_until(suffix.as_str())(i)?;
-            let (i, _) = bytes::tag(suffix.as_str())(i)?;
-            let mut desc_n = vec![desc0];
-            for line in comment.lines() {
-                let trimmed_line = trim_borders(&borders, line);
-                desc_n.push(trimmed_line);
-            }
-            desc_n.retain(|desc| !desc.is_empty());
-            Ok((i, (may_name, title, desc_n)))
+        let (_, line) = take_to_eol(i)?;
+        // Find the macro on this line, not as the end of a longer name
+        let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+        let start = line
+            .match_indices(name.as_str())
+            .map(|(n, _)| n)
+            .find(|n| !line[..*n].ends_with(is_ident))
+            .ok_or(Err::Error(nom::error::Error {
+                input: i,
+                code: ErrorKind::Tag,
+            }))?;
+        // The message may run on over the following lines
+        let j = &i[start + name.len()..];
+        let (j, _) = character::space0(j)?;
+        let (j, _) = character::char('(')(j)?;
+        let (j, _) = character::multispace0(j)?;
+        let (j, mut literals) = rust_string_literals(j)?;
+        let (j, _) =
+            combinator::opt(sequence::pair(character::multispace0, character::char(')')))(j)?;
+        let (rest, _) = take_to_eol(j)?;
+        let content = literals[0].contents();
+        let (content, may_name) = match combinator::opt(|i| tags.todo_tag(i))(content)? {
+            (content, Some(may_name)) => (content, may_name),
+            // A message without a tag may still start with an assignee
+            (content, None) => match macro_assignee(content) {
+                Ok((content, name)) => (content, Some(name)),
+                Err(_) => (content, None),
+            },
+        };
+        literals[0] = match literals[0] {
+            RustLiteral::Cooked(_) => RustLiteral::Cooked(content),
+            RustLiteral::Raw(_) => RustLiteral::Raw(content),
+        };
+        if literals
+            .iter()
+            .all(|literal| literal.contents().trim().is_empty())
+        {
+            return Err(Err::Error(nom::error::Error {
+                input: i,
+                code: ErrorKind::Verify,
+            }));
         }
+        // Macros count as `TODO`, whatever tag their message starts with
+        let (_, parts) = rust_todo_message(TODO_KEYWORDS[0], may_name, &literals)?;
+        Ok((rest, parts))
     }
 }
 
+/// Eat the assignee at the start of a macro's message without a tag, as in
+/// `todo!("(schell) Fix the thing.")`, and the `:` and spaces after it.
+fn macro_assignee(i: &str) -> IResult<&str, &str> {
+    let (i, _) = character::space0(i)?;
+    let (i, name) = assignee(i)?;
+    let (i, _) = combinator::opt(character::char(':'))(i)?;
+    let (i, _) = character::space0(i)?;
+    Ok((i, name))
+}
+
 /// A todo parser configuration.
 #[derive(Clone, Debug, PartialEq)]
 pub struct TodoParserConfig {
@@ -552,6 +2053,29 @@ pub struct TodoParserConfig {
     /// A list of comment borders.
     /// Eg. `vec!["|".into()]` for Haskell
     pub borders: Vec<String>,
+    /// Whether to look for todos in Rust's `#[doc = "..."]` attributes.
+    pub doc_attributes: bool,
+    /// Whether to look for todos in the notes of Rust attributes, see
+    /// `attribute_note_todo`.
+    pub attribute_notes: bool,
+    /// Whether any line may start a todo, as in Markdown, see `prose_todo`.
+    pub prose: bool,
+    /// A list of macros whose message is a todo.
+    /// Eg. `vec!["todo!".into()]` for Rust
+    pub macros: Vec<String>,
+    /// How titles are found.
+    pub title_mode: TitleMode,
+    /// The keywords that start a todo.
+    pub tags: TodoTags,
+    /// Emoji that mark a todo's severity at the start of its title, like
+    /// `🔴`, and the label each adds, see `emoji_label`.
+    pub emoji_labels: Vec<(String, String)>,
+}
+
+impl Default for TodoParserConfig {
+    fn default() -> Self {
+        TodoParserConfig::new()
+    }
 }
 
 impl TodoParserConfig {
@@ -560,6 +2084,13 @@ impl TodoParserConfig {
             singles: vec![],
             multis: vec![],
             borders: vec![],
+            doc_attributes: false,
+            attribute_notes: false,
+            prose: false,
+            macros: vec![],
+            title_mode: TitleMode::Sentence,
+            tags: TodoTags::default(),
+            emoji_labels: vec![],
         }
     }
 
@@ -572,6 +2103,10 @@ impl TodoParserConfig {
                 self.multis.push((p, s));
             }
             CommentStyle::Border(b) => self.borders.push(b),
+            CommentStyle::DocAttribute => self.doc_attributes = true,
+            CommentStyle::AttributeNote => self.attribute_notes = true,
+            CommentStyle::Prose => self.prose = true,
+            CommentStyle::Macro(name) => self.macros.push(name),
         }
     }
 
@@ -584,14 +2119,24 @@ impl TodoParserConfig {
     }
 
     pub fn add_parser_config(&mut self, cfg: TodoParserConfig) {
-        self.singles.extend(cfg.singles.into_iter());
-        self.multis.extend(cfg.multis.into_iter());
-        self.borders.extend(cfg.borders.into_iter());
+        self.singles.extend(cfg.singles);
+        self.multis.extend(cfg.multis);
+        self.borders.extend(cfg.borders);
+        self.doc_attributes |= cfg.doc_attributes;
+        self.attribute_notes |= cfg.attribute_notes;
+        self.prose |= cfg.prose;
+        self.macros.extend(cfg.macros);
     }
 }
 
 pub struct ParserConfigLookup(pub HashMap<String, TodoParserConfig>);
 
+impl Default for ParserConfigLookup {
+    fn default() -> Self {
+        ParserConfigLookup::new()
+    }
+}
+
 impl ParserConfigLookup {
     pub fn new() -> Self {
         ParserConfigLookup(HashMap::new())
@@ -600,7 +2145,7 @@ impl ParserConfigLookup {
     pub fn add_lang(&mut self, language: SupportedLanguage) {
         let cfg = TodoParserConfig::from_comment_styles(language.comment_styles);
         for ext in language.file_extensions {
-            let old_cfg = self.0.entry(ext).or_insert(TodoParserConfig::new());
+            let old_cfg = self.0.entry(ext).or_default();
             old_cfg.add_parser_config(cfg.clone());
         }
     }
@@ -614,9 +2159,198 @@ impl ParserConfigLookup {
 /// A structure to conveniently hold a fully parsed todo.
 #[derive(Clone, Debug, PartialEq)]
 pub struct ParsedTodo<'a> {
-    pub title: &'a str,
+    pub title: Cow<'a, str>,
     pub assignee: Option<&'a str>,
     pub desc_lines: Vec<&'a str>,
+    /// The keyword that started the todo, eg. `FIXME`.
+    pub tag: &'a str,
+    /// Labels like `backend` in `TODO[backend]` followed by trailing hashtags
+    /// like `#frontend`, without the `#`.
+    pub tags: Vec<String>,
+    /// How urgent the todo is, from `0` down, eg. `1` for `TODO(p1)`, see
+    /// `todo_priority`.
+    pub priority: Option<u8>,
+    /// The issue referenced in place of an assignee, eg. `123` for
+    /// `TODO(#123)`, see `issue_reference`.
+    pub reference: Option<u64>,
+}
+
+impl<'a> ParsedTodo<'a> {
+    /// A plain `TODO` with the title and nothing else, to fill in the rest of
+    /// with struct update syntax.
+    ///
+    /// ```rust
+    /// use todo_finder_lib::parser::source::ParsedTodo;
+    ///
+    /// let todo = ParsedTodo {
+    ///     assignee: Some("schell"),
+    ///     ..ParsedTodo::new("Do it.")
+    /// };
+    /// assert_eq!(todo.tag, "TODO");
+    /// assert!(todo.desc_lines.is_empty());
+    /// ```
+    pub fn new(title: impl Into<Cow<'a, str>>) -> Self {
+        ParsedTodo {
+            title: title.into(),
+            assignee: None,
+            desc_lines: vec![],
+            tag: "TODO",
+            tags: vec![],
+            priority: None,
+            reference: None,
+        }
+    }
+}
+
+/// The issue number of what was parsed as a todo's assignee, if it is an
+/// issue reference like `#123` rather than anyone's name.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::issue_reference;
+///
+/// assert_eq!(issue_reference("#123"), Some(123));
+/// assert_eq!(issue_reference("#"), None);
+/// assert_eq!(issue_reference("#12abc"), None);
+/// assert_eq!(issue_reference("schell"), None);
+/// ```
+pub fn issue_reference(assignee: &str) -> Option<u64> {
+    assignee
+        .strip_prefix('#')
+        .filter(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+        .and_then(|n| n.parse().ok())
+}
+
+/// The priority of a todo from what was parsed as its assignee, like `p1` in
+/// `TODO(p1)`, or else from the number of bangs after its keyword: `TODO!!!`
+/// is priority 0, `TODO!!` is 1 and `TODO!` is 2.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::todo_priority;
+///
+/// assert_eq!(todo_priority(Some("p1"), 0), Some(1));
+/// assert_eq!(todo_priority(Some("P0"), 0), Some(0));
+/// assert_eq!(todo_priority(Some("schell"), 0), None);
+/// assert_eq!(todo_priority(None, 3), Some(0));
+/// assert_eq!(todo_priority(None, 5), Some(0));
+/// assert_eq!(todo_priority(None, 1), Some(2));
+/// ```
+pub fn todo_priority(assignee: Option<&str>, bangs: usize) -> Option<u8> {
+    let marker = assignee
+        .and_then(|name| name.strip_prefix('p').or_else(|| name.strip_prefix('P')))
+        .filter(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+        .and_then(|n| n.parse().ok());
+    match (marker, bangs) {
+        (Some(priority), _) => Some(priority),
+        (None, 0) => None,
+        (None, bangs) => Some(3 - bangs.min(3) as u8),
+    }
+}
+
+/// Split a severity emoji off of the start of a title, returning the rest of
+/// the title and the emoji's label, if the title starts with one of the
+/// given emoji.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::emoji_label;
+///
+/// let emoji_labels = vec![
+///     ("🔴".to_string(), "critical".to_string()),
+///     ("🟡".to_string(), "minor".to_string()),
+/// ];
+/// assert_eq!(
+///     emoji_label("🔴 Fix the crash.", &emoji_labels),
+///     Some(("Fix the crash.", "critical"))
+/// );
+/// assert_eq!(emoji_label("Fix the 🔴 crash.", &emoji_labels), None);
+/// ```
+pub fn emoji_label<'a, 'b>(
+    title: &'a str,
+    emoji_labels: &'b [(String, String)],
+) -> Option<(&'a str, &'b str)> {
+    emoji_labels.iter().find_map(|(emoji, label)| {
+        let rest = title.strip_prefix(emoji.as_str())?;
+        // Some emoji are followed by a variation selector
+        let rest = rest.strip_prefix('\u{fe0f}').unwrap_or(rest);
+        Some((rest.trim_start(), label.as_str()))
+    })
+}
+
+/// A title as it's compared to ignored titles: lowercase, with runs of
+/// whitespace collapsed to one space and without a trailing period.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::normalize_title;
+///
+/// assert_eq!(normalize_title("  Fix   the Example. "), "fix the example");
+/// ```
+pub fn normalize_title(title: &str) -> String {
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    title.trim_end_matches('.').to_lowercase()
+}
+
+/// Whether a glob matches all of some text, where `*` matches any run of
+/// characters and `?` any one character.
+fn glob_matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|skip| glob_matches(rest, &text[skip..])),
+        Some(('?', rest)) => !text.is_empty() && glob_matches(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && glob_matches(rest, &text[1..]),
+    }
+}
+
+/// Whether a title matches any of the ignored titles, either exactly or as a
+/// glob with `*` and `?`. Both are normalized first, see `normalize_title`.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::title_is_ignored;
+///
+/// let ignored = vec!["Implement this.".to_string(), "example: *".to_string()];
+/// assert!(title_is_ignored("implement  this", &ignored));
+/// assert!(title_is_ignored("Example: Handle errors.", &ignored));
+/// assert!(!title_is_ignored("Implement this later.", &ignored));
+/// ```
+pub fn title_is_ignored(title: &str, ignored: &[String]) -> bool {
+    let title = normalize_title(title).chars().collect::<Vec<_>>();
+    ignored.iter().any(|pattern| {
+        let pattern = normalize_title(pattern).chars().collect::<Vec<_>>();
+        glob_matches(&pattern, &title)
+    })
+}
+
+/// Whether a word is a hashtag like `#frontend` or `#p2`. Issue references
+/// like `#123` are not hashtags.
+fn is_hashtag(word: &str) -> bool {
+    let mut chars = word.chars();
+    chars.next() == Some('#')
+        && chars.next().map(|c| c.is_alphabetic()).unwrap_or(false)
+        && chars.all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Split trailing hashtags off of a line, returning the rest of the line and
+/// the tags without their `#`.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::trailing_hashtags;
+///
+/// assert_eq!(
+///     trailing_hashtags("Fix the layout. #frontend #p2"),
+///     ("Fix the layout.", vec!["frontend", "p2"])
+/// );
+/// assert_eq!(trailing_hashtags("Close #123"), ("Close #123", vec![]));
+/// ```
+pub fn trailing_hashtags(line: &str) -> (&str, Vec<&str>) {
+    let mut rest = line.trim_end();
+    let mut tags = vec![];
+    while let Some(word) = rest.split_whitespace().last() {
+        if !is_hashtag(word) {
+            break;
+        }
+        tags.push(&word[1..]);
+        rest = rest[..rest.len() - word.len()].trim_end();
+    }
+    tags.reverse();
+    (rest, tags)
 }
 
 /// Configures a parser to eat a todo from the input.
@@ -628,6 +2362,7 @@ pub struct ParsedTodo<'a> {
 ///     singles: vec!["--".into()],
 ///     multis: vec![("{-".into(), "-}".into())],
 ///     borders: vec!["|".into()],
+///     ..TodoParserConfig::new()
 /// });
 ///
 /// let bytes = "{- | TODO (soundwave) List the steps to draw an owl. -}\n";
@@ -636,9 +2371,13 @@ pub struct ParsedTodo<'a> {
 ///     Ok((
 ///         "",
 ///         ParsedTodo {
-///             title: "List the steps to draw an owl.",
+///             title: "List the steps to draw an owl.".into(),
 ///             assignee: Some("soundwave"),
-///             desc_lines: vec![]
+///             desc_lines: vec![],
+///             tag: "TODO",
+///             tags: vec![],
+///             priority: None,
+///             reference: None,
 ///         }
 ///     ))
 /// );
@@ -647,31 +2386,146 @@ pub fn parse_todo<'a>(
     cfg: TodoParserConfig,
 ) -> impl Fn(&'a str) -> IResult<&'a str, ParsedTodo<'a>> {
     move |i| {
-        let to_todo = |(input, todo): (&'a str, (Option<&'a str>, &'a str, Vec<&'a str>))| {
+        let to_todo = |(input, todo): (&'a str, TodoParts<'a>)| {
+            let text = &i[..i.len() - input.len()];
+            let tag = todo.3;
+            // The keyword was sliced from the text, unless it's the `TODO` a
+            // macro counts as
+            let after_tag = if text.as_bytes().as_ptr_range().contains(&tag.as_ptr()) {
+                &text[tag.as_ptr() as usize - text.as_ptr() as usize + tag.len()..]
+            } else {
+                ""
+            };
+            let bangs = after_tag.chars().take_while(|c| *c == '!').count();
+            // Labels come before any hashtags
+            let mut tags = tag_labels(&after_tag[bangs..])
+                .map(|(_, labels)| labels.into_iter().map(String::from).collect())
+                .unwrap_or_else(|_| vec![]);
+            let title: Cow<'a, str> = match todo.1 {
+                Cow::Borrowed(title) => {
+                    let title = match emoji_label(title, &cfg.emoji_labels) {
+                        Some((title, label)) => {
+                            tags.push(label.to_string());
+                            title
+                        }
+                        None => title,
+                    };
+                    let (title, title_tags) = trailing_hashtags(title);
+                    tags.extend(title_tags.into_iter().map(String::from));
+                    Cow::Borrowed(title)
+                }
+                Cow::Owned(title) => {
+                    let title = match emoji_label(&title, &cfg.emoji_labels) {
+                        Some((title, label)) => {
+                            tags.push(label.to_string());
+                            title
+                        }
+                        None => &title,
+                    };
+                    let (title, title_tags) = trailing_hashtags(title);
+                    tags.extend(title_tags.into_iter().map(String::from));
+                    Cow::Owned(title.to_string())
+                }
+            };
+            // Only the line holding the tag may end in hashtags, the rest of
+            // the description is prose or code, like `#include <stdio.h>`
+            let first_line = text.lines().next().unwrap_or("").as_bytes().as_ptr_range();
+            let mut desc_lines = vec![];
+            for line in todo.2 {
+                let line = if first_line.contains(&line.as_ptr()) {
+                    let (line, line_tags) = trailing_hashtags(line);
+                    tags.extend(line_tags.into_iter().map(String::from));
+                    line
+                } else {
+                    line
+                };
+                if !line.is_empty() {
+                    desc_lines.push(line);
+                }
+            }
+            let priority = todo_priority(todo.0, bangs);
+            let reference = todo.0.and_then(issue_reference);
+            // Neither a priority marker like `p1` nor an issue reference like
+            // `#123` is anyone's name
+            let assignee = todo
+                .0
+                .filter(|name| todo_priority(Some(name), 0).is_none() && reference.is_none());
             Ok((
                 input,
                 ParsedTodo {
-                    title: todo.1,
-                    assignee: todo.0,
-                    desc_lines: todo.2,
+                    title,
+                    assignee,
+                    desc_lines,
+                    tag,
+                    tags,
+                    priority,
+                    reference,
                 },
             ))
         };
 
+        // A multi-line todo that hit the lookahead limit, reported if nothing
+        // else parses
+        let mut failure = None;
         for (prefix, suffix) in cfg.multis.clone() {
-            let res = multi_line_todo(cfg.borders.clone(), prefix, suffix)(i);
+            let res = multi_line_todo_in_mode(
+                cfg.borders.clone(),
+                prefix,
+                suffix,
+                cfg.title_mode.clone(),
+                cfg.tags.clone(),
+            )(i);
+            match res {
+                Ok(res) => return to_todo(res),
+                Err(Err::Failure(e)) => failure = Some(e),
+                Err(_) => {}
+            }
+        }
+
+        for prefix in cfg.singles.clone() {
+            let res = single_line_todo_in_mode(
+                cfg.borders.clone(),
+                prefix,
+                cfg.title_mode.clone(),
+                cfg.tags.clone(),
+            )(i);
             if let Ok(res) = res {
                 return to_todo(res);
             }
         }
 
-        for prefix in cfg.singles.clone() {
-            let res = single_line_todo(cfg.borders.clone(), prefix)(i);
+        if cfg.doc_attributes {
+            if let Ok(res) = doc_attribute_todo(&cfg.tags, i) {
+                return to_todo(res);
+            }
+        }
+
+        if cfg.attribute_notes {
+            if let Ok(res) = attribute_note_todo(&cfg.tags, i) {
+                return to_todo(res);
+            }
+        }
+
+        for name in cfg.macros.clone() {
+            if let Ok(res) = macro_todo(name, cfg.tags.clone())(i) {
+                return to_todo(res);
+            }
+        }
+
+        if cfg.prose {
+            let res = prose_todo(
+                cfg.borders.clone(),
+                cfg.title_mode.clone(),
+                cfg.tags.clone(),
+            )(i);
             if let Ok(res) = res {
                 return to_todo(res);
             }
         }
 
+        if let Some(e) = failure {
+            return Err(Err::Failure(e));
+        }
         Err(Err::Error(nom::error::Error {
             input: i,
             code: ErrorKind::Tag,
@@ -679,6 +2533,81 @@ pub fn parse_todo<'a>(
     }
 }
 
+/// The most blank lines allowed between a todo and a comment block that
+/// continues its description.
+pub const ADJACENT_BLOCK_MAX_GAP: usize = 2;
+
+/// Eat a block of single line comments that follows a todo after at most
+/// `max_gap` blank lines, returning its non-empty lines. Fails if there is code
+/// in between or if the block starts another todo.
+///
+/// ```rust
+/// use todo_finder_lib::parser::source::{adjacent_comment_block, TodoTags};
+///
+/// let singles = vec!["//".to_string()];
+/// let bytes = "\n// It continues here.\n// And here.\nfn main() {}\n";
+/// assert_eq!(
+///     adjacent_comment_block(&singles, &TodoTags::default(), 2, bytes),
+///     Ok(("fn main() {}\n", vec!["It continues here.", "And here."]))
+/// );
+/// ```
+pub fn adjacent_comment_block<'a>(
+    singles: &[String],
+    tags: &TodoTags,
+    max_gap: usize,
+    i: &'a str,
+) -> IResult<&'a str, Vec<&'a str>> {
+    let fail = |i| {
+        Err(Err::Error(nom::error::Error {
+            input: i,
+            code: ErrorKind::Verify,
+        }))
+    };
+
+    let mut i = i;
+    let mut gap = 0;
+    loop {
+        if i.is_empty() {
+            return fail(i);
+        }
+        let (j, line) = take_to_eol(i)?;
+        if !line.trim().is_empty() {
+            break;
+        }
+        gap += 1;
+        if gap > max_gap {
+            return fail(i);
+        }
+        i = j;
+    }
+
+    let mut lines = vec![];
+    while !i.is_empty() {
+        let (j, line) = take_to_eol(i)?;
+        let line = line.trim_start();
+        let text = match singles
+            .iter()
+            .find(|prefix| line.starts_with(prefix.as_str()))
+        {
+            Some(prefix) => line[prefix.len()..].trim(),
+            None => break,
+        };
+        if tags.todo_tag(text).is_ok() {
+            break;
+        }
+        if !text.is_empty() {
+            lines.push(text);
+        }
+        i = j;
+    }
+
+    if lines.is_empty() {
+        fail(i)
+    } else {
+        Ok((i, lines))
+    }
+}
+
 /// Using the given config, return a parser that will parse any and all todos
 /// from the string.
 pub fn parse_todos<'a>(cfg: TodoParserConfig) -> impl FnMut(&'a str) -> Vec<ParsedTodo<'a>> {
@@ -691,11 +2620,17 @@ pub fn parse_todos<'a>(cfg: TodoParserConfig) -> impl FnMut(&'a str) -> Vec<Pars
             if ii.is_empty() {
                 break 'find;
             }
-            if let Ok((j, (_, todo))) = parser(ii) {
-                ii = j;
-                todos.push(todo);
-            } else {
-                break 'find;
+            match parser(ii) {
+                Ok((j, (_, todo))) => {
+                    ii = j;
+                    todos.push(todo);
+                }
+                // Skip past a todo that couldn't be parsed
+                Err(Err::Failure(e)) => match take_to_eol(e.input) {
+                    Ok((j, _)) if j.len() < ii.len() => ii = j,
+                    _ => break 'find,
+                },
+                Err(_) => break 'find,
             }
         }
 
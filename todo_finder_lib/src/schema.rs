@@ -0,0 +1,214 @@
+//! The JSON Schema of the TODOs as they are written by `-o json` and to
+//! `--exec` commands.
+use serde_json::{json, Value};
+
+/// The JSON Schema of `IssueMap::as_json`. This is written by hand, so it
+/// must be kept in sync with `as_json_sorted`.
+pub fn todos_schema() -> Value {
+    let strings = json!({ "type": "array", "items": { "type": "string" } });
+    let location = json!({
+        "type": "object",
+        "properties": {
+            "file": { "type": "string" },
+            "line": {
+                "description": "The first line of the TODO.",
+                "type": "integer",
+                "minimum": 1
+            },
+            "end_line": {
+                "description": "The last line of the TODO, if it spans more than one.",
+                "type": ["integer", "null"],
+                "minimum": 1
+            },
+            "desc_lines": strings
+        },
+        "required": ["file", "line", "end_line", "desc_lines"],
+        "additionalProperties": false
+    });
+    let todo = json!({
+        "type": "object",
+        "properties": {
+            "id": {
+                "description": "A stable id for linking to the TODO, a hash of its title, or of what tells TODOs apart with --dedupe-by.",
+                "type": "string"
+            },
+            "title": { "type": "string" },
+            "assignees": strings,
+            "tag": {
+                "description": "The keyword the TODO was tagged with, eg. FIXME.",
+                "type": ["string", "null"]
+            },
+            "tags": {
+                "description": "Labels listed after the TODO's keyword, like TODO[backend], and hashtags found at its end.",
+                "type": "array",
+                "items": { "type": "string" }
+            },
+            "priority": {
+                "description": "How urgent the TODO is, from 0 down, eg. 1 for TODO(p1).",
+                "type": ["integer", "null"],
+                "minimum": 0
+            },
+            "locations": {
+                "description": "Each occurrence of the TODO, by file and then line.",
+                "type": "array",
+                "items": location
+            }
+        },
+        "required": ["id", "title", "assignees", "tag", "tags", "priority", "locations"],
+        "additionalProperties": false
+    });
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "TODOs",
+        "type": "object",
+        "properties": {
+            "todos": {
+                "description": "The TODOs, by title unless sorted with --sort.",
+                "type": "array",
+                "items": todo
+            }
+        },
+        "required": ["todos"],
+        "additionalProperties": false
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{source::ParsedTodo, FileTodoLocation, IssueMap};
+
+    /// Check a value against the parts of JSON Schema used by
+    /// `todos_schema`, returning the path of the first mismatch.
+    fn validate(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+        let fail = |why: &str| Err(format!("{}: {}", path, why));
+        if let Some(types) = schema.get("type") {
+            let types = match types {
+                Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+                ty => vec![ty.as_str().unwrap()],
+            };
+            let matches = |ty: &&str| match *ty {
+                "object" => value.is_object(),
+                "array" => value.is_array(),
+                "string" => value.is_string(),
+                "boolean" => value.is_boolean(),
+                "integer" => value.is_u64() || value.is_i64(),
+                "null" => value.is_null(),
+                _ => false,
+            };
+            if !types.iter().any(matches) {
+                return fail(&format!("{} is not one of {:?}", value, types));
+            }
+        }
+        if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+            if !options.contains(value) {
+                return fail(&format!("{} is not one of {:?}", value, options));
+            }
+        }
+        if let Some(schemas) = schema.get("oneOf").and_then(Value::as_array) {
+            let n = schemas
+                .iter()
+                .filter(|schema| validate(schema, value, path).is_ok())
+                .count();
+            if n != 1 {
+                return fail(&format!("{} matches {} of oneOf", value, n));
+            }
+        }
+        if let (Some(min), Some(n)) = (
+            schema.get("minimum").and_then(Value::as_u64),
+            value.as_u64(),
+        ) {
+            if n < min {
+                return fail(&format!("{} is less than {}", n, min));
+            }
+        }
+        if let Some(object) = value.as_object() {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for key in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                if !object.contains_key(key.as_str().unwrap()) {
+                    return fail(&format!("missing {}", key));
+                }
+            }
+            for (key, value) in object.iter() {
+                let path = format!("{}.{}", path, key);
+                match (
+                    properties.and_then(|p| p.get(key)),
+                    schema.get("additionalProperties"),
+                ) {
+                    (Some(schema), _) => validate(schema, value, &path)?,
+                    (None, Some(Value::Bool(false))) => {
+                        return Err(format!("{}: unexpected property", path))
+                    }
+                    (None, Some(schema)) if schema.is_object() => validate(schema, value, &path)?,
+                    _ => {}
+                }
+            }
+        }
+        if let Some(items) = value.as_array() {
+            let count = |key: &str| schema.get(key).and_then(Value::as_u64);
+            if count("minItems").is_some_and(|min| (items.len() as u64) < min)
+                || count("maxItems").is_some_and(|max| (items.len() as u64) > max)
+            {
+                return fail(&format!("wrong number of items in {}", value));
+            }
+            let prefix = schema
+                .get("prefixItems")
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default();
+            for (n, item) in items.iter().enumerate() {
+                let path = format!("{}[{}]", path, n);
+                if let Some(schema) = prefix.get(n).or_else(|| schema.get("items")) {
+                    validate(schema, item, &path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn serialized_todos_match_the_schema() {
+        let mut issues = IssueMap::new_source_todos();
+        issues.add_parsed_todo(
+            &ParsedTodo {
+                assignee: Some("schell"),
+                desc_lines: vec!["Some detail."],
+                tag: "FIXME",
+                tags: vec!["frontend".into()],
+                ..ParsedTodo::new("Do A.")
+            },
+            FileTodoLocation {
+                file: "/repo/src/lib.rs".into(),
+                src_span: (12, Some(14)),
+            },
+        );
+        issues.add_parsed_todo(
+            &ParsedTodo::new("Do A."),
+            FileTodoLocation {
+                file: "/repo/src/main.rs".into(),
+                src_span: (3, None),
+            },
+        );
+        let schema = todos_schema();
+        let value: Value = serde_json::from_str(&issues.as_json()).unwrap();
+        assert_eq!(validate(&schema, &value, "$"), Ok(()));
+
+        let mut broken = value.clone();
+        broken["todos"][0].as_object_mut().unwrap().remove("tags");
+        assert_eq!(
+            validate(&schema, &broken, "$"),
+            Err("$.todos[0]: missing \"tags\"".into())
+        );
+        broken["todos"][0]["tags"] = json!([]);
+        broken["todos"][0]["locations"][1]["line"] = json!(0);
+        assert_eq!(
+            validate(&schema, &broken, "$"),
+            Err("$.todos[0].locations[1].line: 0 is less than 1".into())
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Summaries of the TODOs found.
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Component, Path},
+};
+
+use super::parser::{FileTodoLocation, IssueMap};
+
+/// A ranking of assignees by the number of TODOs they own, along with how
+/// many TODOs are owned by nobody.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AssigneeStats {
+    /// Assignees and their TODO counts, most TODOs first.
+    pub ranking: Vec<(String, usize)>,
+    pub unassigned: usize,
+    pub total: usize,
+}
+
+impl AssigneeStats {
+    /// Count the distinct TODOs in the map by assignee. A TODO with more than
+    /// one assignee counts toward each of them.
+    pub fn from_issue_map<K, V: Eq>(issues: &IssueMap<K, V>) -> Self {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        let mut unassigned = 0;
+        for issue in issues.todos.values() {
+            if issue.head.assignees.is_empty() {
+                unassigned += 1;
+            }
+            for assignee in issue.head.assignees.iter() {
+                *counts.entry(assignee).or_insert(0) += 1;
+            }
+        }
+        let mut ranking = counts
+            .into_iter()
+            .map(|(name, n)| (name.to_string(), n))
+            .collect::<Vec<_>>();
+        ranking.sort_by(|(a_name, a_n), (b_name, b_n)| b_n.cmp(a_n).then(a_name.cmp(b_name)));
+        AssigneeStats {
+            ranking,
+            unassigned,
+            total: issues.todos.len(),
+        }
+    }
+
+    /// The percentage of TODOs without an assignee.
+    pub fn unassigned_percent(&self) -> f32 {
+        if self.total == 0 {
+            0.0
+        } else {
+            100.0 * self.unassigned as f32 / self.total as f32
+        }
+    }
+
+    pub fn as_markdown(&self) -> String {
+        let mut lines = vec!["## Assignees".to_string()];
+        for (name, n) in self.ranking.iter() {
+            lines.push(format!("* {}: {}", name, n));
+        }
+        lines.push(format!(
+            "* unassigned: {} of {} ({:.0}%)",
+            self.unassigned,
+            self.total,
+            self.unassigned_percent()
+        ));
+        lines.join("\n")
+    }
+}
+
+impl fmt::Display for AssigneeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "TODO assignees:")?;
+        for (name, n) in self.ranking.iter() {
+            writeln!(f, "  {}: {}", name, n)?;
+        }
+        write!(
+            f,
+            "  unassigned: {} of {} ({:.0}%)",
+            self.unassigned,
+            self.total,
+            self.unassigned_percent()
+        )
+    }
+}
+
+/// TODOs found in more than one location, which are often copy-paste debt.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DuplicateTodos {
+    /// Titles and their number of locations, most locations first.
+    pub entries: Vec<(String, usize)>,
+}
+
+impl DuplicateTodos {
+    pub fn from_issue_map<K, V: Eq>(issues: &IssueMap<K, V>) -> Self {
+        let mut entries = issues
+            .todos
+            .values()
+            .map(|issue| (issue.head.title.clone(), issue.body.descs_and_srcs.len()))
+            .filter(|(_, n)| *n > 1)
+            .collect::<Vec<_>>();
+        entries.sort_by(|(a_title, a_n), (b_title, b_n)| b_n.cmp(a_n).then(a_title.cmp(b_title)));
+        DuplicateTodos { entries }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn as_markdown(&self) -> String {
+        let mut lines = vec!["## Duplicates".to_string()];
+        for (title, n) in self.entries.iter() {
+            lines.push(format!("* {}: {}", title, n));
+        }
+        lines.join("\n")
+    }
+}
+
+impl fmt::Display for DuplicateTodos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TODOs found in more than one place:")?;
+        for (title, n) in self.entries.iter() {
+            write!(f, "\n  {}: {}", title, n)?;
+        }
+        Ok(())
+    }
+}
+
+/// The number of TODO locations in each top-level directory.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DirectoryStats {
+    /// Directories and their TODO counts, most TODOs first. Files at the top
+    /// level are counted under `.`.
+    pub counts: Vec<(String, usize)>,
+}
+
+impl DirectoryStats {
+    /// Count the TODO locations in the map by their top-level directory,
+    /// relative to `cwd`.
+    pub fn from_issue_map(issues: &IssueMap<(), FileTodoLocation>, cwd: &str) -> Self {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for issue in issues.todos.values() {
+            for (_, loc) in issue.body.descs_and_srcs.iter() {
+                let path = Path::new(&loc.file);
+                let path = path.strip_prefix(cwd).unwrap_or(path);
+                let mut components = path
+                    .components()
+                    .filter(|c| matches!(c, Component::Normal(_)));
+                let dir = match (components.next(), components.next()) {
+                    (Some(dir), Some(_)) => dir.as_os_str().to_string_lossy().to_string(),
+                    _ => ".".to_string(),
+                };
+                *counts.entry(dir).or_insert(0) += 1;
+            }
+        }
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_by(|(a_dir, a_n), (b_dir, b_n)| b_n.cmp(a_n).then(a_dir.cmp(b_dir)));
+        DirectoryStats { counts }
+    }
+
+    /// A mermaid pie chart, which renders in GitHub markdown.
+    pub fn as_mermaid(&self) -> String {
+        let mut lines = vec![
+            "```mermaid".to_string(),
+            "pie title TODOs by directory".into(),
+        ];
+        for (dir, n) in self.counts.iter() {
+            lines.push(format!("    \"{}\" : {}", dir.replace('"', "'"), n));
+        }
+        lines.push("```".into());
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{source::ParsedTodo, FileTodoLocation};
+
+    #[test]
+    fn can_rank_assignees() {
+        let mut issues = IssueMap::new_source_todos();
+        for (line, (title, assignee)) in [
+            ("Do A.", Some("schell")),
+            ("Do B.", Some("mitchellwrosen")),
+            ("Do C.", Some("schell")),
+            ("Do C.", Some("imalsogreg")),
+            ("Do D.", None),
+            ("Do E.", Some("schell")),
+            ("Do F.", None),
+        ]
+        .iter()
+        .enumerate()
+        {
+            issues.add_parsed_todo(
+                &ParsedTodo {
+                    assignee: *assignee,
+                    ..ParsedTodo::new(*title)
+                },
+                FileTodoLocation {
+                    file: "/repo/src/lib.rs".into(),
+                    src_span: (line + 1, None),
+                },
+            );
+        }
+
+        let stats = AssigneeStats::from_issue_map(&issues);
+        assert_eq!(
+            stats,
+            AssigneeStats {
+                ranking: vec![
+                    ("schell".into(), 3),
+                    ("imalsogreg".into(), 1),
+                    ("mitchellwrosen".into(), 1)
+                ],
+                unassigned: 2,
+                total: 6
+            }
+        );
+        assert_eq!(format!("{:.1}", stats.unassigned_percent()), "33.3");
+        assert_eq!(
+            stats.as_markdown(),
+            "## Assignees\n* schell: 3\n* imalsogreg: 1\n* mitchellwrosen: 1\n* unassigned: 2 \
+             of 6 (33%)"
+        );
+    }
+
+    #[test]
+    fn can_count_todos_per_directory() {
+        let mut issues = IssueMap::new_source_todos();
+        for (line, (title, file)) in [
+            ("Do A.", "/repo/src/lib.rs"),
+            ("Do B.", "/repo/src/parser/source.rs"),
+            ("Do A.", "/repo/tests/it.rs"),
+            ("Do C.", "/repo/build.rs"),
+            ("Do D.", "/repo/src/main.rs"),
+        ]
+        .iter()
+        .enumerate()
+        {
+            issues.add_parsed_todo(
+                &ParsedTodo::new(*title),
+                FileTodoLocation {
+                    file: file.to_string(),
+                    src_span: (line + 1, None),
+                },
+            );
+        }
+
+        let stats = DirectoryStats::from_issue_map(&issues, "/repo");
+        assert_eq!(
+            stats.counts,
+            vec![
+                ("src".to_string(), 3),
+                (".".to_string(), 1),
+                ("tests".to_string(), 1)
+            ]
+        );
+        assert_eq!(
+            stats.as_mermaid(),
+            "```mermaid\npie title TODOs by directory\n    \"src\" : 3\n    \".\" : 1\n    \
+             \"tests\" : 1\n```"
+        );
+    }
+
+    #[test]
+    fn can_count_duplicate_todos() {
+        let mut issues = IssueMap::new_source_todos();
+        for (line, (title, file)) in [
+            ("Do A.", "/repo/src/lib.rs"),
+            ("Do B.", "/repo/src/lib.rs"),
+            ("Do A.", "/repo/src/main.rs"),
+            ("Do C.", "/repo/src/main.rs"),
+            ("Do C.", "/repo/tests/it.rs"),
+            ("Do C.", "/repo/build.rs"),
+        ]
+        .iter()
+        .enumerate()
+        {
+            issues.add_parsed_todo(
+                &ParsedTodo::new(*title),
+                FileTodoLocation {
+                    file: file.to_string(),
+                    src_span: (line + 1, None),
+                },
+            );
+        }
+
+        let duplicates = DuplicateTodos::from_issue_map(&issues);
+        assert_eq!(
+            duplicates.entries,
+            vec![("Do C.".to_string(), 3), ("Do A.".to_string(), 2)]
+        );
+        assert_eq!(
+            duplicates.as_markdown(),
+            "## Duplicates\n* Do C.: 3\n* Do A.: 2"
+        );
+        assert!(issues
+            .as_markdown()
+            .ends_with("\n\n## Duplicates\n* Do C.: 3\n* Do A.: 2"));
+    }
+}
@@ -0,0 +1,230 @@
+//! A single tracking issue whose body is a task list of all TODOs, as an
+//! alternative to one issue per TODO.
+use super::{
+    github::GitHubPatch,
+    parser::{issue::GitHubTodoLocation, FileTodoLocation, Issue, IssueMap, ParsingSource},
+};
+
+/// One entry in the tracker's task list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackerItem {
+    /// Whether the TODO has disappeared from the source.
+    pub done: bool,
+    pub title: String,
+    /// Links to the TODO's locations.
+    pub links: Vec<String>,
+}
+
+/// The task list in the body of a tracker issue.
+///
+/// ```rust
+/// use todo_finder_lib::tracker::*;
+///
+/// let body = "- [ ] Do the thing.\n  - https://github.com/schell/repo/blob/abc/src/lib.rs#L3\n- [x] \
+///             Did the other thing.\n";
+/// let checklist = TrackerChecklist::parse(body);
+/// assert_eq!(checklist.items.len(), 2);
+/// assert!(checklist.items[1].done);
+/// assert_eq!(checklist.as_github_string(), body);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrackerChecklist {
+    pub items: Vec<TrackerItem>,
+}
+
+impl TrackerChecklist {
+    /// Parse the task list out of a tracker issue's body. Lines that are not
+    /// part of the task list are ignored.
+    pub fn parse(body: &str) -> Self {
+        let mut items: Vec<TrackerItem> = vec![];
+        for line in body.lines() {
+            let item = line
+                .strip_prefix("- [ ] ")
+                .map(|title| (false, title))
+                .or_else(|| {
+                    line.strip_prefix("- [x] ")
+                        .or_else(|| line.strip_prefix("- [X] "))
+                        .map(|title| (true, title))
+                });
+            if let Some((done, title)) = item {
+                items.push(TrackerItem {
+                    done,
+                    title: title.trim().to_string(),
+                    links: vec![],
+                });
+            } else if let (Some(link), Some(item)) = (line.strip_prefix("  - "), items.last_mut()) {
+                item.links.push(link.trim().to_string());
+            }
+        }
+        TrackerChecklist { items }
+    }
+
+    pub fn as_github_string(&self) -> String {
+        let mut lines = vec![];
+        for item in self.items.iter() {
+            lines.push(format!(
+                "- [{}] {}\n",
+                if item.done { "x" } else { " " },
+                item.title
+            ));
+            for link in item.links.iter() {
+                lines.push(format!("  - {}\n", link));
+            }
+        }
+        lines.concat()
+    }
+
+    /// The open items as remote issues keyed by title, so they can be diffed
+    /// against the local TODOs with `prepare_patch`. Each issue's external id
+    /// is the index of its item.
+    pub fn as_issue_map(&self) -> IssueMap<u64, GitHubTodoLocation> {
+        let mut issues = IssueMap::new(ParsingSource::MarkdownFile);
+        for (n, item) in self.items.iter().enumerate() {
+            if !item.done {
+                issues
+                    .todos
+                    .insert(item.title.clone(), Issue::new(n as u64, item.title.clone()));
+            }
+        }
+        issues
+    }
+
+    /// Update the task list with a patch from `prepare_patch`. New TODOs are
+    /// added as open items (or reopened, if they were checked off before),
+    /// existing ones get fresh links and the ones that disappeared or are
+    /// marked won't fix are checked off.
+    pub fn apply_patch(
+        &mut self,
+        patch: GitHubPatch,
+        to_link: impl Fn(&FileTodoLocation) -> Result<String, String>,
+    ) -> Result<(), String> {
+        let links = |issue_locs: &[(Vec<String>, FileTodoLocation)]| {
+            issue_locs
+                .iter()
+                .map(|(_, loc)| to_link(loc))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        for id in patch.delete.into_iter().chain(patch.wontfix) {
+            if let Some(item) = self.items.get_mut(id as usize) {
+                item.done = true;
+            }
+        }
+
+        for (_, issue) in patch.edit.todos.into_iter() {
+            if let Some(item) = self.items.get_mut(issue.head.external_id as usize) {
+                item.links = links(&issue.body.descs_and_srcs)?;
+            }
+        }
+
+        let mut created = patch.create.todos.into_iter().collect::<Vec<_>>();
+        created.sort_by(|a, b| a.0.cmp(&b.0));
+        for (_, issue) in created.into_iter() {
+            let title = issue.head.title;
+            let links = links(&issue.body.descs_and_srcs)?;
+            if let Some(item) = self.items.iter_mut().find(|item| item.title == title) {
+                item.done = false;
+                item.links = links;
+            } else {
+                self.items.push(TrackerItem {
+                    done: false,
+                    title,
+                    links,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::source::ParsedTodo;
+
+    fn local_todos(titles: &[&str]) -> IssueMap<(), FileTodoLocation> {
+        let mut todos = IssueMap::new_source_todos();
+        for (line, title) in titles.iter().enumerate() {
+            todos.add_parsed_todo(
+                &ParsedTodo::new(*title),
+                FileTodoLocation {
+                    file: "/repo/src/lib.rs".into(),
+                    src_span: (line + 1, None),
+                },
+            );
+        }
+        todos
+    }
+
+    fn sync(checklist: &mut TrackerChecklist, titles: &[&str]) {
+        let patch = checklist.as_issue_map().prepare_patch(local_todos(titles));
+        checklist
+            .apply_patch(patch, |loc| {
+                loc.to_github_link("/repo", "https://github.com/schell/repo", "abc")
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn checklist_round_trips() {
+        let body = "- [ ] Do A.\n  - https://github.com/schell/repo/blob/abc/src/lib.rs#L1\n  - \
+                    https://github.com/schell/repo/blob/abc/src/main.rs#L9-L12\n- [x] Do B.\n- \
+                    [ ] Do C.\n";
+        let checklist = TrackerChecklist::parse(body);
+        assert_eq!(
+            checklist.items[0],
+            TrackerItem {
+                done: false,
+                title: "Do A.".into(),
+                links: vec![
+                    "https://github.com/schell/repo/blob/abc/src/lib.rs#L1".into(),
+                    "https://github.com/schell/repo/blob/abc/src/main.rs#L9-L12".into()
+                ]
+            }
+        );
+        assert_eq!(checklist.as_github_string(), body);
+        assert_eq!(
+            TrackerChecklist::parse(&checklist.as_github_string()),
+            checklist
+        );
+    }
+
+    #[test]
+    fn checklist_ignores_other_lines() {
+        let body = "Tracks all TODOs.\n\n- [X] Do A.\n* not an item\n";
+        assert_eq!(
+            TrackerChecklist::parse(body).as_github_string(),
+            "- [x] Do A.\n"
+        );
+    }
+
+    #[test]
+    fn checklist_syncs_with_local_todos() {
+        let mut checklist = TrackerChecklist::default();
+        sync(&mut checklist, &["Do B.", "Do A."]);
+        assert_eq!(
+            checklist.as_github_string(),
+            "- [ ] Do A.\n  - https://github.com/schell/repo/blob/abc/src/lib.rs#L2\n- [ ] Do \
+             B.\n  - https://github.com/schell/repo/blob/abc/src/lib.rs#L1\n"
+        );
+
+        sync(&mut checklist, &["Do B.", "Do C."]);
+        assert_eq!(
+            checklist.as_github_string(),
+            "- [x] Do A.\n  - https://github.com/schell/repo/blob/abc/src/lib.rs#L2\n- [ ] Do \
+             B.\n  - https://github.com/schell/repo/blob/abc/src/lib.rs#L1\n- [ ] Do C.\n  - \
+             https://github.com/schell/repo/blob/abc/src/lib.rs#L2\n"
+        );
+
+        // Round trip through the issue body, then reopen A
+        let mut checklist = TrackerChecklist::parse(&checklist.as_github_string());
+        sync(&mut checklist, &["Do A."]);
+        assert_eq!(
+            checklist.as_github_string(),
+            "- [ ] Do A.\n  - https://github.com/schell/repo/blob/abc/src/lib.rs#L1\n- [x] Do \
+             B.\n  - https://github.com/schell/repo/blob/abc/src/lib.rs#L1\n- [x] Do C.\n  - \
+             https://github.com/schell/repo/blob/abc/src/lib.rs#L2\n"
+        );
+    }
+}
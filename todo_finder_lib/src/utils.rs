@@ -0,0 +1,321 @@
+//! Running external commands.
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{ErrorKind, Write},
+    process::{Command, Output, Stdio},
+    sync::{Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long the output of a cached command is considered fresh.
+pub const COMMAND_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// A command as it would be typed in a shell, with the arguments that need it
+/// quoted, so a failing command can be copied and run again.
+///
+/// ```rust
+/// use todo_finder_lib::utils::shell_line;
+///
+/// assert_eq!(shell_line("git", &["log", "-1", "--format=%H"]), "git log -1 --format=%H");
+/// assert_eq!(shell_line("rg", &["-g", "!*.md", "TODO"]), "rg -g '!*.md' TODO");
+/// assert_eq!(shell_line("echo", &["it's", ""]), "echo 'it'\\''s' ''");
+/// ```
+pub fn shell_line(program: &str, args: &[&str]) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:,@+%".contains(c);
+    std::iter::once(&program)
+        .chain(args.iter())
+        .map(|arg| {
+            if !arg.is_empty() && arg.chars().all(is_plain) {
+                arg.to_string()
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Run a command in the given directory, returning its trimmed stdout if
+/// successful.
+pub fn command(program: &str, args: &[&str], dir: &str) -> Result<String, String> {
+    let argv = shell_line(program, args);
+    let output = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| format!("could not run {}: {}", argv, e))?;
+    command_result(&argv, dir, output)
+}
+
+/// Run a command in the given directory with `input` written to its stdin,
+/// returning its trimmed stdout if successful.
+pub fn command_with_stdin(
+    program: &str,
+    args: &[&str],
+    dir: &str,
+    input: &str,
+) -> Result<String, String> {
+    let argv = shell_line(program, args);
+    let output = run_with_stdin(program, args, dir, input, Stdio::piped)?;
+    command_result(&argv, dir, output)
+}
+
+/// Run a command in the given directory with `input` written to its stdin,
+/// its stdout and stderr going straight to ours as it runs.
+pub fn stream_command_with_stdin(
+    program: &str,
+    args: &[&str],
+    dir: &str,
+    input: &str,
+) -> Result<(), String> {
+    let argv = shell_line(program, args);
+    let output = run_with_stdin(program, args, dir, input, Stdio::inherit)?;
+    command_result(&argv, dir, output).map(|_| ())
+}
+
+fn run_with_stdin(
+    program: &str,
+    args: &[&str],
+    dir: &str,
+    input: &str,
+    output: fn() -> Stdio,
+) -> Result<Output, String> {
+    let argv = shell_line(program, args);
+    let mut child = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(output())
+        .stderr(output())
+        .spawn()
+        .map_err(|e| format!("could not run {}: {}", argv, e))?;
+
+    // Write from another thread so a child filling its stdout before reading
+    // all of stdin can't deadlock us.
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| format!("could not open the stdin of {}", argv))?;
+    let input = input.to_string();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("could not wait on {}: {}", argv, e))?;
+    match writer.join() {
+        Ok(Ok(())) => {}
+        // The child is free to exit without reading all of its input.
+        Ok(Err(e)) if e.kind() == ErrorKind::BrokenPipe => {}
+        Ok(Err(e)) => return Err(format!("could not write to the stdin of {}: {}", argv, e)),
+        Err(_) => return Err(format!("writing to the stdin of {} panicked", argv)),
+    }
+    Ok(output)
+}
+
+fn command_result(argv: &str, dir: &str, output: Output) -> Result<String, String> {
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // Empty when the command's stderr went straight to ours
+        if stderr.trim().is_empty() {
+            return Err(format!("`{}` in {} failed ({})", argv, dir, output.status));
+        }
+        return Err(format!(
+            "`{}` in {} failed ({}): '{}'",
+            argv,
+            dir,
+            output.status,
+            stderr.trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Caches command output by working directory and arguments, so metadata like
+/// the git origin isn't shelled out for on every scan.
+pub struct CommandCache {
+    ttl: Duration,
+    entries: HashMap<(String, Vec<String>), (Instant, String)>,
+}
+
+impl CommandCache {
+    pub fn new(ttl: Duration) -> Self {
+        CommandCache {
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Return the cached output for the given directory and arguments, or call
+    /// `run` and cache its output if there is none or it has gone stale.
+    /// Errors are not cached.
+    pub fn get_or_run(
+        &mut self,
+        dir: &str,
+        args: &[&str],
+        run: impl FnOnce() -> Result<String, String>,
+    ) -> Result<String, String> {
+        let key = (
+            dir.to_string(),
+            args.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+        );
+        if let Some((at, output)) = self.entries.get(&key) {
+            if at.elapsed() < self.ttl {
+                return Ok(output.clone());
+            }
+        }
+        let output = run()?;
+        self.entries.insert(key, (Instant::now(), output.clone()));
+        Ok(output)
+    }
+}
+
+fn command_cache() -> &'static Mutex<CommandCache> {
+    static CACHE: OnceLock<Mutex<CommandCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(CommandCache::new(COMMAND_CACHE_TTL)))
+}
+
+/// Run a command in the given directory, reusing its output if it was already
+/// run there within `COMMAND_CACHE_TTL`. Nothing else invalidates the cache,
+/// so output that depends on the repo's state may be that stale.
+pub fn cached_command(program: &str, args: &[&str], dir: &str) -> Result<String, String> {
+    let argv = [&[program], args].concat();
+    let mut cache = command_cache()
+        .lock()
+        .map_err(|e| format!("command cache is poisoned: {}", e))?;
+    cache.get_or_run(dir, &argv, || command(program, args, dir))
+}
+
+/// The GitHub login of the author of the first line in `git blame
+/// --porcelain` output, if their email is a GitHub noreply address or is
+/// mapped to a login in the `assignee_map`. Other authors, and lines that
+/// aren't committed yet, have none, as their name could be anyone's login.
+///
+/// ```rust
+/// use std::collections::BTreeMap;
+/// use todo_finder_lib::utils::blame_author;
+///
+/// let blame = "\
+/// 8a2b3c4d 3 3 1
+/// author Schell Scivally
+/// author-mail <12345+schell@users.noreply.github.com>
+/// \t// TODO: Blame me.";
+/// assert_eq!(blame_author(blame, &BTreeMap::new()), Some("schell".to_string()));
+///
+/// let blame = "8a2b3c4d 3 3 1\nauthor bob\nauthor-mail <bob@example.com>\n";
+/// assert_eq!(blame_author(blame, &BTreeMap::new()), None);
+/// let map = BTreeMap::from([("bob@example.com".to_string(), "bobby".to_string())]);
+/// assert_eq!(blame_author(blame, &map), Some("bobby".to_string()));
+///
+/// let blame = "0000000 3 3 1\nauthor Not Committed Yet\nauthor-mail <not.committed.yet>\n";
+/// assert_eq!(blame_author(blame, &BTreeMap::new()), None);
+/// ```
+pub fn blame_author(porcelain: &str, assignee_map: &BTreeMap<String, String>) -> Option<String> {
+    let field = |name: &str| {
+        porcelain
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+    };
+    if field("author")? == "Not Committed Yet" {
+        return None;
+    }
+    let mail = field("author-mail")?.trim_matches(['<', '>']);
+    match mail.strip_suffix("@users.noreply.github.com") {
+        // Newer noreply addresses start with the user's id
+        Some(user) => Some(
+            user.split_once('+')
+                .map_or(user, |(_, login)| login)
+                .to_string(),
+        ),
+        None => assignee_map.get(mail).cloned(),
+    }
+}
+
+/// Run `git blame` on lines `start` to `end` of a file in the given directory
+/// and return the login of the author of the first, see `blame_author`.
+pub fn git_blame_author(
+    dir: &str,
+    file: &str,
+    start: usize,
+    end: usize,
+    assignee_map: &BTreeMap<String, String>,
+) -> Result<Option<String>, String> {
+    let range = format!("{},{}", start, end);
+    let output = command(
+        "git",
+        &["blame", "-L", &range, "--porcelain", "--", file],
+        dir,
+    )?;
+    Ok(blame_author(&output, assignee_map))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_runs_command_once_per_dir() {
+        let mut cache = CommandCache::new(Duration::from_secs(60));
+        let mut runs = 0;
+        for _ in 0..3 {
+            let output = cache
+                .get_or_run("/some/repo", &["git", "rev-parse", "HEAD"], || {
+                    runs += 1;
+                    Ok("abc123".into())
+                })
+                .unwrap();
+            assert_eq!(output, "abc123");
+        }
+        assert_eq!(runs, 1);
+
+        let _ = cache.get_or_run("/other/repo", &["git", "rev-parse", "HEAD"], || {
+            runs += 1;
+            Ok("def456".into())
+        });
+        assert_eq!(runs, 2);
+    }
+
+    #[test]
+    fn can_pipe_to_a_command() {
+        assert_eq!(
+            command_with_stdin("cat", &[], ".", "{\"todos\": {}}\n"),
+            Ok("{\"todos\": {}}".to_string())
+        );
+        assert!(command_with_stdin("false", &[], ".", "{}").is_err());
+        assert_eq!(stream_command_with_stdin("cat", &[], ".", "{}\n"), Ok(()));
+        assert_eq!(
+            stream_command_with_stdin("sh", &["-c", "exit 3"], ".", ""),
+            Err("`sh -c 'exit 3'` in . failed (exit status: 3)".to_string())
+        );
+    }
+
+    #[test]
+    fn failed_commands_show_their_argv() {
+        let err = command("git", &["rev-parse", "--verify", "no such ref"], ".").unwrap_err();
+        assert!(
+            err.starts_with("`git rev-parse --verify 'no such ref'` in . failed (exit status: "),
+            "{}",
+            err
+        );
+        let err = command_with_stdin("sh", &["-c", "exit 3"], ".", "").unwrap_err();
+        assert!(
+            err.starts_with("`sh -c 'exit 3'` in . failed (exit status: 3)"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn cache_reruns_stale_commands() {
+        let mut cache = CommandCache::new(Duration::from_secs(0));
+        let mut runs = 0;
+        for _ in 0..2 {
+            let _ = cache.get_or_run("/some/repo", &["git", "rev-parse", "HEAD"], || {
+                runs += 1;
+                Ok("abc123".into())
+            });
+        }
+        assert_eq!(runs, 2);
+    }
+}